@@ -0,0 +1,23 @@
+// BP Core Library implementing LNP/BP specifications & standards related to
+// bitcoin protocol
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+fn main() {
+    // `codecov` cfg is set by the coverage CI job. It is declared from the
+    // build script rather than with `[lints]` manifest table, which is not
+    // supported by the minimal supported Cargo version; older Cargo versions
+    // ignore the instruction.
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rustc-check-cfg=cfg(codecov)");
+}
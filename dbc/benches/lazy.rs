@@ -0,0 +1,89 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Compares time of reading witness transaction ids from a large number of
+//! strict-encoded anchors with the full decoding and with [`AnchorRef`].
+//!
+//! Run with `cargo bench -p bp-dbc --bench lazy`.
+
+use std::time::Instant;
+
+use bitcoin::hashes::Hash;
+use bitcoin::Txid;
+use commit_verify::lnpbp4;
+use dbc::test_utils::lnpbp4_fixture_with;
+use dbc::{Anchor, AnchorRef, Proof};
+use strict_encoding::{StrictDecode, StrictEncode};
+
+const ANCHORS: usize = 10_000;
+const PROTOCOLS: u8 = 20;
+
+fn main() {
+    let tree = lnpbp4_fixture_with(0..PROTOCOLS);
+    let lnpbp4_proof = lnpbp4::MerkleBlock::from(tree);
+    let serialized = (0..ANCHORS)
+        .map(|no| {
+            Anchor::from_parts(
+                Txid::hash(&(no as u32).to_le_bytes()),
+                lnpbp4_proof.clone(),
+                Proof::OpretFirst,
+            )
+            .strict_serialize()
+            .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    let start = Instant::now();
+    let owned_txids = serialized
+        .iter()
+        .map(|data| Anchor::<lnpbp4::MerkleBlock>::strict_decode(&data[..]))
+        .map(|anchor| anchor.unwrap().txid())
+        .collect::<Vec<_>>();
+    let owned_time = start.elapsed();
+
+    let start = Instant::now();
+    let lazy_txids = serialized
+        .iter()
+        .map(|data| AnchorRef::parse(data).unwrap().txid())
+        .collect::<Vec<_>>();
+    let lazy_time = start.elapsed();
+
+    let start = Instant::now();
+    let owned_ids = serialized
+        .iter()
+        .map(|data| Anchor::<lnpbp4::MerkleBlock>::strict_decode(&data[..]))
+        .map(|anchor| anchor.unwrap().anchor_id())
+        .collect::<Vec<_>>();
+    let owned_id_time = start.elapsed();
+
+    let start = Instant::now();
+    let lazy_ids = serialized
+        .iter()
+        .map(|data| AnchorRef::parse(data).unwrap().anchor_id())
+        .collect::<Vec<_>>();
+    let lazy_id_time = start.elapsed();
+
+    assert_eq!(owned_txids, lazy_txids);
+    assert_eq!(owned_ids, lazy_ids);
+
+    println!(
+        "reading {} anchors with {} protocols each",
+        ANCHORS, PROTOCOLS
+    );
+    println!("  txid, owned:      {:?}", owned_time);
+    println!("  txid, lazy:       {:?}", lazy_time);
+    println!("  anchor id, owned: {:?}", owned_id_time);
+    println!("  anchor id, lazy:  {:?}", lazy_id_time);
+}
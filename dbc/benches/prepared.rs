@@ -0,0 +1,101 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Compares verification of many anchors against a single transaction with
+//! and without [`PreparedTx`].
+//!
+//! Run with `cargo bench -p bp-dbc --bench prepared`.
+
+use std::time::Instant;
+
+use bitcoin::hashes::Hash;
+use bitcoin::{PackedLockTime, Script, Transaction, TxIn, TxOut};
+use commit_verify::{lnpbp4, ConsensusCommit};
+use dbc::opret::opret_script;
+use dbc::test_utils::{lnpbp4_fixture_with, protocol_fixture};
+use dbc::{Anchor, PreparedTx, Proof};
+
+const ANCHORS: usize = 500;
+const PROTOCOLS: u8 = 20;
+const OUTPUTS: usize = 400;
+
+fn main() {
+    let tree = lnpbp4_fixture_with(0..PROTOCOLS);
+    let commitment = tree.consensus_commit();
+
+    let mut output = vec![
+        TxOut {
+            value: 1000,
+            script_pubkey: Script::new_p2pkh(&Hash::hash(b"pkh")),
+        };
+        OUTPUTS - 1
+    ];
+    output.push(TxOut {
+        value: 0,
+        script_pubkey: opret_script(&commitment[..]).unwrap(),
+    });
+    let tx = Transaction {
+        version: 2,
+        lock_time: PackedLockTime::ZERO,
+        input: vec![TxIn::default()],
+        output,
+    };
+
+    let anchor = Anchor::from_parts(
+        tx.txid(),
+        lnpbp4::MerkleBlock::from(tree),
+        Proof::OpretFirst,
+    );
+    let anchors = (0..ANCHORS)
+        .map(|no| protocol_fixture((no % PROTOCOLS as usize) as u8))
+        .map(|(protocol_id, message)| {
+            let anchor = anchor.to_merkle_proof(protocol_id).unwrap();
+            (anchor, protocol_id, message)
+        })
+        .collect::<Vec<_>>();
+
+    let start = Instant::now();
+    let unprepared = anchors
+        .iter()
+        .map(|(anchor, protocol_id, message)| {
+            tx.txid() == anchor.txid()
+                && anchor.verify(*protocol_id, *message, &tx).unwrap()
+        })
+        .collect::<Vec<_>>();
+    let unprepared_time = start.elapsed();
+
+    let start = Instant::now();
+    let prepared_tx = PreparedTx::new(tx.clone());
+    let prepared = anchors
+        .iter()
+        .map(|(anchor, protocol_id, message)| {
+            prepared_tx.txid() == anchor.txid()
+                && anchor
+                    .verify_prepared(*protocol_id, *message, &prepared_tx)
+                    .unwrap()
+        })
+        .collect::<Vec<_>>();
+    let prepared_time = start.elapsed();
+
+    assert_eq!(prepared, unprepared);
+    assert!(prepared.iter().all(|valid| *valid));
+
+    println!(
+        "verifying {} anchors against {}-output transaction",
+        ANCHORS, OUTPUTS
+    );
+    println!("  unprepared: {:?}", unprepared_time);
+    println!("  prepared:   {:?}", prepared_time);
+}
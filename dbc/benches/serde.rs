@@ -0,0 +1,137 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Measures deserialization of a consignment-like JSON document with anchors
+//! of both commitment types, and parsing of bech32m anchor ids alone.
+//!
+//! Run with `cargo bench -p bp-dbc --features serde --bench serde`.
+
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use bitcoin::hashes::Hash;
+use bitcoin::Txid;
+use commit_verify::lnpbp4;
+use dbc::opret::OpretProof;
+use dbc::tapret::{TapretPathProof, TapretProof};
+use dbc::test_utils::{lnpbp4_fixture_with, protocol_fixture};
+use dbc::{Anchor, AnchorId, Proof};
+use secp256k1::XOnlyPublicKey;
+use serde_crate::{Deserialize, Serialize};
+
+const DOCUMENT_LEN: usize = 10 * 1024 * 1024;
+const PROTOCOLS: u8 = 20;
+const ROUNDS: usize = 5;
+const IDS: usize = 200_000;
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "serde_crate")]
+struct Consignment {
+    anchor_ids: Vec<AnchorId>,
+    anchors: Vec<Anchor<lnpbp4::MerkleProof>>,
+}
+
+fn anchor(
+    no: usize,
+    lnpbp4_proof: lnpbp4::MerkleBlock,
+) -> Anchor<lnpbp4::MerkleBlock> {
+    let internal_key = XOnlyPublicKey::from_str(
+        "c5f93479093e2b8f724a79844cc10928dd44e9a390b539843fb83fbf842723f3",
+    )
+    .unwrap();
+    let proof = if no % 2 == 0 {
+        Proof::OpretFirstAt(OpretProof {
+            vout: no as u32 % 4,
+        })
+    } else {
+        Proof::TapretFirst(TapretProof::with(
+            internal_key,
+            TapretPathProof::new(),
+        ))
+    };
+    Anchor::from_parts(
+        Txid::hash(&(no as u64).to_le_bytes()),
+        lnpbp4_proof,
+        proof,
+    )
+}
+
+/// Consignment-like document revealing one protocol of each anchor.
+fn consignment(anchors: usize) -> Consignment {
+    let tree = lnpbp4_fixture_with(0..PROTOCOLS);
+    let block = lnpbp4::MerkleBlock::from(tree);
+    let anchors = (0..anchors)
+        .map(|no| anchor(no, block.clone()))
+        .collect::<Vec<_>>();
+    Consignment {
+        anchor_ids: anchors.iter().map(|anchor| anchor.anchor_id()).collect(),
+        anchors: anchors
+            .iter()
+            .enumerate()
+            .map(|(no, anchor)| {
+                anchor
+                    .to_merkle_proof(
+                        protocol_fixture((no % PROTOCOLS as usize) as u8).0,
+                    )
+                    .unwrap()
+            })
+            .collect(),
+    }
+}
+
+fn main() {
+    let sample = serde_json::to_string(&consignment(100)).unwrap();
+    let consignment = consignment(DOCUMENT_LEN * 100 / sample.len() + 1);
+    let json = serde_json::to_string(&consignment).unwrap();
+
+    let mut best = Duration::MAX;
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        let decoded = serde_json::from_str::<Consignment>(&json).unwrap();
+        best = best.min(start.elapsed());
+        assert_eq!(decoded.anchors, consignment.anchors);
+        assert_eq!(decoded.anchor_ids, consignment.anchor_ids);
+    }
+
+    println!(
+        "JSON document of {} bytes with {} anchors",
+        json.len(),
+        consignment.anchors.len()
+    );
+    println!("  deserialization: {:?} (best of {})", best, ROUNDS);
+
+    let ids = consignment
+        .anchor_ids
+        .iter()
+        .cycle()
+        .take(IDS)
+        .map(AnchorId::to_string)
+        .collect::<Vec<_>>();
+    let mut best = Duration::MAX;
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        let parsed = ids
+            .iter()
+            .map(|id| AnchorId::from_str(id).unwrap())
+            .collect::<Vec<_>>();
+        best = best.min(start.elapsed());
+        assert!(parsed
+            .iter()
+            .zip(&consignment.anchor_ids)
+            .all(|(a, b)| a == b));
+    }
+    println!("{} bech32m anchor ids", IDS);
+    println!("  parsing: {:?} (best of {})", best, ROUNDS);
+}
@@ -0,0 +1,105 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Compares memory usage and construction time of an index of anchors for the
+//! same witness transaction with owned and shared LNPBP-4 merkle blocks.
+//!
+//! Run with `cargo bench -p bp-dbc --bench shared`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use bitcoin::hashes::Hash;
+use bitcoin::Txid;
+use commit_verify::lnpbp4;
+use dbc::test_utils::{lnpbp4_fixture_with, protocol_fixture};
+use dbc::{Anchor, Proof, SharedMerkleBlock};
+
+const ANCHORS: usize = 10_000;
+const PROTOCOLS: u8 = 20;
+
+struct CountingAlloc;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn main() {
+    let tree = lnpbp4_fixture_with(0..PROTOCOLS);
+    let anchor = Anchor::from_parts(
+        Txid::hash(b"witness"),
+        lnpbp4::MerkleBlock::from(tree),
+        Proof::OpretFirst,
+    );
+    let shared = Anchor::<SharedMerkleBlock>::from(anchor.clone());
+
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let owned_index = (0..ANCHORS).map(|_| anchor.clone()).collect::<Vec<_>>();
+    let owned_time = start.elapsed();
+    let owned_mem = ALLOCATED.load(Ordering::Relaxed) - before;
+
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let shared_index = (0..ANCHORS)
+        .map(|_| shared.shallow_clone())
+        .collect::<Vec<_>>();
+    let shared_time = start.elapsed();
+    let shared_mem = ALLOCATED.load(Ordering::Relaxed) - before;
+
+    let (protocol_id, _) = protocol_fixture(0);
+    let start = Instant::now();
+    let concealed = shared_index
+        .iter()
+        .take(ANCHORS / 100)
+        .map(|anchor| {
+            let mut anchor = anchor.shallow_clone();
+            anchor.conceal_except([protocol_id]).unwrap();
+            anchor
+        })
+        .collect::<Vec<_>>();
+    let conceal_time = start.elapsed();
+
+    assert_eq!(owned_index.len(), shared_index.len());
+    assert!(concealed
+        .iter()
+        .all(|concealed| concealed.anchor_id() == anchor.anchor_id()));
+
+    println!(
+        "index of {} anchors with {} protocols each",
+        ANCHORS, PROTOCOLS
+    );
+    println!("  owned:  {:>10} bytes, {:?}", owned_mem, owned_time);
+    println!("  shared: {:>10} bytes, {:?}", shared_mem, shared_time);
+    println!(
+        "  concealing {} shared anchors: {:?}",
+        concealed.len(),
+        conceal_time
+    );
+}
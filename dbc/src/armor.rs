@@ -0,0 +1,560 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! ASCII string representation of anchors with LNPBP-4 merkle proofs, suitable
+//! for URLs, QR codes and copy-pasting.
+//!
+//! Both forms encode the versioned strict encoding of the anchor (see
+//! [`Anchor::strict_serialize_versioned`]):
+//! - anchors fitting into [`BECH32_MAX_LEN`] characters are encoded as bech32m
+//!   strings with [`ANCHOR_HRP`] human-readable part (like `bpanchor1...`);
+//!   upper-case strings are accepted as well, since they are more compact in QR
+//!   codes;
+//! - larger anchors are encoded as Base85 block using the Z85 alphabet and
+//!   wrapped into [`ARMOR_BEGIN`] and [`ARMOR_END`] lines, with the data
+//!   followed by the first 4 bytes of SHA256 hash of the data as a checksum.
+//!   Trailing group of less than 4 bytes is encoded with one character more
+//!   than the number of bytes in the group, like in Ascii85.
+//!
+//! The format is frozen: strings produced by the previous versions of the
+//! library are decoded by the future versions.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use bitcoin::bech32::{self, FromBase32, ToBase32, Variant};
+use bitcoin::hashes::{sha256, Hash};
+use commit_verify::lnpbp4;
+
+use crate::consts::ANCHOR_MAX_LEN;
+use crate::{Anchor, VersionedAnchorError};
+
+/// Human-readable part of the anchor bech32m string representation.
+pub const ANCHOR_HRP: &str = "bpanchor";
+
+/// Maximal length of the anchor bech32m string; larger anchors are armored.
+/// The bech32m checksum is guaranteed to detect errors only in strings up to
+/// this length.
+pub const BECH32_MAX_LEN: usize = 1023;
+
+/// First line of the armored anchor.
+pub const ARMOR_BEGIN: &str = "-----BEGIN BP ANCHOR-----";
+
+/// Last line of the armored anchor.
+pub const ARMOR_END: &str = "-----END BP ANCHOR-----";
+
+/// Number of Base85 characters in each line of the armored anchor.
+pub const ARMOR_LINE_WIDTH: usize = 64;
+
+const CHECKSUM_LEN: usize = 4;
+
+const Z85_ALPHABET: &[u8; 85] = b"0123456789abcdefghijklmnopqrstuvwxyz\
+    ABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#";
+
+/// Errors parsing anchor from its string representation.
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum AnchorArmorError {
+    /// anchor string has {len} characters, exceeding the limit of {max}
+    /// characters.
+    Oversized {
+        /// Length of the string.
+        len: usize,
+        /// Maximal length of the string.
+        max: usize,
+    },
+
+    /// anchor string has `{0}` human-readable part, while `bpanchor` is
+    /// expected. Hint: the string is probably not an anchor.
+    WrongHrp(String),
+
+    /// anchor string has invalid checksum. Hint: check the string for typos
+    /// or damage during the transfer.
+    BadChecksum,
+
+    /// anchor string is truncated. Hint: make sure the string was copied
+    /// entirely, including the armor end line.
+    Truncated,
+
+    /// anchor armor contains invalid Base85 data at position {0}.
+    InvalidBase85(usize),
+
+    /// anchor string is neither a valid bech32m string nor an armored anchor
+    /// – {0}
+    Bech32(bech32::Error),
+
+    /// anchor data are not correctly encoded – {0}
+    #[from]
+    Encoding(VersionedAnchorError),
+}
+
+impl Anchor<lnpbp4::MerkleProof> {
+    /// Returns bech32m string representation of the anchor with
+    /// [`ANCHOR_HRP`] human-readable part, or `None` if the string would
+    /// exceed [`BECH32_MAX_LEN`] characters.
+    ///
+    /// ```
+    /// use commit_verify::lnpbp4::MerkleProof;
+    /// use dbc::test_utils::{opret_fixture, protocol_fixture};
+    /// use dbc::Anchor;
+    ///
+    /// let (anchor, _) = opret_fixture();
+    /// let (protocol_id, _) = protocol_fixture(1);
+    /// let anchor = anchor.to_merkle_proof(protocol_id).unwrap();
+    /// let s = anchor.to_bech32m_string().unwrap();
+    /// assert!(s.starts_with("bpanchor1"));
+    /// assert_eq!(s, anchor.to_string());
+    /// assert_eq!(s.parse::<Anchor<MerkleProof>>().unwrap(), anchor);
+    /// ```
+    pub fn to_bech32m_string(&self) -> Option<String> {
+        let data = self.strict_serialize_versioned();
+        let len = ANCHOR_HRP.len() + 1 + (data.len() * 8 + 4) / 5 + 6;
+        if len > BECH32_MAX_LEN {
+            return None;
+        }
+        let s = bech32::encode(ANCHOR_HRP, data.to_base32(), Variant::Bech32m)
+            .expect("anchor HRP is a valid bech32 HRP");
+        debug_assert_eq!(s.len(), len);
+        Some(s)
+    }
+
+    /// Returns armored Base85 representation of the anchor, which is used by
+    /// `Display` for the anchors not fitting into the bech32m string. Lines
+    /// are separated with `\n` and the string does not end with a newline.
+    ///
+    /// ```
+    /// use commit_verify::lnpbp4::MerkleProof;
+    /// use dbc::armor::{ARMOR_BEGIN, ARMOR_END};
+    /// use dbc::test_utils::{opret_fixture, protocol_fixture};
+    /// use dbc::Anchor;
+    ///
+    /// let (anchor, _) = opret_fixture();
+    /// let (protocol_id, _) = protocol_fixture(1);
+    /// let anchor = anchor.to_merkle_proof(protocol_id).unwrap();
+    /// let s = anchor.to_armored_string();
+    /// assert!(s.starts_with(ARMOR_BEGIN));
+    /// assert!(s.ends_with(ARMOR_END));
+    /// assert_eq!(s.parse::<Anchor<MerkleProof>>().unwrap(), anchor);
+    /// ```
+    pub fn to_armored_string(&self) -> String {
+        let mut data = self.strict_serialize_versioned();
+        let checksum = sha256::Hash::hash(&data);
+        data.extend_from_slice(&checksum[..CHECKSUM_LEN]);
+        let encoded = base85_encode(&data);
+        let mut s = String::with_capacity(
+            ARMOR_BEGIN.len() + ARMOR_END.len() + encoded.len() * 65 / 64 + 2,
+        );
+        s.push_str(ARMOR_BEGIN);
+        for line in encoded.as_bytes().chunks(ARMOR_LINE_WIDTH) {
+            s.push('\n');
+            s.push_str(
+                std::str::from_utf8(line).expect("Base85 alphabet is ASCII"),
+            );
+        }
+        s.push('\n');
+        s.push_str(ARMOR_END);
+        s
+    }
+}
+
+/// Displays the anchor as bech32m string if it fits into [`BECH32_MAX_LEN`]
+/// characters, or as armored Base85 block otherwise.
+impl Display for Anchor<lnpbp4::MerkleProof> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.to_bech32m_string() {
+            Some(s) => f.write_str(&s),
+            None => f.write_str(&self.to_armored_string()),
+        }
+    }
+}
+
+/// Parses anchor from either of its string representations, ignoring leading
+/// and trailing whitespace. Line breaks and whitespace within the armored
+/// data are ignored as well.
+impl FromStr for Anchor<lnpbp4::MerkleProof> {
+    type Err = AnchorArmorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let max = ANCHOR_MAX_LEN * 2;
+        if s.len() > max {
+            return Err(AnchorArmorError::Oversized { len: s.len(), max });
+        }
+        let data = match s.strip_prefix(ARMOR_BEGIN) {
+            Some(armor) => {
+                let armor = armor
+                    .strip_suffix(ARMOR_END)
+                    .ok_or(AnchorArmorError::Truncated)?;
+                let encoded = armor
+                    .bytes()
+                    .filter(|b| !b.is_ascii_whitespace())
+                    .collect::<Vec<_>>();
+                let mut data = base85_decode(&encoded)?;
+                if data.len() <= CHECKSUM_LEN {
+                    return Err(AnchorArmorError::Truncated);
+                }
+                let checksum = data.split_off(data.len() - CHECKSUM_LEN);
+                if checksum[..] != sha256::Hash::hash(&data)[..CHECKSUM_LEN] {
+                    return Err(AnchorArmorError::BadChecksum);
+                }
+                data
+            }
+            None => {
+                if s.len() > BECH32_MAX_LEN {
+                    return Err(AnchorArmorError::Oversized {
+                        len: s.len(),
+                        max: BECH32_MAX_LEN,
+                    });
+                }
+                let (hrp, data, variant) =
+                    bech32::decode(s).map_err(|err| match err {
+                        bech32::Error::InvalidChecksum => {
+                            AnchorArmorError::BadChecksum
+                        }
+                        bech32::Error::InvalidLength => {
+                            AnchorArmorError::Truncated
+                        }
+                        err => AnchorArmorError::Bech32(err),
+                    })?;
+                if hrp != ANCHOR_HRP {
+                    return Err(AnchorArmorError::WrongHrp(hrp));
+                }
+                if variant != Variant::Bech32m {
+                    return Err(AnchorArmorError::BadChecksum);
+                }
+                Vec::<u8>::from_base32(&data)
+                    .map_err(AnchorArmorError::Bech32)?
+            }
+        };
+        Ok(Anchor::strict_deserialize_versioned(data)?)
+    }
+}
+
+fn base85_encode(data: &[u8]) -> String {
+    let mut s = String::with_capacity((data.len() + 3) / 4 * 5);
+    for chunk in data.chunks(4) {
+        let mut group = [0u8; 4];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let mut value = u32::from_be_bytes(group);
+        let mut digits = [0u8; 5];
+        for digit in digits.iter_mut().rev() {
+            *digit = Z85_ALPHABET[(value % 85) as usize];
+            value /= 85;
+        }
+        for digit in &digits[..chunk.len() + 1] {
+            s.push(*digit as char);
+        }
+    }
+    s
+}
+
+fn base85_decode(encoded: &[u8]) -> Result<Vec<u8>, AnchorArmorError> {
+    if encoded.len() % 5 == 1 {
+        return Err(AnchorArmorError::Truncated);
+    }
+    let mut data = Vec::with_capacity(encoded.len() / 5 * 4 + 3);
+    for (no, chunk) in encoded.chunks(5).enumerate() {
+        let mut value = 0u64;
+        for pos in 0..5 {
+            let digit = match chunk.get(pos) {
+                Some(c) => Z85_ALPHABET
+                    .iter()
+                    .position(|d| d == c)
+                    .ok_or(AnchorArmorError::InvalidBase85(no * 5 + pos))?,
+                // Trailing group is padded with the largest digit, such that
+                // the dropped bytes do not affect the decoded ones
+                None => 84,
+            };
+            value = value * 85 + digit as u64;
+        }
+        let value = u32::try_from(value)
+            .map_err(|_| AnchorArmorError::InvalidBase85(no * 5))?;
+        data.extend_from_slice(&value.to_be_bytes()[..chunk.len() - 1]);
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod test {
+    use strict_encoding::StrictDecode;
+
+    use super::*;
+    use crate::test_utils::{
+        opret_fixture, protocol_fixture, tapret_fixture, FIXTURE_PROTOCOLS,
+    };
+
+    fn proof_anchors() -> Vec<Anchor<lnpbp4::MerkleProof>> {
+        [opret_fixture().0, tapret_fixture().0]
+            .into_iter()
+            .flat_map(|anchor| {
+                (0..FIXTURE_PROTOCOLS).map(move |no| {
+                    anchor
+                        .to_merkle_proof(protocol_fixture(no).0)
+                        .expect("fixture protocol")
+                })
+            })
+            .collect()
+    }
+
+    // Anchor with LNPBP-4 proof path of 40 nodes, which does not fit into
+    // bech32m string
+    fn large_anchor() -> Anchor<lnpbp4::MerkleProof> {
+        let mut data = vec![0x05, 0x00, 40, 0x00];
+        for no in 0..40u8 {
+            data.extend(sha256::Hash::hash(&[no]).into_inner());
+        }
+        let proof = lnpbp4::MerkleProof::strict_deserialize(data).unwrap();
+        let (anchor, _) = tapret_fixture();
+        let (txid, _, dbc_proof) = anchor.into_parts();
+        Anchor::from_parts(txid, proof, dbc_proof)
+    }
+
+    #[test]
+    fn base85_roundtrip() {
+        let data = (0..=255u8)
+            .map(|b| b.wrapping_mul(151).wrapping_add(7))
+            .collect::<Vec<_>>();
+        for len in 0..data.len() {
+            let encoded = base85_encode(&data[..len]);
+            assert_eq!(encoded.len(), len + (len + 3) / 4);
+            assert_eq!(base85_decode(encoded.as_bytes()).unwrap(), data[..len]);
+        }
+        assert_eq!(base85_encode(&[0xFF; 4]), "%nSc0");
+        assert_eq!(base85_decode(b"%nSc0").unwrap(), vec![0xFF; 4]);
+        // Z85 reference vector
+        assert_eq!(
+            base85_encode(&[0x86, 0x4F, 0xD2, 0x6F, 0xB5, 0x59, 0xF7, 0x5B]),
+            "HelloWorld"
+        );
+    }
+
+    #[test]
+    fn base85_invalid() {
+        assert!(matches!(
+            base85_decode(b"Hello\""),
+            Err(AnchorArmorError::Truncated)
+        ));
+        assert!(matches!(
+            base85_decode(b"Hello Wor"),
+            Err(AnchorArmorError::InvalidBase85(5))
+        ));
+        // 85^5 - 1 exceeds 32 bits
+        assert!(matches!(
+            base85_decode(b"#####"),
+            Err(AnchorArmorError::InvalidBase85(0))
+        ));
+    }
+
+    #[test]
+    fn roundtrip() {
+        for anchor in proof_anchors().into_iter().chain([large_anchor()]) {
+            let s = anchor.to_string();
+            assert_eq!(
+                s.parse::<Anchor<lnpbp4::MerkleProof>>().unwrap(),
+                anchor
+            );
+            let armored = anchor.to_armored_string();
+            assert_eq!(
+                armored.parse::<Anchor<lnpbp4::MerkleProof>>().unwrap(),
+                anchor
+            );
+            assert!(armored
+                .lines()
+                .all(|line| line.len() <= ARMOR_LINE_WIDTH
+                    || line == ARMOR_BEGIN));
+            match anchor.to_bech32m_string() {
+                Some(bech32) => {
+                    assert_eq!(s, bech32);
+                    assert!(s.len() <= BECH32_MAX_LEN);
+                    assert_eq!(
+                        s.to_uppercase()
+                            .parse::<Anchor<lnpbp4::MerkleProof>>()
+                            .unwrap(),
+                        anchor
+                    );
+                }
+                None => assert_eq!(s, armored),
+            }
+        }
+    }
+
+    #[test]
+    fn form_selection() {
+        for anchor in proof_anchors() {
+            assert!(anchor.to_bech32m_string().is_some());
+        }
+        let anchor = large_anchor();
+        assert_eq!(anchor.to_bech32m_string(), None);
+        assert!(anchor.to_string().starts_with(ARMOR_BEGIN));
+    }
+
+    #[test]
+    fn armor_whitespace() {
+        let anchor = large_anchor();
+        let armored = anchor.to_armored_string();
+        let reformatted =
+            format!("\r\n  {}\r\n", armored.replace('\n', "\r\n"));
+        assert_eq!(
+            reformatted.parse::<Anchor<lnpbp4::MerkleProof>>().unwrap(),
+            anchor
+        );
+        let unwrapped = format!(
+            "{}{}{}",
+            ARMOR_BEGIN,
+            armored
+                .strip_prefix(ARMOR_BEGIN)
+                .unwrap()
+                .strip_suffix(ARMOR_END)
+                .unwrap()
+                .replace('\n', ""),
+            ARMOR_END
+        );
+        assert_eq!(
+            unwrapped.parse::<Anchor<lnpbp4::MerkleProof>>().unwrap(),
+            anchor
+        );
+    }
+
+    #[test]
+    fn armor_errors() {
+        let armored = large_anchor().to_armored_string();
+        let lines = armored.lines().collect::<Vec<_>>();
+
+        // Missing end line
+        let s = lines[..lines.len() - 1].join("\n");
+        assert!(matches!(
+            s.parse::<Anchor<lnpbp4::MerkleProof>>(),
+            Err(AnchorArmorError::Truncated)
+        ));
+
+        // Missing data line
+        let mut truncated = lines.clone();
+        truncated.remove(lines.len() - 2);
+        let s = truncated.join("\n");
+        assert!(matches!(
+            s.parse::<Anchor<lnpbp4::MerkleProof>>(),
+            Err(AnchorArmorError::BadChecksum | AnchorArmorError::Truncated)
+        ));
+
+        // No data
+        let s = format!("{}\n{}", ARMOR_BEGIN, ARMOR_END);
+        assert!(matches!(
+            s.parse::<Anchor<lnpbp4::MerkleProof>>(),
+            Err(AnchorArmorError::Truncated)
+        ));
+
+        // Damaged data
+        let mut damaged =
+            lines.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        let line = &mut damaged[1];
+        let c = if line.starts_with('0') { "1" } else { "0" };
+        line.replace_range(..1, c);
+        let s = damaged.join("\n");
+        assert!(matches!(
+            s.parse::<Anchor<lnpbp4::MerkleProof>>(),
+            Err(AnchorArmorError::BadChecksum)
+        ));
+
+        // Invalid character
+        let mut invalid = armored.clone();
+        let pos = ARMOR_BEGIN.len() + 1;
+        invalid.replace_range(pos..pos + 1, "\"");
+        let s = invalid;
+        assert!(matches!(
+            s.parse::<Anchor<lnpbp4::MerkleProof>>(),
+            Err(AnchorArmorError::InvalidBase85(0))
+        ));
+    }
+
+    #[test]
+    fn bech32_errors() {
+        let anchor = &proof_anchors()[0];
+        let s = anchor.to_bech32m_string().unwrap();
+
+        assert!(matches!(
+            s[..s.len() - 1].parse::<Anchor<lnpbp4::MerkleProof>>(),
+            Err(AnchorArmorError::BadChecksum)
+        ));
+        assert!(matches!(
+            "bpanchor1qqqq".parse::<Anchor<lnpbp4::MerkleProof>>(),
+            Err(AnchorArmorError::Truncated)
+        ));
+
+        let mut damaged = s.clone();
+        let last = if s.ends_with('q') { "p" } else { "q" };
+        damaged.replace_range(s.len() - 1.., last);
+        assert!(matches!(
+            damaged.parse::<Anchor<lnpbp4::MerkleProof>>(),
+            Err(AnchorArmorError::BadChecksum)
+        ));
+
+        let data = anchor.strict_serialize_versioned().to_base32();
+        let other = bech32::encode("anchor", &data, Variant::Bech32m).unwrap();
+        assert!(matches!(
+            other.parse::<Anchor<lnpbp4::MerkleProof>>(),
+            Err(AnchorArmorError::WrongHrp(hrp)) if hrp == "anchor"
+        ));
+        let bech32 =
+            bech32::encode(ANCHOR_HRP, &data, Variant::Bech32).unwrap();
+        assert!(matches!(
+            bech32.parse::<Anchor<lnpbp4::MerkleProof>>(),
+            Err(AnchorArmorError::BadChecksum)
+        ));
+
+        let mut data = anchor.strict_serialize_versioned();
+        data[0] = 2;
+        let s = bech32::encode(ANCHOR_HRP, data.to_base32(), Variant::Bech32m)
+            .unwrap();
+        assert!(matches!(
+            s.parse::<Anchor<lnpbp4::MerkleProof>>(),
+            Err(AnchorArmorError::Encoding(
+                VersionedAnchorError::UnknownVersion(2)
+            ))
+        ));
+
+        let s = format!("bpanchor1{}", "q".repeat(BECH32_MAX_LEN));
+        assert!(matches!(
+            s.parse::<Anchor<lnpbp4::MerkleProof>>(),
+            Err(AnchorArmorError::Oversized {
+                max: BECH32_MAX_LEN,
+                ..
+            })
+        ));
+        assert!(matches!(
+            "txid".parse::<Anchor<lnpbp4::MerkleProof>>(),
+            Err(AnchorArmorError::Bech32(bech32::Error::MissingSeparator))
+        ));
+    }
+
+    #[test]
+    fn frozen_armor() {
+        let (anchor, _) = opret_fixture();
+        let anchor = anchor.to_merkle_proof(protocol_fixture(1).0).unwrap();
+        assert_eq!(anchor.to_armored_string(), FROZEN_ARMOR);
+        assert_eq!(
+            FROZEN_ARMOR.parse::<Anchor<lnpbp4::MerkleProof>>().unwrap(),
+            anchor
+        );
+    }
+
+    const FROZEN_ARMOR: &str = concat!(
+        "-----BEGIN BP ANCHOR-----\n",
+        "0G%U>lpc9Okenh^v3KNRsC73PnQ$1z45){SnyMXHW*Jft0aZYLK3RW0+-jGSpGjG\n",
+        "too$3Vm[[XM<&QTv30Ms5@alFIm}=bY5)%Q&}Q&YFo1PhbRIUm1^shkY}0]&+O3X\n",
+        "e(<&U0fFNxz$QgZR*Zn1<a-?f$[Pjq6oCbq$t5x7tu(WQO]21*LmLdmUbZL6.$l:\n",
+        "Yc&3pm2/2Q@q.2Rg/S[crKiozO/.0<ykKfgX8o2Up{-[NuvoOU<NxKn%@{(JN\n",
+        "-----END BP ANCHOR-----",
+    );
+}
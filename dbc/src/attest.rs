@@ -0,0 +1,490 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Anchors attested by a signature of the service which produced them.
+//!
+//! Attestation is a BIP-340 signature of the attester over a tagged hash of
+//! the anchor id and the witness transaction id. It proves which service
+//! published the anchor and is verified without access to the bitcoin chain;
+//! the anchor itself still has to be verified against the witness transaction.
+//!
+//! Attested anchors may be exchanged in text form using ASCII armor:
+//!
+//! ```text
+//! -----BEGIN BP ATTESTED ANCHOR-----
+//! Anchor-Id: <anchor id>
+//! Attester: <x-only public key of the attester>
+//!
+//! <hex-encoded strict encoding, 64 characters per line>
+//! -----END BP ATTESTED ANCHOR-----
+//! ```
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use bitcoin::hashes::hex::{self, FromHex, ToHex};
+use bitcoin::hashes::{sha256, sha256t, Hash, HashEngine};
+#[cfg(feature = "wallet")]
+use bitcoin::secp256k1::KeyPair;
+use bitcoin::secp256k1::{schnorr, XOnlyPublicKey, SECP256K1};
+use bitcoin::Txid;
+use commit_verify::lnpbp4::{self, Message, ProtocolId};
+use strict_encoding::{StrictDecode, StrictEncode};
+
+use crate::{Anchor, AnchorId};
+
+static MIDSTATE_ATTESTATION: [u8; 32] = [
+    48, 66, 40, 195, 1, 233, 73, 175, 249, 208, 190, 159, 58, 126, 187, 15, 69,
+    30, 132, 111, 108, 171, 208, 117, 176, 57, 90, 74, 77, 157, 72, 123,
+];
+
+/// Tag used for the [`AttestedAnchor`] signature digest.
+pub struct AttestationTag;
+
+impl sha256t::Tag for AttestationTag {
+    #[inline]
+    fn engine() -> sha256::HashEngine {
+        let midstate = sha256::Midstate::from_inner(MIDSTATE_ATTESTATION);
+        sha256::HashEngine::from_midstate(midstate, 64)
+    }
+}
+
+/// Header line of the ASCII-armored attested anchor.
+pub const ARMOR_HEADER: &str = "-----BEGIN BP ATTESTED ANCHOR-----";
+
+/// Footer line of the ASCII-armored attested anchor.
+pub const ARMOR_FOOTER: &str = "-----END BP ATTESTED ANCHOR-----";
+
+const ARMOR_LINE_LEN: usize = 64;
+
+/// Errors verifying anchor attestation.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum AttestError {
+    /// attested anchor does not commit to the message under protocol
+    /// {0}.
+    UnrelatedProof(ProtocolId),
+
+    /// attestation signature is not valid for the attester key {0}.
+    InvalidSignature(XOnlyPublicKey),
+}
+
+/// Errors parsing ASCII-armored attested anchor.
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum ArmorError {
+    /// ASCII armor header is absent.
+    NoHeader,
+
+    /// ASCII armor footer is absent.
+    NoFooter,
+
+    /// ASCII armor contains unknown header `{0}`.
+    UnknownHeader(String),
+
+    /// ASCII armor header `{0}` does not match the armored data.
+    HeaderMismatch(&'static str),
+
+    /// ASCII-armored data are not a valid hex string – {0}
+    #[from]
+    Hex(hex::Error),
+
+    /// ASCII-armored data are not a valid attested anchor – {0}
+    #[from]
+    Encoding(strict_encoding::Error),
+}
+
+/// Anchor with the attestation signature of the service which produced it.
+///
+/// Since the id of an anchor with a merkle proof depends on the LNPBP-4
+/// message, the attestation carries the protocol id and the message the
+/// anchor commits to.
+#[derive(Clone, PartialEq, Eq, Debug, StrictEncode, StrictDecode)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+pub struct AttestedAnchor {
+    /// Attested anchor.
+    pub anchor: Anchor<lnpbp4::MerkleProof>,
+
+    /// LNPBP-4 protocol id the anchor commits under.
+    pub protocol_id: ProtocolId,
+
+    /// LNPBP-4 message the anchor commits to.
+    pub message: Message,
+
+    /// Public key of the attester.
+    pub attester: XOnlyPublicKey,
+
+    /// BIP-340 signature of the attester.
+    pub signature: schnorr::Signature,
+}
+
+impl AttestedAnchor {
+    /// Attests the anchor committing to the `message` under `protocol_id`
+    /// with the `keypair` of the attester.
+    ///
+    /// Signing is deterministic: the same anchor attested with the same key
+    /// always produces the same signature.
+    ///
+    /// # Errors
+    ///
+    /// If the anchor does not commit to the message under the protocol.
+    ///
+    /// ```
+    /// use bitcoin::secp256k1::{KeyPair, SECP256K1};
+    /// use dbc::attest::AttestedAnchor;
+    /// use dbc::test_utils::{opret_fixture, protocol_fixture};
+    ///
+    /// let (anchor, _) = opret_fixture();
+    /// let (protocol_id, message) = protocol_fixture(0);
+    /// let proof = anchor.to_merkle_proof(protocol_id).unwrap();
+    /// let keypair = KeyPair::from_seckey_slice(SECP256K1, &[1u8; 32]).unwrap();
+    ///
+    /// let attested =
+    ///     AttestedAnchor::sign(proof, (protocol_id, message), &keypair).unwrap();
+    /// assert_eq!(attested.attester, keypair.x_only_public_key().0);
+    /// assert_eq!(attested.anchor_id(), Ok(anchor.anchor_id()));
+    /// assert_eq!(attested.verify_attestation(), Ok(()));
+    /// ```
+    #[cfg(feature = "wallet")]
+    pub fn sign(
+        anchor: Anchor<lnpbp4::MerkleProof>,
+        commitment: (ProtocolId, Message),
+        keypair: &KeyPair,
+    ) -> Result<AttestedAnchor, AttestError> {
+        let (protocol_id, message) = commitment;
+        let anchor_id = anchor
+            .anchor_id(protocol_id, message)
+            .map_err(|_| AttestError::UnrelatedProof(protocol_id))?;
+        let digest = attestation_digest(anchor_id, anchor.txid());
+        let signature = SECP256K1.sign_schnorr_no_aux_rand(&digest, keypair);
+        Ok(AttestedAnchor {
+            anchor,
+            protocol_id,
+            message,
+            attester: keypair.x_only_public_key().0,
+            signature,
+        })
+    }
+
+    /// Returns id of the attested anchor.
+    ///
+    /// # Errors
+    ///
+    /// Errors with [`AttestError::UnrelatedProof`] if the anchor does not
+    /// commit to the attested message, which is possible for attestations
+    /// read from external data; such attestations also fail
+    /// [`AttestedAnchor::verify_attestation`].
+    pub fn anchor_id(&self) -> Result<AnchorId, AttestError> {
+        self.anchor
+            .anchor_id(self.protocol_id, self.message)
+            .map_err(|_| AttestError::UnrelatedProof(self.protocol_id))
+    }
+
+    /// Verifies that the attestation signature is produced by the attester
+    /// over the anchor id and the witness transaction id. Does not verify the
+    /// anchor against the witness transaction.
+    ///
+    /// # Errors
+    ///
+    /// If the anchor does not commit to the attested message or the signature
+    /// is invalid.
+    ///
+    /// ```
+    /// # #[cfg(feature = "wallet")] {
+    /// use bitcoin::hashes::Hash;
+    /// use bitcoin::secp256k1::{KeyPair, SECP256K1};
+    /// use bitcoin::Txid;
+    /// use dbc::attest::{AttestError, AttestedAnchor};
+    /// use dbc::test_utils::{opret_fixture, protocol_fixture};
+    ///
+    /// let (anchor, _) = opret_fixture();
+    /// let (protocol_id, message) = protocol_fixture(0);
+    /// let proof = anchor.to_merkle_proof(protocol_id).unwrap();
+    /// let keypair = KeyPair::from_seckey_slice(SECP256K1, &[1u8; 32]).unwrap();
+    /// let attested =
+    ///     AttestedAnchor::sign(proof, (protocol_id, message), &keypair).unwrap();
+    ///
+    /// // Signature covers the witness transaction id
+    /// let mut forged = attested.clone();
+    /// forged.anchor = forged.anchor.with_txid(Txid::all_zeros());
+    /// assert_eq!(
+    ///     forged.verify_attestation(),
+    ///     Err(AttestError::InvalidSignature(attested.attester))
+    /// );
+    /// # }
+    /// ```
+    pub fn verify_attestation(&self) -> Result<(), AttestError> {
+        let anchor_id = self.anchor_id()?;
+        let digest = attestation_digest(anchor_id, self.anchor.txid());
+        SECP256K1
+            .verify_schnorr(&self.signature, &digest, &self.attester)
+            .map_err(|_| AttestError::InvalidSignature(self.attester))
+    }
+}
+
+/// Displays the attested anchor in ASCII armor.
+impl Display for AttestedAnchor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let data = self.strict_serialize().map_err(|_| fmt::Error)?.to_hex();
+        writeln!(f, "{}", ARMOR_HEADER)?;
+        if let Ok(anchor_id) = self.anchor_id() {
+            writeln!(f, "Anchor-Id: {}", anchor_id)?;
+        }
+        writeln!(f, "Attester: {}", self.attester)?;
+        writeln!(f)?;
+        for line in data.as_bytes().chunks(ARMOR_LINE_LEN) {
+            writeln!(
+                f,
+                "{}",
+                std::str::from_utf8(line).expect("hex string is ASCII")
+            )?;
+        }
+        write!(f, "{}", ARMOR_FOOTER)
+    }
+}
+
+/// Parses ASCII-armored attested anchor, checking that the armor headers match
+/// the armored data. Does not verify the attestation.
+impl FromStr for AttestedAnchor {
+    type Err = ArmorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().map(str::trim).skip_while(|l| l.is_empty());
+        if lines.next() != Some(ARMOR_HEADER) {
+            return Err(ArmorError::NoHeader);
+        }
+
+        let mut anchor_id = None;
+        let mut attester = None;
+        for line in lines.by_ref().take_while(|l| !l.is_empty()) {
+            match line.split_once(':').map(|(k, v)| (k, v.trim())) {
+                Some(("Anchor-Id", value)) => anchor_id = Some(value),
+                Some(("Attester", value)) => attester = Some(value),
+                _ => return Err(ArmorError::UnknownHeader(line.to_owned())),
+            }
+        }
+
+        let mut data = String::new();
+        let mut footer = false;
+        for line in lines {
+            if line == ARMOR_FOOTER {
+                footer = true;
+                break;
+            }
+            data.push_str(line);
+        }
+        if !footer {
+            return Err(ArmorError::NoFooter);
+        }
+
+        let attested: AttestedAnchor =
+            strict_encoding::strict_deserialize(Vec::<u8>::from_hex(&data)?)?;
+        let actual_id = attested.anchor_id().ok();
+        if anchor_id.and_then(|id| AnchorId::from_str(id).ok()) != actual_id {
+            return Err(ArmorError::HeaderMismatch("Anchor-Id"));
+        }
+        if attester != Some(attested.attester.to_string().as_str()) {
+            return Err(ArmorError::HeaderMismatch("Attester"));
+        }
+        Ok(attested)
+    }
+}
+
+/// Computes message signed by the attester.
+fn attestation_digest(
+    anchor_id: AnchorId,
+    txid: Txid,
+) -> bitcoin::secp256k1::Message {
+    let mut engine = sha256t::Hash::<AttestationTag>::engine();
+    engine.input(&anchor_id[..]);
+    engine.input(&txid[..]);
+    let digest = sha256t::Hash::<AttestationTag>::from_engine(engine);
+    bitcoin::secp256k1::Message::from_slice(&digest[..])
+        .expect("hash has the size of secp256k1 message")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(feature = "wallet")]
+    use crate::test_utils::{opret_fixture, protocol_fixture};
+
+    #[cfg(feature = "wallet")]
+    fn attested_fixture() -> AttestedAnchor {
+        let (anchor, _) = opret_fixture();
+        let (protocol_id, message) = protocol_fixture(0);
+        let proof = anchor.to_merkle_proof(protocol_id).unwrap();
+        let keypair =
+            KeyPair::from_seckey_slice(SECP256K1, &[0x11u8; 32]).unwrap();
+        AttestedAnchor::sign(proof, (protocol_id, message), &keypair).unwrap()
+    }
+
+    #[test]
+    #[cfg(feature = "wallet")]
+    fn signature_vector() {
+        let attested = attested_fixture();
+        assert_eq!(
+            attested.attester.to_string(),
+            "4f355bdcb7cc0af728ef3cceb9615d90684bb5b2ca5f859ab0f0b704075871aa"
+        );
+        assert_eq!(
+            attested.signature.to_string(),
+            "6fb3c9fce5eb1461921d28db13b718e5b7486a3f80453c05e0a75171535cbd9d\
+             bfdbb901b3f57f7989aaa7614a73524110f7d0e957ae0d7724b629bcddb30821"
+        );
+        assert_eq!(attested.verify_attestation(), Ok(()));
+        assert_eq!(attested, attested_fixture());
+    }
+
+    #[test]
+    #[cfg(feature = "wallet")]
+    fn attestation_tampering() {
+        let attested = attested_fixture();
+        let (other_id, other_msg) = protocol_fixture(1);
+
+        // Attestation covers the LNPBP-4 commitment through the anchor id
+        let mut forged = attested.clone();
+        forged.message = other_msg;
+        assert_eq!(
+            forged.verify_attestation(),
+            Err(AttestError::InvalidSignature(attested.attester))
+        );
+
+        let mut forged = attested.clone();
+        forged.protocol_id = other_id;
+        assert_eq!(
+            forged.verify_attestation(),
+            Err(AttestError::UnrelatedProof(other_id))
+        );
+        // Attestations read from external data may be unrelated as well
+        let decoded = AttestedAnchor::strict_deserialize(
+            forged.strict_serialize().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            decoded.anchor_id(),
+            Err(AttestError::UnrelatedProof(other_id))
+        );
+        assert!(!decoded.to_string().contains("Anchor-Id"));
+
+        let keypair =
+            KeyPair::from_seckey_slice(SECP256K1, &[0x22u8; 32]).unwrap();
+        let mut forged = attested.clone();
+        forged.attester = keypair.x_only_public_key().0;
+        assert_eq!(
+            forged.verify_attestation(),
+            Err(AttestError::InvalidSignature(forged.attester))
+        );
+
+        let mut forged = attested.clone();
+        let (tapret, _) = crate::test_utils::tapret_fixture();
+        forged.anchor =
+            forged.anchor.with_dbc_proof(tapret.dbc_proof().clone());
+        assert_eq!(
+            forged.verify_attestation(),
+            Err(AttestError::InvalidSignature(attested.attester))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "wallet")]
+    fn encoding_roundtrip() {
+        let attested = attested_fixture();
+
+        let data = attested.strict_serialize().unwrap();
+        assert_eq!(
+            AttestedAnchor::strict_deserialize(&data).unwrap(),
+            attested
+        );
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&attested).unwrap();
+            let decoded: AttestedAnchor = serde_json::from_str(&json).unwrap();
+            assert_eq!(decoded, attested);
+        }
+
+        let armored = attested.to_string();
+        assert!(armored.starts_with(ARMOR_HEADER));
+        assert!(armored.ends_with(ARMOR_FOOTER));
+        assert!(armored.contains(&format!(
+            "Anchor-Id: {}",
+            attested.anchor_id().unwrap()
+        )));
+        assert_eq!(AttestedAnchor::from_str(&armored).unwrap(), attested);
+        let padded = format!("\n  {}\n\n", armored.replace('\n', "\r\n"));
+        assert_eq!(AttestedAnchor::from_str(&padded).unwrap(), attested);
+    }
+
+    #[test]
+    #[cfg(feature = "wallet")]
+    fn armor_errors() {
+        let armored = attested_fixture().to_string();
+        assert!(matches!(
+            AttestedAnchor::from_str(&armored[1..]),
+            Err(ArmorError::NoHeader)
+        ));
+        assert!(matches!(
+            AttestedAnchor::from_str(&armored[..armored.len() - 1]),
+            Err(ArmorError::NoFooter)
+        ));
+        assert!(matches!(
+            AttestedAnchor::from_str(&armored.replace("Attester", "Signer")),
+            Err(ArmorError::UnknownHeader(header)) if header.starts_with("Signer:")
+        ));
+
+        let other = attested_fixture().anchor.txid().to_string();
+        let (prefix, rest) = armored.split_once("Anchor-Id: ").unwrap();
+        let (_, rest) = rest.split_once('\n').unwrap();
+        let forged = format!("{}Anchor-Id: {}\n{}", prefix, other, rest);
+        assert!(matches!(
+            AttestedAnchor::from_str(&forged),
+            Err(ArmorError::HeaderMismatch("Anchor-Id"))
+        ));
+
+        let lines = armored.lines().collect::<Vec<_>>();
+        let truncated = [&lines[..4], &lines[5..]].concat().join("\n");
+        assert!(matches!(
+            AttestedAnchor::from_str(&truncated),
+            Err(ArmorError::Encoding(_))
+        ));
+        let trailing =
+            armored.replace(ARMOR_FOOTER, &format!("00\n{}", ARMOR_FOOTER));
+        assert!(matches!(
+            AttestedAnchor::from_str(&trailing),
+            Err(ArmorError::Encoding(
+                strict_encoding::Error::DataNotEntirelyConsumed
+            ))
+        ));
+        let invalid = armored.replacen("\n\n", "\n\nzz", 1);
+        assert!(matches!(
+            AttestedAnchor::from_str(&invalid),
+            Err(ArmorError::Hex(_))
+        ));
+    }
+
+    #[test]
+    fn attestation_midstate() {
+        let tag_hash = sha256::Hash::hash(b"bp:dbc:attestation:v1");
+        let mut engine = sha256::Hash::engine();
+        engine.input(&tag_hash[..]);
+        engine.input(&tag_hash[..]);
+        assert_eq!(engine.midstate().into_inner(), MIDSTATE_ATTESTATION);
+    }
+}
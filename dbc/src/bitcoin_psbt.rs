@@ -0,0 +1,250 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Commitments to PSBTs represented with rust-bitcoin
+//! [`PartiallySignedTransaction`] type.
+//!
+//! The commitment workflow of the library operates on [`Psbt`] type of the
+//! `psbt` crate. With `bitcoin-psbt` feature the same commit-verify traits are
+//! implemented for [`PartiallySignedTransaction`]: the PSBT is converted into
+//! [`Psbt`] with [`psbt_from_v0`], committed with the [`Psbt`] implementation
+//! and converted back. Both conversions preserve all the proprietary keys,
+//! including the ones set by the commitment, so anchors produced from either
+//! PSBT type are identical.
+//!
+//! ```
+//! use bitcoin::psbt::PartiallySignedTransaction;
+//! use dbc::anchor::PsbtEmbeddedMessage;
+//! use dbc::test_utils::psbt_fixture;
+//! use dbc::{CloseMethod, EmbedCommitVerifyStatic};
+//!
+//! let mut psbt = psbt_fixture(CloseMethod::OpretFirst);
+//! let mut v0 = PartiallySignedTransaction::from(psbt.clone());
+//!
+//! let anchor = v0.embed_commit_static(&PsbtEmbeddedMessage).unwrap();
+//! assert_eq!(
+//!     anchor,
+//!     psbt.embed_commit_static(&PsbtEmbeddedMessage).unwrap()
+//! );
+//! assert_eq!(v0, PartiallySignedTransaction::from(psbt));
+//! ```
+
+use bitcoin::psbt::PartiallySignedTransaction;
+use commit_verify::lnpbp4;
+#[cfg(not(feature = "deterministic"))]
+use commit_verify::{EmbedCommitProof, EmbedCommitVerify};
+use psbt::Psbt;
+
+use crate::anchor::PsbtEmbeddedMessage;
+use crate::commit::psbt_from_v0;
+use crate::tapret::{Lnpbp6, PsbtCommitError, PsbtVerifyError};
+use crate::{Anchor, EmbedCommitProofStatic, EmbedCommitVerifyStatic};
+
+/// Runs commitment procedure `f` over `psbt` converted into [`Psbt`], writing
+/// the committed PSBT back. The `psbt` is left unchanged if the procedure
+/// fails.
+fn commit_converted<T>(
+    psbt: &mut PartiallySignedTransaction,
+    f: impl FnOnce(&mut Psbt) -> Result<T, PsbtCommitError>,
+) -> Result<T, PsbtCommitError> {
+    let mut converted = psbt_from_v0(psbt.clone())?;
+    let res = f(&mut converted)?;
+    *psbt = converted.into();
+    Ok(res)
+}
+
+#[cfg(not(feature = "deterministic"))]
+impl EmbedCommitProof<PsbtEmbeddedMessage, PartiallySignedTransaction, Lnpbp6>
+    for Anchor<lnpbp4::MerkleTree>
+{
+    fn restore_original_container(
+        &self,
+        psbt: &PartiallySignedTransaction,
+    ) -> Result<PartiallySignedTransaction, PsbtVerifyError> {
+        let psbt = psbt_from_v0(psbt.clone())?;
+        EmbedCommitProof::<_, Psbt, Lnpbp6>::restore_original_container(
+            self, &psbt,
+        )
+        .map(PartiallySignedTransaction::from)
+    }
+}
+
+impl
+    EmbedCommitProofStatic<
+        PsbtEmbeddedMessage,
+        PartiallySignedTransaction,
+        Lnpbp6,
+    > for Anchor<lnpbp4::MerkleTree>
+{
+    fn restore_original_container(
+        &self,
+        psbt: &PartiallySignedTransaction,
+    ) -> Result<PartiallySignedTransaction, PsbtVerifyError> {
+        let psbt = psbt_from_v0(psbt.clone())?;
+        EmbedCommitProofStatic::<_, Psbt, Lnpbp6>::restore_original_container(
+            self, &psbt,
+        )
+        .map(PartiallySignedTransaction::from)
+    }
+}
+
+#[cfg(not(feature = "deterministic"))]
+impl EmbedCommitVerify<PsbtEmbeddedMessage, Lnpbp6>
+    for PartiallySignedTransaction
+{
+    type Proof = Anchor<lnpbp4::MerkleTree>;
+    type CommitError = PsbtCommitError;
+    type VerifyError = PsbtVerifyError;
+
+    #[inline]
+    fn embed_commit(
+        &mut self,
+        msg: &PsbtEmbeddedMessage,
+    ) -> Result<Self::Proof, Self::CommitError> {
+        commit_converted(self, |psbt| psbt.embed_commit(msg))
+    }
+}
+
+impl EmbedCommitVerifyStatic<PsbtEmbeddedMessage, Lnpbp6>
+    for PartiallySignedTransaction
+{
+    type Proof = Anchor<lnpbp4::MerkleTree>;
+    type CommitError = PsbtCommitError;
+    type VerifyError = PsbtVerifyError;
+
+    #[inline]
+    fn embed_commit_static(
+        &mut self,
+        msg: &PsbtEmbeddedMessage,
+    ) -> Result<Self::Proof, Self::CommitError> {
+        commit_converted(self, |psbt| psbt.embed_commit_static(msg))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::psbt::raw::ProprietaryKey;
+    use bitcoin::{Script, TxOut};
+
+    use super::*;
+    use crate::test_utils::psbt_fixture;
+    use crate::CloseMethod;
+
+    /// PSBT fixture with custom proprietary keys on the global, input and
+    /// output maps.
+    fn v0_fixture(method: CloseMethod) -> PartiallySignedTransaction {
+        let mut psbt = PartiallySignedTransaction::from(psbt_fixture(method));
+        let key = |subtype: u8| ProprietaryKey {
+            prefix: b"test".to_vec(),
+            subtype,
+            key: vec![subtype],
+        };
+        psbt.proprietary.insert(key(0), vec![0xA0]);
+        psbt.inputs[0].proprietary.insert(key(1), vec![0xA1]);
+        for output in &mut psbt.outputs {
+            output.proprietary.insert(key(2), vec![0xA2]);
+        }
+        psbt
+    }
+
+    #[test]
+    fn conversion_roundtrip() {
+        for method in [CloseMethod::TapretFirst, CloseMethod::OpretFirst] {
+            let v0 = v0_fixture(method);
+            let psbt = psbt_from_v0(v0.clone()).unwrap();
+            assert_eq!(psbt.proprietary, v0.proprietary);
+            assert_eq!(psbt.inputs[0].proprietary, v0.inputs[0].proprietary);
+            for (output, v0_output) in psbt.outputs.iter().zip(&v0.outputs) {
+                assert_eq!(output.proprietary, v0_output.proprietary);
+            }
+            assert_eq!(PartiallySignedTransaction::from(psbt), v0);
+
+            // Keys set by the commitment survive conversion as well
+            let mut committed = v0.clone();
+            committed.embed_commit_static(&PsbtEmbeddedMessage).unwrap();
+            let psbt = psbt_from_v0(committed.clone()).unwrap();
+            assert_eq!(PartiallySignedTransaction::from(psbt), committed);
+        }
+    }
+
+    #[test]
+    fn same_anchors() {
+        for method in [CloseMethod::TapretFirst, CloseMethod::OpretFirst] {
+            let mut v0 = v0_fixture(method);
+            let mut psbt = psbt_from_v0(v0.clone()).unwrap();
+
+            let anchor = v0.embed_commit_static(&PsbtEmbeddedMessage).unwrap();
+            assert_eq!(
+                psbt.embed_commit_static(&PsbtEmbeddedMessage).unwrap(),
+                anchor
+            );
+            assert_eq!(v0, PartiallySignedTransaction::from(psbt.clone()));
+            assert_eq!(anchor.txid(), v0.unsigned_tx.txid());
+
+            let restored =
+                EmbedCommitProofStatic::<_, Psbt, Lnpbp6>::restore_original_container(
+                    &anchor, &psbt,
+                )
+                .map(PartiallySignedTransaction::from);
+            assert_eq!(
+                EmbedCommitProofStatic::<_, _, Lnpbp6>::restore_original_container(
+                    &anchor, &v0
+                ),
+                restored
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "deterministic"))]
+    fn random_entropy() {
+        for method in [CloseMethod::TapretFirst, CloseMethod::OpretFirst] {
+            let original = v0_fixture(method);
+            let mut v0 = original.clone();
+            let anchor = v0.embed_commit(&PsbtEmbeddedMessage).unwrap();
+            assert_eq!(anchor.txid(), v0.unsigned_tx.txid());
+            assert_ne!(v0, original);
+
+            let psbt = psbt_from_v0(v0.clone()).unwrap();
+            assert_eq!(
+                EmbedCommitProof::<_, _, Lnpbp6>::restore_original_container(
+                    &anchor, &v0
+                ),
+                EmbedCommitProof::<_, Psbt, Lnpbp6>::restore_original_container(
+                    &anchor, &psbt
+                )
+                .map(PartiallySignedTransaction::from)
+            );
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        let mut v0 = v0_fixture(CloseMethod::OpretFirst);
+        v0.unsigned_tx.output.push(TxOut {
+            value: 500,
+            script_pubkey: Script::new_op_return(&[]),
+        });
+        let original = v0.clone();
+        assert_eq!(
+            v0.embed_commit_static(&PsbtEmbeddedMessage),
+            Err(PsbtCommitError::MalformedPsbt {
+                outputs: 2,
+                tx_outputs: 3
+            })
+        );
+        assert_eq!(v0, original);
+    }
+}
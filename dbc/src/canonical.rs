@@ -0,0 +1,221 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Decoding of strict-encoded data accepting only their canonical encoding.
+//!
+//! Anchors are identified by [`AnchorId`](crate::AnchorId) computed from their
+//! commit encoding; however, applications often address anchors by hashes of
+//! their raw strict-encoded bytes. This is safe only if two different byte
+//! strings never decode into equal values, which is ensured by
+//! [`StrictDecodeCanonical::strict_decode_canonical`].
+
+use std::io;
+
+use commit_verify::lnpbp4;
+use strict_encoding::{StrictDecode, StrictEncode};
+
+use crate::anchor::DbcProof;
+use crate::tapret::TapretProof;
+use crate::Anchor;
+
+/// Errors decoding data in canonical strict encoding.
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum DecodeError {
+    /// data are not correctly strict-encoded – {0}
+    #[from]
+    Encoding(strict_encoding::Error),
+
+    /// data are not encoded canonically: they contain trailing bytes or their
+    /// re-encoding produces different bytes.
+    NonCanonical,
+}
+
+/// Strict decoding accepting only the canonical encoding of the data, i.e.
+/// the one which is produced by the strict encoding of the decoded value.
+pub trait StrictDecodeCanonical: StrictEncode + StrictDecode {
+    /// Decodes value from the `data` and checks that the data are the
+    /// canonical encoding of the value by re-encoding it.
+    ///
+    /// # Errors
+    ///
+    /// [`DecodeError::NonCanonical`] if the data contain bytes after the
+    /// encoded value or differ from the re-encoded value.
+    ///
+    /// ```
+    /// use commit_verify::lnpbp4::MerkleBlock;
+    /// use dbc::canonical::{DecodeError, StrictDecodeCanonical};
+    /// use dbc::test_utils::opret_fixture;
+    /// use dbc::Anchor;
+    /// use strict_encoding::StrictEncode;
+    ///
+    /// let (anchor, _) = opret_fixture();
+    /// let mut data = anchor.strict_serialize().unwrap();
+    /// let decoded = Anchor::<MerkleBlock>::strict_decode_canonical(&data);
+    /// assert_eq!(decoded.unwrap(), anchor);
+    ///
+    /// data.push(0);
+    /// assert!(matches!(
+    ///     Anchor::<MerkleBlock>::strict_decode_canonical(&data),
+    ///     Err(DecodeError::NonCanonical)
+    /// ));
+    /// ```
+    fn strict_decode_canonical(
+        data: impl AsRef<[u8]>,
+    ) -> Result<Self, DecodeError> {
+        let data = data.as_ref();
+        let mut cursor = io::Cursor::new(data);
+        let value = Self::strict_decode(&mut cursor)?;
+        if cursor.position() != data.len() as u64
+            || value.strict_serialize()? != data
+        {
+            return Err(DecodeError::NonCanonical);
+        }
+        Ok(value)
+    }
+}
+
+impl<L, P> StrictDecodeCanonical for Anchor<L, P>
+where
+    L: lnpbp4::Proof,
+    P: DbcProof,
+    Self: StrictEncode + StrictDecode,
+{
+}
+
+impl StrictDecodeCanonical for TapretProof {}
+
+#[cfg(test)]
+mod test {
+    use bitcoin_scripts::LeafScript;
+
+    use super::*;
+    use crate::tapret::{TapretNodePartner, TapretPathProof};
+    use crate::test_utils::{
+        internal_key_fixture, opret_fixture, protocol_fixture, tapret_fixture,
+        TestRng,
+    };
+    use crate::ProofOrUnknown;
+
+    /// Produces mutations of the valid encoding by replacing, inserting,
+    /// removing and appending bytes, as well as by truncating the data.
+    fn mutations(data: &[u8], rng: &mut TestRng) -> Vec<Vec<u8>> {
+        (0..2000)
+            .map(|_| {
+                let mut data = data.to_vec();
+                for _ in 0..=rng.below(3) {
+                    let pos = rng.below(data.len() + 1);
+                    let byte = rng.next_u64() as u8;
+                    match rng.below(5) {
+                        0 if pos < data.len() => data[pos] = byte,
+                        1 => data.insert(pos, byte),
+                        2 if pos < data.len() => {
+                            data.remove(pos);
+                        }
+                        3 => data.truncate(pos),
+                        _ => data.push(byte),
+                    }
+                }
+                data
+            })
+            .collect()
+    }
+
+    /// Checks that decoding is the inverse of encoding on all accepted
+    /// mutations of the valid `value` encoding; returns number of the accepted
+    /// mutations.
+    fn fuzz<T>(value: &T, rng: &mut TestRng) -> usize
+    where
+        T: StrictDecodeCanonical + Eq + std::fmt::Debug,
+    {
+        let data = value.strict_serialize().unwrap();
+        assert_eq!(&T::strict_decode_canonical(&data).unwrap(), value);
+
+        let mut accepted = 0;
+        for mutation in mutations(&data, rng) {
+            // Whatever the plain strict decoding accepts, must be canonical
+            if let Ok(decoded) =
+                strict_encoding::strict_deserialize::<T>(&mutation)
+            {
+                assert_eq!(decoded.strict_serialize().unwrap(), mutation);
+            }
+            if let Ok(decoded) = T::strict_decode_canonical(&mutation) {
+                assert_eq!(decoded.strict_serialize().unwrap(), mutation);
+                accepted += 1;
+            }
+        }
+        accepted
+    }
+
+    fn tapret_proof_fixtures() -> Vec<TapretProof> {
+        let leaf = LeafScript::tapscript(default!());
+        vec![
+            TapretProof::with(internal_key_fixture(), TapretPathProof::new()),
+            TapretProof::with(
+                internal_key_fixture(),
+                TapretPathProof::with(TapretNodePartner::RightLeaf(leaf), 1)
+                    .unwrap(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        let (anchor, _) = opret_fixture();
+        let mut data = anchor.strict_serialize().unwrap();
+        data.extend([0u8; 4]);
+        assert!(matches!(
+            Anchor::<lnpbp4::MerkleBlock>::strict_decode_canonical(&data),
+            Err(DecodeError::NonCanonical)
+        ));
+        data.truncate(data.len() - 5);
+        assert!(matches!(
+            Anchor::<lnpbp4::MerkleBlock>::strict_decode_canonical(&data),
+            Err(DecodeError::Encoding(_))
+        ));
+
+        for proof in tapret_proof_fixtures() {
+            let mut data = proof.strict_serialize().unwrap();
+            data.push(0);
+            assert!(matches!(
+                TapretProof::strict_decode_canonical(&data),
+                Err(DecodeError::NonCanonical)
+            ));
+        }
+    }
+
+    #[test]
+    fn fuzz_identity() {
+        let mut rng = TestRng::with_seed(0x5eed);
+        let (protocol_id, _) = protocol_fixture(0);
+        let mut accepted = 0;
+        for (anchor, _) in [opret_fixture(), tapret_fixture()] {
+            accepted += fuzz(&anchor, &mut rng);
+            accepted +=
+                fuzz(&anchor.to_merkle_proof(protocol_id).unwrap(), &mut rng);
+            let tolerant = Anchor::from_parts(
+                anchor.txid(),
+                anchor.lnpbp4_proof().clone(),
+                ProofOrUnknown::from(anchor.dbc_proof().clone()),
+            );
+            accepted += fuzz(&tolerant, &mut rng);
+        }
+        for proof in tapret_proof_fixtures() {
+            accepted += fuzz(&proof, &mut rng);
+        }
+        // Mutations of the hashes and the unknown proof payloads are valid
+        assert!(accepted > 0);
+    }
+}
@@ -0,0 +1,325 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Anchors of a commitment split across multiple witness transactions.
+//!
+//! Some protocols split a single logical commitment across several
+//! transactions, like a commit transaction followed by a reveal transaction.
+//! [`AnchorChain`] keeps anchors of each of the transactions together with
+//! the declared linkage between them: each next witness transaction must
+//! spend a specific output of the previous one.
+
+use bitcoin::{OutPoint, Txid};
+use bitcoin_onchain::{ResolveTx, TxResolverError};
+use commit_verify::lnpbp4::{self, Message, ProtocolId};
+
+use crate::anchor::VerifyError;
+use crate::Anchor;
+
+/// Errors verifying [`AnchorChain`].
+#[derive(Debug, Display, Error, From)]
+#[display(inner)]
+pub enum AnchorChainError {
+    /// Anchor verification failure.
+    #[from]
+    Verify(VerifyError),
+
+    /// Failure retrieving witness transaction.
+    #[from]
+    Resolver(TxResolverError),
+
+    /// anchor chain has {anchors} anchors, while {messages} protocol messages
+    /// were provided for the verification. Hint: provide a message for each
+    /// of the anchors, in the order of the chain.
+    #[display(doc_comments)]
+    LengthMismatch {
+        /// Number of anchors in the chain.
+        anchors: usize,
+        /// Number of the provided protocol messages.
+        messages: usize,
+    },
+
+    /// witness transaction {spender} of the anchor #{index} does not spend
+    /// output {outpoint} of the previous witness transaction. Hint: the
+    /// anchors do not belong to the same commitment.
+    #[display(doc_comments)]
+    LinkageViolation {
+        /// Index of the anchor within the chain.
+        index: usize,
+        /// Output of the previous witness transaction declared as spent.
+        outpoint: OutPoint,
+        /// Id of the witness transaction of the anchor.
+        spender: Txid,
+    },
+}
+
+/// Anchor following the previous anchor in [`AnchorChain`].
+#[derive(Clone, PartialEq, Eq, Debug, StrictEncode, StrictDecode)]
+pub struct AnchorLink {
+    /// Number of the output of the previous witness transaction which is
+    /// spent by the witness transaction of the anchor.
+    pub vout: u32,
+
+    /// Anchor of the commitment part.
+    pub anchor: Anchor<lnpbp4::MerkleProof>,
+}
+
+/// Ordered sequence of anchors with witness transactions linked by spending:
+/// witness transaction of each of the anchors after the first one spends a
+/// declared output of the previous witness transaction.
+///
+/// ```
+/// use std::collections::BTreeMap;
+///
+/// use bitcoin::OutPoint;
+/// use dbc::chain::AnchorChain;
+/// use dbc::test_utils::{opret_fixture, protocol_fixture, tapret_fixture};
+/// use dbc::Anchor;
+///
+/// let (protocol_id, message) = protocol_fixture(0);
+/// let (commit, commit_tx) = opret_fixture();
+/// let (reveal, mut reveal_tx) = tapret_fixture();
+/// reveal_tx.input[0].previous_output = OutPoint::new(commit_tx.txid(), 0);
+/// let reveal = reveal.with_txid(reveal_tx.txid());
+///
+/// let mut chain =
+///     AnchorChain::new(commit.into_merkle_proof(protocol_id).unwrap());
+/// chain.push(0, reveal.into_merkle_proof(protocol_id).unwrap());
+///
+/// let resolver: BTreeMap<_, _> =
+///     [(commit_tx.txid(), commit_tx), (reveal_tx.txid(), reveal_tx)].into();
+/// let messages = [(protocol_id, message); 2];
+/// assert!(chain.verify(&messages, &resolver).unwrap());
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug, StrictEncode, StrictDecode)]
+pub struct AnchorChain {
+    first: Anchor<lnpbp4::MerkleProof>,
+    links: Vec<AnchorLink>,
+}
+
+impl AnchorChain {
+    /// Constructs chain consisting of a single anchor.
+    #[inline]
+    pub fn new(first: Anchor<lnpbp4::MerkleProof>) -> AnchorChain {
+        AnchorChain {
+            first,
+            links: empty!(),
+        }
+    }
+
+    /// Returns number of anchors in the chain.
+    #[inline]
+    pub fn len(&self) -> usize { self.links.len() + 1 }
+
+    /// Returns `false`, since chains always contain at least one anchor.
+    #[inline]
+    pub fn is_empty(&self) -> bool { false }
+
+    /// Returns the first anchor of the chain.
+    #[inline]
+    pub fn first(&self) -> &Anchor<lnpbp4::MerkleProof> { &self.first }
+
+    /// Returns links to the anchors following the first one.
+    #[inline]
+    pub fn links(&self) -> &[AnchorLink] { &self.links }
+
+    /// Iterates over all anchors of the chain in their order.
+    pub fn anchors(
+        &self,
+    ) -> impl Iterator<Item = &Anchor<lnpbp4::MerkleProof>> + '_ {
+        std::iter::once(&self.first)
+            .chain(self.links.iter().map(|link| &link.anchor))
+    }
+
+    /// Appends anchor whose witness transaction spends output `vout` of the
+    /// witness transaction of the last anchor in the chain. The linkage is not
+    /// checked until [`AnchorChain::verify`].
+    pub fn push(&mut self, vout: u32, anchor: Anchor<lnpbp4::MerkleProof>) {
+        self.links.push(AnchorLink { vout, anchor });
+    }
+
+    /// Verifies that each of the anchors commits to the corresponding protocol
+    /// message from `messages`, and that witness transactions retrieved with
+    /// the `resolver` are linked as declared by the chain.
+    ///
+    /// Anchors are verified independently of each other. Returns `Ok(false)`
+    /// if some of the anchors does not commit to its message, or if the
+    /// resolver returns transaction with other id.
+    ///
+    /// # Errors
+    ///
+    /// - [`AnchorChainError::LengthMismatch`] if the number of messages does
+    ///   not match the number of anchors;
+    /// - [`AnchorChainError::LinkageViolation`] if some of the witness
+    ///   transactions does not spend the declared output of the previous one;
+    /// - [`AnchorChainError::Resolver`] if the resolver fails to retrieve some
+    ///   of the witness transactions;
+    /// - [`AnchorChainError::Verify`] if anchor verification fails.
+    pub fn verify(
+        &self,
+        messages: &[(ProtocolId, Message)],
+        resolver: &impl ResolveTx,
+    ) -> Result<bool, AnchorChainError> {
+        if messages.len() != self.len() {
+            return Err(AnchorChainError::LengthMismatch {
+                anchors: self.len(),
+                messages: messages.len(),
+            });
+        }
+
+        let mut committed = true;
+        // Id and number of outputs of the previous witness transaction
+        let mut prev: Option<(Txid, usize)> = None;
+        for (index, (anchor, (protocol_id, message))) in
+            self.anchors().zip(messages).enumerate()
+        {
+            let tx = match resolver.resolve_tx(anchor.txid()) {
+                Ok(tx) => tx,
+                Err(err) => {
+                    #[cfg(feature = "metrics")]
+                    crate::metrics::record_resolver_failure();
+                    return Err(err.into());
+                }
+            };
+            if !anchor.is_witness(&tx) {
+                return Ok(false);
+            }
+
+            if let Some((prev_txid, prev_outputs)) = prev {
+                let vout = self.links[index - 1].vout;
+                let outpoint = OutPoint::new(prev_txid, vout);
+                let spends = tx
+                    .input
+                    .iter()
+                    .any(|txin| txin.previous_output == outpoint);
+                if !spends || vout as usize >= prev_outputs {
+                    return Err(AnchorChainError::LinkageViolation {
+                        index,
+                        outpoint,
+                        spender: anchor.txid(),
+                    });
+                }
+            }
+
+            committed &= anchor.verify(*protocol_id, *message, &tx)?;
+            prev = Some((anchor.txid(), tx.output.len()));
+        }
+        Ok(committed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use bitcoin::Transaction;
+    use strict_encoding::{StrictDecode, StrictEncode};
+
+    use super::*;
+    use crate::test_utils::{opret_fixture, protocol_fixture, tapret_fixture};
+
+    fn chain_fixture() -> (AnchorChain, BTreeMap<Txid, Transaction>) {
+        let (protocol_id, _) = protocol_fixture(0);
+        let (commit, commit_tx) = opret_fixture();
+        let (reveal, mut reveal_tx) = tapret_fixture();
+        reveal_tx.input.push(reveal_tx.input[0].clone());
+        reveal_tx.input[1].previous_output = OutPoint::new(commit_tx.txid(), 0);
+        let reveal = reveal.with_txid(reveal_tx.txid());
+
+        let mut chain =
+            AnchorChain::new(commit.into_merkle_proof(protocol_id).unwrap());
+        chain.push(0, reveal.into_merkle_proof(protocol_id).unwrap());
+        let resolver = bmap! { commit_tx.txid() => commit_tx, reveal_tx.txid() => reveal_tx };
+        (chain, resolver)
+    }
+
+    #[test]
+    fn verify() {
+        let (chain, resolver) = chain_fixture();
+        let (protocol_id, message) = protocol_fixture(0);
+        let (_, other_message) = protocol_fixture(1);
+        assert_eq!(chain.len(), 2);
+        assert_eq!(chain.anchors().count(), 2);
+
+        assert!(chain
+            .verify(&[(protocol_id, message); 2], &resolver)
+            .unwrap());
+        assert!(!chain
+            .verify(
+                &[(protocol_id, message), (protocol_id, other_message)],
+                &resolver
+            )
+            .unwrap());
+        assert!(!chain
+            .verify(
+                &[(protocol_id, other_message), (protocol_id, message)],
+                &resolver
+            )
+            .unwrap());
+        assert!(matches!(
+            chain.verify(&[(protocol_id, message)], &resolver),
+            Err(AnchorChainError::LengthMismatch {
+                anchors: 2,
+                messages: 1
+            })
+        ));
+
+        // Single anchor is verified as a regular anchor
+        let single = AnchorChain::new(chain.first().clone());
+        assert!(single.verify(&[(protocol_id, message)], &resolver).unwrap());
+
+        let mut missing = resolver.clone();
+        missing.remove(&chain.links()[0].anchor.txid());
+        assert!(matches!(
+            chain.verify(&[(protocol_id, message); 2], &missing),
+            Err(AnchorChainError::Resolver(_))
+        ));
+    }
+
+    #[test]
+    fn linkage_violation() {
+        let (mut chain, resolver) = chain_fixture();
+        let (protocol_id, message) = protocol_fixture(0);
+        let commit_txid = chain.first().txid();
+        let reveal_txid = chain.links()[0].anchor.txid();
+
+        // Declared output is not spent by the reveal transaction
+        chain.links[0].vout = 1;
+        assert!(matches!(
+            chain.verify(&[(protocol_id, message); 2], &resolver),
+            Err(AnchorChainError::LinkageViolation {
+                index: 1,
+                outpoint,
+                spender
+            }) if outpoint == OutPoint::new(commit_txid, 1) && spender == reveal_txid
+        ));
+
+        // Anchors in the reverse order
+        let (chain, _) = chain_fixture();
+        let mut reversed = AnchorChain::new(chain.links()[0].anchor.clone());
+        reversed.push(0, chain.first().clone());
+        assert!(matches!(
+            reversed.verify(&[(protocol_id, message); 2], &resolver),
+            Err(AnchorChainError::LinkageViolation { index: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn strict_encoding() {
+        let (chain, _) = chain_fixture();
+        let data = chain.strict_serialize().unwrap();
+        assert_eq!(AnchorChain::strict_deserialize(data).unwrap(), chain);
+    }
+}
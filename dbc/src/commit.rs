@@ -0,0 +1,2162 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Embedding of deterministic bitcoin commitments into PSBTs and raw
+//! transactions, producing anchors for the modified transactions.
+//!
+//! The output hosting the commitment, the source of LNPBP-4 entropy, the
+//! handling of LNPBP-4 messages found in other outputs and the depth limit of
+//! the tapret script tree are selected with [`CommitOptions`] passed to
+//! [`Anchor::commit_with`]. [`Anchor::commit`], [`Anchor::commit_with_entropy`]
+//! and [`Anchor::commit_static`] commit with the default options.
+
+use amplify::Wrapper;
+use bitcoin::psbt::{PartiallySignedTransaction, TapTree};
+use bitcoin::{Transaction, XOnlyPublicKey};
+use bitcoin_scripts::PubkeyScript;
+use commit_verify::{lnpbp4, ConsensusCommit, EmbedCommitProof, TaggedHash};
+#[cfg(not(feature = "deterministic"))]
+use commit_verify::{EmbedCommitVerify, TryCommitVerify};
+use psbt::commit::{
+    ProprietaryKeyOpret, ProprietaryKeyTapret, PSBT_LNPBP4_PREFIX,
+    PSBT_OUT_LNPBP4_MESSAGE,
+};
+use psbt::{ProprietaryKey, Psbt};
+
+use crate::anchor::{
+    lnpbp4_tree_with_entropy, Error, PsbtEmbeddedMessage,
+    ANCHOR_MIN_LNPBP4_DEPTH,
+};
+use crate::commit_log::{CommitEvent, CommitLog};
+#[cfg(not(feature = "deterministic"))]
+use crate::consts::TAPRET_CONSENSUS_MAX_PATH_DEPTH;
+use crate::consts::{LNPBP4_STATIC_ENTROPY, TAPRET_MAX_PATH_DEPTH};
+use crate::host::{self, TxOutHost};
+use crate::method::CloseMethod;
+use crate::opret::OpretProof;
+use crate::static_commit::{EmbedCommitProofStatic, EmbedCommitVerifyStatic};
+use crate::tapret::{Lnpbp6, PsbtCommitError, PsbtVerifyError};
+use crate::{proprietary, Anchor, AnchorId, Proof};
+
+/// Selection of the PSBT output hosting the commitment.
+///
+/// ```
+/// use bitcoin_scripts::taproot::DfsPath;
+/// use dbc::commit::{CommitOptions, Entropy, HostSelection};
+/// use dbc::opret::OpretProof;
+/// use dbc::tapret::PsbtCommitError;
+/// use dbc::test_utils::{internal_key_fixture, psbt_fixture};
+/// use dbc::{Anchor, CloseMethod, Proof};
+///
+/// // Both outputs are host candidates, and the messages are on the opret one
+/// let mut psbt = psbt_fixture(CloseMethod::OpretFirst);
+/// psbt.outputs[0].tap_internal_key = Some(internal_key_fixture());
+/// psbt.outputs[0]
+///     .set_tapret_dfs_path(&DfsPath::new())
+///     .unwrap();
+/// let mut options = CommitOptions::with(Entropy::Static);
+/// assert_eq!(
+///     Anchor::commit_with(&mut psbt.clone(), &options),
+///     Err(PsbtCommitError::AmbiguousHosts {
+///         tapret_vout: 0,
+///         opret_vout: 1
+///     }
+///     .into())
+/// );
+///
+/// options.host = HostSelection::Preferring(CloseMethod::OpretFirst);
+/// let preferred = Anchor::commit_with(&mut psbt.clone(), &options).unwrap();
+/// options.host = HostSelection::Vout(1);
+/// let info = Anchor::commit_with(&mut psbt, &options).unwrap();
+/// assert_eq!(info, preferred);
+/// let proof = Proof::OpretFirstAt(OpretProof { vout: 1 });
+/// assert_eq!(info.anchor.dbc_proof(), &proof);
+/// assert_eq!(info.anchor.txid(), psbt.to_txid());
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum HostSelection {
+    /// The first output marked as the commitment host; fails with
+    /// [`PsbtCommitError::AmbiguousHosts`] if there are both tapret and opret
+    /// hosts.
+    Unambiguous,
+
+    /// The first output marked as the commitment host with the given method,
+    /// or the first host of the other method if there are no such outputs.
+    Preferring(CloseMethod),
+
+    /// Output with the given number, which must be marked as the commitment
+    /// host; fails with [`PsbtCommitError::InvalidHost`] otherwise.
+    Vout(usize),
+}
+
+impl Default for HostSelection {
+    fn default() -> Self { HostSelection::Unambiguous }
+}
+
+/// Source of the LNPBP-4 entropy used for the commitment.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Entropy {
+    /// Entropy generated with OS random number generator. Not available with
+    /// `deterministic` feature.
+    #[cfg(not(feature = "deterministic"))]
+    Random,
+
+    /// Static entropy value, producing the same anchor for the same PSBT.
+    Static,
+
+    /// Entropy value provided by the caller. Entropy must be unique and secret
+    /// for each of the commitments; reused or predictable entropy allows to
+    /// probe which protocols are committed to by an anchor with concealed
+    /// LNPBP-4 data.
+    Explicit(u64),
+}
+
+/// Handling of LNPBP-4 messages found in PSBT outputs other than the
+/// commitment host. Such messages are never committed to, since the
+/// commitment is constructed from the messages of the host output only.
+///
+/// ```
+/// use dbc::commit::{CommitOptions, Entropy, ForeignMessages};
+/// use dbc::test_utils::{protocol_fixture, psbt_fixture};
+/// use dbc::{Anchor, CloseMethod};
+///
+/// let mut psbt = psbt_fixture(CloseMethod::TapretFirst);
+/// let (protocol_id, message) = protocol_fixture(10);
+/// psbt.outputs[1]
+///     .set_lnpbp4_message(protocol_id, message)
+///     .unwrap();
+/// assert!(Anchor::commit_with_entropy(&mut psbt.clone(), 0x5eed).is_err());
+///
+/// let options = CommitOptions {
+///     foreign_messages: ForeignMessages::Ignore,
+///     ..CommitOptions::with(Entropy::Explicit(0x5eed))
+/// };
+/// let info = Anchor::commit_with(&mut psbt, &options).unwrap();
+/// assert!(info.anchor.to_merkle_proof(protocol_id).is_err());
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ForeignMessages {
+    /// Fail with [`PsbtCommitError::MessagesOnNonHostOutput`].
+    Reject,
+
+    /// Commit to the messages of the host output, leaving messages of the
+    /// other outputs uncommitted.
+    Ignore,
+}
+
+impl Default for ForeignMessages {
+    fn default() -> Self { ForeignMessages::Reject }
+}
+
+/// Options of embedding the commitment into a PSBT with
+/// [`Anchor::commit_with`].
+///
+/// ```
+/// use bitcoin::psbt::TapTree;
+/// use bitcoin::util::taproot::TaprootBuilder;
+/// use bitcoin::Script;
+/// use dbc::commit::{CommitOptions, Entropy};
+/// use dbc::consts::TAPRET_MAX_PATH_DEPTH;
+/// use dbc::tapret::PsbtCommitError;
+/// use dbc::test_utils::psbt_fixture;
+/// use dbc::{Anchor, CloseMethod};
+///
+/// // Script tree with a leaf at the maximal default depth
+/// let mut builder = TaprootBuilder::new();
+/// for depth in 1..=TAPRET_MAX_PATH_DEPTH as u8 {
+///     builder = builder.add_leaf(depth, Script::from(vec![depth])).unwrap();
+/// }
+/// let builder = builder
+///     .add_leaf(TAPRET_MAX_PATH_DEPTH as u8, Script::from(vec![0x51]))
+///     .unwrap();
+/// let mut psbt = psbt_fixture(CloseMethod::TapretFirst);
+/// psbt.outputs[0].tap_tree = Some(TapTree::try_from(builder).unwrap());
+///
+/// let mut options = CommitOptions::with(Entropy::Static);
+/// assert_eq!(
+///     Anchor::commit_with(&mut psbt.clone(), &options),
+///     Err(PsbtCommitError::TreeTooDeep {
+///         depth: TAPRET_MAX_PATH_DEPTH + 1,
+///         max: TAPRET_MAX_PATH_DEPTH
+///     }
+///     .into())
+/// );
+///
+/// options.max_depth = TAPRET_MAX_PATH_DEPTH + 1;
+/// let info = Anchor::commit_with(&mut psbt, &options).unwrap();
+/// assert_eq!(info.anchor.txid(), psbt.to_txid());
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct CommitOptions {
+    /// Selection of the output hosting the commitment.
+    pub host: HostSelection,
+
+    /// Source of the LNPBP-4 entropy.
+    pub entropy: Entropy,
+
+    /// Handling of LNPBP-4 messages found in outputs other than the host.
+    pub foreign_messages: ForeignMessages,
+
+    /// Maximal depth of the tapret host script tree after the commitment.
+    /// Values above [`crate::consts::TAPRET_CONSENSUS_MAX_PATH_DEPTH`] are
+    /// treated as the consensus limit.
+    pub max_depth: usize,
+
+    /// Whether [`CommitmentInfo::log`] reports the steps performed during
+    /// embedding the commitment; the log is left empty otherwise.
+    pub log: bool,
+}
+
+impl CommitOptions {
+    /// Constructs options committing to the unambiguous host with the given
+    /// `entropy`, rejecting foreign messages and limiting tapret script tree
+    /// depth to [`TAPRET_MAX_PATH_DEPTH`], without logging.
+    pub fn with(entropy: Entropy) -> CommitOptions {
+        CommitOptions {
+            host: HostSelection::Unambiguous,
+            entropy,
+            foreign_messages: ForeignMessages::Reject,
+            max_depth: TAPRET_MAX_PATH_DEPTH,
+            log: false,
+        }
+    }
+}
+
+/// Options of [`Anchor::commit`], using OS random number generator for the
+/// entropy. Not available with `deterministic` feature.
+#[cfg(not(feature = "deterministic"))]
+impl Default for CommitOptions {
+    fn default() -> Self { CommitOptions::with(Entropy::Random) }
+}
+
+/// Anchor produced by [`Anchor::commit_with`] together with the information
+/// about the output hosting the commitment.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CommitmentInfo {
+    /// Anchor produced by the commitment.
+    pub anchor: Anchor<lnpbp4::MerkleBlock>,
+
+    /// Number of the PSBT output which received the commitment.
+    pub vout: u32,
+
+    /// Method of the commitment.
+    pub method: CloseMethod,
+
+    /// Steps performed during embedding the commitment, if requested with
+    /// [`CommitOptions::log`].
+    pub log: CommitLog,
+}
+
+impl Anchor<lnpbp4::MerkleBlock> {
+    /// Convenience constructor for anchor, which also does embedding of LNPBP4
+    /// commitment into PSBT.
+    ///
+    /// The host output is selected by its PSBT proprietary flags, so the
+    /// outputs may be reordered (for instance with BIP-69 sorting) after the
+    /// host is marked, but must not be reordered after the commitment. Since
+    /// the commitment is verified against the first taproot (for tapret) or
+    /// first `OP_RETURN` (for opret) output, the host must be the first output
+    /// of its type in the final output order; otherwise the method fails with
+    /// [`PsbtCommitError::HostNotFirst`].
+    ///
+    /// ```
+    /// use dbc::test_utils::{protocol_fixture, psbt_fixture};
+    /// use dbc::{Anchor, CloseMethod};
+    ///
+    /// let mut psbt = psbt_fixture(CloseMethod::TapretFirst);
+    /// let anchor = Anchor::commit(&mut psbt).unwrap();
+    /// assert_eq!(anchor.txid, psbt.to_txid());
+    ///
+    /// let (protocol_id, message) = protocol_fixture(0);
+    /// let anchor = anchor.to_merkle_proof(protocol_id).unwrap();
+    /// assert!(anchor
+    ///     .verify(protocol_id, message, &psbt.to_unsigned_tx())
+    ///     .unwrap());
+    /// ```
+    ///
+    /// Not available with `deterministic` feature; use
+    /// [`Anchor::commit_with_entropy`] or [`Anchor::commit_static`] instead.
+    #[cfg(not(feature = "deterministic"))]
+    pub fn commit(
+        psbt: &mut Psbt,
+    ) -> Result<Anchor<lnpbp4::MerkleBlock>, Error> {
+        Ok(psbt.embed_commit(&PsbtEmbeddedMessage)?.into_merkle_block())
+    }
+
+    /// Version of the commit method using entropy value provided by the
+    /// caller instead of the one generated with OS random number generator.
+    /// Entropy must be unique and secret for each of the commitments; reused
+    /// or predictable entropy allows to probe which protocols are committed
+    /// to by an anchor with concealed LNPBP-4 data.
+    ///
+    /// ```
+    /// use dbc::test_utils::psbt_fixture;
+    /// use dbc::{Anchor, CloseMethod};
+    ///
+    /// let mut psbt1 = psbt_fixture(CloseMethod::TapretFirst);
+    /// let mut psbt2 = psbt1.clone();
+    /// let anchor = Anchor::commit_with_entropy(&mut psbt1, 0x5eed).unwrap();
+    /// assert_eq!(anchor.lnpbp4_proof().entropy(), Some(0x5eed));
+    /// assert_eq!(
+    ///     Anchor::commit_with_entropy(&mut psbt2, 0x5eed).unwrap(),
+    ///     anchor
+    /// );
+    /// assert_eq!(psbt1, psbt2);
+    /// ```
+    pub fn commit_with_entropy(
+        psbt: &mut Psbt,
+        entropy: u64,
+    ) -> Result<Anchor<lnpbp4::MerkleBlock>, Error> {
+        let options = CommitOptions::with(Entropy::Explicit(entropy));
+        Ok(Anchor::commit_with(psbt, &options)?.anchor)
+    }
+
+    /// Static entropy version of the commit method
+    ///
+    /// ```
+    /// use dbc::test_utils::psbt_fixture;
+    /// use dbc::{Anchor, CloseMethod};
+    ///
+    /// let mut psbt1 = psbt_fixture(CloseMethod::OpretFirst);
+    /// let mut psbt2 = psbt1.clone();
+    /// assert_eq!(
+    ///     Anchor::commit_static(&mut psbt1).unwrap(),
+    ///     Anchor::commit_static(&mut psbt2).unwrap()
+    /// );
+    /// assert_eq!(psbt1, psbt2);
+    /// ```
+    pub fn commit_static(
+        psbt: &mut Psbt,
+    ) -> Result<Anchor<lnpbp4::MerkleBlock>, Error> {
+        Ok(psbt
+            .embed_commit_static(&PsbtEmbeddedMessage)?
+            .into_merkle_block())
+    }
+
+    /// Embeds LNPBP-4 commitment into PSBT with the given `options`, which
+    /// select the host output, the entropy, the handling of the messages of
+    /// other outputs and the tapret script tree depth limit. Returns the anchor
+    /// together with the output which received the commitment and, if
+    /// requested, the log of the steps performed.
+    ///
+    /// ```
+    /// use dbc::commit::{CommitOptions, Entropy};
+    /// use dbc::test_utils::psbt_fixture;
+    /// use dbc::{Anchor, CloseMethod, CommitEvent};
+    ///
+    /// let mut psbt = psbt_fixture(CloseMethod::OpretFirst);
+    /// let original = psbt.to_unsigned_tx();
+    /// let options = CommitOptions {
+    ///     log: true,
+    ///     ..CommitOptions::with(Entropy::Static)
+    /// };
+    /// let info = Anchor::commit_with(&mut psbt, &options).unwrap();
+    /// assert_eq!(info.vout, 1);
+    /// assert_eq!(info.method, CloseMethod::OpretFirst);
+    /// assert_eq!(info.anchor.txid(), psbt.to_txid());
+    /// assert_eq!(info.log.events()[0], CommitEvent::HostSelected {
+    ///     vout: 1,
+    ///     method: CloseMethod::OpretFirst
+    /// });
+    ///
+    /// // The host output is the one which gets the commitment
+    /// let tx = psbt.to_unsigned_tx();
+    /// assert_eq!(tx.output[0], original.output[0]);
+    /// assert_eq!(info.log.events()[2], CommitEvent::OpretScriptSet {
+    ///     script_pubkey: tx.output[1].script_pubkey.clone()
+    /// });
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Errors of selecting the host output with [`CommitOptions::host`] and
+    /// of embedding the commitment; the PSBT is left intact on error.
+    pub fn commit_with(
+        psbt: &mut Psbt,
+        options: &CommitOptions,
+    ) -> Result<CommitmentInfo, Error> {
+        let mut log = CommitLog::new();
+        let (anchor, vout, method) =
+            embed_psbt_commit(psbt, options, &mut log)?;
+        Ok(CommitmentInfo {
+            anchor: anchor.into_merkle_block(),
+            vout: vout as u32,
+            method,
+            log: if options.log { log } else { CommitLog::new() },
+        })
+    }
+
+    /// Embeds commitment to the LNPBP-4 `lnpbp4_tree` into output `vout` of a
+    /// raw transaction, constructing anchor for the modified transaction.
+    ///
+    /// Tapret commitments require taproot internal key of the output; the
+    /// taproot script tree of the output, if any, is provided via `tap_tree`
+    /// and gets updated with the commitment leaf. The caller is responsible
+    /// for selecting `vout` matching the rules of the commitment `method`
+    /// (first `OP_RETURN` output for opret and first taproot output for
+    /// tapret); otherwise the anchor will fail verification.
+    ///
+    /// Produces exactly the same transaction and anchor as PSBT-based
+    /// [`Anchor::commit_static`] for the same transaction and LNPBP-4 tree.
+    ///
+    /// ```
+    /// use dbc::test_utils::{internal_key_fixture, lnpbp4_fixture, psbt_fixture};
+    /// use dbc::{Anchor, CloseMethod};
+    ///
+    /// let mut psbt = psbt_fixture(CloseMethod::TapretFirst);
+    /// let mut tx = psbt.to_unsigned_tx();
+    /// let mut tap_tree = None;
+    /// let anchor = Anchor::commit_tx(
+    ///     &mut tx,
+    ///     0,
+    ///     CloseMethod::TapretFirst,
+    ///     Some(internal_key_fixture()),
+    ///     &mut tap_tree,
+    ///     lnpbp4_fixture(),
+    /// )
+    /// .unwrap();
+    /// assert_eq!(anchor.txid(), tx.txid());
+    /// assert!(tap_tree.is_some());
+    ///
+    /// // Committing to the PSBT gives the same result
+    /// assert_eq!(Anchor::commit_static(&mut psbt).unwrap(), anchor);
+    /// assert_eq!(psbt.to_unsigned_tx(), tx);
+    /// ```
+    pub fn commit_tx(
+        tx: &mut Transaction,
+        vout: usize,
+        method: CloseMethod,
+        tap_internal_key: Option<XOnlyPublicKey>,
+        tap_tree: &mut Option<TapTree>,
+        lnpbp4_tree: lnpbp4::MerkleTree,
+    ) -> Result<Anchor<lnpbp4::MerkleBlock>, Error> {
+        if lnpbp4_tree.messages().is_empty() {
+            return Err(PsbtCommitError::NoMessages.into());
+        }
+        let outputs = tx.output.len();
+        let txout = tx
+            .output
+            .get_mut(vout)
+            .ok_or(PsbtCommitError::CommitmentImpossible)?;
+        check_opret_host(method, outputs, vout)?;
+        let mut host = TxOutHost {
+            txout: txout.clone(),
+            tap_internal_key,
+            tap_tree: tap_tree.take(),
+        };
+        let res =
+            host::embed(&mut host, method, &lnpbp4_tree.consensus_commit());
+        *tap_tree = host.tap_tree;
+        let dbc_proof = match res? {
+            Proof::OpretFirst => {
+                Proof::OpretFirstAt(OpretProof { vout: vout as u32 })
+            }
+            proof => proof,
+        };
+        *txout = host.txout;
+
+        Ok(Anchor::from_parts(
+            tx.txid(),
+            lnpbp4::MerkleBlock::from(lnpbp4_tree),
+            dbc_proof,
+        ))
+    }
+}
+
+/// Extension trait for PSBTs providing anchor-related operations not covered
+/// by the commit-verify traits.
+pub trait PsbtAnchorExt {
+    /// Computes id of the anchor which would be produced by
+    /// [`Anchor::commit_static`] for this PSBT, without modifying the PSBT.
+    ///
+    /// Allows coordinators to inform participants about the expected anchor id
+    /// before the transaction is signed.
+    ///
+    /// ```
+    /// use dbc::commit::PsbtAnchorExt;
+    /// use dbc::test_utils::psbt_fixture;
+    /// use dbc::{Anchor, CloseMethod};
+    ///
+    /// let mut psbt = psbt_fixture(CloseMethod::OpretFirst);
+    /// let expected_id = psbt.expected_anchor_id().unwrap();
+    /// let anchor = Anchor::commit_static(&mut psbt).unwrap();
+    /// assert_eq!(anchor.anchor_id(), expected_id);
+    /// ```
+    fn expected_anchor_id(&self) -> Result<AnchorId, Error>;
+
+    /// Embeds the commitment to the LNPBP-4 `messages` constructed by the
+    /// caller, instead of the messages taken from the proprietary keys of the
+    /// host output like in [`EmbedCommitVerify::embed_commit`]. The LNPBP-4
+    /// tree has at least `min_depth` depth, or the depth from the output keys,
+    /// or [`ANCHOR_MIN_LNPBP4_DEPTH`] if neither is given.
+    ///
+    /// The messages and the minimal tree depth are written into the host
+    /// output keys together with the commitment data, so other parties can
+    /// verify the commitment with the PSBT. The PSBT is left intact on error.
+    ///
+    /// ```
+    /// use commit_verify::lnpbp4::MessageMap;
+    /// use dbc::commit::PsbtAnchorExt;
+    /// use dbc::tapret::PsbtCommitError;
+    /// use dbc::test_utils::{protocol_fixture, psbt_fixture};
+    /// use dbc::CloseMethod;
+    ///
+    /// let messages = (0u8..3).map(protocol_fixture).collect::<MessageMap>();
+    /// let mut psbt = psbt_fixture(CloseMethod::OpretFirst);
+    /// // Messages of the fixture are the same as the supplied ones
+    /// let anchor = psbt
+    ///     .embed_commit_with_messages(messages.clone(), None)
+    ///     .unwrap();
+    /// assert_eq!(anchor.txid(), psbt.to_txid());
+    ///
+    /// // The message for protocol 1 differs from the one in the PSBT keys
+    /// let mut psbt = psbt_fixture(CloseMethod::OpretFirst);
+    /// let (protocol_id, _) = protocol_fixture(1);
+    /// let (_, other) = protocol_fixture(3);
+    /// let mut conflicting = messages;
+    /// conflicting.insert(protocol_id, other);
+    /// assert_eq!(
+    ///     psbt.embed_commit_with_messages(conflicting, None),
+    ///     Err(PsbtCommitError::MessageConflict {
+    ///         vout: 1,
+    ///         protocol_id
+    ///     })
+    /// );
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// [`PsbtCommitError::MessageConflict`] if the host output keys contain a
+    /// message for a protocol which is absent from `messages` or has a
+    /// different message there, and [`PsbtCommitError::MinDepthConflict`] if
+    /// they contain a minimal tree depth different from `min_depth`; other
+    /// errors are the same as of [`EmbedCommitVerify::embed_commit`].
+    #[cfg(not(feature = "deterministic"))]
+    fn embed_commit_with_messages(
+        &mut self,
+        messages: lnpbp4::MessageMap,
+        min_depth: Option<u8>,
+    ) -> Result<Anchor<lnpbp4::MerkleTree>, PsbtCommitError>;
+}
+
+impl PsbtAnchorExt for Psbt {
+    fn expected_anchor_id(&self) -> Result<AnchorId, Error> {
+        let mut psbt = self.clone();
+        let anchor = Anchor::commit_static(&mut psbt)?;
+        Ok(anchor.anchor_id())
+    }
+
+    #[cfg(not(feature = "deterministic"))]
+    fn embed_commit_with_messages(
+        &mut self,
+        messages: lnpbp4::MessageMap,
+        min_depth: Option<u8>,
+    ) -> Result<Anchor<lnpbp4::MerkleTree>, PsbtCommitError> {
+        let mut psbt = self.clone();
+        set_host_messages(&mut psbt, messages, min_depth)?;
+        let (anchor, ..) = embed_psbt_commit(
+            &mut psbt,
+            &CommitOptions::default(),
+            &mut CommitLog::new(),
+        )?;
+        *self = psbt;
+        Ok(anchor)
+    }
+}
+
+/// Converts BIP-174 PSBT into [`Psbt`], checking that the PSBT has output
+/// maps for each of the unsigned transaction outputs.
+///
+/// The conversion provided by the `psbt` crate silently drops unsigned
+/// transaction outputs lacking the output map, such that commitments made to
+/// the converted PSBT would be made against a different transaction. Tools
+/// producing BIP-174 PSBTs directly (not via the consensus deserialization,
+/// which enforces the number of output maps) should use this function instead.
+///
+/// # Errors
+///
+/// [`PsbtCommitError::MalformedPsbt`] if the number of output maps and the
+/// number of unsigned transaction outputs differ.
+pub fn psbt_from_v0(
+    psbt: PartiallySignedTransaction,
+) -> Result<Psbt, PsbtCommitError> {
+    let outputs = psbt.outputs.len();
+    let tx_outputs = psbt.unsigned_tx.output.len();
+    if outputs != tx_outputs {
+        return Err(PsbtCommitError::MalformedPsbt {
+            outputs,
+            tx_outputs,
+        });
+    }
+    Ok(Psbt::from(psbt))
+}
+
+#[cfg(not(feature = "deterministic"))]
+impl EmbedCommitProof<PsbtEmbeddedMessage, Psbt, Lnpbp6>
+    for Anchor<lnpbp4::MerkleTree>
+{
+    #[inline]
+    fn restore_original_container(
+        &self,
+        psbt: &Psbt,
+    ) -> Result<Psbt, PsbtVerifyError> {
+        restore_psbt(self, psbt)
+    }
+}
+
+impl EmbedCommitProofStatic<PsbtEmbeddedMessage, Psbt, Lnpbp6>
+    for Anchor<lnpbp4::MerkleTree>
+{
+    #[inline]
+    fn restore_original_container(
+        &self,
+        psbt: &Psbt,
+    ) -> Result<Psbt, PsbtVerifyError> {
+        restore_psbt(self, psbt)
+    }
+}
+
+/// Restores PSBT before the commitment described by `anchor`. Opret
+/// commitments are reverted by removing the commitment key from the host
+/// output, since the commitment script is overwritten on the next commitment;
+/// tapret commitments are reverted in the host output together with the
+/// removal of its commitment keys.
+fn restore_psbt(
+    anchor: &Anchor<lnpbp4::MerkleTree>,
+    psbt: &Psbt,
+) -> Result<Psbt, PsbtVerifyError> {
+    check_psbt_outputs(psbt)?;
+    let mut psbt = psbt.clone();
+    let host = proof_host(&psbt, anchor.dbc_proof())?;
+    let output = &mut psbt.outputs[host];
+    match anchor.dbc_proof() {
+        Proof::OpretFirst | Proof::OpretFirstAt(_) => {
+            output
+                .proprietary
+                .remove(&ProprietaryKey::opret_commitment());
+        }
+        Proof::TapretFirst(proof) => {
+            *output = EmbedCommitProof::<_, psbt::Output, Lnpbp6>::restore_original_container(proof, output)?;
+            output
+                .proprietary
+                .remove(&ProprietaryKey::tapret_commitment());
+            output.proprietary.remove(&ProprietaryKey::tapret_proof());
+        }
+    }
+    Ok(psbt)
+}
+
+/// Returns the number of the output hosting the commitment with the given
+/// `proof`: output [`OpretProof::vout`] for [`Proof::OpretFirstAt`] and the
+/// first host output of the proof method otherwise, such that anchors are
+/// restored and verified against the output which received the commitment
+/// even if the PSBT marks hosts of both methods.
+fn proof_host(psbt: &Psbt, proof: &Proof) -> Result<usize, PsbtCommitError> {
+    match proof {
+        Proof::OpretFirst => {
+            psbt.outputs.iter().position(|o| o.is_opret_host())
+        }
+        Proof::OpretFirstAt(OpretProof { vout }) => {
+            let vout = *vout as usize;
+            match psbt.outputs.get(vout) {
+                Some(o) if o.is_opret_host() => Some(vout),
+                _ => return Err(PsbtCommitError::InvalidHost(vout)),
+            }
+        }
+        Proof::TapretFirst(_) => {
+            psbt.outputs.iter().position(|o| o.is_tapret_host())
+        }
+    }
+    .ok_or(PsbtCommitError::CommitmentImpossible)
+}
+
+/// Checks that the PSBT has outputs, which is required both for embedding
+/// and for restoring the commitment.
+fn check_psbt_outputs(psbt: &Psbt) -> Result<(), PsbtCommitError> {
+    if psbt.outputs.is_empty() {
+        return Err(PsbtCommitError::EmptyPsbt);
+    }
+    Ok(())
+}
+
+/// Checks that the transaction with `outputs` outputs has other outputs than
+/// the opret commitment `host`. Tapret commitments may use the only output of
+/// the transaction, which remains spendable.
+fn check_opret_host(
+    method: CloseMethod,
+    outputs: usize,
+    host: usize,
+) -> Result<(), PsbtCommitError> {
+    if method == CloseMethod::OpretFirst && outputs == 1 {
+        return Err(PsbtCommitError::OpretSoleOutput(host));
+    }
+    Ok(())
+}
+
+/// Returns numbers of the PSBT outputs other than `host` which have LNPBP-4
+/// message keys.
+fn foreign_message_vouts(psbt: &Psbt, host: usize) -> Vec<usize> {
+    psbt.outputs
+        .iter()
+        .enumerate()
+        .filter(|(vout, output)| {
+            *vout != host
+                && output.proprietary.keys().any(|key| {
+                    key.prefix == PSBT_LNPBP4_PREFIX
+                        && key.subtype == PSBT_OUT_LNPBP4_MESSAGE
+                })
+        })
+        .map(|(vout, _)| vout)
+        .collect()
+}
+
+/// Selects the output hosting the commitment and the commitment method.
+fn select_psbt_host(
+    psbt: &Psbt,
+    host: HostSelection,
+) -> Result<(usize, CloseMethod), PsbtCommitError> {
+    // PSBTs prepared by several tools may mark hosts of both types, in which
+    // case the caller has to select the method deliberately
+    let tapret_host = psbt.outputs.iter().position(|o| o.is_tapret_host());
+    let opret_host = psbt.outputs.iter().position(|o| o.is_opret_host());
+    let tapret = tapret_host.map(|vout| (vout, CloseMethod::TapretFirst));
+    let opret = opret_host.map(|vout| (vout, CloseMethod::OpretFirst));
+    match host {
+        HostSelection::Unambiguous => match (tapret_host, opret_host) {
+            (Some(tapret_vout), Some(opret_vout)) => {
+                return Err(PsbtCommitError::AmbiguousHosts {
+                    tapret_vout,
+                    opret_vout,
+                })
+            }
+            _ => tapret.or(opret),
+        },
+        HostSelection::Preferring(CloseMethod::TapretFirst) => tapret.or(opret),
+        HostSelection::Preferring(CloseMethod::OpretFirst) => opret.or(tapret),
+        HostSelection::Vout(vout) => match psbt.outputs.get(vout) {
+            Some(o) if o.is_tapret_host() => {
+                Some((vout, CloseMethod::TapretFirst))
+            }
+            Some(o) if o.is_opret_host() => {
+                Some((vout, CloseMethod::OpretFirst))
+            }
+            _ => return Err(PsbtCommitError::InvalidHost(vout)),
+        },
+    }
+    .ok_or(PsbtCommitError::CommitmentImpossible)
+}
+
+/// Writes LNPBP-4 `messages` and the minimal tree depth, if given, into the
+/// proprietary keys of the unambiguous commitment host output, checking that
+/// they do not conflict with the data already present in the keys.
+#[cfg(not(feature = "deterministic"))]
+fn set_host_messages(
+    psbt: &mut Psbt,
+    messages: lnpbp4::MessageMap,
+    min_depth: Option<u8>,
+) -> Result<(), PsbtCommitError> {
+    proprietary::check_psbt(psbt)?;
+    check_psbt_outputs(psbt)?;
+    let (vout, _) = select_psbt_host(psbt, HostSelection::Unambiguous)?;
+    let output = &mut psbt.outputs[vout];
+
+    let present = output.lnpbp4_message_map()?;
+    if let Some((protocol_id, _)) =
+        present.into_iter().find(|(protocol_id, message)| {
+            messages.get(protocol_id) != Some(message)
+        })
+    {
+        return Err(PsbtCommitError::MessageConflict { vout, protocol_id });
+    }
+    if let Some(supplied) = min_depth {
+        match output.lnpbp4_min_tree_depth()? {
+            Some(present) if present != supplied => {
+                return Err(PsbtCommitError::MinDepthConflict {
+                    vout,
+                    present,
+                    supplied,
+                })
+            }
+            _ => {
+                output.set_lnpbp4_min_tree_depth(supplied);
+            }
+        }
+    }
+    for (protocol_id, message) in messages {
+        output.set_lnpbp4_message(protocol_id, message)?;
+    }
+    Ok(())
+}
+
+/// Embeds the commitment into the PSBT with the given `options`, returning
+/// the anchor together with the number of the host output and the commitment
+/// method.
+fn embed_psbt_commit(
+    psbt: &mut Psbt,
+    options: &CommitOptions,
+    log: &mut CommitLog,
+) -> Result<(Anchor<lnpbp4::MerkleTree>, usize, CloseMethod), PsbtCommitError> {
+    proprietary::check_psbt(psbt)?;
+    check_psbt_outputs(psbt)?;
+    let (host, method) = select_psbt_host(psbt, options.host)?;
+    check_opret_host(method, psbt.outputs.len(), host)?;
+
+    // Messages of outputs other than the host are not committed to, and
+    // unless they are explicitly ignored the user must fix the PSBT
+    if options.foreign_messages == ForeignMessages::Reject {
+        let vouts = foreign_message_vouts(psbt, host);
+        if !vouts.is_empty() {
+            return Err(PsbtCommitError::MessagesOnNonHostOutput { vouts });
+        }
+    }
+
+    // Commitments are verified against the first output of the host type, so
+    // the positions are taken from the final output order
+    let first = psbt.outputs.iter().position(|o| match method {
+        CloseMethod::TapretFirst => o.script.is_v1_p2tr(),
+        CloseMethod::OpretFirst => o.script.is_op_return(),
+    });
+    match first {
+        Some(first) if first < host => {
+            return Err(PsbtCommitError::HostNotFirst { host, first })
+        }
+        _ => {}
+    }
+
+    let lnpbp4_tree = |output: &mut psbt::Output,
+                       log: &mut CommitLog|
+     -> Result<_, PsbtCommitError> {
+        let messages = output.lnpbp4_message_map()?;
+        if messages.is_empty() {
+            return Err(PsbtCommitError::NoMessages);
+        }
+        let min_depth = output
+            .lnpbp4_min_tree_depth()?
+            .unwrap_or(ANCHOR_MIN_LNPBP4_DEPTH);
+        let multi_source = lnpbp4::MultiSource {
+            min_depth,
+            messages,
+        };
+        let tree = match options.entropy {
+            #[cfg(not(feature = "deterministic"))]
+            Entropy::Random => lnpbp4::MerkleTree::try_commit(&multi_source)?,
+            Entropy::Static => {
+                lnpbp4_tree_with_entropy(&multi_source, LNPBP4_STATIC_ENTROPY)?
+                    .ok_or(PsbtCommitError::EntropyUnsupported)?
+            }
+            Entropy::Explicit(entropy) => {
+                lnpbp4_tree_with_entropy(&multi_source, entropy)?
+                    .ok_or(PsbtCommitError::EntropyUnsupported)?
+            }
+        };
+        output.set_lnpbp4_entropy(tree.entropy())?;
+        log.push(CommitEvent::TreeBuilt {
+            depth: tree.depth(),
+            leaves: tree.width(),
+            protocols: tree.messages().len(),
+        });
+        Ok(tree)
+    };
+
+    let output_key = |script: &PubkeyScript| {
+        if script.is_v1_p2tr() {
+            XOnlyPublicKey::from_slice(&script[2..]).ok()
+        } else {
+            None
+        }
+    };
+
+    log.push(CommitEvent::HostSelected {
+        vout: host as u32,
+        method,
+    });
+    let output = &mut psbt.outputs[host];
+    let (dbc_proof, lnpbp4_proof) = match method {
+        CloseMethod::TapretFirst => {
+            // Checked before the entropy is written to keep the PSBT intact
+            host::check_tapret_depth(output, options.max_depth)?;
+            let tree = lnpbp4_tree(output, log)?;
+            let commitment = tree.consensus_commit();
+            let old_key = output_key(&output.script);
+            let tree_extended = output.tap_tree.is_some();
+            let proof = host::embed_tapret_with_max_depth(
+                output,
+                &commitment,
+                options.max_depth,
+            )?;
+            output.set_tapret_commitment(commitment.into_array(), &proof)?;
+            log.push(CommitEvent::OutputTweaked {
+                old_key,
+                new_key: output_key(&output.script)
+                    .expect("tapret commitment produces taproot output"),
+                tree_extended,
+            });
+            (Proof::TapretFirst(proof), tree)
+        }
+        CloseMethod::OpretFirst => {
+            let tree = lnpbp4_tree(output, log)?;
+            let commitment = tree.consensus_commit();
+            host::embed_opret(output, &commitment)?;
+            output.set_opret_commitment(commitment.into_array())?;
+            log.push(CommitEvent::OpretScriptSet {
+                script_pubkey: output.script.to_inner(),
+            });
+            (Proof::OpretFirstAt(OpretProof { vout: host as u32 }), tree)
+        }
+    };
+
+    let anchor = Anchor::from_parts(psbt.to_txid(), lnpbp4_proof, dbc_proof);
+    Ok((anchor, host, method))
+}
+
+#[cfg(not(feature = "deterministic"))]
+impl EmbedCommitVerify<PsbtEmbeddedMessage, Lnpbp6> for Psbt {
+    type Proof = Anchor<lnpbp4::MerkleTree>;
+    type CommitError = PsbtCommitError;
+    type VerifyError = PsbtVerifyError;
+
+    #[inline]
+    fn embed_commit(
+        &mut self,
+        _: &PsbtEmbeddedMessage,
+    ) -> Result<Self::Proof, Self::CommitError> {
+        embed_psbt_commit(
+            self,
+            &CommitOptions::default(),
+            &mut CommitLog::new(),
+        )
+        .map(|(anchor, ..)| anchor)
+    }
+
+    /// Verifies the commitment by restoring the original PSBT and repeating
+    /// the commitment procedure with the LNPBP-4 entropy of the `proof` for
+    /// the host output the `proof` refers to. PSBTs without outputs are
+    /// rejected with [`PsbtCommitError::EmptyPsbt`] before any other work
+    /// is done. Tapret script trees are accepted up to
+    /// [`TAPRET_CONSENSUS_MAX_PATH_DEPTH`] deep, such that commitments made
+    /// with a raised depth limit are verified as well.
+    fn verify(
+        &self,
+        _: &PsbtEmbeddedMessage,
+        proof: Self::Proof,
+    ) -> Result<bool, Self::VerifyError> {
+        check_psbt_outputs(self)?;
+        let mut psbt = restore_psbt(&proof, self)?;
+        let host = proof_host(&psbt, proof.dbc_proof())?;
+        let options = CommitOptions {
+            host: HostSelection::Vout(host),
+            max_depth: TAPRET_CONSENSUS_MAX_PATH_DEPTH,
+            ..CommitOptions::with(Entropy::Explicit(
+                proof.lnpbp4_proof().entropy(),
+            ))
+        };
+        let (mut proof_prime, ..) =
+            embed_psbt_commit(&mut psbt, &options, &mut CommitLog::new())?;
+        // Opret hosts are always the first `OP_RETURN` output, so the legacy
+        // proof without the output index describes the same commitment
+        if *proof.dbc_proof() == Proof::OpretFirst
+            && matches!(proof_prime.dbc_proof(), Proof::OpretFirstAt(_))
+        {
+            proof_prime = proof_prime.with_dbc_proof(Proof::OpretFirst);
+        }
+        Ok(proof_prime == proof && *self == psbt)
+    }
+}
+
+impl EmbedCommitVerifyStatic<PsbtEmbeddedMessage, Lnpbp6> for Psbt {
+    type Proof = Anchor<lnpbp4::MerkleTree>;
+    type CommitError = PsbtCommitError;
+    type VerifyError = PsbtVerifyError;
+
+    #[inline]
+    fn embed_commit_static(
+        &mut self,
+        _: &PsbtEmbeddedMessage,
+    ) -> Result<Self::Proof, Self::CommitError> {
+        let options = CommitOptions::with(Entropy::Static);
+        embed_psbt_commit(self, &options, &mut CommitLog::new())
+            .map(|(anchor, ..)| anchor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use bitcoin::util::taproot::TaprootBuilder;
+    use bitcoin::{Script, TxOut};
+    #[cfg(not(feature = "deterministic"))]
+    use psbt::commit::Lnpbp4KeyError;
+    use secp256k1::SECP256K1;
+
+    use super::*;
+    use crate::consts::LNPBP4_MAX_DEPTH;
+    use crate::test_utils::{
+        host_fixture, internal_key_fixture, lnpbp4_fixture,
+        lnpbp4_tree_fixture, protocol_fixture, psbt_fixture, tapret_fixture,
+        tx_fixture,
+    };
+    use crate::VerificationOutcome;
+
+    #[cfg(not(feature = "deterministic"))]
+    fn commit(psbt: &mut Psbt) -> Result<Anchor<lnpbp4::MerkleBlock>, Error> {
+        Anchor::commit(psbt)
+    }
+
+    /// OS entropy is not available in deterministic builds.
+    #[cfg(feature = "deterministic")]
+    fn commit(psbt: &mut Psbt) -> Result<Anchor<lnpbp4::MerkleBlock>, Error> {
+        Anchor::commit_with_entropy(psbt, 0x5eed)
+    }
+
+    #[test]
+    fn test_expected_anchor_id() {
+        for method in [CloseMethod::TapretFirst, CloseMethod::OpretFirst] {
+            let mut psbt = psbt_fixture(method);
+            let original = psbt.clone();
+            let expected_id = psbt.expected_anchor_id().unwrap();
+            assert_eq!(psbt, original);
+            assert_eq!(psbt.expected_anchor_id().unwrap(), expected_id);
+
+            let anchor = Anchor::commit_static(&mut psbt).unwrap();
+            assert_eq!(anchor.anchor_id(), expected_id);
+            assert_eq!(anchor.txid(), psbt.to_txid());
+            assert_ne!(psbt, original);
+        }
+        assert_ne!(
+            psbt_fixture(CloseMethod::TapretFirst)
+                .expected_anchor_id()
+                .unwrap(),
+            psbt_fixture(CloseMethod::OpretFirst)
+                .expected_anchor_id()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_commit_with_entropy() {
+        use commit_verify::TryCommitVerify;
+
+        let source = lnpbp4::MultiSource {
+            min_depth: ANCHOR_MIN_LNPBP4_DEPTH,
+            messages: (0u8..3).map(protocol_fixture).collect(),
+        };
+        // Trees built by `commit_verify` with random entropy have the same
+        // layout
+        let random = lnpbp4::MerkleTree::try_commit(&source).unwrap();
+        let tree = lnpbp4_tree_with_entropy(&source, LNPBP4_STATIC_ENTROPY)
+            .unwrap()
+            .unwrap();
+        assert_eq!(tree.entropy(), LNPBP4_STATIC_ENTROPY);
+        let other = lnpbp4_tree_with_entropy(&source, 0x5eed).unwrap().unwrap();
+        assert_eq!(other.entropy(), 0x5eed);
+        for tree in [&tree, &other] {
+            assert_eq!(tree.depth(), random.depth());
+            assert_eq!(tree.messages(), random.messages());
+        }
+        assert_ne!(other.consensus_commit(), tree.consensus_commit());
+
+        for method in [CloseMethod::TapretFirst, CloseMethod::OpretFirst] {
+            let mut psbt = psbt_fixture(method);
+            let anchor = Anchor::commit_with_entropy(&mut psbt, 1).unwrap();
+            let mut expected = psbt_fixture(method);
+            assert_eq!(Anchor::commit_static(&mut expected).unwrap(), anchor);
+            assert_eq!(psbt, expected);
+
+            let mut psbt = psbt_fixture(method);
+            let anchor =
+                Anchor::commit_with_entropy(&mut psbt, 0x5eed).unwrap();
+            assert_eq!(anchor.lnpbp4_proof().entropy(), Some(0x5eed));
+            assert_ne!(
+                anchor.anchor_id(),
+                psbt_fixture(method).expected_anchor_id().unwrap()
+            );
+            let (protocol_id, message) = protocol_fixture(1);
+            assert!(anchor
+                .to_merkle_proof(protocol_id)
+                .unwrap()
+                .verify(protocol_id, message, psbt.to_unsigned_tx())
+                .unwrap());
+        }
+    }
+
+    #[test]
+    fn test_messages_on_non_host_output() {
+        for method in [CloseMethod::TapretFirst, CloseMethod::OpretFirst] {
+            let tapret = method == CloseMethod::TapretFirst;
+            let (host, other) = if tapret { (0, 1) } else { (1, 0) };
+            let mut expected = psbt_fixture(method);
+            let anchor =
+                Anchor::commit_with_entropy(&mut expected, 0x5eed).unwrap();
+
+            let mut psbt = psbt_fixture(method);
+            let (protocol_id, message) = protocol_fixture(3);
+            psbt.outputs[other]
+                .set_lnpbp4_message(protocol_id, message)
+                .unwrap();
+            let original = psbt.clone();
+            let err = Err(PsbtCommitError::MessagesOnNonHostOutput {
+                vouts: vec![other],
+            }
+            .into());
+            assert_eq!(Anchor::commit_with_entropy(&mut psbt, 0x5eed), err);
+            assert_eq!(Anchor::commit_static(&mut psbt), err);
+            let mut options = CommitOptions::with(Entropy::Explicit(0x5eed));
+            assert_eq!(
+                Anchor::commit_with(&mut psbt, &options)
+                    .map(|info| info.anchor),
+                err
+            );
+            assert_eq!(psbt, original);
+
+            // Ignored messages are left in place and are not committed to
+            options.foreign_messages = ForeignMessages::Ignore;
+            let permissive =
+                Anchor::commit_with(&mut psbt, &options).unwrap().anchor;
+            assert_eq!(permissive, anchor);
+            assert!(permissive.message(protocol_id).unwrap().is_none());
+            assert_eq!(
+                psbt.outputs[other].lnpbp4_message_map().unwrap().len(),
+                1
+            );
+            assert_eq!(psbt.outputs[host], expected.outputs[host]);
+        }
+
+        // Messages of the opret host are not committed to when tapret host is
+        // selected
+        let mut psbt = psbt_fixture(CloseMethod::TapretFirst);
+        psbt.outputs[1].set_opret_host().unwrap();
+        let (protocol_id, message) = protocol_fixture(0);
+        psbt.outputs[1]
+            .set_lnpbp4_message(protocol_id, message)
+            .unwrap();
+        assert_eq!(
+            Anchor::commit_static(&mut psbt),
+            Err(PsbtCommitError::AmbiguousHosts {
+                tapret_vout: 0,
+                opret_vout: 1
+            }
+            .into())
+        );
+        let options = CommitOptions {
+            host: HostSelection::Preferring(CloseMethod::TapretFirst),
+            ..CommitOptions::with(Entropy::Static)
+        };
+        assert_eq!(
+            Anchor::commit_with(&mut psbt, &options),
+            Err(PsbtCommitError::MessagesOnNonHostOutput { vouts: vec![1] }
+                .into())
+        );
+    }
+
+    /// Embeds commitment with the given `options`, returning the anchor with
+    /// the LNPBP-4 tree, which is verified against the PSBT.
+    fn commit_tree(
+        psbt: &mut Psbt,
+        options: &CommitOptions,
+    ) -> Result<Anchor<lnpbp4::MerkleTree>, PsbtCommitError> {
+        embed_psbt_commit(psbt, options, &mut CommitLog::new())
+            .map(|(anchor, ..)| anchor)
+    }
+
+    #[test]
+    fn test_commit_with_host_vout() {
+        // Both outputs are marked as host candidates
+        let candidates = |vout: usize| {
+            let mut psbt = host_fixture(CloseMethod::TapretFirst);
+            psbt.outputs[1].set_opret_host().unwrap();
+            for (protocol_id, message) in (0u8..3).map(protocol_fixture) {
+                psbt.outputs[vout]
+                    .set_lnpbp4_message(protocol_id, message)
+                    .unwrap();
+            }
+            psbt
+        };
+
+        for (vout, tapret) in [(0, true), (1, false)] {
+            let mut psbt = candidates(vout);
+            let options = CommitOptions {
+                host: HostSelection::Vout(vout),
+                ..CommitOptions::with(Entropy::Static)
+            };
+            let anchor = commit_tree(&mut psbt, &options).unwrap();
+            assert_eq!(
+                matches!(anchor.dbc_proof(), Proof::TapretFirst(_)),
+                tapret
+            );
+            assert_eq!(anchor.txid(), psbt.to_txid());
+            #[cfg(not(feature = "deterministic"))]
+            assert!(psbt.verify(&PsbtEmbeddedMessage, anchor.clone()).unwrap());
+            let (protocol_id, message) = protocol_fixture(1);
+            assert!(anchor
+                .into_merkle_block()
+                .to_merkle_proof(protocol_id)
+                .unwrap()
+                .verify(protocol_id, message, psbt.to_unsigned_tx())
+                .unwrap());
+        }
+
+        // Automatic selection rejects ambiguous hosts
+        let mut psbt = candidates(1);
+        assert_eq!(
+            commit_tree(&mut psbt, &CommitOptions::with(Entropy::Static)),
+            Err(PsbtCommitError::AmbiguousHosts {
+                tapret_vout: 0,
+                opret_vout: 1
+            })
+        );
+
+        let mut psbt = psbt_fixture(CloseMethod::OpretFirst);
+        let original = psbt.clone();
+        for vout in [0, 2] {
+            let options = CommitOptions {
+                host: HostSelection::Vout(vout),
+                ..CommitOptions::with(Entropy::Static)
+            };
+            assert_eq!(
+                commit_tree(&mut psbt, &options),
+                Err(PsbtCommitError::InvalidHost(vout))
+            );
+        }
+        assert_eq!(psbt, original);
+    }
+
+    #[test]
+    fn test_commit_with_info() {
+        let options = CommitOptions::with(Entropy::Static);
+        for (vout, method) in
+            [(0, CloseMethod::TapretFirst), (1, CloseMethod::OpretFirst)]
+        {
+            let mut psbt = psbt_fixture(method);
+            let original = psbt.to_unsigned_tx();
+            let info = Anchor::commit_with(&mut psbt, &options).unwrap();
+            assert_eq!(info.vout, vout);
+            assert_eq!(info.method, method);
+            assert_eq!(info.anchor.txid(), psbt.to_txid());
+            assert!(info.log.events().is_empty());
+            let tx = psbt.to_unsigned_tx();
+            for no in 0..2 {
+                assert_eq!(
+                    tx.output[no] == original.output[no],
+                    no != vout as usize
+                );
+            }
+        }
+
+        // With several host candidates of the same type the first one is
+        // selected
+        let txout = TxOut {
+            value: 0,
+            script_pubkey: Script::new_op_return(&[]),
+        };
+        let tx = tx_fixture(vec![txout.clone(), txout]);
+        let mut psbt = Psbt::with(tx, psbt::PsbtVersion::V0).unwrap();
+        for output in &mut psbt.outputs {
+            output.set_opret_host().unwrap();
+        }
+        for (protocol_id, message) in (0u8..3).map(protocol_fixture) {
+            psbt.outputs[0]
+                .set_lnpbp4_message(protocol_id, message)
+                .unwrap();
+        }
+        let original = psbt.to_unsigned_tx();
+        let info = Anchor::commit_with(&mut psbt, &options).unwrap();
+        assert_eq!(info.vout, 0);
+        assert_eq!(info.method, CloseMethod::OpretFirst);
+        assert_eq!(psbt.to_unsigned_tx().output[1], original.output[1]);
+
+        let mut psbt = host_fixture(CloseMethod::OpretFirst);
+        assert_eq!(
+            Anchor::commit_with(&mut psbt, &options),
+            Err(PsbtCommitError::NoMessages.into())
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "deterministic"))]
+    fn test_commit_options_default() {
+        let options = CommitOptions::default();
+        assert_eq!(options, CommitOptions::with(Entropy::Random));
+        assert_eq!(options.host, HostSelection::default());
+        assert_eq!(options.foreign_messages, ForeignMessages::default());
+        assert_eq!(options.max_depth, TAPRET_MAX_PATH_DEPTH);
+        assert!(!options.log);
+
+        // Random entropy makes the commitments distinct
+        let info = Anchor::commit_with(
+            &mut psbt_fixture(CloseMethod::OpretFirst),
+            &options,
+        )
+        .unwrap();
+        let other = Anchor::commit_with(
+            &mut psbt_fixture(CloseMethod::OpretFirst),
+            &options,
+        )
+        .unwrap();
+        assert_eq!((info.vout, info.method), (other.vout, other.method));
+        assert_ne!(info.anchor, other.anchor);
+    }
+
+    /// PSBT with messages on output `vout`, where the outputs are marked as
+    /// the commitment hosts according to `tapret` and `opret` flags.
+    fn hosts_fixture(tapret: bool, opret: bool, vout: usize) -> Psbt {
+        let internal_key = internal_key_fixture();
+        let tx = tx_fixture(vec![
+            TxOut {
+                value: 1000,
+                script_pubkey: Script::new_v1_p2tr(
+                    secp256k1::SECP256K1,
+                    internal_key,
+                    None,
+                ),
+            },
+            TxOut {
+                value: 0,
+                script_pubkey: Script::new_op_return(&[]),
+            },
+        ]);
+        let mut psbt = Psbt::with(tx, psbt::PsbtVersion::V0).unwrap();
+        if tapret {
+            let output = &mut psbt.outputs[0];
+            output.tap_internal_key = Some(internal_key);
+            output
+                .set_tapret_dfs_path(&bitcoin_scripts::taproot::DfsPath::new())
+                .unwrap();
+        }
+        if opret {
+            psbt.outputs[1].set_opret_host().unwrap();
+        }
+        for (protocol_id, message) in (0u8..3).map(protocol_fixture) {
+            psbt.outputs[vout]
+                .set_lnpbp4_message(protocol_id, message)
+                .unwrap();
+        }
+        psbt
+    }
+
+    #[test]
+    fn test_ambiguous_hosts() {
+        for (tapret, opret, vout) in [(true, false, 0), (false, true, 1)] {
+            let mut psbt = hosts_fixture(tapret, opret, vout);
+            let anchor = Anchor::commit_static(&mut psbt).unwrap();
+            assert_eq!(
+                matches!(anchor.dbc_proof(), Proof::TapretFirst(_)),
+                tapret
+            );
+        }
+
+        for vout in [0, 1] {
+            let mut psbt = hosts_fixture(true, true, vout);
+            let original = psbt.clone();
+            assert_eq!(
+                Anchor::commit_static(&mut psbt),
+                Err(PsbtCommitError::AmbiguousHosts {
+                    tapret_vout: 0,
+                    opret_vout: 1
+                }
+                .into())
+            );
+            assert_eq!(psbt, original);
+        }
+
+        let mut psbt = hosts_fixture(false, false, 0);
+        assert_eq!(
+            Anchor::commit_static(&mut psbt),
+            Err(PsbtCommitError::CommitmentImpossible.into())
+        );
+    }
+
+    #[test]
+    fn test_commit_with_host_preferring() {
+        for method in [CloseMethod::TapretFirst, CloseMethod::OpretFirst] {
+            let options = CommitOptions {
+                host: HostSelection::Preferring(method),
+                ..CommitOptions::with(Entropy::Static)
+            };
+
+            // Both hosts present: the preferred one is used
+            let vout = if method == CloseMethod::TapretFirst {
+                0
+            } else {
+                1
+            };
+            let mut psbt = hosts_fixture(true, true, vout);
+            let anchor = commit_tree(&mut psbt, &options).unwrap();
+            assert_eq!(
+                matches!(anchor.dbc_proof(), Proof::TapretFirst(_)),
+                method == CloseMethod::TapretFirst
+            );
+            assert_eq!(anchor.txid(), psbt.to_txid());
+            #[cfg(not(feature = "deterministic"))]
+            assert!(psbt.verify(&PsbtEmbeddedMessage, anchor).unwrap());
+
+            // Only the other host present: it is used as well
+            for (tapret, opret, vout) in [(true, false, 0), (false, true, 1)] {
+                let mut psbt = hosts_fixture(tapret, opret, vout);
+                let anchor = commit_tree(&mut psbt, &options).unwrap();
+                assert_eq!(
+                    matches!(anchor.dbc_proof(), Proof::TapretFirst(_)),
+                    tapret
+                );
+                #[cfg(not(feature = "deterministic"))]
+                assert!(psbt.verify(&PsbtEmbeddedMessage, anchor).unwrap());
+            }
+
+            let mut psbt = hosts_fixture(false, false, 0);
+            assert_eq!(
+                commit_tree(&mut psbt, &options),
+                Err(PsbtCommitError::CommitmentImpossible)
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "deterministic"))]
+    fn test_embed_commit_with_messages() {
+        let messages = (0u8..3)
+            .map(protocol_fixture)
+            .collect::<lnpbp4::MessageMap>();
+        for method in [CloseMethod::OpretFirst, CloseMethod::TapretFirst] {
+            let tapret = method == CloseMethod::TapretFirst;
+            let vout = if tapret { 0 } else { 1 };
+            let mut psbt = host_fixture(method);
+            let anchor = psbt
+                .embed_commit_with_messages(messages.clone(), Some(5))
+                .unwrap();
+            assert_eq!(anchor.txid(), psbt.to_txid());
+            assert_eq!(anchor.lnpbp4_proof().messages(), &messages);
+            assert!(anchor.lnpbp4_proof().depth() >= 5);
+
+            // Messages, depth and entropy are available to the other parties
+            let output = &psbt.outputs[vout];
+            assert_eq!(output.lnpbp4_message_map().unwrap(), messages);
+            assert_eq!(output.lnpbp4_min_tree_depth().unwrap(), Some(5));
+            assert_eq!(
+                output.lnpbp4_entropy().unwrap(),
+                Some(anchor.lnpbp4_proof().entropy())
+            );
+            let (protocol_id, message) = protocol_fixture(1);
+            assert!(anchor
+                .into_merkle_block()
+                .to_merkle_proof(protocol_id)
+                .unwrap()
+                .verify(protocol_id, message, psbt.to_unsigned_tx())
+                .unwrap());
+
+            // Messages already present in the keys are not conflicting
+            let mut psbt = psbt_fixture(method);
+            psbt.outputs[vout].set_lnpbp4_min_tree_depth(4);
+            let anchor = psbt
+                .embed_commit_with_messages(messages.clone(), Some(4))
+                .unwrap();
+            assert_eq!(anchor.lnpbp4_proof().messages(), &messages);
+        }
+
+        let mut psbt = psbt_fixture(CloseMethod::OpretFirst);
+        let original = psbt.clone();
+        let (protocol_id, _) = protocol_fixture(1);
+        let (_, other) = protocol_fixture(3);
+        let mut conflicting = messages.clone();
+        conflicting.insert(protocol_id, other);
+        assert_eq!(
+            psbt.embed_commit_with_messages(conflicting, None),
+            Err(PsbtCommitError::MessageConflict {
+                vout: 1,
+                protocol_id
+            })
+        );
+        let mut missing = messages.clone();
+        missing.remove(&protocol_id);
+        assert_eq!(
+            psbt.embed_commit_with_messages(missing, None),
+            Err(PsbtCommitError::MessageConflict {
+                vout: 1,
+                protocol_id
+            })
+        );
+        assert_eq!(psbt, original);
+        psbt.outputs[1].set_lnpbp4_min_tree_depth(4);
+        let with_depth = psbt.clone();
+        assert_eq!(
+            psbt.embed_commit_with_messages(messages.clone(), Some(5)),
+            Err(PsbtCommitError::MinDepthConflict {
+                vout: 1,
+                present: 4,
+                supplied: 5
+            })
+        );
+        assert_eq!(psbt, with_depth);
+
+        let mut psbt = host_fixture(CloseMethod::OpretFirst);
+        assert_eq!(
+            psbt.embed_commit_with_messages(lnpbp4::MessageMap::new(), None),
+            Err(PsbtCommitError::NoMessages)
+        );
+        assert_eq!(psbt, host_fixture(CloseMethod::OpretFirst));
+    }
+
+    /// Script tree with a single leaf at each level down to `depth` and two
+    /// leaves at the deepest level.
+    fn deep_tree_fixture(depth: u8) -> TapTree {
+        let builder = (1..=depth).chain([depth]).enumerate().fold(
+            TaprootBuilder::new(),
+            |builder, (no, depth)| {
+                builder
+                    .add_leaf(depth, Script::from(vec![0x01, no as u8]))
+                    .unwrap()
+            },
+        );
+        TapTree::try_from(builder).unwrap()
+    }
+
+    #[test]
+    fn test_tapret_tree_depth() {
+        use crate::consts::TAPRET_CONSENSUS_MAX_PATH_DEPTH;
+
+        let max = TAPRET_MAX_PATH_DEPTH;
+
+        // Commitment makes the tree one level deeper
+        let mut psbt = psbt_fixture(CloseMethod::TapretFirst);
+        psbt.outputs[0].tap_tree = Some(deep_tree_fixture(max as u8 - 1));
+        assert!(Anchor::commit_static(&mut psbt).is_ok());
+
+        let mut psbt = psbt_fixture(CloseMethod::TapretFirst);
+        psbt.outputs[0].tap_tree = Some(deep_tree_fixture(max as u8));
+        let original = psbt.clone();
+        assert_eq!(
+            Anchor::commit_static(&mut psbt),
+            Err(PsbtCommitError::TreeTooDeep {
+                depth: max + 1,
+                max
+            }
+            .into())
+        );
+        assert_eq!(psbt, original);
+
+        // Commitments made with the raised limit are still verified
+        let options = CommitOptions {
+            max_depth: max + 1,
+            ..CommitOptions::with(Entropy::Static)
+        };
+        let anchor = commit_tree(&mut psbt, &options).unwrap();
+        let (protocol_id, message) = protocol_fixture(0);
+        let anchor = anchor
+            .to_merkle_block()
+            .to_merkle_proof(protocol_id)
+            .unwrap();
+        assert_eq!(
+            anchor.verify(protocol_id, message, psbt.to_unsigned_tx()),
+            Ok(true)
+        );
+
+        // Limits above the consensus one are ignored
+        let consensus = TAPRET_CONSENSUS_MAX_PATH_DEPTH;
+        let msg = lnpbp4_fixture().consensus_commit();
+        let mut output = original.outputs[0].clone();
+        output.tap_tree = Some(deep_tree_fixture(consensus as u8));
+        assert_eq!(
+            host::embed_tapret_with_max_depth(&mut output, &msg, usize::MAX),
+            Err(PsbtCommitError::TreeTooDeep {
+                depth: consensus + 1,
+                max: consensus
+            })
+        );
+    }
+
+    #[test]
+    fn test_restore_empty_psbt() {
+        let empty =
+            Psbt::with(tx_fixture(vec![]), psbt::PsbtVersion::V0).unwrap();
+        for method in [CloseMethod::TapretFirst, CloseMethod::OpretFirst] {
+            let anchor = psbt_fixture(method)
+                .embed_commit_static(&PsbtEmbeddedMessage)
+                .unwrap();
+            let err = PsbtVerifyError::Commit(PsbtCommitError::EmptyPsbt);
+            assert_eq!(
+                EmbedCommitProofStatic::<_, Psbt, Lnpbp6>::restore_original_container(
+                    &anchor, &empty
+                ),
+                Err(err.clone())
+            );
+            #[cfg(not(feature = "deterministic"))]
+            {
+                assert_eq!(
+                    EmbedCommitProof::<_, Psbt, Lnpbp6>::restore_original_container(
+                        &anchor, &empty
+                    ),
+                    Err(err.clone())
+                );
+                assert_eq!(
+                    empty.verify(&PsbtEmbeddedMessage, anchor),
+                    Err(err)
+                );
+            }
+        }
+        assert_eq!(
+            empty.clone().embed_commit_static(&PsbtEmbeddedMessage),
+            Err(PsbtCommitError::EmptyPsbt)
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "deterministic"))]
+    fn test_embed_commit_verify() {
+        for method in [CloseMethod::TapretFirst, CloseMethod::OpretFirst] {
+            let mut psbt = psbt_fixture(method);
+            let anchor = psbt.embed_commit(&PsbtEmbeddedMessage).unwrap();
+            assert_eq!(
+                psbt.verify(&PsbtEmbeddedMessage, anchor.clone()),
+                Ok(true)
+            );
+
+            // Commitment to the same messages with other entropy, which
+            // conflicts with the entropy of the anchor
+            let mut other = psbt_fixture(method);
+            let other_anchor =
+                other.embed_commit(&PsbtEmbeddedMessage).unwrap();
+            assert_ne!(other_anchor, anchor);
+            assert_eq!(
+                other.verify(&PsbtEmbeddedMessage, anchor),
+                Err(PsbtVerifyError::Commit(PsbtCommitError::PsbtLnpbp4(
+                    Lnpbp4KeyError::InvalidKeyValue
+                )))
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "deterministic"))]
+    fn test_embed_commit_verify_legacy_opret() {
+        let mut psbt = psbt_fixture(CloseMethod::OpretFirst);
+        let anchor = psbt.embed_commit(&PsbtEmbeddedMessage).unwrap();
+        assert_eq!(
+            anchor.dbc_proof(),
+            &Proof::OpretFirstAt(OpretProof { vout: 1 })
+        );
+
+        let legacy = anchor.clone().with_dbc_proof(Proof::OpretFirst);
+        assert_eq!(psbt.verify(&PsbtEmbeddedMessage, legacy), Ok(true));
+
+        // Legacy proofs are not accepted for tapret commitments
+        let mut psbt = psbt_fixture(CloseMethod::TapretFirst);
+        let anchor = psbt.embed_commit(&PsbtEmbeddedMessage).unwrap();
+        let legacy = anchor.with_dbc_proof(Proof::OpretFirst);
+        assert!(psbt.verify(&PsbtEmbeddedMessage, legacy).is_err());
+    }
+
+    #[test]
+    fn test_restore_single_output_psbt() {
+        let internal_key = internal_key_fixture();
+        for method in [CloseMethod::TapretFirst, CloseMethod::OpretFirst] {
+            let tapret = method == CloseMethod::TapretFirst;
+            let script_pubkey = if tapret {
+                Script::new_v1_p2tr(SECP256K1, internal_key, None)
+            } else {
+                Script::new_op_return(&[])
+            };
+            let tx = tx_fixture(vec![TxOut {
+                value: 1000,
+                script_pubkey,
+            }]);
+            let mut psbt = Psbt::with(tx, psbt::PsbtVersion::V0).unwrap();
+            let output = &mut psbt.outputs[0];
+            if tapret {
+                output.tap_internal_key = Some(internal_key);
+                output
+                    .set_tapret_dfs_path(
+                        &bitcoin_scripts::taproot::DfsPath::new(),
+                    )
+                    .unwrap();
+            } else {
+                output.set_opret_host().unwrap();
+            }
+            for (protocol_id, message) in (0u8..3).map(protocol_fixture) {
+                output.set_lnpbp4_message(protocol_id, message).unwrap();
+            }
+            let original = psbt.clone();
+
+            // Opret commitment can't consume the only output
+            if !tapret {
+                assert_eq!(
+                    psbt.embed_commit_static(&PsbtEmbeddedMessage),
+                    Err(PsbtCommitError::OpretSoleOutput(0))
+                );
+                assert_eq!(psbt, original);
+
+                // Tapret anchor can't be restored from the opret host PSBT
+                let (tapret_anchor, _) = tapret_fixture();
+                let tapret_anchor =
+                    tapret_anchor.with_lnpbp4_proof(lnpbp4_fixture());
+                assert_eq!(
+                    EmbedCommitProofStatic::<_, Psbt, Lnpbp6>::restore_original_container(
+                        &tapret_anchor, &psbt
+                    ),
+                    Err(PsbtVerifyError::Commit(
+                        PsbtCommitError::CommitmentImpossible
+                    ))
+                );
+                continue;
+            }
+
+            let anchor =
+                psbt.embed_commit_static(&PsbtEmbeddedMessage).unwrap();
+            let restored = EmbedCommitProofStatic::<_, Psbt, Lnpbp6>::restore_original_container(
+                &anchor, &psbt,
+            )
+            .unwrap();
+            assert_eq!(restored.outputs.len(), 1);
+            assert_eq!(restored.outputs[0].script, original.outputs[0].script);
+        }
+    }
+
+    #[test]
+    fn test_commit_single_output() {
+        let internal_key = internal_key_fixture();
+        let mut psbt = Psbt::with(
+            tx_fixture(vec![TxOut {
+                value: 1000,
+                script_pubkey: Script::new_v1_p2tr(
+                    SECP256K1,
+                    internal_key,
+                    None,
+                ),
+            }]),
+            psbt::PsbtVersion::V0,
+        )
+        .unwrap();
+        let output = &mut psbt.outputs[0];
+        output.tap_internal_key = Some(internal_key);
+        output
+            .set_tapret_dfs_path(&bitcoin_scripts::taproot::DfsPath::new())
+            .unwrap();
+        for (protocol_id, message) in (0u8..3).map(protocol_fixture) {
+            output.set_lnpbp4_message(protocol_id, message).unwrap();
+        }
+
+        // Sweep into the only output hosting tapret commitment
+        let anchor = Anchor::commit_with_entropy(&mut psbt, 0x5eed).unwrap();
+        assert_eq!(anchor.dbc_proof().method(), CloseMethod::TapretFirst);
+        assert_eq!(psbt.outputs.len(), 1);
+        assert_eq!(psbt.outputs[0].script.len(), 34);
+        for input in &mut psbt.inputs {
+            input.final_script_witness = Some(bitcoin::Witness::new());
+        }
+        let tx = crate::extract_witness_tx(&psbt, &anchor).unwrap();
+        assert_eq!(tx.output.len(), 1);
+        for (protocol_id, message) in (0u8..3).map(protocol_fixture) {
+            let proof = anchor.to_merkle_proof(protocol_id).unwrap();
+            assert_eq!(
+                proof.verify_detailed(protocol_id, message, &tx),
+                Ok(VerificationOutcome::Committed)
+            );
+        }
+
+        // Opret commitment would consume the only output
+        let mut tx = tx_fixture(vec![TxOut {
+            value: 1000,
+            script_pubkey: Script::new_op_return(&[]),
+        }]);
+        let original = tx.clone();
+        assert_eq!(
+            Anchor::commit_tx(
+                &mut tx,
+                0,
+                CloseMethod::OpretFirst,
+                None,
+                &mut None,
+                lnpbp4_fixture(),
+            ),
+            Err(Error::EmbedCommit(PsbtCommitError::OpretSoleOutput(0)))
+        );
+        assert_eq!(tx, original);
+    }
+
+    fn commit_logged(
+        entropy: Entropy,
+        psbt: &mut Psbt,
+    ) -> Result<(Anchor<lnpbp4::MerkleBlock>, CommitLog), Error> {
+        let options = CommitOptions {
+            log: true,
+            ..CommitOptions::with(entropy)
+        };
+        Anchor::commit_with(psbt, &options).map(|info| (info.anchor, info.log))
+    }
+
+    #[test]
+    fn test_commit_log() {
+        let tree = lnpbp4_fixture();
+        let tree_built = CommitEvent::TreeBuilt {
+            depth: tree.depth(),
+            leaves: tree.width(),
+            protocols: 3,
+        };
+
+        let mut psbt = psbt_fixture(CloseMethod::TapretFirst);
+        let old_key =
+            XOnlyPublicKey::from_slice(&psbt.outputs[0].script[2..]).unwrap();
+        let (anchor, log) = commit_logged(Entropy::Static, &mut psbt).unwrap();
+        let mut expected = psbt_fixture(CloseMethod::TapretFirst);
+        assert_eq!(Anchor::commit_static(&mut expected).unwrap(), anchor);
+        let new_key =
+            XOnlyPublicKey::from_slice(&psbt.outputs[0].script[2..]).unwrap();
+        assert_eq!(log.events(), &[
+            CommitEvent::HostSelected {
+                vout: 0,
+                method: CloseMethod::TapretFirst
+            },
+            tree_built.clone(),
+            CommitEvent::OutputTweaked {
+                old_key: Some(old_key),
+                new_key,
+                tree_extended: false
+            },
+        ]);
+
+        #[cfg(not(feature = "deterministic"))]
+        {
+            let mut psbt = psbt_fixture(CloseMethod::OpretFirst);
+            let (_, log) = commit_logged(Entropy::Random, &mut psbt).unwrap();
+            let events = log.into_events();
+            assert_eq!(events.len(), 3);
+            assert_eq!(events[0], CommitEvent::HostSelected {
+                vout: 1,
+                method: CloseMethod::OpretFirst
+            });
+            assert!(matches!(events[1], CommitEvent::TreeBuilt {
+                protocols: 3,
+                ..
+            }));
+            assert_eq!(events[2], CommitEvent::OpretScriptSet {
+                script_pubkey: psbt.outputs[1].script.to_inner()
+            });
+        }
+
+        let (_, log) = commit_logged(
+            Entropy::Static,
+            &mut psbt_fixture(CloseMethod::OpretFirst),
+        )
+        .unwrap();
+        assert_eq!(log.events()[1], tree_built);
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&log).unwrap();
+            assert_eq!(serde_json::from_str::<CommitLog>(&json).unwrap(), log);
+        }
+
+        let mut psbt = psbt_fixture(CloseMethod::OpretFirst);
+        psbt.outputs[1].proprietary.clear();
+        assert!(commit_logged(Entropy::Static, &mut psbt).is_err());
+        #[cfg(not(feature = "deterministic"))]
+        assert!(commit_logged(Entropy::Random, &mut psbt).is_err());
+    }
+
+    /// Appends output with the given `script_pubkey` to the PSBT and sorts
+    /// PSBT outputs according to BIP-69.
+    fn add_output_and_sort(psbt: &mut Psbt, script_pubkey: Script) {
+        let mut output = psbt.outputs[0].clone();
+        output.amount = 500;
+        output.script = script_pubkey.into();
+        output.tap_internal_key = None;
+        output.tap_tree = None;
+        output.proprietary.clear();
+        psbt.outputs.push(output);
+        psbt.outputs.sort_by(|a, b| {
+            (a.amount, a.script.as_bytes())
+                .cmp(&(b.amount, b.script.as_bytes()))
+        });
+    }
+
+    #[test]
+    fn test_commit_sorted_outputs() {
+        let wpkh = Script::new_v0_p2wpkh(&bitcoin::WPubkeyHash::hash(b"a"));
+        for method in [CloseMethod::TapretFirst, CloseMethod::OpretFirst] {
+            let tapret = method == CloseMethod::TapretFirst;
+            let mut psbt = psbt_fixture(method);
+            add_output_and_sort(&mut psbt, wpkh.clone());
+            // OP_RETURN, P2WPKH and taproot outputs after the sorting
+            let vout = if tapret { 2 } else { 0 };
+            assert!(if tapret {
+                psbt.outputs[vout].is_tapret_host()
+            } else {
+                psbt.outputs[vout].is_opret_host()
+            });
+
+            let (anchor, log) =
+                commit_logged(Entropy::Static, &mut psbt).unwrap();
+            assert!(matches!(
+                log.events()[0],
+                CommitEvent::HostSelected { vout: v, .. } if v as usize == vout
+            ));
+            for (protocol_id, message) in (0u8..3).map(protocol_fixture) {
+                let proof = anchor.to_merkle_proof(protocol_id).unwrap();
+                assert!(proof
+                    .verify(protocol_id, message, psbt.to_unsigned_tx())
+                    .unwrap());
+            }
+        }
+
+        // Sorting puts another taproot output before the tapret host
+        let other = Script::new_v1_p2tr_tweaked(
+            bitcoin::schnorr::TweakedPublicKey::dangerous_assume_tweaked(
+                internal_key_fixture(),
+            ),
+        );
+        let mut psbt = psbt_fixture(CloseMethod::TapretFirst);
+        add_output_and_sort(&mut psbt, other);
+        let original = psbt.clone();
+        assert_eq!(
+            commit(&mut psbt),
+            Err(Error::EmbedCommit(PsbtCommitError::HostNotFirst {
+                host: 2,
+                first: 1
+            }))
+        );
+        assert_eq!(psbt, original);
+    }
+
+    #[test]
+    fn test_psbt_from_v0() {
+        for method in [CloseMethod::TapretFirst, CloseMethod::OpretFirst] {
+            let psbt = psbt_fixture(method);
+            let v0 = PartiallySignedTransaction::from(psbt.clone());
+            assert_eq!(psbt_from_v0(v0.clone()).unwrap(), psbt);
+
+            // Output map for the last unsigned transaction output is missing
+            let mut desync = v0;
+            desync.unsigned_tx.output.push(TxOut {
+                value: 500,
+                script_pubkey: Script::new_v0_p2wpkh(
+                    &bitcoin::WPubkeyHash::hash(b"a"),
+                ),
+            });
+            let tx_outputs = desync.unsigned_tx.output.len();
+            assert_eq!(
+                psbt_from_v0(desync.clone()),
+                Err(PsbtCommitError::MalformedPsbt {
+                    outputs: tx_outputs - 1,
+                    tx_outputs
+                })
+            );
+            // Plain conversion drops the output, changing the transaction
+            assert_ne!(
+                Psbt::from(desync.clone()).to_txid(),
+                desync.unsigned_tx.txid()
+            );
+        }
+    }
+
+    #[test]
+    fn test_commit_tx_equivalence() {
+        for method in [CloseMethod::TapretFirst, CloseMethod::OpretFirst] {
+            let tapret = method == CloseMethod::TapretFirst;
+            let mut psbt = psbt_fixture(method);
+            let mut tx = psbt.to_unsigned_tx();
+            let psbt_anchor = Anchor::commit_static(&mut psbt).unwrap();
+
+            let (vout, internal_key) = if tapret {
+                (0, Some(internal_key_fixture()))
+            } else {
+                (1, None)
+            };
+            let mut tap_tree = None;
+            let tx_anchor = Anchor::commit_tx(
+                &mut tx,
+                vout,
+                method,
+                internal_key,
+                &mut tap_tree,
+                lnpbp4_fixture(),
+            )
+            .unwrap();
+
+            assert_eq!(tx_anchor, psbt_anchor);
+            assert_eq!(tx, psbt.to_unsigned_tx());
+            assert_eq!(tap_tree, psbt.outputs[vout].tap_tree);
+            assert_eq!(tx_anchor.anchor_id(), psbt_anchor.anchor_id());
+
+            let tree_anchor = psbt_fixture(method)
+                .embed_commit_static(&PsbtEmbeddedMessage)
+                .unwrap();
+            assert!(tree_anchor.corresponds_to(&tx_anchor));
+            assert_eq!(tree_anchor.into_merkle_block(), psbt_anchor);
+        }
+
+        let mut tx = psbt_fixture(CloseMethod::TapretFirst).to_unsigned_tx();
+        let original = tx.clone();
+        assert_eq!(
+            Anchor::commit_tx(
+                &mut tx,
+                0,
+                CloseMethod::TapretFirst,
+                None,
+                &mut None,
+                lnpbp4_fixture()
+            ),
+            Err(PsbtCommitError::InternalKeyMissed.into())
+        );
+        assert_eq!(
+            Anchor::commit_tx(
+                &mut tx,
+                2,
+                CloseMethod::OpretFirst,
+                None,
+                &mut None,
+                lnpbp4_fixture()
+            ),
+            Err(PsbtCommitError::CommitmentImpossible.into())
+        );
+        assert_eq!(tx, original);
+    }
+
+    #[test]
+    fn test_commit_tx_max_values() {
+        let mut tx = psbt_fixture(CloseMethod::OpretFirst).to_unsigned_tx();
+        for txout in &mut tx.output {
+            txout.value = u64::MAX;
+        }
+        let original = tx.clone();
+        assert_eq!(
+            Anchor::commit_tx(
+                &mut tx,
+                usize::MAX,
+                CloseMethod::OpretFirst,
+                None,
+                &mut None,
+                lnpbp4_fixture()
+            ),
+            Err(PsbtCommitError::CommitmentImpossible.into())
+        );
+        assert_eq!(tx, original);
+
+        let anchor = Anchor::commit_tx(
+            &mut tx,
+            1,
+            CloseMethod::OpretFirst,
+            None,
+            &mut None,
+            lnpbp4_fixture(),
+        )
+        .unwrap();
+        assert!(tx.output.iter().all(|txout| txout.value == u64::MAX));
+        let (protocol_id, message) = protocol_fixture(0);
+        assert!(anchor
+            .into_merkle_proof(protocol_id)
+            .unwrap()
+            .verify(protocol_id, message, &tx)
+            .unwrap());
+    }
+
+    #[test]
+    fn test_no_messages() {
+        let empty_tree = || {
+            lnpbp4_tree_fixture(&lnpbp4::MultiSource {
+                min_depth: ANCHOR_MIN_LNPBP4_DEPTH,
+                messages: empty!(),
+            })
+        };
+        let err = Error::EmbedCommit(PsbtCommitError::NoMessages);
+
+        for method in [CloseMethod::TapretFirst, CloseMethod::OpretFirst] {
+            let tapret = method == CloseMethod::TapretFirst;
+            let mut psbt = host_fixture(method);
+            let original = psbt.clone();
+            assert_eq!(commit(&mut psbt), Err(err.clone()));
+            assert_eq!(Anchor::commit_static(&mut psbt), Err(err.clone()));
+            assert_eq!(psbt.expected_anchor_id(), Err(err.clone()));
+            assert_eq!(psbt, original);
+
+            let mut tx = psbt.to_unsigned_tx();
+            let (vout, internal_key) = if tapret {
+                (0, Some(internal_key_fixture()))
+            } else {
+                (1, None)
+            };
+            assert_eq!(
+                Anchor::commit_tx(
+                    &mut tx,
+                    vout,
+                    method,
+                    internal_key,
+                    &mut None,
+                    empty_tree()
+                ),
+                Err(err.clone())
+            );
+            assert_eq!(tx, original.to_unsigned_tx());
+        }
+    }
+
+    #[test]
+    fn test_oversized_psbt_values() {
+        use psbt::commit::ProprietaryKeyLnpbp4;
+        use psbt::ProprietaryKey;
+
+        use crate::proprietary::{KeyError, LNPBP4_MESSAGE_MAX_LEN};
+
+        for method in [CloseMethod::TapretFirst, CloseMethod::OpretFirst] {
+            let mut psbt = psbt_fixture(method);
+            let (protocol_id, _) = protocol_fixture(1);
+            let key = ProprietaryKey::lnpbp4_message(protocol_id);
+            let output = psbt
+                .outputs
+                .iter_mut()
+                .find(|output| output.proprietary.contains_key(&key))
+                .unwrap();
+            output
+                .proprietary
+                .insert(key.clone(), vec![0u8; 1024 * 1024]);
+            let original = psbt.clone();
+
+            assert_eq!(
+                commit(&mut psbt).unwrap_err(),
+                Error::EmbedCommit(PsbtCommitError::ProprietaryKey(
+                    KeyError::Oversized {
+                        key,
+                        len: 1024 * 1024,
+                        max: LNPBP4_MESSAGE_MAX_LEN,
+                    }
+                ))
+            );
+            assert_eq!(psbt, original);
+            assert!(psbt.expected_anchor_id().is_err());
+        }
+    }
+
+    #[test]
+    fn test_min_tree_depth_overflow() {
+        use psbt::commit::ProprietaryKeyLnpbp4;
+        use psbt::ProprietaryKey;
+
+        use crate::proprietary::KeyError;
+
+        for method in [CloseMethod::TapretFirst, CloseMethod::OpretFirst] {
+            for depth in [LNPBP4_MAX_DEPTH + 1, 64, u8::MAX] {
+                let mut psbt = psbt_fixture(method);
+                let key = ProprietaryKey::lnpbp4_min_tree_depth();
+                for output in &mut psbt.outputs {
+                    output.proprietary.insert(key.clone(), vec![depth]);
+                }
+                let original = psbt.clone();
+
+                assert_eq!(
+                    commit(&mut psbt).unwrap_err(),
+                    Error::EmbedCommit(PsbtCommitError::ProprietaryKey(
+                        KeyError::MinTreeDepthOverflow(depth)
+                    ))
+                );
+                assert_eq!(psbt, original);
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Structured log of the steps performed while embedding deterministic bitcoin
+//! commitment into a PSBT.
+//!
+//! The log is returned by [`crate::Anchor::commit_with`] when requested with
+//! [`crate::CommitOptions::log`] and allows wallets to present the details of
+//! the commitment to the user. With `tracing` feature the events are also
+//! emitted as `tracing` events under `dbc::commit` target.
+
+use std::slice;
+
+use bitcoin::{Script, XOnlyPublicKey};
+
+use crate::method::CloseMethod;
+
+/// Single step of embedding commitment into a PSBT.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Display)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub enum CommitEvent {
+    /// Transaction output hosting the commitment was selected.
+    #[display("output #{vout} selected to host {method} commitment")]
+    HostSelected {
+        /// Number of the host output.
+        vout: u32,
+
+        /// Commitment method used by the host output.
+        method: CloseMethod,
+    },
+
+    /// LNPBP-4 merkle tree was constructed.
+    #[display(
+        "LNPBP-4 tree of depth {depth} with {leaves} leaves built for \
+         {protocols} protocol(s)"
+    )]
+    TreeBuilt {
+        /// Depth of the tree.
+        depth: u8,
+
+        /// Number of the tree leaves, including placeholders.
+        leaves: usize,
+
+        /// Number of protocols committed by the tree.
+        protocols: usize,
+    },
+
+    /// Taproot output key was tweaked with the commitment.
+    #[display("taproot output key tweaked to {new_key}")]
+    OutputTweaked {
+        /// Output key before the commitment, if the output already had
+        /// taproot scriptPubkey.
+        old_key: Option<XOnlyPublicKey>,
+
+        /// Output key committing to the tapret commitment.
+        new_key: XOnlyPublicKey,
+
+        /// Whether an existing script tree was extended with the commitment
+        /// leaf (otherwise, the commitment leaf is the only leaf of the tree).
+        tree_extended: bool,
+    },
+
+    /// `OP_RETURN` output script was set to the commitment.
+    #[display("OP_RETURN output script set to {script_pubkey}")]
+    OpretScriptSet {
+        /// Output scriptPubkey containing the commitment.
+        script_pubkey: Script,
+    },
+}
+
+/// Ordered log of the events happened during embedding commitment into a PSBT.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+pub struct CommitLog {
+    events: Vec<CommitEvent>,
+}
+
+impl<'log> IntoIterator for &'log CommitLog {
+    type Item = &'log CommitEvent;
+    type IntoIter = slice::Iter<'log, CommitEvent>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter { self.events.iter() }
+}
+
+impl CommitLog {
+    /// Constructs empty log.
+    #[inline]
+    pub fn new() -> CommitLog { CommitLog::default() }
+
+    /// Returns logged events in the order of their occurrence.
+    #[inline]
+    pub fn events(&self) -> &[CommitEvent] { &self.events }
+
+    /// Releases logged events.
+    #[inline]
+    pub fn into_events(self) -> Vec<CommitEvent> { self.events }
+
+    /// Returns iterator over the logged events.
+    #[inline]
+    pub fn iter(&self) -> slice::Iter<'_, CommitEvent> { self.events.iter() }
+
+    pub(crate) fn push(&mut self, event: CommitEvent) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(target: "dbc::commit", "{}", event);
+        self.events.push(event);
+    }
+}
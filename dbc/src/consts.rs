@@ -0,0 +1,167 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Sizes and limits of deterministic bitcoin commitments.
+//!
+//! The library uses these constants internally instead of literal values, so
+//! downstream code may rely on them staying in sync with the actual behavior
+//! of commitment construction and verification.
+
+use commit_verify::lnpbp4;
+
+/// Default minimal depth of LNPBP-4 commitment tree used when committing to
+/// the anchors, unless PSBT output specifies other minimal depth.
+pub const ANCHOR_MIN_LNPBP4_DEPTH: u8 = 3;
+
+/// Maximal depth of LNPBP-4 commitment tree. Trees with larger minimal depth
+/// can't be constructed, and PSBT outputs requesting them are rejected.
+pub const LNPBP4_MAX_DEPTH: u8 = lnpbp4::MAX_TREE_DEPTH;
+
+/// Entropy of LNPBP-4 commitment trees constructed by the static commitment
+/// methods, which produce the same tree for the same set of messages.
+pub const LNPBP4_STATIC_ENTROPY: u64 = 1;
+
+/// Size of LNPBP-4 protocol ids, messages and commitments, which is also the
+/// size of both opret and tapret commitments.
+pub const COMMITMENT_LEN: usize = 32;
+
+/// Maximal size of data pushed into `OP_RETURN` output which is still relayed
+/// by the nodes with the default standardness policy.
+pub const OPRET_PAYLOAD_LEN: usize = 80;
+
+/// Default maximal depth of the taproot script tree with the tapret commitment
+/// (i.e. the maximal length of the script path in the tree) accepted when
+/// embedding the commitment. Realistic descriptors have much shallower trees,
+/// and some wallets can't sign spendings from the deeper ones. The limit may
+/// be raised up to [`TAPRET_CONSENSUS_MAX_PATH_DEPTH`].
+pub const TAPRET_MAX_PATH_DEPTH: usize = 8;
+
+/// Maximal depth of the taproot script tree allowed by the consensus, and thus
+/// the maximal number of steps in the DFS path of the tapret commitment within
+/// the tree. The limit can't be overridden and is applied when decoding and
+/// verifying commitments.
+pub const TAPRET_CONSENSUS_MAX_PATH_DEPTH: usize =
+    bitcoin::util::taproot::TAPROOT_CONTROL_MAX_NODE_COUNT;
+
+/// Version of the anchor encoding produced by
+/// [`Anchor::strict_serialize_versioned`](crate::Anchor::strict_serialize_versioned).
+/// Versioned decoding rejects anchors of other versions.
+pub const ANCHOR_ENCODING_VERSION: u8 = 1;
+
+/// Maximal size of strict-encoded anchor accepted by
+/// [`Anchor::from_hex`](crate::Anchor::from_hex). Fits anchors with LNPBP-4
+/// tree of the maximal depth fully revealed.
+pub const ANCHOR_MAX_LEN: usize = 1 << 23;
+
+/// Maximal number of the protocols revealed by the anchor which are reported
+/// by [`ProofExtractError`](crate::anchor::ProofExtractError).
+pub const PROOF_EXTRACT_MAX_KNOWN: usize = 16;
+
+// If `commit_verify` ever changes the width of the LNPBP-4 commitment we must
+// not silently produce non-standard OP_RETURN outputs.
+const _: () =
+    assert!(std::mem::size_of::<lnpbp4::CommitmentHash>() == COMMITMENT_LEN);
+const _: () = assert!(COMMITMENT_LEN <= OPRET_PAYLOAD_LEN);
+const _: () = assert!(ANCHOR_MIN_LNPBP4_DEPTH <= LNPBP4_MAX_DEPTH);
+const _: () = assert!(TAPRET_MAX_PATH_DEPTH <= TAPRET_CONSENSUS_MAX_PATH_DEPTH);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::opret::{opret_script, OpretError};
+
+    #[test]
+    fn commitment_len() {
+        let script = opret_script(&[0xA5u8; COMMITMENT_LEN]).unwrap();
+        // `OP_RETURN` and a single push opcode precede the payload
+        assert_eq!(script.len(), COMMITMENT_LEN + 2);
+        assert_eq!(
+            opret_script(&[0xA5u8; COMMITMENT_LEN + 1]),
+            Err(OpretError::InvalidCommitmentSize(COMMITMENT_LEN + 1))
+        );
+        assert_eq!(
+            opret_script(&[0xA5u8; OPRET_PAYLOAD_LEN]),
+            Err(OpretError::InvalidCommitmentSize(OPRET_PAYLOAD_LEN))
+        );
+    }
+
+    #[test]
+    fn lnpbp4_depth() {
+        use crate::anchor::lnpbp4_tree_with_entropy;
+        use crate::test_utils::{lnpbp4_fixture, protocol_fixture};
+
+        // Trees may be deeper than requested to fit all protocols
+        assert!(lnpbp4_fixture().depth() >= ANCHOR_MIN_LNPBP4_DEPTH);
+
+        let source = |min_depth| lnpbp4::MultiSource {
+            min_depth,
+            messages: [protocol_fixture(0)].into_iter().collect(),
+        };
+        for depth in [ANCHOR_MIN_LNPBP4_DEPTH, LNPBP4_MAX_DEPTH] {
+            let tree =
+                lnpbp4_tree_with_entropy(&source(depth), LNPBP4_STATIC_ENTROPY)
+                    .unwrap()
+                    .unwrap();
+            assert_eq!(tree.depth(), depth);
+        }
+        assert_eq!(
+            lnpbp4_tree_with_entropy(
+                &source(LNPBP4_MAX_DEPTH + 1),
+                LNPBP4_STATIC_ENTROPY
+            ),
+            Err(lnpbp4::Error::CantFitInMaxSlots)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "wallet")]
+    fn psbt_limits() {
+        use bitcoin_scripts::taproot::{DfsOrder, DfsPath};
+        use psbt::commit::ProprietaryKeyTapret;
+        use psbt::ProprietaryKey;
+
+        use crate::proprietary::{check_output, KeyError, TAPRET_HOST_MAX_LEN};
+
+        let mut output = psbt::Output::new(0, bitcoin::TxOut::default());
+        output.set_lnpbp4_min_tree_depth(LNPBP4_MAX_DEPTH);
+        assert_eq!(check_output(&output), Ok(()));
+        output.set_lnpbp4_min_tree_depth(LNPBP4_MAX_DEPTH + 1);
+        assert_eq!(
+            check_output(&output),
+            Err(KeyError::MinTreeDepthOverflow(LNPBP4_MAX_DEPTH + 1))
+        );
+
+        let mut output = psbt::Output::new(0, bitcoin::TxOut::default());
+        let path = [DfsOrder::First; TAPRET_CONSENSUS_MAX_PATH_DEPTH]
+            .into_iter()
+            .collect::<DfsPath>();
+        output.set_tapret_dfs_path(&path).unwrap();
+        assert_eq!(check_output(&output), Ok(()));
+
+        let key = ProprietaryKey::tapret_host();
+        let mut value = output.proprietary[&key].clone();
+        assert_eq!(value.len(), TAPRET_HOST_MAX_LEN);
+        value.push(0);
+        output.proprietary.insert(key.clone(), value);
+        assert_eq!(
+            check_output(&output),
+            Err(KeyError::Oversized {
+                key,
+                len: TAPRET_HOST_MAX_LEN + 1,
+                max: TAPRET_HOST_MAX_LEN
+            })
+        );
+    }
+}
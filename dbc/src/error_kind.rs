@@ -0,0 +1,765 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Classification of the library errors for mapping them into transport-level
+//! failures, logging and metrics.
+//!
+//! Error codes are stable across library releases: codes of the existing
+//! variants are never changed or reused, and new variants receive new codes.
+//! Codes are allocated in ranges per error type:
+//! - `100..=109`: [`TapretError`];
+//! - `110..=119`: [`OpretError`];
+//! - `120..=129`: [`VerifyError`];
+//! - `130..=139`: [`PolicyError`];
+//! - `140..=149`: [`ResolveVerifyError`];
+//! - `150..=159`: [`RecoverError`];
+//! - `160..=169`: [`AnchorChainError`];
+//! - `200..=299`: [`PsbtCommitError`](crate::tapret::PsbtCommitError);
+//! - `300..=399`: reserved for seal verification errors of `bp-seals`.
+
+use std::io;
+
+use crate::anchor::{RecoverError, VerifyError};
+use crate::chain::AnchorChainError;
+use crate::opret::OpretError;
+use crate::policy::PolicyError;
+#[cfg(feature = "wallet")]
+use crate::tapret::PsbtCommitError;
+use crate::tapret::TapretError;
+use crate::verify::ResolveVerifyError;
+
+/// Class of an error.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
+#[display(lowercase)]
+pub enum ErrorClass {
+    /// Data are well-formed, but do not pass the validation (for instance
+    /// commitment is absent or does not match the data).
+    ValidationFailure,
+
+    /// Failure which may be resolved by retrying the operation later (for
+    /// instance network or data source failure).
+    Transient,
+
+    /// Provided data are malformed or inconsistent.
+    MalformedInput,
+}
+
+/// Classification of the library errors.
+///
+/// All implementations match error variants exhaustively, such that adding a
+/// new variant requires its classification.
+pub trait ErrorKindExt {
+    /// Returns class of the error.
+    fn error_class(&self) -> ErrorClass;
+
+    /// Returns numeric error code, which is stable across library releases.
+    fn error_code(&self) -> u16;
+
+    /// Detects whether the error is a failure of the validation of
+    /// well-formed data.
+    #[inline]
+    fn is_validation_failure(&self) -> bool {
+        self.error_class() == ErrorClass::ValidationFailure
+    }
+
+    /// Detects whether the operation may succeed if retried later.
+    #[inline]
+    fn is_transient(&self) -> bool {
+        self.error_class() == ErrorClass::Transient
+    }
+
+    /// Detects whether the error is caused by malformed input data.
+    #[inline]
+    fn is_malformed_input(&self) -> bool {
+        self.error_class() == ErrorClass::MalformedInput
+    }
+
+    /// Returns kind of [`io::Error`] corresponding to the error class.
+    #[inline]
+    fn io_error_kind(&self) -> io::ErrorKind {
+        match self.error_class() {
+            ErrorClass::ValidationFailure | ErrorClass::MalformedInput => {
+                io::ErrorKind::InvalidData
+            }
+            ErrorClass::Transient => io::ErrorKind::Other,
+        }
+    }
+}
+
+impl ErrorKindExt for TapretError {
+    fn error_class(&self) -> ErrorClass {
+        match self {
+            TapretError::TreeEmbedding(_) => ErrorClass::MalformedInput,
+            TapretError::NoTaprootOutput | TapretError::UntweakedOutput => {
+                ErrorClass::ValidationFailure
+            }
+        }
+    }
+
+    fn error_code(&self) -> u16 {
+        match self {
+            TapretError::TreeEmbedding(_) => 100,
+            TapretError::NoTaprootOutput => 101,
+            TapretError::UntweakedOutput => 102,
+        }
+    }
+}
+
+impl ErrorKindExt for OpretError {
+    fn error_class(&self) -> ErrorClass {
+        match self {
+            OpretError::InvalidCommitmentSize(_) => ErrorClass::MalformedInput,
+        }
+    }
+
+    fn error_code(&self) -> u16 {
+        match self {
+            OpretError::InvalidCommitmentSize(_) => 110,
+        }
+    }
+}
+
+impl ErrorKindExt for VerifyError {
+    fn error_class(&self) -> ErrorClass {
+        match self {
+            VerifyError::Tapret(err) => err.error_class(),
+            VerifyError::Opret(err) => err.error_class(),
+            VerifyError::Lnpbp4UnrelatedProtocol => {
+                ErrorClass::ValidationFailure
+            }
+            VerifyError::UnknownProofType(_)
+            | VerifyError::WitnessMismatch { .. }
+            | VerifyError::InvalidMerklePath(_) => ErrorClass::MalformedInput,
+            VerifyError::Policy(err) => err.error_class(),
+            VerifyError::WitnessNotFinal { .. } => ErrorClass::Transient,
+            VerifyError::MerkleRootMismatch { .. }
+            | VerifyError::WitnessNotIncluded(_) => {
+                ErrorClass::ValidationFailure
+            }
+        }
+    }
+
+    fn error_code(&self) -> u16 {
+        match self {
+            VerifyError::Tapret(err) => err.error_code(),
+            VerifyError::Opret(err) => err.error_code(),
+            VerifyError::Lnpbp4UnrelatedProtocol => 120,
+            VerifyError::UnknownProofType(_) => 121,
+            VerifyError::WitnessMismatch { .. } => 122,
+            VerifyError::Policy(err) => err.error_code(),
+            VerifyError::WitnessNotFinal { .. } => 123,
+            VerifyError::MerkleRootMismatch { .. } => 124,
+            VerifyError::WitnessNotIncluded(_) => 125,
+            VerifyError::InvalidMerklePath(_) => 126,
+        }
+    }
+}
+
+impl ErrorKindExt for PolicyError {
+    fn error_class(&self) -> ErrorClass {
+        match self {
+            PolicyError::TxWeightExceeded { .. }
+            | PolicyError::TxOutputsExceeded { .. }
+            | PolicyError::AnchorLeavesExceeded { .. }
+            | PolicyError::TapretDepthExceeded { .. } => {
+                ErrorClass::ValidationFailure
+            }
+            PolicyError::InsufficientConfirmations { .. } => {
+                ErrorClass::Transient
+            }
+        }
+    }
+
+    fn error_code(&self) -> u16 {
+        match self {
+            PolicyError::TxWeightExceeded { .. } => 130,
+            PolicyError::AnchorLeavesExceeded { .. } => 131,
+            PolicyError::TapretDepthExceeded { .. } => 132,
+            PolicyError::InsufficientConfirmations { .. } => 133,
+            PolicyError::TxOutputsExceeded { .. } => 134,
+        }
+    }
+}
+
+impl ErrorKindExt for ResolveVerifyError {
+    fn error_class(&self) -> ErrorClass {
+        match self {
+            ResolveVerifyError::Verify(err) => err.error_class(),
+            ResolveVerifyError::Resolver(_)
+            | ResolveVerifyError::Cancelled { .. }
+            | ResolveVerifyError::AnchorNotFound(_)
+            | ResolveVerifyError::Store(_) => ErrorClass::Transient,
+        }
+    }
+
+    fn error_code(&self) -> u16 {
+        match self {
+            ResolveVerifyError::Verify(err) => err.error_code(),
+            ResolveVerifyError::Resolver(_) => 140,
+            ResolveVerifyError::Cancelled { .. } => 141,
+            ResolveVerifyError::AnchorNotFound(_) => 142,
+            ResolveVerifyError::Store(_) => 143,
+        }
+    }
+}
+
+impl ErrorKindExt for RecoverError {
+    fn error_class(&self) -> ErrorClass {
+        match self {
+            RecoverError::Verify(err) => err.error_class(),
+            RecoverError::TxidMismatch { .. }
+            | RecoverError::Lnpbp4(_)
+            | RecoverError::EntropyUnsupported => ErrorClass::MalformedInput,
+            RecoverError::NoHostOutput | RecoverError::CommitmentMismatch => {
+                ErrorClass::ValidationFailure
+            }
+        }
+    }
+
+    fn error_code(&self) -> u16 {
+        match self {
+            RecoverError::Verify(err) => err.error_code(),
+            RecoverError::TxidMismatch { .. } => 150,
+            RecoverError::NoHostOutput => 151,
+            RecoverError::Lnpbp4(_) => 152,
+            RecoverError::CommitmentMismatch => 153,
+            RecoverError::EntropyUnsupported => 154,
+        }
+    }
+}
+
+impl ErrorKindExt for AnchorChainError {
+    fn error_class(&self) -> ErrorClass {
+        match self {
+            AnchorChainError::Verify(err) => err.error_class(),
+            AnchorChainError::Resolver(_) => ErrorClass::Transient,
+            AnchorChainError::LengthMismatch { .. } => {
+                ErrorClass::MalformedInput
+            }
+            AnchorChainError::LinkageViolation { .. } => {
+                ErrorClass::ValidationFailure
+            }
+        }
+    }
+
+    fn error_code(&self) -> u16 {
+        match self {
+            AnchorChainError::Verify(err) => err.error_code(),
+            AnchorChainError::Resolver(_) => 160,
+            AnchorChainError::LengthMismatch { .. } => 161,
+            AnchorChainError::LinkageViolation { .. } => 162,
+        }
+    }
+}
+
+impl From<VerifyError> for io::Error {
+    #[inline]
+    fn from(err: VerifyError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+#[cfg(feature = "wallet")]
+impl ErrorKindExt for PsbtCommitError {
+    fn error_class(&self) -> ErrorClass {
+        match self {
+            PsbtCommitError::InternalKeyMismatch
+            | PsbtCommitError::TreeTooDeep { .. } => {
+                ErrorClass::ValidationFailure
+            }
+            PsbtCommitError::SourceError(_)
+            | PsbtCommitError::CommitmentImpossible
+            | PsbtCommitError::PsbtLnpbp4(_)
+            | PsbtCommitError::TapretLnpbp4(_)
+            | PsbtCommitError::OpretLnpbp4(_)
+            | PsbtCommitError::Lnpbp4(_)
+            | PsbtCommitError::Opret(_)
+            | PsbtCommitError::ProprietaryKey(_)
+            | PsbtCommitError::NoTaprootOutput
+            | PsbtCommitError::InternalKeyMissed
+            | PsbtCommitError::TapretPathInvalid
+            | PsbtCommitError::TapretPathMissed
+            | PsbtCommitError::TapTreeError
+            | PsbtCommitError::NoMessages
+            | PsbtCommitError::HostNotFirst { .. }
+            | PsbtCommitError::MalformedPsbt { .. }
+            | PsbtCommitError::MessagesOnNonHostOutput { .. }
+            | PsbtCommitError::InvalidHost(_)
+            | PsbtCommitError::AmbiguousHosts { .. }
+            | PsbtCommitError::EmptyPsbt
+            | PsbtCommitError::OpretSoleOutput(_)
+            | PsbtCommitError::MessageConflict { .. }
+            | PsbtCommitError::MinDepthConflict { .. }
+            | PsbtCommitError::EntropyUnsupported => ErrorClass::MalformedInput,
+        }
+    }
+
+    fn error_code(&self) -> u16 {
+        match self {
+            PsbtCommitError::SourceError(_) => 200,
+            PsbtCommitError::CommitmentImpossible => 201,
+            PsbtCommitError::PsbtLnpbp4(_) => 202,
+            PsbtCommitError::TapretLnpbp4(_) => 203,
+            PsbtCommitError::OpretLnpbp4(_) => 204,
+            PsbtCommitError::Lnpbp4(_) => 205,
+            PsbtCommitError::Opret(_) => 206,
+            PsbtCommitError::ProprietaryKey(_) => 207,
+            PsbtCommitError::NoTaprootOutput => 208,
+            PsbtCommitError::InternalKeyMissed => 209,
+            PsbtCommitError::InternalKeyMismatch => 210,
+            PsbtCommitError::TapretPathInvalid => 211,
+            PsbtCommitError::TapretPathMissed => 212,
+            PsbtCommitError::TapTreeError => 213,
+            PsbtCommitError::NoMessages => 214,
+            PsbtCommitError::HostNotFirst { .. } => 215,
+            PsbtCommitError::MalformedPsbt { .. } => 216,
+            PsbtCommitError::MessagesOnNonHostOutput { .. } => 217,
+            PsbtCommitError::InvalidHost(_) => 218,
+            PsbtCommitError::AmbiguousHosts { .. } => 219,
+            PsbtCommitError::EmptyPsbt => 220,
+            PsbtCommitError::TreeTooDeep { .. } => 221,
+            PsbtCommitError::OpretSoleOutput(_) => 222,
+            PsbtCommitError::MessageConflict { .. } => 223,
+            PsbtCommitError::MinDepthConflict { .. } => 224,
+            PsbtCommitError::EntropyUnsupported => 225,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::tapret::TapretTreeError;
+
+    fn check<E: ErrorKindExt>(errors: Vec<(E, ErrorClass, u16)>) {
+        let mut codes = BTreeSet::new();
+        for (err, class, code) in errors {
+            assert_eq!(err.error_class(), class);
+            assert_eq!(err.error_code(), code);
+            assert!(codes.insert(code), "duplicate error code {}", code);
+            assert_eq!(
+                [
+                    err.is_validation_failure(),
+                    err.is_transient(),
+                    err.is_malformed_input()
+                ]
+                .into_iter()
+                .filter(|flag| *flag)
+                .count(),
+                1
+            );
+        }
+    }
+
+    #[test]
+    fn verify_error_classes() {
+        use bitcoin::hashes::Hash;
+        use bitcoin::util::merkleblock::MerkleBlockError;
+        use bitcoin::{TxMerkleNode, Txid};
+
+        check(vec![
+            (
+                VerifyError::Tapret(TapretError::TreeEmbedding(
+                    TapretTreeError::MaxDepthExceeded,
+                )),
+                ErrorClass::MalformedInput,
+                100,
+            ),
+            (
+                VerifyError::Tapret(TapretError::NoTaprootOutput),
+                ErrorClass::ValidationFailure,
+                101,
+            ),
+            (
+                VerifyError::Tapret(TapretError::UntweakedOutput),
+                ErrorClass::ValidationFailure,
+                102,
+            ),
+            (
+                VerifyError::Opret(OpretError::InvalidCommitmentSize(0)),
+                ErrorClass::MalformedInput,
+                110,
+            ),
+            (
+                VerifyError::Lnpbp4UnrelatedProtocol,
+                ErrorClass::ValidationFailure,
+                120,
+            ),
+            (
+                VerifyError::UnknownProofType(2),
+                ErrorClass::MalformedInput,
+                121,
+            ),
+            (
+                VerifyError::WitnessMismatch {
+                    expected: Txid::all_zeros(),
+                    actual: Txid::all_zeros(),
+                },
+                ErrorClass::MalformedInput,
+                122,
+            ),
+            (
+                VerifyError::WitnessNotFinal { lock_time: 1 },
+                ErrorClass::Transient,
+                123,
+            ),
+            (
+                VerifyError::MerkleRootMismatch {
+                    expected: TxMerkleNode::all_zeros(),
+                    actual: TxMerkleNode::all_zeros(),
+                },
+                ErrorClass::ValidationFailure,
+                124,
+            ),
+            (
+                VerifyError::WitnessNotIncluded(Txid::all_zeros()),
+                ErrorClass::ValidationFailure,
+                125,
+            ),
+            (
+                VerifyError::InvalidMerklePath(
+                    MerkleBlockError::NoTransactions,
+                ),
+                ErrorClass::MalformedInput,
+                126,
+            ),
+            (
+                VerifyError::Policy(PolicyError::TxWeightExceeded {
+                    weight: 2,
+                    max: 1,
+                }),
+                ErrorClass::ValidationFailure,
+                130,
+            ),
+            (
+                VerifyError::Policy(PolicyError::AnchorLeavesExceeded {
+                    leaves: 2,
+                    max: 1,
+                }),
+                ErrorClass::ValidationFailure,
+                131,
+            ),
+            (
+                VerifyError::Policy(PolicyError::TapretDepthExceeded {
+                    depth: 1,
+                    max: 0,
+                }),
+                ErrorClass::ValidationFailure,
+                132,
+            ),
+            (
+                VerifyError::Policy(PolicyError::InsufficientConfirmations {
+                    confirmations: 0,
+                    min: 1,
+                }),
+                ErrorClass::Transient,
+                133,
+            ),
+            (
+                VerifyError::Policy(PolicyError::TxOutputsExceeded {
+                    outputs: 2,
+                    max: 1,
+                }),
+                ErrorClass::ValidationFailure,
+                134,
+            ),
+        ]);
+
+        let err = io::Error::from(VerifyError::Lnpbp4UnrelatedProtocol);
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err
+            .get_ref()
+            .and_then(|err| err.downcast_ref::<VerifyError>())
+            .is_some());
+    }
+
+    #[test]
+    fn resolve_verify_error_classes() {
+        use bitcoin::hashes::Hash;
+        use bitcoin::Txid;
+        use bitcoin_onchain::TxResolverError;
+
+        check(vec![
+            (
+                ResolveVerifyError::Verify(VerifyError::UnknownProofType(0xFF)),
+                ErrorClass::MalformedInput,
+                121,
+            ),
+            (
+                ResolveVerifyError::Resolver(TxResolverError::with(
+                    Txid::all_zeros(),
+                )),
+                ErrorClass::Transient,
+                140,
+            ),
+            (
+                ResolveVerifyError::Cancelled { completed: 1 },
+                ErrorClass::Transient,
+                141,
+            ),
+            (
+                ResolveVerifyError::AnchorNotFound(crate::AnchorId::zero()),
+                ErrorClass::Transient,
+                142,
+            ),
+            (
+                ResolveVerifyError::Store(Box::new(std::fmt::Error)),
+                ErrorClass::Transient,
+                143,
+            ),
+        ]);
+    }
+
+    #[test]
+    fn recover_error_classes() {
+        use bitcoin::hashes::Hash;
+        use bitcoin::Txid;
+        use commit_verify::lnpbp4;
+
+        check(vec![
+            (
+                RecoverError::TxidMismatch {
+                    expected: Txid::all_zeros(),
+                    actual: Txid::all_zeros(),
+                },
+                ErrorClass::MalformedInput,
+                150,
+            ),
+            (
+                RecoverError::NoHostOutput,
+                ErrorClass::ValidationFailure,
+                151,
+            ),
+            (
+                RecoverError::Lnpbp4(lnpbp4::Error::CantFitInMaxSlots),
+                ErrorClass::MalformedInput,
+                152,
+            ),
+            (
+                RecoverError::CommitmentMismatch,
+                ErrorClass::ValidationFailure,
+                153,
+            ),
+            (
+                RecoverError::EntropyUnsupported,
+                ErrorClass::MalformedInput,
+                154,
+            ),
+            (
+                RecoverError::Verify(VerifyError::UnknownProofType(0xFF)),
+                ErrorClass::MalformedInput,
+                121,
+            ),
+        ]);
+    }
+
+    #[test]
+    fn anchor_chain_error_classes() {
+        use bitcoin::hashes::Hash;
+        use bitcoin::{OutPoint, Txid};
+        use bitcoin_onchain::TxResolverError;
+
+        check(vec![
+            (
+                AnchorChainError::Resolver(TxResolverError::with(
+                    Txid::all_zeros(),
+                )),
+                ErrorClass::Transient,
+                160,
+            ),
+            (
+                AnchorChainError::LengthMismatch {
+                    anchors: 2,
+                    messages: 1,
+                },
+                ErrorClass::MalformedInput,
+                161,
+            ),
+            (
+                AnchorChainError::LinkageViolation {
+                    index: 1,
+                    outpoint: OutPoint::null(),
+                    spender: Txid::all_zeros(),
+                },
+                ErrorClass::ValidationFailure,
+                162,
+            ),
+            (
+                AnchorChainError::Verify(VerifyError::Lnpbp4UnrelatedProtocol),
+                ErrorClass::ValidationFailure,
+                120,
+            ),
+        ]);
+    }
+
+    #[test]
+    #[cfg(feature = "wallet")]
+    fn psbt_commit_error_classes() {
+        use bitcoin_scripts::taproot::MaxDepthExceeded;
+        use commit_verify::lnpbp4;
+        use psbt::commit::{Lnpbp4KeyError, OpretKeyError, TapretKeyError};
+
+        use crate::proprietary::KeyError;
+        use crate::test_utils::protocol_fixture;
+
+        check(vec![
+            (
+                PsbtCommitError::SourceError(MaxDepthExceeded.into()),
+                ErrorClass::MalformedInput,
+                200,
+            ),
+            (
+                PsbtCommitError::CommitmentImpossible,
+                ErrorClass::MalformedInput,
+                201,
+            ),
+            (
+                PsbtCommitError::PsbtLnpbp4(Lnpbp4KeyError::InvalidKeyValue),
+                ErrorClass::MalformedInput,
+                202,
+            ),
+            (
+                PsbtCommitError::TapretLnpbp4(TapretKeyError::InvalidKeyValue),
+                ErrorClass::MalformedInput,
+                203,
+            ),
+            (
+                PsbtCommitError::OpretLnpbp4(OpretKeyError::NonOpReturnOutput),
+                ErrorClass::MalformedInput,
+                204,
+            ),
+            (
+                PsbtCommitError::Lnpbp4(lnpbp4::Error::Empty),
+                ErrorClass::MalformedInput,
+                205,
+            ),
+            (
+                PsbtCommitError::Opret(OpretError::InvalidCommitmentSize(0)),
+                ErrorClass::MalformedInput,
+                206,
+            ),
+            (
+                PsbtCommitError::ProprietaryKey(KeyError::Oversized {
+                    key: psbt::ProprietaryKey {
+                        prefix: vec![],
+                        subtype: 0,
+                        key: vec![],
+                    },
+                    len: 1,
+                    max: 0,
+                }),
+                ErrorClass::MalformedInput,
+                207,
+            ),
+            (
+                PsbtCommitError::NoTaprootOutput,
+                ErrorClass::MalformedInput,
+                208,
+            ),
+            (
+                PsbtCommitError::InternalKeyMissed,
+                ErrorClass::MalformedInput,
+                209,
+            ),
+            (
+                PsbtCommitError::InternalKeyMismatch,
+                ErrorClass::ValidationFailure,
+                210,
+            ),
+            (
+                PsbtCommitError::TapretPathInvalid,
+                ErrorClass::MalformedInput,
+                211,
+            ),
+            (
+                PsbtCommitError::TapretPathMissed,
+                ErrorClass::MalformedInput,
+                212,
+            ),
+            (
+                PsbtCommitError::TapTreeError,
+                ErrorClass::MalformedInput,
+                213,
+            ),
+            (PsbtCommitError::NoMessages, ErrorClass::MalformedInput, 214),
+            (
+                PsbtCommitError::HostNotFirst { host: 1, first: 0 },
+                ErrorClass::MalformedInput,
+                215,
+            ),
+            (
+                PsbtCommitError::MalformedPsbt {
+                    outputs: 1,
+                    tx_outputs: 2,
+                },
+                ErrorClass::MalformedInput,
+                216,
+            ),
+            (
+                PsbtCommitError::MessagesOnNonHostOutput { vouts: vec![1] },
+                ErrorClass::MalformedInput,
+                217,
+            ),
+            (
+                PsbtCommitError::InvalidHost(2),
+                ErrorClass::MalformedInput,
+                218,
+            ),
+            (
+                PsbtCommitError::AmbiguousHosts {
+                    tapret_vout: 0,
+                    opret_vout: 1,
+                },
+                ErrorClass::MalformedInput,
+                219,
+            ),
+            (PsbtCommitError::EmptyPsbt, ErrorClass::MalformedInput, 220),
+            (
+                PsbtCommitError::TreeTooDeep { depth: 9, max: 8 },
+                ErrorClass::ValidationFailure,
+                221,
+            ),
+            (
+                PsbtCommitError::OpretSoleOutput(0),
+                ErrorClass::MalformedInput,
+                222,
+            ),
+            (
+                PsbtCommitError::MessageConflict {
+                    vout: 1,
+                    protocol_id: protocol_fixture(0).0,
+                },
+                ErrorClass::MalformedInput,
+                223,
+            ),
+            (
+                PsbtCommitError::MinDepthConflict {
+                    vout: 1,
+                    present: 3,
+                    supplied: 5,
+                },
+                ErrorClass::MalformedInput,
+                224,
+            ),
+            (
+                PsbtCommitError::EntropyUnsupported,
+                ErrorClass::MalformedInput,
+                225,
+            ),
+        ]);
+    }
+}
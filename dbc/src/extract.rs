@@ -0,0 +1,237 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Extraction of the finalized witness transaction from a PSBT, checking its
+//! consistency with the anchor produced for the PSBT.
+
+use amplify::Wrapper;
+use bitcoin::{Script, Transaction, Txid};
+use commit_verify::convolve_commit::ConvolveCommitVerify;
+use commit_verify::{lnpbp4, ConsensusCommit};
+use psbt::Psbt;
+
+use crate::anchor::VerifyError;
+use crate::method::CloseMethod;
+use crate::opret::{opret_script, OpretError};
+use crate::tapret::TapretError;
+use crate::{Anchor, PreparedTx, Proof};
+
+/// Errors extracting witness transaction from a PSBT.
+#[derive(Clone, PartialEq, Eq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum ExtractTxError {
+    /// PSBT input #{0} is not finalized.
+    NotFinalized(usize),
+
+    /// transaction id {actual} does not match anchor witness transaction id
+    /// {expected}; probably the anchor was created for a different revision
+    /// of the PSBT.
+    TxidMismatch {
+        /// Witness transaction id from the anchor.
+        expected: Txid,
+
+        /// Id of the transaction extracted from the PSBT.
+        actual: Txid,
+    },
+
+    /// transaction has no output which may host {0} commitment.
+    NoHostOutput(CloseMethod),
+
+    /// scriptPubkey of the host output #{vout} does not match the commitment
+    /// from the anchor.
+    HostScriptMismatch {
+        /// Number of the host output.
+        vout: usize,
+
+        /// scriptPubkey expected by the anchor.
+        expected: Script,
+
+        /// Actual scriptPubkey of the output.
+        actual: Script,
+    },
+
+    /// taproot output #{0} does not match the original scriptPubkey of the
+    /// tapret host output, making tapret commitment ambiguous.
+    AmbiguousTaprootOutput(usize),
+
+    /// unable to compute commitment from the anchor – {0}
+    #[from]
+    #[from(TapretError)]
+    #[from(OpretError)]
+    Anchor(VerifyError),
+}
+
+/// Extracts finalized witness transaction from the PSBT, checking that it
+/// matches the `anchor` created for the PSBT.
+///
+/// Checks that all PSBT inputs are finalized, that the transaction id matches
+/// the anchor witness transaction id and that the output hosting the
+/// commitment contains the commitment from the anchor. Intended to be the last
+/// check before broadcasting the transaction.
+pub fn extract_witness_tx(
+    psbt: &Psbt,
+    anchor: &Anchor<lnpbp4::MerkleBlock>,
+) -> Result<Transaction, ExtractTxError> {
+    if let Some(index) = psbt.inputs.iter().position(|input| {
+        input.final_script_sig.is_none() && input.final_script_witness.is_none()
+    }) {
+        return Err(ExtractTxError::NotFinalized(index));
+    }
+
+    let tx = PreparedTx::new(psbt.extract_signed_tx());
+    if tx.txid() != anchor.txid() {
+        return Err(ExtractTxError::TxidMismatch {
+            expected: anchor.txid(),
+            actual: tx.txid(),
+        });
+    }
+
+    let commitment = anchor.lnpbp4_proof().consensus_commit();
+    let (host_outputs, method, expected) = match anchor.dbc_proof() {
+        Proof::OpretFirst | Proof::OpretFirstAt(_) => (
+            tx.op_return_outputs(),
+            CloseMethod::OpretFirst,
+            opret_script(&commitment[..])?,
+        ),
+        Proof::TapretFirst(proof) => {
+            let (output_key, _) = proof
+                .internal_key
+                .convolve_commit(&proof.path_proof, &commitment)
+                .map_err(TapretError::from)?;
+            (
+                tx.p2tr_outputs(),
+                CloseMethod::TapretFirst,
+                Script::new_v1_p2tr_tweaked(output_key),
+            )
+        }
+    };
+
+    let vout = *host_outputs
+        .first()
+        .ok_or(ExtractTxError::NoHostOutput(method))?;
+    if let Proof::OpretFirstAt(proof) = anchor.dbc_proof() {
+        let expected_vout = proof.vout as usize;
+        if vout != expected_vout {
+            return Err(ExtractTxError::HostScriptMismatch {
+                vout: expected_vout,
+                expected,
+                actual: tx
+                    .as_tx()
+                    .output
+                    .get(expected_vout)
+                    .map(|txout| txout.script_pubkey.clone())
+                    .unwrap_or_default(),
+            });
+        }
+    }
+    let actual = &tx.as_tx().output[vout].script_pubkey;
+    if *actual != expected {
+        return Err(ExtractTxError::HostScriptMismatch {
+            vout,
+            expected,
+            actual: actual.clone(),
+        });
+    }
+
+    if let Proof::TapretFirst(proof) = anchor.dbc_proof() {
+        let original = proof.original_pubkey_script();
+        if let Some(vout) = host_outputs[1..].iter().copied().find(|vout| {
+            tx.as_tx().output[*vout].script_pubkey != *original.as_inner()
+        }) {
+            return Err(ExtractTxError::AmbiguousTaprootOutput(vout));
+        }
+    }
+
+    Ok(tx.into_tx())
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::Witness;
+
+    use super::*;
+    use crate::test_utils::psbt_fixture;
+
+    fn finalize(psbt: &mut Psbt) {
+        for input in &mut psbt.inputs {
+            input.final_script_witness = Some(Witness::from_vec(vec![vec![1]]));
+        }
+    }
+
+    #[test]
+    fn extract_committed() {
+        for method in [CloseMethod::TapretFirst, CloseMethod::OpretFirst] {
+            let mut psbt = psbt_fixture(method);
+            let anchor = Anchor::commit_static(&mut psbt).unwrap();
+            assert_eq!(
+                extract_witness_tx(&psbt, &anchor),
+                Err(ExtractTxError::NotFinalized(0))
+            );
+
+            finalize(&mut psbt);
+            let tx = extract_witness_tx(&psbt, &anchor).unwrap();
+            assert_eq!(tx.txid(), anchor.txid());
+            assert!(!tx.input[0].witness.is_empty());
+        }
+    }
+
+    #[test]
+    fn stale_anchor() {
+        for (host, method) in
+            [(0, CloseMethod::TapretFirst), (1, CloseMethod::OpretFirst)]
+        {
+            let mut psbt = psbt_fixture(method);
+            let anchor = Anchor::commit_static(&mut psbt).unwrap();
+            finalize(&mut psbt);
+
+            // Tampered output changes transaction id
+            let mut tampered = psbt.clone();
+            tampered.outputs[0].amount += 1;
+            assert_eq!(
+                extract_witness_tx(&tampered, &anchor),
+                Err(ExtractTxError::TxidMismatch {
+                    expected: anchor.txid(),
+                    actual: tampered.to_txid()
+                })
+            );
+
+            // Anchor from a different commitment claiming the same txid
+            let mut stale = psbt_fixture(method);
+            stale.outputs[0].amount += 1;
+            let stale_anchor = Anchor::commit_with_entropy(&mut stale, 0x5eed)
+                .unwrap()
+                .with_txid(anchor.txid());
+            assert!(matches!(
+                extract_witness_tx(&psbt, &stale_anchor),
+                Err(ExtractTxError::HostScriptMismatch { vout, .. })
+                    if vout == host
+            ));
+        }
+    }
+
+    #[test]
+    fn missing_host() {
+        let mut psbt = psbt_fixture(CloseMethod::TapretFirst);
+        psbt.outputs.remove(1);
+        let anchor = Anchor::commit_static(&mut psbt)
+            .unwrap()
+            .with_dbc_proof(Proof::OpretFirst);
+        finalize(&mut psbt);
+        assert_eq!(
+            extract_witness_tx(&psbt, &anchor),
+            Err(ExtractTxError::NoHostOutput(CloseMethod::OpretFirst))
+        );
+    }
+}
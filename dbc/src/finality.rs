@@ -0,0 +1,278 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Finality of witness transactions with respect to a reference time.
+//!
+//! Witness transaction with lock time in the future, or with relative lock
+//! times (BIP-68) not yet expired, can't be included into the blockchain, and
+//! anchors committed by it should not be treated as settled. Validators may
+//! check finality with [`WitnessFinality`] or request the check during the
+//! anchor verification with [`ValidationPolicy::reference_time`].
+//!
+//! [`ValidationPolicy::reference_time`]: crate::ValidationPolicy::reference_time
+
+use bitcoin::Transaction;
+
+use crate::anchor::VerifyError;
+
+/// Lock time values below the threshold are interpreted as block heights,
+/// while values starting from it are interpreted as UNIX timestamps.
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+/// Sequence flag disabling relative lock time of the input (BIP-68).
+pub const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+
+/// Sequence flag switching relative lock time of the input from blocks to
+/// 512-second intervals (BIP-68).
+pub const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+
+/// Bit mask of the relative lock time value within the input sequence.
+pub const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000_FFFF;
+
+/// Binary logarithm of the relative lock time interval in seconds.
+const SEQUENCE_LOCKTIME_GRANULARITY: u32 = 9;
+
+/// Chain context the witness transaction finality is evaluated against.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+pub struct ReferenceTime {
+    /// Height of the block which would include the witness transaction, i.e.
+    /// height of the chain tip plus one.
+    pub height: u32,
+
+    /// Median time past of the chain tip.
+    pub mtp: u32,
+}
+
+impl ReferenceTime {
+    /// Constructs reference time for the next block after the chain tip at
+    /// `tip_height` with median time past `mtp`.
+    #[inline]
+    pub fn after_tip(tip_height: u32, mtp: u32) -> ReferenceTime {
+        ReferenceTime {
+            height: tip_height.saturating_add(1),
+            mtp,
+        }
+    }
+
+    /// Checks that the lock time of `tx` does not prevent its inclusion into
+    /// the block at the reference time. Relative lock times are not checked,
+    /// since they depend on confirmations of the spent outputs.
+    ///
+    /// # Errors
+    ///
+    /// [`VerifyError::WitnessNotFinal`] if the transaction is not final.
+    pub fn check_final(&self, tx: &Transaction) -> Result<(), VerifyError> {
+        if tx.is_final(self.height, self.mtp) {
+            Ok(())
+        } else {
+            Err(VerifyError::WitnessNotFinal {
+                lock_time: tx.lock_time.0,
+            })
+        }
+    }
+}
+
+/// Evaluation of the witness transaction finality.
+pub trait WitnessFinality {
+    /// Detects whether the lock time of the transaction allows its inclusion
+    /// into the block at `height` with previous blocks having median time
+    /// past `mtp`, following the consensus rules.
+    ///
+    /// Lock time is not enforced if all the transaction inputs have final
+    /// (`0xFFFFFFFF`) sequences.
+    ///
+    /// ```
+    /// use bitcoin::{PackedLockTime, Sequence};
+    /// use dbc::finality::WitnessFinality;
+    /// use dbc::test_utils::opret_fixture;
+    ///
+    /// let (_, mut tx) = opret_fixture();
+    /// tx.lock_time = PackedLockTime(800_000);
+    /// // Lock time is ignored when all inputs have final sequences
+    /// assert!(tx.is_final(700_000, 0));
+    ///
+    /// tx.input[0].sequence = Sequence::ENABLE_LOCKTIME_NO_RBF;
+    /// assert!(!tx.is_final(800_000, 0));
+    /// assert!(tx.is_final(800_001, 0));
+    /// ```
+    fn is_final(&self, height: u32, mtp: u32) -> bool;
+
+    /// Detects whether relative lock times (BIP-68) of the transaction inputs
+    /// allow its inclusion into the block at `height` with previous blocks
+    /// having median time past `mtp`.
+    ///
+    /// `prevouts` provide, for each of the transaction inputs, height of the
+    /// block which includes the spent output and median time past of the
+    /// block preceding it. If the number of `prevouts` does not match the
+    /// number of inputs the finality can't be established and the method
+    /// returns `false`.
+    ///
+    /// ```
+    /// use bitcoin::Sequence;
+    /// use dbc::finality::WitnessFinality;
+    /// use dbc::test_utils::opret_fixture;
+    ///
+    /// let (_, mut tx) = opret_fixture();
+    /// // Spent output must have at least 10 confirmations
+    /// tx.input[0].sequence = Sequence::from_height(10);
+    /// assert!(!tx.is_sequence_final(800_009, 0, &[(800_000, 0)]));
+    /// assert!(tx.is_sequence_final(800_010, 0, &[(800_000, 0)]));
+    /// ```
+    fn is_sequence_final(
+        &self,
+        height: u32,
+        mtp: u32,
+        prevouts: &[(u32, u32)],
+    ) -> bool;
+}
+
+impl WitnessFinality for Transaction {
+    fn is_final(&self, height: u32, mtp: u32) -> bool {
+        let lock_time = self.lock_time.0;
+        if lock_time == 0 {
+            return true;
+        }
+        let reference = if lock_time < LOCKTIME_THRESHOLD {
+            height
+        } else {
+            mtp
+        };
+        lock_time < reference
+            || self.input.iter().all(|txin| txin.sequence.0 == u32::MAX)
+    }
+
+    fn is_sequence_final(
+        &self,
+        height: u32,
+        mtp: u32,
+        prevouts: &[(u32, u32)],
+    ) -> bool {
+        if prevouts.len() != self.input.len() {
+            return false;
+        }
+        // Relative lock times are enforced only since transaction version 2
+        if self.version < 2 {
+            return true;
+        }
+        // Last height and time at which the transaction is still invalid,
+        // with -1 meaning no restrictions
+        let mut min_height = -1i64;
+        let mut min_time = -1i64;
+        for (txin, (coin_height, coin_mtp)) in self.input.iter().zip(prevouts) {
+            let sequence = txin.sequence.0;
+            if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+                continue;
+            }
+            let value = (sequence & SEQUENCE_LOCKTIME_MASK) as i64;
+            if sequence & SEQUENCE_LOCKTIME_TYPE_FLAG != 0 {
+                let time = *coin_mtp as i64
+                    + (value << SEQUENCE_LOCKTIME_GRANULARITY)
+                    - 1;
+                min_time = min_time.max(time);
+            } else {
+                min_height = min_height.max(*coin_height as i64 + value - 1);
+            }
+        }
+        min_height < height as i64 && min_time < mtp as i64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::{PackedLockTime, Sequence, TxIn};
+
+    use super::*;
+    use crate::test_utils::opret_fixture;
+
+    fn tx_fixture(lock_time: u32, sequences: &[u32]) -> Transaction {
+        let (_, mut tx) = opret_fixture();
+        tx.lock_time = PackedLockTime(lock_time);
+        tx.input = sequences
+            .iter()
+            .map(|sequence| TxIn {
+                sequence: Sequence(*sequence),
+                ..default!()
+            })
+            .collect();
+        tx
+    }
+
+    #[test]
+    fn height_lock_time() {
+        let tx = tx_fixture(800_000, &[0xFFFF_FFFE]);
+        assert!(!tx.is_final(799_999, u32::MAX));
+        assert!(!tx.is_final(800_000, u32::MAX));
+        assert!(tx.is_final(800_001, 0));
+
+        // Zero lock time and final sequences disable the lock
+        assert!(tx_fixture(0, &[0]).is_final(0, 0));
+        assert!(tx_fixture(800_000, &[u32::MAX]).is_final(0, 0));
+        // A single non-final sequence enables the lock
+        assert!(!tx_fixture(800_000, &[u32::MAX, 0]).is_final(0, 0));
+
+        let reference = ReferenceTime::after_tip(799_999, 0);
+        assert!(matches!(
+            reference.check_final(&tx),
+            Err(VerifyError::WitnessNotFinal { lock_time: 800_000 })
+        ));
+        assert!(ReferenceTime::after_tip(800_000, 0)
+            .check_final(&tx)
+            .is_ok());
+    }
+
+    #[test]
+    fn time_lock_time() {
+        let lock_time = 1_700_000_000;
+        let tx = tx_fixture(lock_time, &[0]);
+        // Time-based lock time is compared with the median time past only
+        assert!(!tx.is_final(u32::MAX, lock_time - 1));
+        assert!(!tx.is_final(u32::MAX, lock_time));
+        assert!(tx.is_final(0, lock_time + 1));
+
+        let tx = tx_fixture(LOCKTIME_THRESHOLD, &[0]);
+        assert!(!tx.is_final(u32::MAX, LOCKTIME_THRESHOLD));
+        assert!(tx.is_final(0, LOCKTIME_THRESHOLD + 1));
+        let tx = tx_fixture(LOCKTIME_THRESHOLD - 1, &[0]);
+        assert!(tx.is_final(LOCKTIME_THRESHOLD, 0));
+    }
+
+    #[test]
+    fn sequence_locks() {
+        let blocks = Sequence::from_height(10).0;
+        let time = Sequence::from_512_second_intervals(2).0;
+        let tx = tx_fixture(0, &[blocks, time]);
+        let prevouts = [(100, 0), (50, 1_000)];
+
+        assert!(!tx.is_sequence_final(109, 2_024, &prevouts));
+        assert!(!tx.is_sequence_final(110, 2_023, &prevouts));
+        assert!(tx.is_sequence_final(110, 2_024, &prevouts));
+
+        // Disabled relative lock times and version 1 transactions
+        let tx = tx_fixture(0, &[blocks | SEQUENCE_LOCKTIME_DISABLE_FLAG]);
+        assert!(tx.is_sequence_final(0, 0, &[(100, 0)]));
+        let mut tx = tx_fixture(0, &[blocks]);
+        assert!(!tx.is_sequence_final(100, 0, &[(100, 0)]));
+        tx.version = 1;
+        assert!(tx.is_sequence_final(100, 0, &[(100, 0)]));
+
+        // Unknown spent outputs
+        assert!(!tx.is_sequence_final(u32::MAX, u32::MAX, &[]));
+    }
+}
@@ -0,0 +1,267 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Transaction outputs hosting deterministic bitcoin commitments.
+//!
+//! Tapret and opret commitments are embedded with the same code into PSBT
+//! outputs and raw transaction outputs, abstracted as [`CommitmentHost`], such
+//! that both ways of committing always produce the same result.
+
+use amplify::Wrapper;
+use bitcoin::psbt::TapTree;
+use bitcoin::{Script, TxOut, XOnlyPublicKey};
+use commit_verify::convolve_commit::ConvolveCommitVerify;
+use commit_verify::{lnpbp4, EmbedCommitVerify};
+
+use crate::consts::{TAPRET_CONSENSUS_MAX_PATH_DEPTH, TAPRET_MAX_PATH_DEPTH};
+use crate::method::CloseMethod;
+use crate::opret::{opret_script, OpretError};
+use crate::tapret::{PsbtCommitError, TapretProof, TapretSourceInfo};
+use crate::Proof;
+
+/// Transaction output which may host deterministic bitcoin commitment.
+pub trait CommitmentHost {
+    /// Returns scriptPubkey of the output.
+    fn script_pubkey(&self) -> &Script;
+
+    /// Replaces scriptPubkey of the output.
+    fn set_script_pubkey(&mut self, script_pubkey: Script);
+
+    /// Returns taproot internal key of the output, if known.
+    fn tap_internal_key(&self) -> Option<XOnlyPublicKey>;
+
+    /// Returns taproot script tree of the output, if any.
+    fn tap_tree(&self) -> Option<&TapTree>;
+
+    /// Replaces taproot script tree of the output.
+    fn set_tap_tree(&mut self, tap_tree: Option<TapTree>);
+}
+
+impl CommitmentHost for psbt::Output {
+    #[inline]
+    fn script_pubkey(&self) -> &Script { self.script.as_inner() }
+
+    #[inline]
+    fn set_script_pubkey(&mut self, script_pubkey: Script) {
+        self.script = script_pubkey.into();
+    }
+
+    #[inline]
+    fn tap_internal_key(&self) -> Option<XOnlyPublicKey> {
+        self.tap_internal_key
+    }
+
+    #[inline]
+    fn tap_tree(&self) -> Option<&TapTree> { self.tap_tree.as_ref() }
+
+    #[inline]
+    fn set_tap_tree(&mut self, tap_tree: Option<TapTree>) {
+        self.tap_tree = tap_tree;
+    }
+}
+
+/// Raw transaction output together with taproot data required for tapret
+/// commitments, which are not part of the output itself.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TxOutHost {
+    /// Transaction output.
+    pub txout: TxOut,
+
+    /// Taproot internal key of the output.
+    pub tap_internal_key: Option<XOnlyPublicKey>,
+
+    /// Taproot script tree of the output.
+    pub tap_tree: Option<TapTree>,
+}
+
+impl From<TxOut> for TxOutHost {
+    #[inline]
+    fn from(txout: TxOut) -> Self {
+        TxOutHost {
+            txout,
+            tap_internal_key: None,
+            tap_tree: None,
+        }
+    }
+}
+
+impl TxOutHost {
+    /// Constructs host from a taproot output with the given internal key and
+    /// script tree.
+    #[inline]
+    pub fn with_taproot(
+        txout: TxOut,
+        internal_key: XOnlyPublicKey,
+        tap_tree: Option<TapTree>,
+    ) -> TxOutHost {
+        TxOutHost {
+            txout,
+            tap_internal_key: Some(internal_key),
+            tap_tree,
+        }
+    }
+}
+
+impl CommitmentHost for TxOutHost {
+    #[inline]
+    fn script_pubkey(&self) -> &Script { &self.txout.script_pubkey }
+
+    #[inline]
+    fn set_script_pubkey(&mut self, script_pubkey: Script) {
+        self.txout.script_pubkey = script_pubkey;
+    }
+
+    #[inline]
+    fn tap_internal_key(&self) -> Option<XOnlyPublicKey> {
+        self.tap_internal_key
+    }
+
+    #[inline]
+    fn tap_tree(&self) -> Option<&TapTree> { self.tap_tree.as_ref() }
+
+    #[inline]
+    fn set_tap_tree(&mut self, tap_tree: Option<TapTree>) {
+        self.tap_tree = tap_tree;
+    }
+}
+
+/// Embeds opret commitment into the host output, replacing its scriptPubkey.
+pub fn embed_opret(
+    host: &mut impl CommitmentHost,
+    msg: &lnpbp4::CommitmentHash,
+) -> Result<(), OpretError> {
+    host.set_script_pubkey(opret_script(&msg[..])?);
+    Ok(())
+}
+
+/// Embeds tapret commitment into the taproot script tree of the host output,
+/// updating its tree and scriptPubkey.
+///
+/// Fails with [`PsbtCommitError::TreeTooDeep`] if the script tree would become
+/// deeper than [`TAPRET_MAX_PATH_DEPTH`]; use [`embed_tapret_with_max_depth`]
+/// to raise the limit.
+#[inline]
+pub fn embed_tapret(
+    host: &mut impl CommitmentHost,
+    msg: &lnpbp4::CommitmentHash,
+) -> Result<TapretProof, PsbtCommitError> {
+    embed_tapret_with_max_depth(host, msg, TAPRET_MAX_PATH_DEPTH)
+}
+
+/// Embeds tapret commitment into the taproot script tree of the host output
+/// like [`embed_tapret`], allowing the script tree with the commitment to be
+/// up to `max_depth` deep. Values above [`TAPRET_CONSENSUS_MAX_PATH_DEPTH`]
+/// are treated as the consensus limit.
+///
+/// The commitment is joined with the original script tree at its root, so the
+/// tree becomes one level deeper. The host is not modified if the depth limit
+/// is exceeded.
+///
+/// ```
+/// use bitcoin::psbt::TapTree;
+/// use bitcoin::util::taproot::TaprootBuilder;
+/// use bitcoin::{Script, TxOut};
+/// use commit_verify::ConsensusCommit;
+/// use dbc::host::{embed_tapret_with_max_depth, TxOutHost};
+/// use dbc::tapret::PsbtCommitError;
+/// use dbc::test_utils::{internal_key_fixture, lnpbp4_fixture};
+///
+/// // Script tree with leaves at depths 1 and 2
+/// let mut builder = TaprootBuilder::new();
+/// for (depth, script) in [(1, 0x51), (2, 0x52), (2, 0x53)] {
+///     builder = builder.add_leaf(depth, Script::from(vec![script])).unwrap();
+/// }
+/// let tap_tree = TapTree::try_from(builder).unwrap();
+/// let mut host = TxOutHost::with_taproot(
+///     TxOut::default(),
+///     internal_key_fixture(),
+///     Some(tap_tree),
+/// );
+///
+/// let msg = lnpbp4_fixture().consensus_commit();
+/// assert_eq!(
+///     embed_tapret_with_max_depth(&mut host.clone(), &msg, 2),
+///     Err(PsbtCommitError::TreeTooDeep { depth: 3, max: 2 })
+/// );
+/// assert!(embed_tapret_with_max_depth(&mut host, &msg, 3).is_ok());
+/// ```
+pub fn embed_tapret_with_max_depth(
+    host: &mut impl CommitmentHost,
+    msg: &lnpbp4::CommitmentHash,
+    max_depth: usize,
+) -> Result<TapretProof, PsbtCommitError> {
+    let internal_key = host
+        .tap_internal_key()
+        .ok_or(PsbtCommitError::InternalKeyMissed)?;
+
+    check_tapret_depth(host, max_depth)?;
+
+    let mut source =
+        TapretSourceInfo::<TapTree>::with(host.tap_tree().cloned())?;
+    let path_proof = source.embed_commit(msg)?;
+    host.set_tap_tree(source.into_tap_tree());
+
+    let (output_key, _) = internal_key
+        .convolve_commit(&path_proof, msg)
+        .map_err(|_| PsbtCommitError::TapTreeError)?;
+    host.set_script_pubkey(Script::new_v1_p2tr_tweaked(output_key));
+
+    Ok(TapretProof {
+        path_proof,
+        internal_key,
+    })
+}
+
+/// Checks that the script tree of the host stays within `max_depth` levels
+/// (capped by the consensus limit) once the tapret commitment is added.
+pub(crate) fn check_tapret_depth(
+    host: &impl CommitmentHost,
+    max_depth: usize,
+) -> Result<(), PsbtCommitError> {
+    let max = max_depth.min(TAPRET_CONSENSUS_MAX_PATH_DEPTH);
+    let depth = host
+        .tap_tree()
+        .and_then(|tree| {
+            tree.script_leaves()
+                .map(|leaf| leaf.depth() as usize + 1)
+                .max()
+        })
+        .unwrap_or_default();
+    if depth > max {
+        return Err(PsbtCommitError::TreeTooDeep { depth, max });
+    }
+    Ok(())
+}
+
+/// Embeds commitment into the host output with the given method.
+///
+/// Since the host does not know its index in the transaction, opret
+/// commitments produce [`Proof::OpretFirst`] proof; callers knowing the index
+/// should replace it with [`Proof::OpretFirstAt`].
+pub fn embed(
+    host: &mut impl CommitmentHost,
+    method: CloseMethod,
+    msg: &lnpbp4::CommitmentHash,
+) -> Result<Proof, PsbtCommitError> {
+    match method {
+        CloseMethod::OpretFirst => {
+            embed_opret(host, msg)?;
+            Ok(Proof::OpretFirst)
+        }
+        CloseMethod::TapretFirst => {
+            embed_tapret(host, msg).map(Proof::TapretFirst)
+        }
+    }
+}
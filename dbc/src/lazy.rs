@@ -0,0 +1,267 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Borrowed views of strict-encoded anchors.
+//!
+//! Indexers reading large numbers of anchors (for instance from a
+//! memory-mapped file) often need only the witness transaction id or the
+//! commitment method of each anchor. [`AnchorRef`] checks the strict encoding
+//! framing of an anchor without decoding its LNPBP-4 merkle block, and decodes
+//! the rest of the data only on request.
+//!
+//! Anchor strict encoding consists of:
+//! - witness transaction id (32 bytes);
+//! - LNPBP-4 merkle block: tree depth (`u8`), number of cross-section nodes
+//!   (`u16`), the nodes, each being either a concealed node (tag `0`, depth
+//!   `u8` and 32-byte hash) or a commitment leaf (tag `1`, 32-byte protocol id
+//!   and 32-byte message), and optional entropy (`u64`);
+//! - DBC proof, which starts with the commitment method byte.
+
+use std::io;
+use std::ops::Range;
+
+use bitcoin::hashes::Hash;
+use bitcoin::Txid;
+use commit_verify::{lnpbp4, CommitEncode, CommitVerify};
+use strict_encoding::StrictDecode;
+
+use crate::{Anchor, AnchorId, Proof};
+
+const TXID_LEN: usize = 32;
+const CONCEALED_NODE_LEN: usize = 1 + 32;
+const COMMITMENT_LEAF_LEN: usize = 32 + 32;
+
+/// Borrowed view of a strict-encoded `Anchor<lnpbp4::MerkleBlock>`.
+///
+/// Constructing the view with [`AnchorRef::parse`] accepts exactly the data
+/// accepted by the strict decoding of the anchor, while only the DBC proof
+/// (which is small) gets decoded.
+///
+/// ```
+/// use dbc::lazy::AnchorRef;
+/// use dbc::test_utils::tapret_fixture;
+/// use strict_encoding::StrictEncode;
+///
+/// let (anchor, _) = tapret_fixture();
+/// let data = anchor.strict_serialize().unwrap();
+///
+/// let view = AnchorRef::parse(&data).unwrap();
+/// assert_eq!(view.txid(), anchor.txid());
+/// assert_eq!(view.anchor_id(), anchor.anchor_id());
+/// assert_eq!(view.to_owned(), anchor);
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct AnchorRef<'a> {
+    data: &'a [u8],
+    proof_start: usize,
+}
+
+/// Returns range of the next `len` bytes of `data` after `pos`, advancing the
+/// position.
+fn advance(
+    data: &[u8],
+    pos: &mut usize,
+    len: usize,
+) -> Result<Range<usize>, strict_encoding::Error> {
+    let start = *pos;
+    match start.checked_add(len) {
+        Some(end) if end <= data.len() => {
+            *pos = end;
+            Ok(start..end)
+        }
+        _ => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+    }
+}
+
+impl<'a> AnchorRef<'a> {
+    /// Checks framing of the strict-encoded anchor `data` and constructs view
+    /// of it.
+    ///
+    /// # Errors
+    ///
+    /// If the `data` are not a valid strict encoding of an anchor, including
+    /// the case of trailing bytes after the encoded anchor.
+    pub fn parse(
+        data: &'a [u8],
+    ) -> Result<AnchorRef<'a>, strict_encoding::Error> {
+        let mut pos = 0usize;
+        advance(data, &mut pos, TXID_LEN)?;
+
+        // LNPBP-4 tree depth, followed by the cross-section length
+        advance(data, &mut pos, 1)?;
+        let len = &data[advance(data, &mut pos, 2)?];
+        let count = u16::from_le_bytes([len[0], len[1]]);
+        for _ in 0..count {
+            let tag = data[advance(data, &mut pos, 1)?.start];
+            let len = match tag {
+                0 => CONCEALED_NODE_LEN,
+                1 => COMMITMENT_LEAF_LEN,
+                tag => {
+                    return Err(strict_encoding::Error::EnumValueNotKnown(
+                        "TreeNode",
+                        tag as usize,
+                    ))
+                }
+            };
+            advance(data, &mut pos, len)?;
+        }
+        match data[advance(data, &mut pos, 1)?.start] {
+            0 => {}
+            1 => {
+                advance(data, &mut pos, 8)?;
+            }
+            invalid => {
+                return Err(strict_encoding::Error::WrongOptionalEncoding(
+                    invalid,
+                ))
+            }
+        }
+
+        let proof_start = pos;
+        let mut proof = &data[proof_start..];
+        Proof::strict_decode(&mut proof)?;
+        if !proof.is_empty() {
+            return Err(strict_encoding::Error::DataNotEntirelyConsumed);
+        }
+
+        Ok(AnchorRef { data, proof_start })
+    }
+
+    /// Returns the strict-encoded anchor data.
+    #[inline]
+    pub fn as_bytes(&self) -> &'a [u8] { self.data }
+
+    /// Returns id of the anchor witness transaction.
+    #[inline]
+    pub fn txid(&self) -> Txid {
+        Txid::from_slice(&self.data[..TXID_LEN]).expect("fixed-size slice")
+    }
+
+    /// Returns commitment method of the anchor DBC proof: `0x00` for legacy
+    /// opret, `0x02` for opret with the output index and `0x01` for tapret
+    /// commitments.
+    #[inline]
+    pub fn method(&self) -> u8 { self.data[self.proof_start] }
+
+    /// Computes id of the anchor.
+    ///
+    /// The witness transaction id and the DBC proof enter the commit encoding
+    /// as they are, while the LNPBP-4 merkle root requires decoding of the
+    /// merkle block cross-section.
+    pub fn anchor_id(&self) -> AnchorId {
+        let block = lnpbp4::MerkleBlock::strict_deserialize(
+            &self.data[TXID_LEN..self.proof_start],
+        )
+        .expect("anchor framing is checked during parsing");
+        let mut encoding =
+            Vec::with_capacity(self.data.len() - self.proof_start + 64);
+        encoding.extend_from_slice(&self.data[..TXID_LEN]);
+        encoding.extend_from_slice(&self.data[self.proof_start..]);
+        block.commit_encode(&mut encoding);
+        AnchorId::commit(&encoding)
+    }
+
+    /// Decodes the anchor.
+    pub fn to_owned(&self) -> Anchor<lnpbp4::MerkleBlock> {
+        Anchor::strict_deserialize(self.data)
+            .expect("anchor framing is checked during parsing")
+    }
+}
+
+impl<'a> From<AnchorRef<'a>> for Anchor<lnpbp4::MerkleBlock> {
+    #[inline]
+    fn from(view: AnchorRef<'a>) -> Self { view.to_owned() }
+}
+
+#[cfg(test)]
+mod test {
+    use commit_verify::ConsensusCommit;
+    use strict_encoding::StrictEncode;
+
+    use super::*;
+    use crate::test_utils::{opret_fixture, protocol_fixture, tapret_fixture};
+
+    fn anchor_fixtures() -> Vec<Anchor<lnpbp4::MerkleBlock>> {
+        let (protocol_id, _) = protocol_fixture(1);
+        [opret_fixture(), tapret_fixture()]
+            .into_iter()
+            .flat_map(|(anchor, _)| {
+                let mut concealed = anchor.clone();
+                concealed.conceal_except([protocol_id]).unwrap();
+                [anchor, concealed]
+            })
+            .collect()
+    }
+
+    /// Checks that the view accepts the `data` if and only if the strict
+    /// decoding accepts them, and that both produce the same anchor.
+    fn check_equivalence(data: &[u8]) -> bool {
+        let decoded = strict_encoding::strict_deserialize::<
+            Anchor<lnpbp4::MerkleBlock>,
+        >(data);
+        let view = AnchorRef::parse(data);
+        assert_eq!(view.is_ok(), decoded.is_ok(), "{:?}", data);
+        let (view, decoded) = match (view, decoded) {
+            (Ok(view), Ok(decoded)) => (view, decoded),
+            _ => return false,
+        };
+        assert_eq!(view.txid(), decoded.txid());
+        assert_eq!(view.to_owned(), decoded);
+        let method = decoded.dbc_proof().strict_serialize().unwrap()[0];
+        assert_eq!(view.method(), method);
+        true
+    }
+
+    #[test]
+    fn equivalence() {
+        for anchor in anchor_fixtures() {
+            let data = anchor.strict_serialize().unwrap();
+            let view = AnchorRef::parse(&data).unwrap();
+            assert_eq!(view.as_bytes(), &data[..]);
+            assert_eq!(view.anchor_id(), anchor.anchor_id());
+            assert_eq!(view.anchor_id(), anchor.consensus_commit());
+            assert!(check_equivalence(&data));
+
+            let mut accepted = 0;
+            for len in 0..data.len() {
+                assert!(!check_equivalence(&data[..len]));
+            }
+            for pos in 0..data.len() {
+                for byte in [0x00, 0x01, 0x02, 0xFF] {
+                    let mut mutation = data.clone();
+                    mutation[pos] = byte;
+                    accepted += check_equivalence(&mutation) as usize;
+                }
+            }
+            for extra in [&[0u8][..], &[0x01, 0x00]] {
+                let mut mutation = data.clone();
+                mutation.extend(extra);
+                assert!(!check_equivalence(&mutation));
+            }
+            // Mutations of hashes, ids and messages are valid encodings
+            assert!(accepted > 0);
+        }
+    }
+
+    #[test]
+    fn method() {
+        let (opret, _) = opret_fixture();
+        let (tapret, _) = tapret_fixture();
+        let data = opret.strict_serialize().unwrap();
+        assert_eq!(AnchorRef::parse(&data).unwrap().method(), 0x00);
+        let data = tapret.strict_serialize().unwrap();
+        assert_eq!(AnchorRef::parse(&data).unwrap().method(), 0x01);
+    }
+}
@@ -27,8 +27,6 @@
 
 #[macro_use]
 extern crate amplify;
-#[cfg(feature = "miniscript")]
-extern crate miniscript_crate as miniscript;
 #[cfg(feature = "serde")]
 #[macro_use]
 extern crate serde_crate as serde;
@@ -36,9 +34,132 @@ extern crate serde_crate as serde;
 extern crate strict_encoding;
 
 pub mod anchor;
+pub mod armor;
+pub mod attest;
+#[cfg(feature = "bitcoin-psbt")]
+pub mod bitcoin_psbt;
+pub mod canonical;
+pub mod chain;
+#[cfg(feature = "wallet")]
+pub mod commit;
+#[cfg(feature = "wallet")]
+pub mod commit_log;
+pub mod consts;
+pub mod error_kind;
+#[cfg(feature = "wallet")]
+pub mod extract;
+pub mod finality;
+#[cfg(feature = "wallet")]
+pub mod host;
 pub mod keytweak;
+pub mod lazy;
+pub mod method;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod opret;
+pub mod pack;
+pub mod policy;
+pub mod prepared;
+#[cfg(feature = "wallet")]
+pub mod proprietary;
+pub mod protocol;
+pub mod report;
+pub mod scan;
+pub mod shared;
 pub mod sigtweak;
+pub mod static_commit;
+pub mod store;
 pub mod tapret;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+pub mod tracker;
+#[cfg(feature = "test-utils")]
+pub mod vectors;
+pub mod verify;
+pub mod witness;
+#[cfg(feature = "wallet")]
+pub mod workflow;
 
-pub use anchor::{Anchor, AnchorId, Proof};
+pub use anchor::{
+    Anchor, AnchorCommitmentId, AnchorHexError, AnchorId, AnchorIdParseError,
+    Proof, ProofExtractError, ProofOrUnknown, ShortAnchorId,
+    VerificationOutcome, VersionedAnchorError, WitnessId,
+};
+pub use armor::AnchorArmorError;
+pub use attest::AttestedAnchor;
+pub use canonical::StrictDecodeCanonical;
+pub use chain::{AnchorChain, AnchorChainError};
+#[cfg(feature = "wallet")]
+pub use commit::{
+    CommitOptions, CommitmentInfo, Entropy, ForeignMessages, HostSelection,
+    PsbtAnchorExt,
+};
+#[cfg(feature = "wallet")]
+pub use commit_log::{CommitEvent, CommitLog};
+pub use error_kind::{ErrorClass, ErrorKindExt};
+#[cfg(feature = "wallet")]
+pub use extract::{extract_witness_tx, ExtractTxError};
+pub use finality::{ReferenceTime, WitnessFinality};
+#[cfg(feature = "wallet")]
+pub use host::{CommitmentHost, TxOutHost};
+pub use lazy::AnchorRef;
+pub use method::{CloseMethod, MethodParseError};
+pub use pack::AnchorPack;
+pub use policy::ValidationPolicy;
+pub use prepared::PreparedTx;
+pub use protocol::{Msg, ProtoId, ProtocolDomain};
+pub use report::{BatchSummary, ItemResult};
+pub use scan::{
+    scan_block, BlockScan, BlockScanHit, ScanCandidate, ScanOutcome,
+};
+pub use shared::SharedMerkleBlock;
+pub use static_commit::{EmbedCommitProofStatic, EmbedCommitVerifyStatic};
+pub use store::{AnchorStore, InsertOutcome, MemAnchorStore};
+pub use tracker::{AnchorEvent, AnchorTracker, ResolveTxStatus, TxStatus};
+pub use verify::DbcVerify;
+pub use witness::WitnessStore;
+#[cfg(feature = "wallet")]
+pub use workflow::{Committed, DbcPsbt, Uncommitted};
+
+/// Compile tests of the `deterministic` feature, which removes all APIs using
+/// OS entropy, leaving only the ones with static or caller-provided entropy:
+///
+/// ```
+/// use commit_verify::lnpbp4::MerkleBlock;
+/// use dbc::workflow::{DbcPsbt, Uncommitted};
+/// use dbc::Anchor;
+///
+/// let _ = Anchor::<MerkleBlock>::commit_with_entropy;
+/// let _ = Anchor::<MerkleBlock>::commit_static;
+/// let _ = Anchor::<MerkleBlock>::commit_with;
+/// let _ = dbc::commit::Entropy::Static;
+/// let _ = DbcPsbt::<Uncommitted>::commit_with_entropy;
+/// ```
+///
+/// ```compile_fail
+/// use commit_verify::lnpbp4::MerkleBlock;
+/// use dbc::Anchor;
+///
+/// let _ = Anchor::<MerkleBlock>::commit;
+/// ```
+///
+/// ```compile_fail
+/// let _ = dbc::commit::Entropy::Random;
+/// ```
+///
+/// ```compile_fail
+/// use dbc::workflow::{DbcPsbt, Uncommitted};
+///
+/// let _ = DbcPsbt::<Uncommitted>::commit;
+/// ```
+///
+/// ```compile_fail
+/// use commit_verify::EmbedCommitVerify;
+/// use dbc::anchor::PsbtEmbeddedMessage;
+///
+/// fn commit(psbt: &mut psbt::Psbt) {
+///     let _ = psbt.embed_commit(&PsbtEmbeddedMessage);
+/// }
+/// ```
+#[cfg(all(doctest, feature = "deterministic", feature = "wallet"))]
+pub struct DeterministicBuild;
@@ -0,0 +1,125 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Methods of the deterministic bitcoin commitments, which are the methods of
+//! closing bitcoin single-use-seals.
+
+use std::str::FromStr;
+
+/// wrong transaction output-based single-use-seal closing method id '{0}'.
+#[derive(Clone, PartialEq, Eq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub struct MethodParseError(pub String);
+
+/// Method of single-use-seal closing, i.e. the method of the deterministic
+/// bitcoin commitment in the witness transaction.
+///
+/// Allows storing the method independently of the commitment proof; the method
+/// of the proof is returned by [`crate::Proof::method`].
+///
+/// ```
+/// use dbc::CloseMethod;
+///
+/// assert_eq!(CloseMethod::TapretFirst.to_string(), "tapret1st");
+/// assert_eq!("opret1st".parse(), Ok(CloseMethod::OpretFirst));
+/// assert!("opret".parse::<CloseMethod>().is_err());
+/// ```
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+#[derive(StrictEncode, StrictDecode)]
+#[strict_encoding(by_value)]
+#[repr(u8)]
+#[non_exhaustive]
+pub enum CloseMethod {
+    /// Seal is closed over the message in form of OP_RETURN commitment present
+    /// in the first OP_RETURN-containing transaction output.
+    #[display("opret1st")]
+    OpretFirst = 0x00,
+
+    /// Seal is closed over the message in form of Taproot-based OP_RETURN
+    /// commitment present in the first Taproot transaction output.
+    #[display("tapret1st")]
+    TapretFirst = 0x01,
+}
+
+impl FromStr for CloseMethod {
+    type Err = MethodParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase() {
+            s if s == CloseMethod::OpretFirst.to_string() => {
+                CloseMethod::OpretFirst
+            }
+            s if s == CloseMethod::TapretFirst.to_string() => {
+                CloseMethod::TapretFirst
+            }
+            _ => return Err(MethodParseError(s.to_owned())),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use strict_encoding::{StrictDecode, StrictEncode};
+
+    use super::*;
+
+    #[test]
+    fn parse() {
+        for (method, strings) in [
+            (CloseMethod::OpretFirst, [
+                "opret1st", "OPRET1ST", "Opret1st",
+            ]),
+            (CloseMethod::TapretFirst, [
+                "tapret1st",
+                "TAPRET1ST",
+                "TapRet1st",
+            ]),
+        ] {
+            for s in strings {
+                assert_eq!(CloseMethod::from_str(s), Ok(method));
+            }
+            assert_eq!(CloseMethod::from_str(&method.to_string()), Ok(method));
+        }
+
+        for s in ["", "opret", "tapret1", "tapret1st ", "opret_first"] {
+            assert_eq!(
+                CloseMethod::from_str(s),
+                Err(MethodParseError(s.to_owned()))
+            );
+        }
+        assert_eq!(
+            MethodParseError(s!("opret")).to_string(),
+            "wrong transaction output-based single-use-seal closing method id \
+             'opret'."
+        );
+    }
+
+    #[test]
+    fn encoding() {
+        for (method, byte) in [
+            (CloseMethod::OpretFirst, 0x00),
+            (CloseMethod::TapretFirst, 0x01),
+        ] {
+            assert_eq!(method.strict_serialize().unwrap(), vec![byte]);
+            assert_eq!(CloseMethod::strict_deserialize([byte]), Ok(method));
+        }
+        assert!(CloseMethod::strict_deserialize([0x02]).is_err());
+    }
+}
@@ -0,0 +1,444 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Process-wide verification statistics, available with `metrics` feature.
+//!
+//! Counters are updated by [`Proof`] and [`Anchor`](crate::Anchor)
+//! verification methods, by [`resolve_verify_anchor`] and by the seal
+//! verification in `bp-seals` (if its `metrics` feature is enabled). Without
+//! the feature the module and all the counting code are compiled out.
+//!
+//! Counters are relaxed atomics: a [`snapshot`] taken while verifications are
+//! running in other threads may not be consistent between different
+//! counters.
+//!
+//! [`resolve_verify_anchor`]: crate::verify::resolve_verify_anchor
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::anchor::VerifyError;
+use crate::Proof;
+
+#[cfg(test)]
+thread_local! {
+    /// Whether the current thread records the metrics. Tests enable recording
+    /// only for the threads checking the counters, such that verifications
+    /// done by concurrently running tests do not affect them.
+    static RECORDING: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Outcome of a verification.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
+#[display(lowercase)]
+pub enum Outcome {
+    /// Verification succeeded.
+    Valid,
+
+    /// Data are well-formed, but do not pass the verification.
+    Invalid,
+
+    /// Verification failed with an error.
+    Error,
+}
+
+impl<E> From<&Result<bool, E>> for Outcome {
+    fn from(result: &Result<bool, E>) -> Self {
+        match result {
+            Ok(true) => Outcome::Valid,
+            Ok(false) => Outcome::Invalid,
+            Err(_) => Outcome::Error,
+        }
+    }
+}
+
+/// Number of verifications by their outcome.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Outcomes {
+    /// Number of successful verifications.
+    pub valid: u64,
+
+    /// Number of verifications of well-formed data not passing the checks.
+    pub invalid: u64,
+
+    /// Number of verifications failed with an error.
+    pub errors: u64,
+}
+
+impl Outcomes {
+    /// Returns total number of verifications.
+    #[inline]
+    pub fn total(&self) -> u64 { self.valid + self.invalid + self.errors }
+}
+
+/// Number of verification errors by their reason.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Failures {
+    /// Tapret commitment verification errors.
+    pub tapret: u64,
+
+    /// Opret commitment verification errors.
+    pub opret: u64,
+
+    /// Anchors not committing to the protocol being verified.
+    pub lnpbp4: u64,
+
+    /// Proofs of types unknown to the library.
+    pub unknown_proof: u64,
+
+    /// Anchors rejected by a validation policy; these are not counted as
+    /// anchor verifications.
+    pub policy: u64,
+
+    /// Anchors verified against a transaction other than their witness
+    /// transaction or with a witness transaction not proven to be included
+    /// into a block; these are not counted as anchor verifications.
+    pub witness: u64,
+
+    /// Failures retrieving witness transactions.
+    pub resolver: u64,
+}
+
+/// Snapshot of the verification statistics.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Metrics {
+    /// Verifications of opret DBC proofs.
+    pub opret: Outcomes,
+
+    /// Verifications of tapret DBC proofs.
+    pub tapret: Outcomes,
+
+    /// Verifications of anchors.
+    pub anchors: Outcomes,
+
+    /// Verifications of seal closings.
+    pub seals: Outcomes,
+
+    /// Verification errors by their reason.
+    pub failures: Failures,
+
+    /// Total time spent in anchor verification.
+    pub anchor_time: Duration,
+}
+
+impl Metrics {
+    /// Returns average time of anchor verification, or `None` if no anchors
+    /// were verified.
+    pub fn average_anchor_time(&self) -> Option<Duration> {
+        let total = self.anchors.total();
+        if total == 0 {
+            return None;
+        }
+        Some(Duration::from_nanos(
+            (self.anchor_time.as_nanos() / total as u128) as u64,
+        ))
+    }
+}
+
+struct OutcomeCounters {
+    valid: AtomicU64,
+    invalid: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl OutcomeCounters {
+    const fn new() -> Self {
+        OutcomeCounters {
+            valid: AtomicU64::new(0),
+            invalid: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, outcome: Outcome) {
+        let counter = match outcome {
+            Outcome::Valid => &self.valid,
+            Outcome::Invalid => &self.invalid,
+            Outcome::Error => &self.errors,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> Outcomes {
+        Outcomes {
+            valid: self.valid.load(Ordering::Relaxed),
+            invalid: self.invalid.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+        }
+    }
+
+    fn reset(&self) {
+        self.valid.store(0, Ordering::Relaxed);
+        self.invalid.store(0, Ordering::Relaxed);
+        self.errors.store(0, Ordering::Relaxed);
+    }
+}
+
+static OPRET: OutcomeCounters = OutcomeCounters::new();
+static TAPRET: OutcomeCounters = OutcomeCounters::new();
+static ANCHORS: OutcomeCounters = OutcomeCounters::new();
+static SEALS: OutcomeCounters = OutcomeCounters::new();
+
+static FAILED_TAPRET: AtomicU64 = AtomicU64::new(0);
+static FAILED_OPRET: AtomicU64 = AtomicU64::new(0);
+static FAILED_LNPBP4: AtomicU64 = AtomicU64::new(0);
+static FAILED_UNKNOWN_PROOF: AtomicU64 = AtomicU64::new(0);
+static FAILED_POLICY: AtomicU64 = AtomicU64::new(0);
+static FAILED_WITNESS: AtomicU64 = AtomicU64::new(0);
+static FAILED_RESOLVER: AtomicU64 = AtomicU64::new(0);
+
+static ANCHOR_NANOS: AtomicU64 = AtomicU64::new(0);
+
+/// Returns snapshot of the current values of the counters.
+pub fn snapshot() -> Metrics {
+    Metrics {
+        opret: OPRET.snapshot(),
+        tapret: TAPRET.snapshot(),
+        anchors: ANCHORS.snapshot(),
+        seals: SEALS.snapshot(),
+        failures: Failures {
+            tapret: FAILED_TAPRET.load(Ordering::Relaxed),
+            opret: FAILED_OPRET.load(Ordering::Relaxed),
+            lnpbp4: FAILED_LNPBP4.load(Ordering::Relaxed),
+            unknown_proof: FAILED_UNKNOWN_PROOF.load(Ordering::Relaxed),
+            policy: FAILED_POLICY.load(Ordering::Relaxed),
+            witness: FAILED_WITNESS.load(Ordering::Relaxed),
+            resolver: FAILED_RESOLVER.load(Ordering::Relaxed),
+        },
+        anchor_time: Duration::from_nanos(ANCHOR_NANOS.load(Ordering::Relaxed)),
+    }
+}
+
+/// Resets all counters to zero.
+pub fn reset() {
+    for counters in [&OPRET, &TAPRET, &ANCHORS, &SEALS] {
+        counters.reset();
+    }
+    for counter in [
+        &FAILED_TAPRET,
+        &FAILED_OPRET,
+        &FAILED_LNPBP4,
+        &FAILED_UNKNOWN_PROOF,
+        &FAILED_POLICY,
+        &FAILED_WITNESS,
+        &FAILED_RESOLVER,
+        &ANCHOR_NANOS,
+    ] {
+        counter.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Records outcome of a seal closing verification. Used by the seal
+/// implementations.
+pub fn record_seal(outcome: Outcome) {
+    if recording() {
+        SEALS.record(outcome);
+    }
+}
+
+#[cfg(not(test))]
+#[inline]
+fn recording() -> bool { true }
+
+#[cfg(test)]
+fn recording() -> bool { RECORDING.with(|recording| recording.get()) }
+
+pub(crate) fn record_proof(proof: &Proof, result: &Result<bool, VerifyError>) {
+    if !recording() {
+        return;
+    }
+    match proof {
+        Proof::OpretFirst | Proof::OpretFirstAt(_) => {
+            OPRET.record(result.into())
+        }
+        Proof::TapretFirst(_) => TAPRET.record(result.into()),
+    }
+    if let Err(err) = result {
+        record_failure(err);
+    }
+}
+
+/// Records anchor verification started at `started`. Errors of DBC proofs
+/// are counted by [`record_proof`], so only LNPBP-4 errors are counted here.
+pub(crate) fn record_anchor(
+    started: Instant,
+    result: &Result<bool, VerifyError>,
+) {
+    if !recording() {
+        return;
+    }
+    ANCHORS.record(result.into());
+    let nanos = u64::try_from(started.elapsed().as_nanos()).unwrap_or(u64::MAX);
+    ANCHOR_NANOS.fetch_add(nanos, Ordering::Relaxed);
+    if let Err(err @ VerifyError::Lnpbp4UnrelatedProtocol) = result {
+        record_failure(err);
+    }
+}
+
+pub(crate) fn record_failure(err: &VerifyError) {
+    if !recording() {
+        return;
+    }
+    let counter = match err {
+        VerifyError::Tapret(_) => &FAILED_TAPRET,
+        VerifyError::Opret(_) => &FAILED_OPRET,
+        VerifyError::Lnpbp4UnrelatedProtocol => &FAILED_LNPBP4,
+        VerifyError::UnknownProofType(_) => &FAILED_UNKNOWN_PROOF,
+        VerifyError::Policy(_) | VerifyError::WitnessNotFinal { .. } => {
+            &FAILED_POLICY
+        }
+        VerifyError::WitnessMismatch { .. }
+        | VerifyError::MerkleRootMismatch { .. }
+        | VerifyError::WitnessNotIncluded(_)
+        | VerifyError::InvalidMerklePath(_) => &FAILED_WITNESS,
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn record_resolver_failure() {
+    if recording() {
+        FAILED_RESOLVER.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use bitcoin::{Transaction, Txid};
+    use commit_verify::lnpbp4::{Message, ProtocolId};
+
+    use super::*;
+    use crate::test_utils::{
+        opret_fixture, protocol_fixture, tapret_fixture, tx_fixture,
+    };
+    use crate::verify::{resolve_verify_anchor, DbcVerify};
+    use crate::{Anchor, ProofOrUnknown, ValidationPolicy};
+
+    struct Item(ProtocolId, Message);
+
+    impl DbcVerify for Item {
+        fn protocol_id(&self) -> ProtocolId { self.0 }
+        fn message(&self) -> Message { self.1 }
+    }
+
+    #[test]
+    fn counters() {
+        RECORDING.with(|recording| recording.set(true));
+        reset();
+        assert_eq!(snapshot(), Metrics::default());
+        assert_eq!(snapshot().average_anchor_time(), None);
+
+        let (protocol_id, message) = protocol_fixture(0);
+        let (_, other_message) = protocol_fixture(1);
+        let (unknown_protocol, _) = protocol_fixture(10);
+
+        let (opret, opret_tx) = opret_fixture();
+        let (tapret, tapret_tx) = tapret_fixture();
+        let opret = opret.into_merkle_proof(protocol_id).unwrap();
+        let tapret = tapret.into_merkle_proof(protocol_id).unwrap();
+
+        // 3 valid, 1 invalid and 1 failed opret anchors
+        for _ in 0..3 {
+            assert!(opret.verify(protocol_id, message, &opret_tx).unwrap());
+        }
+        assert!(!opret.verify(protocol_id, other_message, &opret_tx).unwrap());
+        assert!(opret.verify(unknown_protocol, message, &opret_tx).is_err());
+
+        // 1 valid tapret anchor and 2 failed on a transaction without taproot
+        // outputs
+        assert!(tapret.verify(protocol_id, message, &tapret_tx).unwrap());
+        for _ in 0..2 {
+            assert!(tapret
+                .verify(protocol_id, message, tx_fixture(vec![]))
+                .is_err());
+        }
+
+        // Rejected by the validation policy before the verification
+        let policy = ValidationPolicy {
+            max_tx_weight: Some(1),
+            ..default!()
+        };
+        assert!(opret
+            .verify_with_policy(protocol_id, message, &opret_tx, &policy)
+            .is_err());
+
+        // Unknown proof type
+        let unknown = Anchor::<_, ProofOrUnknown>::from_parts(
+            opret.txid(),
+            opret.lnpbp4_proof().clone(),
+            ProofOrUnknown::Unknown {
+                method: 0xFF,
+                payload: vec![],
+            },
+        );
+        assert!(unknown.verify(protocol_id, message, &opret_tx).is_err());
+
+        // Anchor verified in a batch against other witness transaction
+        assert!(Anchor::verify_batch(
+            [(protocol_id, message, &opret)],
+            &tapret_tx
+        )[0]
+        .outcome
+        .is_err());
+
+        // Failed witness retrieval
+        let resolver = BTreeMap::<Txid, Transaction>::new();
+        assert!(resolve_verify_anchor(
+            &Item(protocol_id, message),
+            &opret,
+            &resolver
+        )
+        .is_err());
+
+        record_seal(Outcome::Valid);
+        record_seal(Outcome::Error);
+
+        let metrics = snapshot();
+        assert_eq!(metrics.opret, Outcomes {
+            valid: 3,
+            invalid: 1,
+            errors: 0
+        });
+        assert_eq!(metrics.tapret, Outcomes {
+            valid: 1,
+            invalid: 0,
+            errors: 2
+        });
+        assert_eq!(metrics.anchors, Outcomes {
+            valid: 4,
+            invalid: 1,
+            errors: 4
+        });
+        assert_eq!(metrics.seals, Outcomes {
+            valid: 1,
+            invalid: 0,
+            errors: 1
+        });
+        assert_eq!(metrics.failures, Failures {
+            tapret: 2,
+            opret: 0,
+            lnpbp4: 1,
+            unknown_proof: 1,
+            policy: 1,
+            witness: 1,
+            resolver: 1,
+        });
+        assert!(metrics.average_anchor_time().is_some());
+
+        reset();
+        assert_eq!(snapshot(), Metrics::default());
+    }
+}
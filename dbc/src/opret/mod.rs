@@ -22,3 +22,77 @@
 //! **Convolve-commit:**
 //! d) `Tx, Amount, Msg -> Tx'`;
 //! e) `Psbt, Amount, Msg -> Psbt'`.
+
+use bitcoin::Script;
+
+use crate::consts::{COMMITMENT_LEN, OPRET_PAYLOAD_LEN};
+
+/// Size of the LNPBP-4 commitment embedded into `OP_RETURN` output.
+pub const OPRET_COMMITMENT_LEN: usize = COMMITMENT_LEN;
+
+/// Maximal size of data pushed into `OP_RETURN` output which is still relayed
+/// by the nodes with the default standardness policy.
+pub const OPRET_MAX_RELAY_LEN: usize = OPRET_PAYLOAD_LEN;
+
+/// Errors constructing opret commitment.
+#[derive(
+    Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, Error
+)]
+#[display(doc_comments)]
+pub enum OpretError {
+    /// opret commitment has size of {0} bytes, while it must be exactly 32
+    /// bytes long; the resulting OP_RETURN output may violate standard relay
+    /// policy.
+    InvalidCommitmentSize(usize),
+}
+
+/// Proof of the opret commitment, pointing to the output of the witness
+/// transaction which contains the commitment.
+///
+/// The output must be the first `OP_RETURN` output of the transaction.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct OpretProof {
+    /// Number of the witness transaction output with the commitment.
+    pub vout: u32,
+}
+
+/// Constructs `OP_RETURN` script pubkey for the opret commitment, checking that
+/// the commitment has the size of [`OPRET_COMMITMENT_LEN`] bytes.
+pub fn opret_script(commitment: &[u8]) -> Result<Script, OpretError> {
+    if commitment.len() != OPRET_COMMITMENT_LEN {
+        return Err(OpretError::InvalidCommitmentSize(commitment.len()));
+    }
+    Ok(Script::new_op_return(commitment))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn opret_script_size() {
+        let commitment = [0xA5u8; OPRET_COMMITMENT_LEN];
+        assert_eq!(
+            opret_script(&commitment).unwrap(),
+            Script::new_op_return(&commitment)
+        );
+        assert_eq!(
+            opret_script(&[0xA5u8; OPRET_COMMITMENT_LEN + 1]),
+            Err(OpretError::InvalidCommitmentSize(OPRET_COMMITMENT_LEN + 1))
+        );
+        assert_eq!(
+            opret_script(&[0xA5u8; OPRET_MAX_RELAY_LEN + 1]),
+            Err(OpretError::InvalidCommitmentSize(OPRET_MAX_RELAY_LEN + 1))
+        );
+        assert_eq!(
+            opret_script(&[]),
+            Err(OpretError::InvalidCommitmentSize(0))
+        );
+    }
+}
@@ -0,0 +1,417 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Anchor packs: single-buffer containers for long-term storage of multiple
+//! anchors.
+//!
+//! Pack is strict-encoded as:
+//! - version (`u16`), equal to [`PACK_VERSION`];
+//! - number of anchors (`u32`);
+//! - index entries, ordered by anchor id, each consisting of anchor id, offset
+//!   (`u32`) and length (`u32`) of the strict-encoded anchor within the pack
+//!   data and the first 4 bytes of SHA256 hash of the encoded anchor;
+//! - length of the pack data (`u32`);
+//! - pack data: concatenated strict-encoded anchors.
+//!
+//! Anchors are decoded only when requested with [`AnchorPack::get`]. The pack
+//! works with byte buffers only and does not access filesystem.
+
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+
+use amplify::Wrapper;
+use bitcoin::hashes::{sha256, Hash};
+use commit_verify::lnpbp4;
+use strict_encoding::{StrictDecode, StrictEncode};
+
+use crate::{Anchor, AnchorId};
+
+/// Current version of the anchor pack format.
+pub const PACK_VERSION: u16 = 1;
+
+/// Errors working with anchor packs.
+#[derive(Clone, PartialEq, Eq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum PackError {
+    /// anchor pack data are truncated.
+    Truncated,
+
+    /// anchor pack version {0} is not supported.
+    UnsupportedVersion(u16),
+
+    /// anchor pack contains anchor {0} more than once.
+    DuplicateId(AnchorId),
+
+    /// data of anchor {0} are outside of the anchor pack data.
+    EntryOutOfBounds(AnchorId),
+
+    /// data of anchor {0} are corrupted: checksum does not match.
+    ChecksumMismatch(AnchorId),
+
+    /// data of anchor {0} decode into an anchor with a different id.
+    IdMismatch(AnchorId),
+
+    /// anchor pack can't exceed 4 GiB.
+    Oversized,
+
+    /// anchor pack encoding error – {0}
+    Encoding(strict_encoding::Error),
+}
+
+impl From<strict_encoding::Error> for PackError {
+    fn from(err: strict_encoding::Error) -> Self {
+        match err {
+            strict_encoding::Error::Io(err)
+                if *err.as_inner() == io::ErrorKind::UnexpectedEof =>
+            {
+                PackError::Truncated
+            }
+            err => PackError::Encoding(err),
+        }
+    }
+}
+
+impl From<io::Error> for PackError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => PackError::Truncated,
+            _ => PackError::Encoding(err.into()),
+        }
+    }
+}
+
+impl From<PackError> for strict_encoding::Error {
+    fn from(err: PackError) -> Self {
+        match err {
+            PackError::Truncated => io::ErrorKind::UnexpectedEof.into(),
+            PackError::Encoding(err) => err,
+            err => strict_encoding::Error::DataIntegrityError(err.to_string()),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+struct PackEntry {
+    offset: u32,
+    len: u32,
+    checksum: [u8; 4],
+}
+
+impl PackEntry {
+    fn checksum(data: &[u8]) -> [u8; 4] {
+        let mut checksum = [0u8; 4];
+        checksum.copy_from_slice(&sha256::Hash::hash(data)[..4]);
+        checksum
+    }
+
+    /// Returns range of the entry data, or `None` if the range end does not
+    /// fit `usize` (which may happen on 32-bit targets for corrupted entries).
+    fn range(&self) -> Option<std::ops::Range<usize>> {
+        let start = usize::try_from(self.offset).ok()?;
+        let end = start.checked_add(usize::try_from(self.len).ok()?)?;
+        Some(start..end)
+    }
+}
+
+/// Container for long-term storage of multiple anchors with an index allowing
+/// to decode each of the anchors separately. See [module-level
+/// documentation](self) for the details.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct AnchorPack {
+    index: BTreeMap<AnchorId, PackEntry>,
+    data: Vec<u8>,
+}
+
+impl AnchorPack {
+    /// Constructs empty pack.
+    #[inline]
+    pub fn new() -> AnchorPack { AnchorPack::default() }
+
+    /// Constructs pack from its serialized representation. Only the pack
+    /// index is decoded; anchors are decoded on request with
+    /// [`AnchorPack::get`].
+    pub fn from_bytes(
+        bytes: impl AsRef<[u8]>,
+    ) -> Result<AnchorPack, PackError> {
+        let bytes = bytes.as_ref();
+        let mut cursor = io::Cursor::new(bytes);
+        let pack = AnchorPack::decode(&mut cursor)?;
+        if cursor.position() as usize != bytes.len() {
+            return Err(strict_encoding::Error::DataNotEntirelyConsumed.into());
+        }
+        Ok(pack)
+    }
+
+    /// Serializes the pack.
+    #[inline]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        strict_encoding::strict_serialize(self)
+            .expect("in-memory encoding of anchor pack")
+    }
+
+    /// Returns number of anchors in the pack.
+    #[inline]
+    pub fn len(&self) -> usize { self.index.len() }
+
+    /// Detects whether the pack contains no anchors.
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.index.is_empty() }
+
+    /// Checks whether the pack contains anchor with the given id.
+    #[inline]
+    pub fn contains(&self, id: &AnchorId) -> bool {
+        self.index.contains_key(id)
+    }
+
+    /// Returns iterator over ids of the anchors in the pack, ordered by the
+    /// id value.
+    #[inline]
+    pub fn iter_ids(&self) -> impl Iterator<Item = AnchorId> + '_ {
+        self.index.keys().copied()
+    }
+
+    /// Adds anchor to the pack, returning its id.
+    ///
+    /// # Errors
+    ///
+    /// If the pack already contains the anchor, or if the pack size exceeds
+    /// 4 GiB.
+    pub fn append(
+        &mut self,
+        anchor: &Anchor<lnpbp4::MerkleBlock>,
+    ) -> Result<AnchorId, PackError> {
+        let id = anchor.anchor_id();
+        if self.index.contains_key(&id) {
+            return Err(PackError::DuplicateId(id));
+        }
+        let data = anchor.strict_serialize()?;
+        let offset =
+            u32::try_from(self.data.len()).map_err(|_| PackError::Oversized)?;
+        let len =
+            u32::try_from(data.len()).map_err(|_| PackError::Oversized)?;
+        if offset.checked_add(len).is_none() {
+            return Err(PackError::Oversized);
+        }
+        self.index.insert(id, PackEntry {
+            offset,
+            len,
+            checksum: PackEntry::checksum(&data),
+        });
+        self.data.extend(data);
+        Ok(id)
+    }
+
+    /// Decodes anchor with the given id, if it is present in the pack.
+    ///
+    /// # Errors
+    ///
+    /// If the anchor data are corrupted.
+    pub fn get(
+        &self,
+        id: &AnchorId,
+    ) -> Result<Option<Anchor<lnpbp4::MerkleBlock>>, PackError> {
+        let entry = match self.index.get(id) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let data = entry
+            .range()
+            .and_then(|range| self.data.get(range))
+            .ok_or(PackError::EntryOutOfBounds(*id))?;
+        if PackEntry::checksum(data) != entry.checksum {
+            return Err(PackError::ChecksumMismatch(*id));
+        }
+        let anchor: Anchor<lnpbp4::MerkleBlock> =
+            strict_encoding::strict_deserialize(data)?;
+        if anchor.anchor_id() != *id {
+            return Err(PackError::IdMismatch(*id));
+        }
+        Ok(Some(anchor))
+    }
+
+    fn decode(mut d: impl Read) -> Result<AnchorPack, PackError> {
+        let version = u16::strict_decode(&mut d)?;
+        if version != PACK_VERSION {
+            return Err(PackError::UnsupportedVersion(version));
+        }
+        let count = u32::strict_decode(&mut d)?;
+        let mut index = BTreeMap::new();
+        for _ in 0..count {
+            let id = AnchorId::strict_decode(&mut d)?;
+            let entry = PackEntry::strict_decode(&mut d)?;
+            if index.insert(id, entry).is_some() {
+                return Err(PackError::DuplicateId(id));
+            }
+        }
+        let len = u32::strict_decode(&mut d)? as usize;
+        let mut data = Vec::new();
+        (&mut d).take(len as u64).read_to_end(&mut data)?;
+        if data.len() != len {
+            return Err(PackError::Truncated);
+        }
+        if let Some((id, _)) = index.iter().find(|(_, entry)| {
+            entry.range().map(|range| range.end > len).unwrap_or(true)
+        }) {
+            return Err(PackError::EntryOutOfBounds(*id));
+        }
+        Ok(AnchorPack { index, data })
+    }
+}
+
+impl StrictEncode for AnchorPack {
+    fn strict_encode<E: Write>(
+        &self,
+        mut e: E,
+    ) -> Result<usize, strict_encoding::Error> {
+        let mut len = PACK_VERSION.strict_encode(&mut e)?;
+        len += (self.index.len() as u32).strict_encode(&mut e)?;
+        for (id, entry) in &self.index {
+            len += id.strict_encode(&mut e)?;
+            len += entry.strict_encode(&mut e)?;
+        }
+        len += (self.data.len() as u32).strict_encode(&mut e)?;
+        e.write_all(&self.data)?;
+        Ok(len + self.data.len())
+    }
+}
+
+impl StrictDecode for AnchorPack {
+    #[inline]
+    fn strict_decode<D: Read>(d: D) -> Result<Self, strict_encoding::Error> {
+        AnchorPack::decode(d).map_err(strict_encoding::Error::from)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::lnpbp4_fixture_with;
+    use crate::Proof;
+
+    fn anchor_fixture(no: u8) -> Anchor<lnpbp4::MerkleBlock> {
+        Anchor::from_parts(
+            bitcoin::Txid::hash(&[no]),
+            lnpbp4::MerkleBlock::from(lnpbp4_fixture_with([no])),
+            Proof::OpretFirst,
+        )
+    }
+
+    fn pack_fixture() -> (AnchorPack, Vec<Anchor<lnpbp4::MerkleBlock>>) {
+        let anchors = (0u8..4).map(anchor_fixture).collect::<Vec<_>>();
+        let mut pack = AnchorPack::new();
+        for anchor in &anchors {
+            assert_eq!(pack.append(anchor).unwrap(), anchor.anchor_id());
+        }
+        (pack, anchors)
+    }
+
+    #[test]
+    fn id_lookup() {
+        let (pack, anchors) = pack_fixture();
+        assert_eq!(pack.len(), 4);
+
+        let pack = AnchorPack::from_bytes(pack.to_bytes()).unwrap();
+        for anchor in &anchors {
+            assert!(pack.contains(&anchor.anchor_id()));
+            assert_eq!(
+                pack.get(&anchor.anchor_id()).unwrap(),
+                Some(anchor.clone())
+            );
+        }
+        assert_eq!(pack.get(&anchor_fixture(5).anchor_id()), Ok(None));
+
+        let mut ids = anchors.iter().map(|a| a.anchor_id()).collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(pack.iter_ids().collect::<Vec<_>>(), ids);
+
+        let mut pack = pack;
+        assert_eq!(
+            pack.append(&anchors[0]),
+            Err(PackError::DuplicateId(anchors[0].anchor_id()))
+        );
+        assert_eq!(
+            AnchorPack::from_bytes(AnchorPack::new().to_bytes()),
+            Ok(AnchorPack::new())
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let (pack, _) = pack_fixture();
+        let bytes = pack.to_bytes();
+        for len in [0, 1, 5, 40, bytes.len() / 2, bytes.len() - 1] {
+            assert_eq!(
+                AnchorPack::from_bytes(&bytes[..len]),
+                Err(PackError::Truncated)
+            );
+        }
+        let mut extended = bytes;
+        extended.push(0);
+        assert!(AnchorPack::from_bytes(extended).is_err());
+    }
+
+    #[test]
+    fn corruption() {
+        let (pack, anchors) = pack_fixture();
+        let id = anchors[2].anchor_id();
+        let mut bytes = pack.to_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        let corrupted = AnchorPack::from_bytes(&bytes).unwrap();
+        let (last_id, _) = pack
+            .index
+            .iter()
+            .max_by_key(|(_, entry)| entry.offset)
+            .unwrap();
+        assert_eq!(
+            corrupted.get(last_id),
+            Err(PackError::ChecksumMismatch(*last_id))
+        );
+
+        let mut bytes = pack.to_bytes();
+        bytes[0] = 2;
+        assert_eq!(
+            AnchorPack::from_bytes(&bytes),
+            Err(PackError::UnsupportedVersion(2))
+        );
+
+        let mut swapped = pack.clone();
+        let entry = swapped.index[&anchors[0].anchor_id()];
+        swapped.index.insert(id, entry);
+        assert_eq!(swapped.get(&id), Err(PackError::IdMismatch(id)));
+
+        let mut out_of_bounds = pack;
+        out_of_bounds.index.get_mut(&id).unwrap().len = u32::MAX / 2;
+        assert_eq!(
+            AnchorPack::from_bytes(out_of_bounds.to_bytes()),
+            Err(PackError::EntryOutOfBounds(id))
+        );
+    }
+
+    #[test]
+    fn max_entry_range() {
+        let (mut pack, anchors) = pack_fixture();
+        let id = anchors[1].anchor_id();
+        let entry = pack.index.get_mut(&id).unwrap();
+        entry.offset = u32::MAX;
+        entry.len = u32::MAX;
+        assert_eq!(pack.get(&id), Err(PackError::EntryOutOfBounds(id)));
+        assert_eq!(
+            AnchorPack::from_bytes(pack.to_bytes()),
+            Err(PackError::EntryOutOfBounds(id))
+        );
+    }
+}
@@ -0,0 +1,335 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Validation policies limiting resources spent on the verification.
+//!
+//! Consensus rules do not limit witness transactions and proofs beyond the
+//! bitcoin limits, so validators exposed to untrusted data (for instance
+//! consignments received over the network) may reject data which are too
+//! costly to verify with a [`ValidationPolicy`].
+
+use bitcoin::Transaction;
+use commit_verify::lnpbp4;
+
+use crate::{Anchor, Proof, ReferenceTime};
+
+/// Errors of data not satisfying a [`ValidationPolicy`].
+#[derive(
+    Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, Error
+)]
+#[display(doc_comments)]
+pub enum PolicyError {
+    /// witness transaction weight {weight} exceeds policy limit of {max}.
+    TxWeightExceeded {
+        /// Weight of the witness transaction.
+        weight: usize,
+
+        /// Maximal weight allowed by the policy.
+        max: usize,
+    },
+
+    /// anchor LNPBP-4 tree has {leaves} leaves, exceeding policy limit of
+    /// {max}.
+    AnchorLeavesExceeded {
+        /// Number of leaves (i.e. width) of the LNPBP-4 tree.
+        leaves: usize,
+
+        /// Maximal number of leaves allowed by the policy.
+        max: usize,
+    },
+
+    /// tapret commitment has depth {depth} within the taproot script tree,
+    /// exceeding policy limit of {max}.
+    TapretDepthExceeded {
+        /// Depth of the tapret commitment leaf.
+        depth: u8,
+
+        /// Maximal depth allowed by the policy.
+        max: u8,
+    },
+
+    /// witness transaction has {confirmations} confirmations, while the policy
+    /// requires at least {min}. Hint: wait for more confirmations and retry.
+    InsufficientConfirmations {
+        /// Number of the witness transaction confirmations.
+        confirmations: u32,
+
+        /// Minimal number of confirmations required by the policy.
+        min: u32,
+    },
+
+    /// witness transaction has {outputs} outputs, exceeding policy limit of
+    /// {max}.
+    TxOutputsExceeded {
+        /// Number of the witness transaction outputs.
+        outputs: usize,
+
+        /// Maximal number of outputs allowed by the policy.
+        max: usize,
+    },
+}
+
+/// Limits applied by validators to the data before their verification.
+///
+/// Limits which are set to `None` are not checked; the default policy does not
+/// limit anything.
+///
+/// ```
+/// use dbc::policy::{PolicyError, ValidationPolicy};
+/// use dbc::test_utils::opret_fixture;
+///
+/// let (_, tx) = opret_fixture();
+/// assert_eq!(ValidationPolicy::default().check_tx(&tx), Ok(()));
+///
+/// let policy = ValidationPolicy {
+///     max_tx_weight: Some(100),
+///     ..Default::default()
+/// };
+/// assert_eq!(
+///     policy.check_tx(&tx),
+///     Err(PolicyError::TxWeightExceeded {
+///         weight: tx.weight(),
+///         max: 100
+///     })
+/// );
+/// ```
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+pub struct ValidationPolicy {
+    /// Maximal weight of the witness transaction.
+    pub max_tx_weight: Option<usize>,
+
+    /// Maximal number of leaves (i.e. width) of the anchor LNPBP-4 tree.
+    pub max_anchor_leaves: Option<usize>,
+
+    /// Maximal depth of the tapret commitment leaf within the taproot script
+    /// tree: zero if the commitment is the only script in the tree and one
+    /// otherwise.
+    pub max_tapret_depth: Option<u8>,
+
+    /// Minimal number of the witness transaction confirmations.
+    pub min_confirmations: Option<u32>,
+
+    /// Maximal number of the witness transaction outputs. Unlike the weight,
+    /// the number of outputs is checked without iterating over the
+    /// transaction data, rejecting transactions crafted to be costly to scan
+    /// before any other work is done.
+    pub max_tx_outputs: Option<usize>,
+
+    /// Chain context against which the witness transaction lock time is
+    /// checked by [`Anchor::verify_with_policy`]: anchors with witness
+    /// transactions which can't be included into the next block are rejected
+    /// with [`VerifyError::WitnessNotFinal`]. Relative lock times are not
+    /// checked; see [`WitnessFinality::is_sequence_final`].
+    ///
+    /// [`VerifyError::WitnessNotFinal`]: crate::anchor::VerifyError::WitnessNotFinal
+    /// [`WitnessFinality::is_sequence_final`]: crate::WitnessFinality::is_sequence_final
+    pub reference_time: Option<ReferenceTime>,
+}
+
+impl ValidationPolicy {
+    /// Checks the witness transaction against the policy limits.
+    pub fn check_tx(&self, tx: &Transaction) -> Result<(), PolicyError> {
+        match (tx.output.len(), self.max_tx_outputs) {
+            (outputs, Some(max)) if outputs > max => {
+                return Err(PolicyError::TxOutputsExceeded { outputs, max })
+            }
+            _ => {}
+        }
+        match (tx.weight(), self.max_tx_weight) {
+            (weight, Some(max)) if weight > max => {
+                Err(PolicyError::TxWeightExceeded { weight, max })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the anchor LNPBP-4 and DBC proofs against the policy limits.
+    pub fn check_anchor(
+        &self,
+        anchor: &Anchor<lnpbp4::MerkleProof>,
+    ) -> Result<(), PolicyError> {
+        match (anchor.lnpbp4_proof().width(), self.max_anchor_leaves) {
+            (leaves, Some(max)) if leaves > max => {
+                return Err(PolicyError::AnchorLeavesExceeded { leaves, max })
+            }
+            _ => {}
+        }
+        self.check_proof(anchor.dbc_proof())
+    }
+
+    /// Checks the DBC proof against the policy limits.
+    pub fn check_proof(&self, proof: &Proof) -> Result<(), PolicyError> {
+        let depth = match proof {
+            Proof::OpretFirst | Proof::OpretFirstAt(_) => return Ok(()),
+            Proof::TapretFirst(proof) => {
+                proof.path_proof.original_merkle_root().is_some() as u8
+            }
+        };
+        match self.max_tapret_depth {
+            Some(max) if depth > max => {
+                Err(PolicyError::TapretDepthExceeded { depth, max })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks number of the witness transaction confirmations against the
+    /// policy requirement. Unconfirmed transactions have zero confirmations.
+    pub fn check_confirmations(
+        &self,
+        confirmations: u32,
+    ) -> Result<(), PolicyError> {
+        match self.min_confirmations {
+            Some(min) if confirmations < min => {
+                Err(PolicyError::InsufficientConfirmations {
+                    confirmations,
+                    min,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin_scripts::LeafScript;
+
+    use super::*;
+    use crate::tapret::{TapretNodePartner, TapretPathProof, TapretProof};
+    use crate::test_utils::{
+        internal_key_fixture, opret_fixture, protocol_fixture, tapret_fixture,
+    };
+
+    #[test]
+    fn default_policy() {
+        let policy = ValidationPolicy::default();
+        let (protocol_id, _) = protocol_fixture(0);
+        for (anchor, tx) in [opret_fixture(), tapret_fixture()] {
+            let anchor = anchor.to_merkle_proof(protocol_id).unwrap();
+            assert_eq!(policy.check_tx(&tx), Ok(()));
+            assert_eq!(policy.check_anchor(&anchor), Ok(()));
+        }
+        assert_eq!(policy.check_confirmations(0), Ok(()));
+    }
+
+    #[test]
+    fn tx_weight() {
+        let (_, tx) = opret_fixture();
+        let weight = tx.weight();
+        let policy = |max| ValidationPolicy {
+            max_tx_weight: Some(max),
+            ..default!()
+        };
+        assert_eq!(policy(weight).check_tx(&tx), Ok(()));
+        assert_eq!(
+            policy(weight - 1).check_tx(&tx),
+            Err(PolicyError::TxWeightExceeded {
+                weight,
+                max: weight - 1
+            })
+        );
+    }
+
+    #[test]
+    fn tx_outputs() {
+        let (_, tx) = tapret_fixture();
+        let outputs = tx.output.len();
+        let policy = |max| ValidationPolicy {
+            max_tx_outputs: Some(max),
+            // Outputs are checked before the weight
+            max_tx_weight: Some(0),
+            ..default!()
+        };
+        assert_eq!(
+            policy(outputs - 1).check_tx(&tx),
+            Err(PolicyError::TxOutputsExceeded {
+                outputs,
+                max: outputs - 1
+            })
+        );
+        assert!(matches!(
+            policy(outputs).check_tx(&tx),
+            Err(PolicyError::TxWeightExceeded { .. })
+        ));
+    }
+
+    #[test]
+    fn anchor_leaves() {
+        let (protocol_id, _) = protocol_fixture(0);
+        let (anchor, _) = opret_fixture();
+        let anchor = anchor.to_merkle_proof(protocol_id).unwrap();
+        let leaves = anchor.lnpbp4_proof().width();
+        let policy = |max| ValidationPolicy {
+            max_anchor_leaves: Some(max),
+            ..default!()
+        };
+        assert_eq!(policy(leaves).check_anchor(&anchor), Ok(()));
+        assert_eq!(
+            policy(leaves - 1).check_anchor(&anchor),
+            Err(PolicyError::AnchorLeavesExceeded {
+                leaves,
+                max: leaves - 1
+            })
+        );
+    }
+
+    #[test]
+    fn tapret_depth() {
+        let policy = |max| ValidationPolicy {
+            max_tapret_depth: Some(max),
+            ..default!()
+        };
+        let single = Proof::TapretFirst(TapretProof::with(
+            internal_key_fixture(),
+            TapretPathProof::new(),
+        ));
+        let leaf = LeafScript::tapscript(default!());
+        let nested = Proof::TapretFirst(TapretProof::with(
+            internal_key_fixture(),
+            TapretPathProof::with(TapretNodePartner::RightLeaf(leaf), 0)
+                .unwrap(),
+        ));
+
+        assert_eq!(policy(0).check_proof(&Proof::OpretFirst), Ok(()));
+        assert_eq!(policy(0).check_proof(&single), Ok(()));
+        assert_eq!(policy(1).check_proof(&nested), Ok(()));
+        assert_eq!(
+            policy(0).check_proof(&nested),
+            Err(PolicyError::TapretDepthExceeded { depth: 1, max: 0 })
+        );
+    }
+
+    #[test]
+    fn confirmations() {
+        let policy = ValidationPolicy {
+            min_confirmations: Some(6),
+            ..default!()
+        };
+        assert_eq!(policy.check_confirmations(6), Ok(()));
+        assert_eq!(
+            policy.check_confirmations(5),
+            Err(PolicyError::InsufficientConfirmations {
+                confirmations: 5,
+                min: 6
+            })
+        );
+    }
+}
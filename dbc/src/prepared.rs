@@ -0,0 +1,126 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Witness transactions prepared for verification of multiple deterministic
+//! bitcoin commitments.
+
+use bitcoin::{Script, Transaction, Txid};
+
+/// Witness transaction with cached data required for the verification of
+/// deterministic bitcoin commitments.
+///
+/// Computing transaction id and scanning transaction outputs are done once,
+/// during the construction, such that verification of multiple proofs against
+/// the same transaction with [`crate::Proof::verify_prepared`] does not repeat
+/// them.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PreparedTx {
+    tx: Transaction,
+    txid: Txid,
+    op_return_outputs: Vec<usize>,
+    p2tr_outputs: Vec<usize>,
+    p2tr_rest_uniform: bool,
+}
+
+impl From<Transaction> for PreparedTx {
+    #[inline]
+    fn from(tx: Transaction) -> Self { PreparedTx::new(tx) }
+}
+
+impl PreparedTx {
+    /// Prepares transaction for the verification.
+    pub fn new(tx: Transaction) -> PreparedTx {
+        let txid = tx.txid();
+        PreparedTx::with_txid(tx, txid)
+    }
+
+    /// Prepares transaction which id is already known to be `txid`.
+    pub(crate) fn with_txid(tx: Transaction, txid: Txid) -> PreparedTx {
+        let mut op_return_outputs = vec![];
+        let mut p2tr_outputs = vec![];
+        for (index, txout) in tx.output.iter().enumerate() {
+            if txout.script_pubkey.is_op_return() {
+                op_return_outputs.push(index);
+            }
+            if txout.script_pubkey.is_v1_p2tr() {
+                p2tr_outputs.push(index);
+            }
+        }
+        let p2tr_rest_uniform = p2tr_outputs
+            .get(1..)
+            .unwrap_or_default()
+            .windows(2)
+            .all(|pair| {
+                tx.output[pair[0]].script_pubkey
+                    == tx.output[pair[1]].script_pubkey
+            });
+        PreparedTx {
+            tx,
+            txid,
+            op_return_outputs,
+            p2tr_outputs,
+            p2tr_rest_uniform,
+        }
+    }
+
+    /// Returns reference to the prepared transaction.
+    #[inline]
+    pub fn as_tx(&self) -> &Transaction { &self.tx }
+
+    /// Releases the prepared transaction.
+    #[inline]
+    pub fn into_tx(self) -> Transaction { self.tx }
+
+    /// Returns cached id of the prepared transaction.
+    #[inline]
+    pub fn txid(&self) -> Txid { self.txid }
+
+    /// Returns indexes of `OP_RETURN` outputs of the transaction.
+    #[inline]
+    pub fn op_return_outputs(&self) -> &[usize] { &self.op_return_outputs }
+
+    /// Returns indexes of taproot outputs of the transaction.
+    #[inline]
+    pub fn p2tr_outputs(&self) -> &[usize] { &self.p2tr_outputs }
+
+    /// Returns scriptPubkey of the first `OP_RETURN` output, if any.
+    #[inline]
+    pub fn first_op_return(&self) -> Option<&Script> {
+        self.op_return_outputs
+            .first()
+            .map(|index| &self.tx.output[*index].script_pubkey)
+    }
+
+    /// Returns scriptPubkey of the first taproot output, if any.
+    #[inline]
+    pub fn first_p2tr(&self) -> Option<&Script> {
+        self.p2tr_outputs
+            .first()
+            .map(|index| &self.tx.output[*index].script_pubkey)
+    }
+
+    /// Checks that all taproot outputs except the first one have the provided
+    /// scriptPubkey. Always succeeds for transactions with less than two
+    /// taproot outputs.
+    pub fn rest_p2tr_match(&self, script_pubkey: &Script) -> bool {
+        match self.p2tr_outputs.get(1) {
+            None => true,
+            Some(index) => {
+                self.p2tr_rest_uniform
+                    && &self.tx.output[*index].script_pubkey == script_pubkey
+            }
+        }
+    }
+}
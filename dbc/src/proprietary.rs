@@ -0,0 +1,426 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Size and range limits for values of PSBT proprietary keys used by
+//! deterministic bitcoin commitments.
+//!
+//! PSBTs may come from untrusted counterparties; the limits are checked before
+//! any of the values is interpreted and strict-decoded. The checks run on an
+//! already parsed [`Psbt`], so the raw values have been allocated by the `psbt`
+//! crate by then; parse-time memory use is bounded only by the size of the
+//! serialized PSBT.
+
+use std::collections::BTreeMap;
+
+use psbt::commit::{
+    ProprietaryKeyLnpbp4, ProprietaryKeyTapret,
+    PSBT_GLOBAL_LNPBP4_PROTOCOL_INFO, PSBT_IN_TAPRET_TWEAK, PSBT_LNPBP4_PREFIX,
+    PSBT_OPRET_PREFIX, PSBT_OUT_LNPBP4_ENTROPY, PSBT_OUT_LNPBP4_MESSAGE,
+    PSBT_OUT_LNPBP4_MIN_TREE_DEPTH, PSBT_OUT_OPRET_COMMITMENT,
+    PSBT_OUT_OPRET_HOST, PSBT_OUT_TAPRET_COMMITMENT, PSBT_OUT_TAPRET_HOST,
+    PSBT_OUT_TAPRET_PROOF, PSBT_TAPRET_PREFIX,
+};
+use psbt::{ProprietaryKey, Psbt};
+
+use crate::consts::{
+    COMMITMENT_LEN, LNPBP4_MAX_DEPTH, TAPRET_CONSENSUS_MAX_PATH_DEPTH,
+};
+
+/// Maximal length of LNPBP-4 message value.
+pub const LNPBP4_MESSAGE_MAX_LEN: usize = COMMITMENT_LEN;
+
+/// Maximal length of LNPBP-4 entropy value.
+pub const LNPBP4_ENTROPY_MAX_LEN: usize = 8;
+
+/// Maximal length of LNPBP-4 minimal tree depth value.
+pub const LNPBP4_MIN_TREE_DEPTH_MAX_LEN: usize = 1;
+
+/// Maximal length of LNPBP-4 protocol information value. Limits protocol hash
+/// tag to 255 bytes.
+pub const LNPBP4_PROTOCOL_INFO_MAX_LEN: usize =
+    1 + 2 + 255 + 1 + COMMITMENT_LEN;
+
+/// Maximal length of opret host value, which must be empty.
+pub const OPRET_HOST_MAX_LEN: usize = 0;
+
+/// Maximal length of opret commitment value.
+pub const OPRET_COMMITMENT_MAX_LEN: usize = COMMITMENT_LEN;
+
+/// Maximal length of tapret host value, containing strict-encoded
+/// [`DfsPath`](bitcoin_scripts::taproot::DfsPath) with at most
+/// [`TAPRET_CONSENSUS_MAX_PATH_DEPTH`] steps.
+pub const TAPRET_HOST_MAX_LEN: usize = 2 + TAPRET_CONSENSUS_MAX_PATH_DEPTH;
+
+/// Maximal length of tapret commitment value.
+pub const TAPRET_COMMITMENT_MAX_LEN: usize = COMMITMENT_LEN;
+
+/// Maximal length of strict-encoded tapret proof value. Actual proofs never
+/// exceed 100 bytes; the cap leaves the room for future proof extensions.
+pub const TAPRET_PROOF_MAX_LEN: usize = 256;
+
+/// Maximal length of tapret tweak value of a transaction input.
+pub const TAPRET_TWEAK_MAX_LEN: usize = COMMITMENT_LEN;
+
+/// Errors in the values of PSBT proprietary keys.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum KeyError {
+    /// value of PSBT proprietary key {key:?} has {len} bytes, exceeding the
+    /// maximum of {max} bytes.
+    Oversized {
+        /// Proprietary key with the oversized value.
+        key: ProprietaryKey,
+
+        /// Length of the value.
+        len: usize,
+
+        /// Maximal allowed length of the value.
+        max: usize,
+    },
+
+    /// LNPBP-4 minimal tree depth {0} exceeds the maximal LNPBP-4 tree depth.
+    MinTreeDepthOverflow(u8),
+
+    /// tapret host path has {depth} steps, exceeding the consensus limit of
+    /// {max} steps.
+    TapretPathTooDeep {
+        /// Number of the steps declared by the path length prefix.
+        depth: usize,
+
+        /// Maximal allowed number of the path steps.
+        max: usize,
+    },
+}
+
+/// Returns maximal length of the value for the known global proprietary keys.
+pub fn global_max_len(key: &ProprietaryKey) -> Option<usize> {
+    match (key.prefix.as_slice(), key.subtype) {
+        (PSBT_LNPBP4_PREFIX, PSBT_GLOBAL_LNPBP4_PROTOCOL_INFO) => {
+            Some(LNPBP4_PROTOCOL_INFO_MAX_LEN)
+        }
+        _ => None,
+    }
+}
+
+/// Returns maximal length of the value for the known input proprietary keys.
+pub fn input_max_len(key: &ProprietaryKey) -> Option<usize> {
+    match (key.prefix.as_slice(), key.subtype) {
+        (PSBT_TAPRET_PREFIX, PSBT_IN_TAPRET_TWEAK) => {
+            Some(TAPRET_TWEAK_MAX_LEN)
+        }
+        _ => None,
+    }
+}
+
+/// Returns maximal length of the value for the known output proprietary keys.
+pub fn output_max_len(key: &ProprietaryKey) -> Option<usize> {
+    match (key.prefix.as_slice(), key.subtype) {
+        (PSBT_LNPBP4_PREFIX, PSBT_OUT_LNPBP4_MESSAGE) => {
+            Some(LNPBP4_MESSAGE_MAX_LEN)
+        }
+        (PSBT_LNPBP4_PREFIX, PSBT_OUT_LNPBP4_ENTROPY) => {
+            Some(LNPBP4_ENTROPY_MAX_LEN)
+        }
+        (PSBT_LNPBP4_PREFIX, PSBT_OUT_LNPBP4_MIN_TREE_DEPTH) => {
+            Some(LNPBP4_MIN_TREE_DEPTH_MAX_LEN)
+        }
+        (PSBT_OPRET_PREFIX, PSBT_OUT_OPRET_HOST) => Some(OPRET_HOST_MAX_LEN),
+        (PSBT_OPRET_PREFIX, PSBT_OUT_OPRET_COMMITMENT) => {
+            Some(OPRET_COMMITMENT_MAX_LEN)
+        }
+        (PSBT_TAPRET_PREFIX, PSBT_OUT_TAPRET_HOST) => Some(TAPRET_HOST_MAX_LEN),
+        (PSBT_TAPRET_PREFIX, PSBT_OUT_TAPRET_COMMITMENT) => {
+            Some(TAPRET_COMMITMENT_MAX_LEN)
+        }
+        (PSBT_TAPRET_PREFIX, PSBT_OUT_TAPRET_PROOF) => {
+            Some(TAPRET_PROOF_MAX_LEN)
+        }
+        _ => None,
+    }
+}
+
+fn check_map(
+    map: &BTreeMap<ProprietaryKey, Vec<u8>>,
+    max_len: fn(&ProprietaryKey) -> Option<usize>,
+) -> Result<(), KeyError> {
+    for (key, value) in map {
+        match max_len(key) {
+            Some(max) if value.len() > max => {
+                return Err(KeyError::Oversized {
+                    key: key.clone(),
+                    len: value.len(),
+                    max,
+                })
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Checks that values of all known proprietary keys of the PSBT output do not
+/// exceed their maximal lengths, that the tapret host path does not declare
+/// more than [`TAPRET_CONSENSUS_MAX_PATH_DEPTH`] steps and that LNPBP-4
+/// minimal tree depth does not exceed [`LNPBP4_MAX_DEPTH`].
+///
+/// The path depth is read from the length prefix of the strict-encoded value,
+/// before the path itself is decoded.
+pub fn check_output(output: &psbt::Output) -> Result<(), KeyError> {
+    check_map(&output.proprietary, output_max_len)?;
+    if let Some(&[lo, hi, ..]) = output
+        .proprietary
+        .get(&ProprietaryKey::tapret_host())
+        .map(Vec::as_slice)
+    {
+        let depth = u16::from_le_bytes([lo, hi]) as usize;
+        if depth > TAPRET_CONSENSUS_MAX_PATH_DEPTH {
+            return Err(KeyError::TapretPathTooDeep {
+                depth,
+                max: TAPRET_CONSENSUS_MAX_PATH_DEPTH,
+            });
+        }
+    }
+    match output
+        .proprietary
+        .get(&ProprietaryKey::lnpbp4_min_tree_depth())
+        .map(Vec::as_slice)
+    {
+        Some(&[depth]) if depth > LNPBP4_MAX_DEPTH => {
+            Err(KeyError::MinTreeDepthOverflow(depth))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks that values of all known global, input and output proprietary keys
+/// of the PSBT do not exceed their maximal lengths. Output values are checked
+/// with [`check_output`].
+///
+/// The check inspects values already allocated while parsing the PSBT; callers
+/// accepting PSBTs from untrusted parties must bound the size of the
+/// serialized PSBT before deserializing it.
+pub fn check_psbt(psbt: &Psbt) -> Result<(), KeyError> {
+    check_map(&psbt.proprietary, global_max_len)?;
+    for input in &psbt.inputs {
+        check_map(&input.proprietary, input_max_len)?;
+    }
+    psbt.outputs.iter().try_for_each(check_output)
+}
+
+/// Removes all proprietary keys used by deterministic bitcoin commitments
+/// (with LNPBP-4, opret and tapret prefixes) from the global, input and output
+/// maps of the PSBT, returning the number of the removed keys.
+///
+/// The keys are not required once the commitment is made and the witness
+/// transaction is extracted; stripping them prevents leaking the committed
+/// protocols and messages to the parties the PSBT is shared with afterwards.
+pub fn strip_psbt(psbt: &mut Psbt) -> usize {
+    fn strip_map(map: &mut BTreeMap<ProprietaryKey, Vec<u8>>) -> usize {
+        let len = map.len();
+        map.retain(|key, _| {
+            ![PSBT_LNPBP4_PREFIX, PSBT_OPRET_PREFIX, PSBT_TAPRET_PREFIX]
+                .contains(&key.prefix.as_slice())
+        });
+        len - map.len()
+    }
+
+    strip_map(&mut psbt.proprietary)
+        + psbt
+            .inputs
+            .iter_mut()
+            .map(|input| strip_map(&mut input.proprietary))
+            .sum::<usize>()
+        + psbt
+            .outputs
+            .iter_mut()
+            .map(|output| strip_map(&mut output.proprietary))
+            .sum::<usize>()
+}
+
+#[cfg(test)]
+mod test {
+    use commit_verify::lnpbp4::ProtocolId;
+    use psbt::commit::{ProprietaryKeyLnpbp4, ProprietaryKeyOpret};
+
+    use super::*;
+
+    #[test]
+    fn oversized_output_values() {
+        let keys = [
+            (
+                ProprietaryKey::lnpbp4_message(ProtocolId::default()),
+                LNPBP4_MESSAGE_MAX_LEN,
+            ),
+            (ProprietaryKey::lnpbp4_entropy(), LNPBP4_ENTROPY_MAX_LEN),
+            (
+                ProprietaryKey::lnpbp4_min_tree_depth(),
+                LNPBP4_MIN_TREE_DEPTH_MAX_LEN,
+            ),
+            (ProprietaryKey::opret_host(), OPRET_HOST_MAX_LEN),
+            (ProprietaryKey::opret_commitment(), OPRET_COMMITMENT_MAX_LEN),
+            (ProprietaryKey::tapret_host(), TAPRET_HOST_MAX_LEN),
+            (
+                ProprietaryKey::tapret_commitment(),
+                TAPRET_COMMITMENT_MAX_LEN,
+            ),
+            (ProprietaryKey::tapret_proof(), TAPRET_PROOF_MAX_LEN),
+        ];
+        for (key, max) in keys {
+            let mut output = psbt::Output::new(0, bitcoin::TxOut::default());
+            output.proprietary.insert(key.clone(), vec![0u8; max]);
+            assert_eq!(check_output(&output), Ok(()));
+
+            output.proprietary.insert(key.clone(), vec![0u8; max + 1]);
+            assert_eq!(
+                check_output(&output),
+                Err(KeyError::Oversized {
+                    key,
+                    len: max + 1,
+                    max
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn min_tree_depth_overflow() {
+        let key = ProprietaryKey::lnpbp4_min_tree_depth();
+        let mut output = psbt::Output::new(0, bitcoin::TxOut::default());
+        output
+            .proprietary
+            .insert(key.clone(), vec![LNPBP4_MAX_DEPTH]);
+        assert_eq!(check_output(&output), Ok(()));
+
+        for depth in [LNPBP4_MAX_DEPTH + 1, 64, u8::MAX] {
+            output.proprietary.insert(key.clone(), vec![depth]);
+            assert_eq!(
+                check_output(&output),
+                Err(KeyError::MinTreeDepthOverflow(depth))
+            );
+        }
+    }
+
+    #[test]
+    fn tapret_path_depth() {
+        let key = ProprietaryKey::tapret_host();
+        let mut output = psbt::Output::new(0, bitcoin::TxOut::default());
+        let depth = TAPRET_CONSENSUS_MAX_PATH_DEPTH as u16;
+        let mut value = depth.to_le_bytes().to_vec();
+        value.extend([0u8; TAPRET_CONSENSUS_MAX_PATH_DEPTH]);
+        output.proprietary.insert(key.clone(), value.clone());
+        assert_eq!(check_output(&output), Ok(()));
+
+        for depth in [depth + 1, u16::MAX] {
+            value[..2].copy_from_slice(&depth.to_le_bytes());
+            output.proprietary.insert(key.clone(), value.clone());
+            assert_eq!(
+                check_output(&output),
+                Err(KeyError::TapretPathTooDeep {
+                    depth: depth as usize,
+                    max: TAPRET_CONSENSUS_MAX_PATH_DEPTH
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn oversized_global_and_input_values() {
+        let tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime::ZERO,
+            input: vec![bitcoin::TxIn::default()],
+            output: vec![],
+        };
+        let psbt = Psbt::with(tx, psbt::PsbtVersion::V0).unwrap();
+        assert_eq!(check_psbt(&psbt), Ok(()));
+
+        let key = ProprietaryKey::lnpbp4_protocol_info(ProtocolId::default());
+        let mut oversized = psbt.clone();
+        oversized.proprietary.insert(key.clone(), vec![
+            0u8;
+            LNPBP4_PROTOCOL_INFO_MAX_LEN
+                + 1
+        ]);
+        assert_eq!(
+            check_psbt(&oversized),
+            Err(KeyError::Oversized {
+                key,
+                len: LNPBP4_PROTOCOL_INFO_MAX_LEN + 1,
+                max: LNPBP4_PROTOCOL_INFO_MAX_LEN
+            })
+        );
+
+        let key = ProprietaryKey::tapret_tweak();
+        let mut oversized = psbt;
+        oversized.inputs[0].proprietary.insert(key.clone(), vec![
+            0u8;
+            TAPRET_TWEAK_MAX_LEN
+                + 1
+        ]);
+        assert_eq!(
+            check_psbt(&oversized),
+            Err(KeyError::Oversized {
+                key,
+                len: TAPRET_TWEAK_MAX_LEN + 1,
+                max: TAPRET_TWEAK_MAX_LEN
+            })
+        );
+    }
+
+    #[test]
+    fn strip_keys() {
+        let tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime::ZERO,
+            input: vec![bitcoin::TxIn::default()],
+            output: vec![bitcoin::TxOut::default()],
+        };
+        let mut psbt = Psbt::with(tx, psbt::PsbtVersion::V0).unwrap();
+        let foreign = ProprietaryKey {
+            prefix: b"RGB".to_vec(),
+            subtype: 0,
+            key: vec![],
+        };
+        psbt.proprietary.insert(
+            ProprietaryKey::lnpbp4_protocol_info(ProtocolId::default()),
+            vec![],
+        );
+        psbt.proprietary.insert(foreign.clone(), vec![1]);
+        psbt.inputs[0]
+            .proprietary
+            .insert(ProprietaryKey::tapret_tweak(), vec![]);
+        let output = &mut psbt.outputs[0];
+        output
+            .set_lnpbp4_message(
+                ProtocolId::default(),
+                bitcoin::hashes::Hash::all_zeros(),
+            )
+            .unwrap();
+        output
+            .proprietary
+            .insert(ProprietaryKey::opret_host(), vec![]);
+        output
+            .proprietary
+            .insert(ProprietaryKey::tapret_host(), vec![]);
+        output.proprietary.insert(foreign.clone(), vec![2]);
+
+        assert_eq!(strip_psbt(&mut psbt), 5);
+        assert_eq!(strip_psbt(&mut psbt), 0);
+        assert_eq!(psbt.proprietary.len(), 1);
+        assert!(psbt.inputs[0].proprietary.is_empty());
+        assert_eq!(psbt.outputs[0].proprietary.get(&foreign), Some(&vec![2]));
+        assert_eq!(psbt.outputs[0].proprietary.len(), 1);
+    }
+}
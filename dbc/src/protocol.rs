@@ -0,0 +1,445 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Hex-encoded LNPBP-4 protocol ids and messages.
+//!
+//! [`ProtoId`] and [`Msg`] wrap LNPBP-4 [`ProtocolId`] and [`Message`] types,
+//! providing uniform hexadecimal string representation, parsing with length
+//! validation and serde support. All APIs of the library accepting protocol
+//! ids and messages accept the wrappers as well.
+//!
+//! Downstream protocols committing merkle roots of their data as LNPBP-4
+//! messages may opt into domain separation with [`ProtocolDomain`], which
+//! derives both the protocol id and the messages from the same domain tag
+//! string, such that equally-sized digests of different protocols never
+//! produce the same message.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use amplify::Wrapper;
+use bitcoin::hashes::hex::{self, FromHex, ToHex};
+use bitcoin::hashes::{sha256, sha256t, Hash, HashEngine};
+use commit_verify::lnpbp4::{Message, ProtocolId};
+
+use crate::consts::COMMITMENT_LEN;
+
+/// Length of LNPBP-4 protocol id and message in bytes.
+pub const LNPBP4_ID_LEN: usize = COMMITMENT_LEN;
+
+/// Errors parsing hexadecimal representation of LNPBP-4 protocol ids and
+/// messages.
+#[derive(
+    Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, Error
+)]
+#[display(doc_comments)]
+pub enum ParseError {
+    /// hexadecimal string contains non-hexadecimal character {0:#04x}.
+    InvalidChar(u8),
+
+    /// hexadecimal string has odd length {0}.
+    OddLength(usize),
+
+    /// value must be exactly 32 bytes (64 hexadecimal characters) long, while
+    /// {0} bytes were provided.
+    InvalidLength(usize),
+}
+
+impl From<hex::Error> for ParseError {
+    fn from(err: hex::Error) -> Self {
+        match err {
+            hex::Error::InvalidChar(ch) => ParseError::InvalidChar(ch),
+            hex::Error::OddLengthString(len) => ParseError::OddLength(len),
+            hex::Error::InvalidLength(_, len) => ParseError::InvalidLength(len),
+        }
+    }
+}
+
+fn parse_hex(s: &str) -> Result<[u8; LNPBP4_ID_LEN], ParseError> {
+    let data = Vec::<u8>::from_hex(s)?;
+    let mut bytes = [0u8; LNPBP4_ID_LEN];
+    if data.len() != LNPBP4_ID_LEN {
+        return Err(ParseError::InvalidLength(data.len()));
+    }
+    bytes.copy_from_slice(&data);
+    Ok(bytes)
+}
+
+static MIDSTATE_DOMAIN_PROTOCOL: [u8; 32] = [
+    18, 74, 89, 80, 75, 176, 49, 11, 71, 254, 19, 9, 241, 55, 48, 107, 197, 7,
+    115, 124, 30, 228, 173, 215, 32, 220, 47, 159, 202, 39, 18, 108,
+];
+
+/// Tag used for protocol ids produced by [`ProtoId::with_domain`]
+pub struct DomainProtocolTag;
+
+impl sha256t::Tag for DomainProtocolTag {
+    #[inline]
+    fn engine() -> sha256::HashEngine {
+        let midstate = sha256::Midstate::from_inner(MIDSTATE_DOMAIN_PROTOCOL);
+        sha256::HashEngine::from_midstate(midstate, 64)
+    }
+}
+
+static MIDSTATE_DOMAIN_MESSAGE: [u8; 32] = [
+    74, 178, 45, 75, 229, 248, 107, 73, 100, 59, 75, 205, 148, 62, 70, 211,
+    126, 96, 139, 47, 26, 233, 166, 205, 18, 238, 39, 141, 49, 212, 86, 73,
+];
+
+/// Tag used for messages produced by [`Msg::with_domain`]
+pub struct DomainMessageTag;
+
+impl sha256t::Tag for DomainMessageTag {
+    #[inline]
+    fn engine() -> sha256::HashEngine {
+        let midstate = sha256::Midstate::from_inner(MIDSTATE_DOMAIN_MESSAGE);
+        sha256::HashEngine::from_midstate(midstate, 64)
+    }
+}
+
+/// Hashes the domain tag prefixed with its length as 16-bit little-endian
+/// integer, followed by `data`, under the tagged hash `T`.
+fn domain_hash<T: sha256t::Tag>(
+    domain_tag: &str,
+    data: &[u8],
+) -> [u8; LNPBP4_ID_LEN] {
+    let len = u16::try_from(domain_tag.len())
+        .expect("domain tag must not exceed 65535 bytes");
+    let mut engine = T::engine();
+    engine.input(&len.to_le_bytes());
+    engine.input(domain_tag.as_bytes());
+    engine.input(data);
+    sha256t::Hash::<T>::from_engine(engine).into_inner()
+}
+
+/// LNPBP-4 protocol id with hexadecimal string representation.
+#[derive(Wrapper, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, From)]
+#[wrapper(Debug)]
+pub struct ProtoId(ProtocolId);
+
+impl From<[u8; LNPBP4_ID_LEN]> for ProtoId {
+    #[inline]
+    fn from(bytes: [u8; LNPBP4_ID_LEN]) -> Self {
+        ProtoId(ProtocolId::from_inner(bytes))
+    }
+}
+
+impl ProtoId {
+    /// Derives protocol id from the domain tag, which must also be used for
+    /// the protocol messages with [`Msg::with_domain`]. Prefer
+    /// [`ProtocolDomain`], which keeps the tag in one place.
+    ///
+    /// # Panics
+    ///
+    /// If the domain tag is longer than 65535 bytes.
+    #[inline]
+    pub fn with_domain(domain_tag: &'static str) -> ProtoId {
+        ProtoId::from(domain_hash::<DomainProtocolTag>(domain_tag, &[]))
+    }
+
+    /// Returns byte representation of the protocol id.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] { &self.0[..] }
+}
+
+impl Display for ProtoId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.as_bytes().to_hex())
+    }
+}
+
+impl FromStr for ProtoId {
+    type Err = ParseError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_hex(s).map(ProtoId::from)
+    }
+}
+
+/// LNPBP-4 message with hexadecimal string representation.
+#[derive(Wrapper, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, From)]
+#[wrapper(Debug)]
+pub struct Msg(Message);
+
+impl From<[u8; LNPBP4_ID_LEN]> for Msg {
+    #[inline]
+    fn from(bytes: [u8; LNPBP4_ID_LEN]) -> Self {
+        Msg(Message::from_inner(bytes))
+    }
+}
+
+impl Msg {
+    /// Constructs message from the digest of the protocol data (like a merkle
+    /// root) by re-hashing it together with the domain tag, such that digests
+    /// of different protocols can't be replayed as each other messages.
+    ///
+    /// The message is a tagged hash with `bp:dbc:message:domain:v1` tag of the
+    /// domain tag length as 16-bit little-endian integer, the domain tag and
+    /// the digest. Messages constructed in other ways are not affected.
+    ///
+    /// # Panics
+    ///
+    /// If the domain tag is longer than 65535 bytes.
+    #[inline]
+    pub fn with_domain(
+        domain_tag: &'static str,
+        digest: [u8; LNPBP4_ID_LEN],
+    ) -> Msg {
+        Msg::from(domain_hash::<DomainMessageTag>(domain_tag, &digest))
+    }
+
+    /// Returns byte representation of the message.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] { &self.0[..] }
+}
+
+impl Display for Msg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.as_bytes().to_hex())
+    }
+}
+
+impl FromStr for Msg {
+    type Err = ParseError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_hex(s).map(Msg::from)
+    }
+}
+
+/// Domain of a protocol committing with LNPBP-4, deriving both the protocol id
+/// and domain-separated messages from the same tag string.
+///
+/// ```
+/// use dbc::protocol::{Msg, ProtoId, ProtocolDomain};
+///
+/// const DOMAIN: ProtocolDomain = ProtocolDomain::new("example.org:tokens:v1");
+///
+/// let root = [0xA5u8; 32];
+/// assert_eq!(
+///     DOMAIN.protocol_id(),
+///     ProtoId::with_domain("example.org:tokens:v1")
+/// );
+/// assert_eq!(
+///     DOMAIN.message(root),
+///     Msg::with_domain("example.org:tokens:v1", root)
+/// );
+/// assert_ne!(
+///     DOMAIN.message(root),
+///     ProtocolDomain::new("example.org:nfts:v1").message(root)
+/// );
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct ProtocolDomain(&'static str);
+
+impl ProtocolDomain {
+    /// Constructs protocol domain from its tag, which should be unique for the
+    /// protocol and its version, like `example.org:tokens:v1`.
+    #[inline]
+    pub const fn new(domain_tag: &'static str) -> ProtocolDomain {
+        ProtocolDomain(domain_tag)
+    }
+
+    /// Returns domain tag.
+    #[inline]
+    pub const fn tag(&self) -> &'static str { self.0 }
+
+    /// Returns protocol id of the domain. See [`ProtoId::with_domain`].
+    #[inline]
+    pub fn protocol_id(&self) -> ProtoId { ProtoId::with_domain(self.0) }
+
+    /// Constructs message from the digest of the protocol data. See
+    /// [`Msg::with_domain`].
+    #[inline]
+    pub fn message(&self, digest: [u8; LNPBP4_ID_LEN]) -> Msg {
+        Msg::with_domain(self.0, digest)
+    }
+}
+
+#[cfg(feature = "serde")]
+mod _serde {
+    use serde::de::{Error, Visitor};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::*;
+
+    struct HexVisitor<T>(std::marker::PhantomData<T>);
+
+    impl<'de, T> Visitor<'de> for HexVisitor<T>
+    where
+        T: FromStr<Err = ParseError> + From<[u8; LNPBP4_ID_LEN]>,
+    {
+        type Value = T;
+
+        fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+            formatter.write_str("32-byte value or 64-character hex string")
+        }
+
+        fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+        where
+            E: Error,
+        {
+            T::from_str(v).map_err(E::custom)
+        }
+
+        fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+        where
+            E: Error,
+        {
+            let mut bytes = [0u8; LNPBP4_ID_LEN];
+            if v.len() != LNPBP4_ID_LEN {
+                return Err(E::invalid_length(v.len(), &"32 bytes"));
+            }
+            bytes.copy_from_slice(v);
+            Ok(T::from(bytes))
+        }
+    }
+
+    macro_rules! impl_serde {
+        ($ty:ty) => {
+            impl Serialize for $ty {
+                fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                where
+                    S: Serializer,
+                {
+                    if serializer.is_human_readable() {
+                        serializer.serialize_str(&self.to_string())
+                    } else {
+                        serializer.serialize_bytes(self.as_bytes())
+                    }
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $ty {
+                fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                where
+                    D: Deserializer<'de>,
+                {
+                    let visitor = HexVisitor(std::marker::PhantomData);
+                    if deserializer.is_human_readable() {
+                        deserializer.deserialize_str(visitor)
+                    } else {
+                        deserializer.deserialize_bytes(visitor)
+                    }
+                }
+            }
+        };
+    }
+
+    impl_serde!(ProtoId);
+    impl_serde!(Msg);
+}
+
+#[cfg(test)]
+mod test {
+    use commit_verify::tagged_hash;
+
+    use super::*;
+
+    const HEX: &str =
+        "4bf5122f344554c53bde2ebb8cd2b7e3d1600ad631c385a5d7cce23c7785459a";
+
+    #[test]
+    fn parse_display() {
+        let id = ProtoId::from_str(HEX).unwrap();
+        assert_eq!(id.to_string(), HEX);
+        assert_eq!(ProtocolId::from(id), ProtocolId::from_str(HEX).unwrap());
+        assert_eq!(ProtoId::from(ProtocolId::from(id)), id);
+
+        let msg = Msg::from_str(HEX).unwrap();
+        assert_eq!(msg.to_string(), HEX);
+        assert_eq!(Message::from(msg), sha256::Hash::from_str(HEX).unwrap());
+        assert_eq!(Msg::from(Message::from(msg)), msg);
+        assert_eq!(msg.as_bytes(), id.as_bytes());
+        assert_eq!(Msg::from(*msg.as_inner().as_inner()), msg);
+    }
+
+    #[test]
+    fn domain_protocol_midstate() {
+        let midstate =
+            tagged_hash::Midstate::with(b"bp:dbc:protocol:domain:v1");
+        assert_eq!(
+            midstate.into_inner().into_inner(),
+            MIDSTATE_DOMAIN_PROTOCOL
+        );
+    }
+
+    #[test]
+    fn domain_message_midstate() {
+        let midstate = tagged_hash::Midstate::with(b"bp:dbc:message:domain:v1");
+        assert_eq!(midstate.into_inner().into_inner(), MIDSTATE_DOMAIN_MESSAGE);
+    }
+
+    #[test]
+    fn domain_separation() {
+        let digest = [0xA5u8; LNPBP4_ID_LEN];
+        let domain = ProtocolDomain::new("bp:test");
+        let msg = domain.message(digest);
+        assert_eq!(
+            msg.to_string(),
+            "a204924e04c129c66db6124314504219245d62709429c774c4a199705c6903ce"
+        );
+        assert_eq!(
+            domain.protocol_id().to_string(),
+            "48636a6eb0b120a52ec5549a79e1fd5253195824e9c18fba90bf7ce0f9fbda82"
+        );
+        assert_ne!(msg, Msg::from(digest));
+        assert_ne!(msg.as_bytes(), domain.protocol_id().as_bytes());
+        assert_ne!(msg, ProtocolDomain::new("bp:test2").message(digest));
+        assert_ne!(msg, domain.message([0x5Au8; LNPBP4_ID_LEN]));
+        assert_ne!(
+            ProtoId::with_domain("bp:test"),
+            ProtoId::with_domain("bp:test2")
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            ProtoId::from_str(&HEX[..63]),
+            Err(ParseError::OddLength(63))
+        );
+        assert_eq!(Msg::from_str(&HEX[..63]), Err(ParseError::OddLength(63)));
+        assert_eq!(
+            ProtoId::from_str(&HEX[..62]),
+            Err(ParseError::InvalidLength(31))
+        );
+        assert_eq!(
+            Msg::from_str(&format!("{}00", HEX)),
+            Err(ParseError::InvalidLength(33))
+        );
+        assert_eq!(Msg::from_str(""), Err(ParseError::InvalidLength(0)));
+        assert_eq!(
+            ProtoId::from_str(&HEX.replace('4', "x")),
+            Err(ParseError::InvalidChar(b'x'))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_hex() {
+        let id = ProtoId::from_str(HEX).unwrap();
+        let msg = Msg::from_str(HEX).unwrap();
+        let json = format!("\"{}\"", HEX);
+        assert_eq!(serde_json::to_string(&id).unwrap(), json);
+        assert_eq!(serde_json::to_string(&msg).unwrap(), json);
+        assert_eq!(serde_json::from_str::<ProtoId>(&json).unwrap(), id);
+        assert_eq!(serde_json::from_str::<Msg>(&json).unwrap(), msg);
+        assert!(serde_json::from_str::<Msg>(&format!("\"{}\"", &HEX[..62]))
+            .is_err());
+    }
+}
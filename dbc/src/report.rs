@@ -0,0 +1,99 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Per-item results of batch verification.
+//!
+//! Batch verification functions returning a single `bool` stop on the first
+//! failed item; `_each` variants of them verify all items and report the
+//! outcome of each of them as [`ItemResult`], such that callers may localize
+//! failures without parsing error messages. Results may be aggregated with
+//! [`summary`].
+
+use std::collections::BTreeMap;
+
+use crate::{ErrorClass, ErrorKindExt};
+
+/// Outcome of verification of a single item of a batch.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ItemResult<Id, E> {
+    /// Position of the item in the batch.
+    pub index: usize,
+
+    /// Identifier of the verified item.
+    pub id: Id,
+
+    /// Verification result: `Ok(false)` if the item is well-formed, but not
+    /// committed or not closed.
+    pub outcome: Result<bool, E>,
+}
+
+impl<Id, E> ItemResult<Id, E> {
+    /// Detects whether the item passed the verification.
+    #[inline]
+    pub fn is_valid(&self) -> bool { matches!(self.outcome, Ok(true)) }
+}
+
+/// Aggregated outcomes of batch verification.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct BatchSummary {
+    /// Number of items passing the verification.
+    pub valid: usize,
+
+    /// Number of well-formed items not passing the verification.
+    pub invalid: usize,
+
+    /// Number of verification errors by their class.
+    pub classes: BTreeMap<ErrorClass, usize>,
+
+    /// Number of verification errors by their code (see
+    /// [`ErrorKindExt::error_code`]).
+    pub codes: BTreeMap<u16, usize>,
+}
+
+impl BatchSummary {
+    /// Returns number of failed verifications.
+    #[inline]
+    pub fn errors(&self) -> usize { self.classes.values().sum() }
+
+    /// Returns total number of the verified items.
+    #[inline]
+    pub fn total(&self) -> usize { self.valid + self.invalid + self.errors() }
+
+    /// Detects whether all items of the batch passed the verification.
+    #[inline]
+    pub fn is_valid(&self) -> bool { self.valid == self.total() }
+}
+
+/// Aggregates outcomes of batch verification by the error kind.
+pub fn summary<'res, Id, E>(
+    results: impl IntoIterator<Item = &'res ItemResult<Id, E>>,
+) -> BatchSummary
+where
+    Id: 'res,
+    E: ErrorKindExt + 'res,
+{
+    let mut summary = BatchSummary::default();
+    for result in results {
+        match &result.outcome {
+            Ok(true) => summary.valid += 1,
+            Ok(false) => summary.invalid += 1,
+            Err(err) => {
+                *summary.classes.entry(err.error_class()).or_default() += 1;
+                *summary.codes.entry(err.error_code()).or_default() += 1;
+            }
+        }
+    }
+    summary
+}
@@ -0,0 +1,345 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Scanning of full blocks for the deterministic bitcoin commitments.
+//!
+//! [`scan_block`] looks up anchors of the block transactions in an
+//! [`AnchorStore`] and verifies the ones revealing the message of a given
+//! protocol, for indexers processing full blocks. Transactions unknown to the
+//! store, which have an output looking like an opret commitment, are reported
+//! separately as commitment candidates for discovery pipelines. Tapret
+//! commitments are indistinguishable from other taproot outputs without the
+//! proof, so they are reported only for the anchors known to the store.
+
+use bitcoin::{Block, Script, Txid};
+use commit_verify::lnpbp4::ProtocolId;
+use commit_verify::ConsensusCommit;
+
+use crate::anchor::VerifyError;
+use crate::opret::OPRET_COMMITMENT_LEN;
+use crate::{AnchorId, AnchorStore, PreparedTx, VerificationOutcome};
+
+/// Result of verification of a block transaction by [`scan_block`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ScanOutcome {
+    /// Anchor from the store was verified against the transaction.
+    Verified(VerificationOutcome),
+
+    /// Anchor from the store can't be verified against the transaction.
+    Failed(VerifyError),
+}
+
+/// Transaction of a block matched by an anchor from the store, reported by
+/// [`scan_block`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BlockScanHit {
+    /// Id of the transaction.
+    pub txid: Txid,
+
+    /// Id of the anchor from the store matching the transaction.
+    pub anchor_id: AnchorId,
+
+    /// Result of the anchor verification.
+    pub outcome: ScanOutcome,
+}
+
+impl BlockScanHit {
+    /// Detects whether the transaction commits to the protocol message of the
+    /// matched anchor.
+    #[inline]
+    pub fn is_committed(&self) -> bool {
+        self.outcome == ScanOutcome::Verified(VerificationOutcome::Committed)
+    }
+}
+
+/// Transaction of a block unknown to the store, which first `OP_RETURN`
+/// output has the form of an opret commitment.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ScanCandidate {
+    /// Id of the transaction.
+    pub txid: Txid,
+
+    /// Number of the transaction output looking like a commitment.
+    pub vout: u32,
+}
+
+/// Results of [`scan_block`].
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct BlockScan {
+    /// Verification results for the anchors from the store, in the order of
+    /// the block transactions (and anchor ids for the same transaction).
+    pub hits: Vec<BlockScanHit>,
+
+    /// Commitment candidates in the transactions unknown to the store, in the
+    /// order of the block transactions.
+    pub candidates: Vec<ScanCandidate>,
+}
+
+/// Scans `block` for the commitments of the anchors from the store revealing
+/// message of the `protocol`.
+///
+/// Each block transaction is prepared once with [`PreparedTx`], which is used
+/// both to verify all matching anchors and to detect commitment candidates.
+/// Anchors not revealing message of the `protocol` are skipped.
+///
+/// ```
+/// use bitcoin::hashes::Hash;
+/// use bitcoin::{Block, BlockHash, BlockHeader, TxMerkleNode};
+/// use dbc::scan::{scan_block, ScanCandidate};
+/// use dbc::store::{AnchorStore, MemAnchorStore};
+/// use dbc::test_utils::{opret_fixture, protocol_fixture, tapret_fixture};
+///
+/// let (opret, opret_tx) = opret_fixture();
+/// let (_, tapret_tx) = tapret_fixture();
+/// let block = Block {
+///     header: BlockHeader {
+///         version: 2,
+///         prev_blockhash: BlockHash::all_zeros(),
+///         merkle_root: TxMerkleNode::all_zeros(),
+///         time: 0,
+///         bits: 0,
+///         nonce: 0,
+///     },
+///     txdata: vec![opret_tx, tapret_tx.clone()],
+/// };
+///
+/// let mut store = MemAnchorStore::new();
+/// store.insert(opret.clone()).unwrap();
+/// let (protocol_id, _) = protocol_fixture(0);
+/// let scan = scan_block(&block, &store, protocol_id).unwrap();
+/// assert_eq!(scan.hits.len(), 1);
+/// assert_eq!(scan.hits[0].anchor_id, opret.anchor_id());
+/// assert!(scan.hits[0].is_committed());
+///
+/// // Tapret witness transaction has `OP_RETURN` output looking like opret
+/// assert_eq!(scan.candidates, vec![ScanCandidate {
+///     txid: tapret_tx.txid(),
+///     vout: 0
+/// }]);
+/// ```
+///
+/// # Errors
+///
+/// Errors of the anchor store backend.
+pub fn scan_block<S: AnchorStore>(
+    block: &Block,
+    anchors: &S,
+    protocol: impl Into<ProtocolId>,
+) -> Result<BlockScan, S::Error> {
+    let protocol = protocol.into();
+    let mut scan = BlockScan::default();
+    for tx in &block.txdata {
+        let txid = tx.txid();
+        let ids = anchors.get_by_txid(&txid)?;
+        let prepared = PreparedTx::with_txid(tx.clone(), txid);
+        if ids.is_empty() {
+            if let Some(vout) =
+                prepared.op_return_outputs().first().filter(|vout| {
+                    is_opret_commitment(&tx.output[**vout].script_pubkey)
+                })
+            {
+                scan.candidates.push(ScanCandidate {
+                    txid,
+                    vout: *vout as u32,
+                });
+            }
+            continue;
+        }
+
+        for id in ids {
+            let anchor = match anchors.get(&id)? {
+                Some(anchor)
+                    if matches!(anchor.message(protocol), Ok(Some(_))) =>
+                {
+                    anchor
+                }
+                _ => continue,
+            };
+            let msg = anchor.lnpbp4_proof().consensus_commit();
+            let outcome = match anchor
+                .dbc_proof()
+                .verify_detailed_prepared(&msg, &prepared)
+            {
+                Ok(outcome) => ScanOutcome::Verified(outcome),
+                Err(err) => ScanOutcome::Failed(err),
+            };
+            scan.hits.push(BlockScanHit {
+                txid,
+                anchor_id: id,
+                outcome,
+            });
+        }
+    }
+    Ok(scan)
+}
+
+/// Detects whether the `OP_RETURN` script has the form of opret commitment,
+/// i.e. pushes exactly [`OPRET_COMMITMENT_LEN`] bytes.
+fn is_opret_commitment(script: &Script) -> bool {
+    let bytes = script.as_bytes();
+    bytes.len() == OPRET_COMMITMENT_LEN + 2
+        && bytes[1] as usize == OPRET_COMMITMENT_LEN
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use bitcoin::{BlockHash, BlockHeader, Transaction, TxMerkleNode, TxOut};
+
+    use super::*;
+    use crate::anchor::Proof;
+    use crate::opret::OpretProof;
+    use crate::store::MemAnchorStore;
+    use crate::test_utils::{
+        opret_fixture, protocol_fixture, tapret_fixture, tx_fixture,
+    };
+
+    /// Block with an opret and a tapret witness transactions among unrelated
+    /// ones, one of which has a commitment-like `OP_RETURN` output.
+    fn block_fixture() -> (Block, Vec<Transaction>) {
+        let (_, opret_tx) = opret_fixture();
+        let (_, tapret_tx) = tapret_fixture();
+        let payment = tx_fixture(vec![TxOut {
+            value: 5000,
+            script_pubkey: Script::new_v0_p2wpkh(&Hash::hash(b"payment")),
+        }]);
+        let short_data = tx_fixture(vec![TxOut {
+            value: 0,
+            script_pubkey: Script::new_op_return(&[0xAAu8; 20]),
+        }]);
+        let unknown = tx_fixture(vec![
+            TxOut {
+                value: 5000,
+                script_pubkey: Script::new_v0_p2wpkh(&Hash::hash(b"change")),
+            },
+            TxOut {
+                value: 0,
+                script_pubkey: Script::new_op_return(
+                    &[0xBBu8; OPRET_COMMITMENT_LEN],
+                ),
+            },
+        ]);
+        let txdata =
+            vec![payment, opret_tx, short_data, tapret_tx, unknown.clone()];
+        let block = Block {
+            header: BlockHeader {
+                version: 2,
+                prev_blockhash: BlockHash::all_zeros(),
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: 0,
+                bits: 0,
+                nonce: 0,
+            },
+            txdata: txdata.clone(),
+        };
+        (block, txdata)
+    }
+
+    #[test]
+    fn scan() {
+        let (block, txdata) = block_fixture();
+        let (opret, _) = opret_fixture();
+        let (tapret, _) = tapret_fixture();
+        let mut store = MemAnchorStore::new();
+        store.insert(tapret.clone()).unwrap();
+        store.insert(opret.clone()).unwrap();
+
+        let (protocol_id, _) = protocol_fixture(1);
+        let scan = scan_block(&block, &store, protocol_id).unwrap();
+        assert_eq!(scan.hits, vec![
+            BlockScanHit {
+                txid: opret.txid(),
+                anchor_id: opret.anchor_id(),
+                outcome: ScanOutcome::Verified(VerificationOutcome::Committed),
+            },
+            BlockScanHit {
+                txid: tapret.txid(),
+                anchor_id: tapret.anchor_id(),
+                outcome: ScanOutcome::Verified(VerificationOutcome::Committed),
+            },
+        ]);
+        assert!(scan.hits.iter().all(BlockScanHit::is_committed));
+        assert_eq!(scan.candidates, vec![ScanCandidate {
+            txid: txdata[4].txid(),
+            vout: 1
+        }]);
+    }
+
+    #[test]
+    fn scan_empty_store() {
+        let (block, txdata) = block_fixture();
+        let (protocol_id, _) = protocol_fixture(0);
+        let scan =
+            scan_block(&block, &MemAnchorStore::new(), protocol_id).unwrap();
+        assert!(scan.hits.is_empty());
+        assert_eq!(scan.candidates, vec![
+            ScanCandidate {
+                txid: txdata[1].txid(),
+                vout: 1
+            },
+            ScanCandidate {
+                txid: txdata[3].txid(),
+                vout: 0
+            },
+            ScanCandidate {
+                txid: txdata[4].txid(),
+                vout: 1
+            },
+        ]);
+    }
+
+    #[test]
+    fn scan_other_protocol() {
+        let (block, _) = block_fixture();
+        let (mut opret, _) = opret_fixture();
+        let (protocol_id, _) = protocol_fixture(1);
+        let (other_id, _) = protocol_fixture(2);
+        opret.conceal_except([other_id]).unwrap();
+        let mut store = MemAnchorStore::new();
+        store.insert(opret.clone()).unwrap();
+
+        // Anchors not revealing the protocol are skipped, but their witness
+        // transactions are not reported as candidates
+        let scan = scan_block(&block, &store, protocol_id).unwrap();
+        assert!(scan.hits.is_empty());
+        assert!(scan.candidates.iter().all(|c| c.txid != opret.txid()));
+        let scan = scan_block(&block, &store, other_id).unwrap();
+        assert_eq!(scan.hits[0].anchor_id, opret.anchor_id());
+        assert!(scan.hits[0].is_committed());
+    }
+
+    #[test]
+    fn scan_mismatch() {
+        let (block, _) = block_fixture();
+        let (opret, _) = opret_fixture();
+        let opret =
+            opret.with_dbc_proof(Proof::OpretFirstAt(OpretProof { vout: 0 }));
+        let mut store = MemAnchorStore::new();
+        store.insert(opret.clone()).unwrap();
+
+        let (protocol_id, _) = protocol_fixture(0);
+        let scan = scan_block(&block, &store, protocol_id).unwrap();
+        assert_eq!(
+            scan.hits[0].outcome,
+            ScanOutcome::Verified(
+                VerificationOutcome::CommitmentOutputIndexMismatch {
+                    expected: 0,
+                    actual: 1
+                }
+            )
+        );
+        assert!(!scan.hits[0].is_committed());
+    }
+}
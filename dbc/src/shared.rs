@@ -0,0 +1,367 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Reference-counted LNPBP-4 merkle blocks, shared between anchors.
+//!
+//! Indexers keep many anchors for the same witness transaction, which differ
+//! only in the concealment state of their LNPBP-4 data. With
+//! `Anchor<SharedMerkleBlock>` such anchors share a single copy of the merkle
+//! block, which gets copied only when one of the anchors is modified.
+
+use std::io;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use commit_verify::lnpbp4::{self, MerkleBlock, ProtocolId};
+use commit_verify::{CommitEncode, ConsensusCommit};
+use strict_encoding::{StrictDecode, StrictEncode};
+
+use crate::anchor::{
+    commit_encode_anchor, merge_blocks, Lnpbp4Leaves, MergeError,
+    ProofExtractError,
+};
+use crate::{Anchor, AnchorId};
+
+#[cfg(test)]
+thread_local! {
+    /// Number of deep copies of merkle blocks made by the current thread.
+    static DEEP_COPIES: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// LNPBP-4 merkle block with copy-on-write shared ownership.
+///
+/// Clones of the block are shallow; the block data are copied only on
+/// mutation of a block which is shared with other clones. Strict encoding,
+/// commit encoding and serde representation are the same as for
+/// [`MerkleBlock`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SharedMerkleBlock(Arc<MerkleBlock>);
+
+impl lnpbp4::Proof for SharedMerkleBlock {}
+
+impl Lnpbp4Leaves for SharedMerkleBlock {
+    #[inline]
+    fn has_known_leaves(&self) -> bool { self.0.has_known_leaves() }
+
+    #[inline]
+    fn known_leaf_count(&self) -> usize { self.0.known_leaf_count() }
+}
+
+impl Deref for SharedMerkleBlock {
+    type Target = MerkleBlock;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target { &self.0 }
+}
+
+impl AsRef<MerkleBlock> for SharedMerkleBlock {
+    #[inline]
+    fn as_ref(&self) -> &MerkleBlock { &self.0 }
+}
+
+impl From<MerkleBlock> for SharedMerkleBlock {
+    #[inline]
+    fn from(block: MerkleBlock) -> Self { SharedMerkleBlock(Arc::new(block)) }
+}
+
+impl From<SharedMerkleBlock> for MerkleBlock {
+    #[inline]
+    fn from(block: SharedMerkleBlock) -> Self { block.into_merkle_block() }
+}
+
+impl SharedMerkleBlock {
+    /// Detects whether the block data are shared with other clones.
+    #[inline]
+    pub fn is_shared(&self) -> bool { Arc::strong_count(&self.0) > 1 }
+
+    /// Detects whether two blocks share the same data.
+    #[inline]
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Returns mutable reference to the block data, copying them first if they
+    /// are shared with other clones.
+    pub fn make_mut(&mut self) -> &mut MerkleBlock {
+        #[cfg(test)]
+        if self.is_shared() {
+            DEEP_COPIES.with(|copies| copies.set(copies.get() + 1));
+        }
+        Arc::make_mut(&mut self.0)
+    }
+
+    /// Releases the block data, copying them if they are shared with other
+    /// clones.
+    pub fn into_merkle_block(self) -> MerkleBlock {
+        Arc::try_unwrap(self.0).unwrap_or_else(|block| {
+            #[cfg(test)]
+            DEEP_COPIES.with(|copies| copies.set(copies.get() + 1));
+            (*block).clone()
+        })
+    }
+}
+
+impl CommitEncode for SharedMerkleBlock {
+    #[inline]
+    fn commit_encode<E: io::Write>(&self, e: E) -> usize {
+        self.0.commit_encode(e)
+    }
+}
+
+impl StrictEncode for SharedMerkleBlock {
+    #[inline]
+    fn strict_encode<E: io::Write>(
+        &self,
+        e: E,
+    ) -> Result<usize, strict_encoding::Error> {
+        self.0.strict_encode(e)
+    }
+}
+
+impl StrictDecode for SharedMerkleBlock {
+    #[inline]
+    fn strict_decode<D: io::Read>(
+        d: D,
+    ) -> Result<Self, strict_encoding::Error> {
+        MerkleBlock::strict_decode(d).map(SharedMerkleBlock::from)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde_crate::Serialize for SharedMerkleBlock {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde_crate::Serializer,
+    {
+        self.0.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde_crate::Deserialize<'de> for SharedMerkleBlock {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde_crate::Deserializer<'de>,
+    {
+        MerkleBlock::deserialize(deserializer).map(SharedMerkleBlock::from)
+    }
+}
+
+impl From<Anchor<MerkleBlock>> for Anchor<SharedMerkleBlock> {
+    #[inline]
+    fn from(anchor: Anchor<MerkleBlock>) -> Self {
+        let (txid, lnpbp4_proof, dbc_proof) = anchor.into_parts();
+        Anchor::from_parts(txid, lnpbp4_proof.into(), dbc_proof)
+    }
+}
+
+impl From<Anchor<SharedMerkleBlock>> for Anchor<MerkleBlock> {
+    #[inline]
+    fn from(anchor: Anchor<SharedMerkleBlock>) -> Self {
+        let (txid, lnpbp4_proof, dbc_proof) = anchor.into_parts();
+        Anchor::from_parts(txid, lnpbp4_proof.into(), dbc_proof)
+    }
+}
+
+/// Commit encoding matches the one of `Anchor<MerkleBlock>`, such that shared
+/// and non-shared anchors have the same [`AnchorId`].
+///
+/// # Panics
+///
+/// If the writer `e` fails; use [`Anchor::commit_encode_fallible`] with
+/// writers performing I/O.
+impl CommitEncode for Anchor<SharedMerkleBlock> {
+    fn commit_encode<E: io::Write>(&self, mut e: E) -> usize {
+        let mut data = vec![];
+        let len = self
+            .commit_encode_fallible(&mut data)
+            .expect("memory encoders do not fail");
+        e.write_all(&data).expect("commit encoding writer failure");
+        len
+    }
+}
+
+impl Anchor<SharedMerkleBlock> {
+    /// Writes commit encoding of the anchor to `e`, returning number of
+    /// written bytes or the writer I/O error.
+    #[inline]
+    pub fn commit_encode_fallible<E: io::Write>(
+        &self,
+        e: E,
+    ) -> io::Result<usize> {
+        commit_encode_anchor(self, e)
+    }
+}
+
+impl ConsensusCommit for Anchor<SharedMerkleBlock> {
+    type Commitment = AnchorId;
+}
+
+impl Anchor<SharedMerkleBlock> {
+    /// Returns id of the anchor (commitment hash).
+    #[inline]
+    pub fn anchor_id(&self) -> AnchorId { self.consensus_commit() }
+
+    /// Clones the anchor sharing its LNPBP-4 merkle block with the original.
+    #[inline]
+    pub fn shallow_clone(&self) -> Self { self.clone() }
+
+    /// Conceals all LNPBP-4 data except specific protocol and produces merkle
+    /// proof anchor.
+    pub fn to_merkle_proof(
+        &self,
+        protocol: impl Into<ProtocolId>,
+    ) -> Result<Anchor<lnpbp4::MerkleProof>, ProofExtractError> {
+        let protocol = protocol.into();
+        let lnpbp4_proof =
+            self.lnpbp4_proof().to_merkle_proof(protocol).map_err(|_| {
+                ProofExtractError::with(protocol, self.lnpbp4_proof())
+            })?;
+        Ok(Anchor::from_parts(
+            self.txid(),
+            lnpbp4_proof,
+            self.dbc_proof().clone(),
+        ))
+    }
+
+    /// Conceals all LNPBP-4 data except specific protocol, copying the merkle
+    /// block if it is shared with other anchors.
+    pub fn conceal_except(
+        &mut self,
+        protocols: impl AsRef<[ProtocolId]>,
+    ) -> Result<usize, lnpbp4::LeafNotKnown> {
+        self.lnpbp4_proof_mut().make_mut().conceal_except(protocols)
+    }
+
+    /// Merges two anchors keeping revealed data. The merkle block is copied
+    /// only if it is shared with other anchors and the merge may change it.
+    pub fn merge_reveal(mut self, other: Self) -> Result<Self, MergeError> {
+        if self.txid() != other.txid() {
+            return Err(MergeError::TxidMismatch);
+        }
+        if self.dbc_proof() != other.dbc_proof() {
+            return Err(MergeError::ProofMismatch);
+        }
+        if self.lnpbp4_proof().ptr_eq(other.lnpbp4_proof())
+            || self.lnpbp4_proof() == other.lnpbp4_proof()
+        {
+            return Ok(self);
+        }
+        let merged = merge_blocks(self.lnpbp4_proof(), other.lnpbp4_proof())?;
+        *self.lnpbp4_proof_mut().make_mut() = merged;
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use bitcoin::Txid;
+
+    use super::*;
+    use crate::test_utils::{lnpbp4_fixture_with, protocol_fixture};
+    use crate::Proof;
+
+    fn anchor_fixture() -> Anchor<MerkleBlock> {
+        Anchor::from_parts(
+            Txid::hash(b"witness"),
+            MerkleBlock::from(lnpbp4_fixture_with(0..4)),
+            Proof::OpretFirst,
+        )
+    }
+
+    fn deep_copies() -> usize { DEEP_COPIES.with(|copies| copies.get()) }
+
+    #[test]
+    fn encoding_equivalence() {
+        let anchor = anchor_fixture();
+        let shared = Anchor::<SharedMerkleBlock>::from(anchor.clone());
+        assert_eq!(shared.anchor_id(), anchor.anchor_id());
+
+        let data = strict_encoding::strict_serialize(&shared).unwrap();
+        assert_eq!(data, strict_encoding::strict_serialize(&anchor).unwrap());
+        let decoded: Anchor<SharedMerkleBlock> =
+            strict_encoding::strict_deserialize(&data).unwrap();
+        assert_eq!(decoded, shared);
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&shared).unwrap();
+            assert_eq!(json, serde_json::to_string(&anchor).unwrap());
+            let decoded: Anchor<SharedMerkleBlock> =
+                serde_json::from_str(&json).unwrap();
+            assert_eq!(decoded, shared);
+        }
+
+        assert_eq!(Anchor::<MerkleBlock>::from(shared), anchor);
+    }
+
+    #[test]
+    fn copy_on_write() {
+        let (protocol_id, _) = protocol_fixture(1);
+        let anchor = anchor_fixture();
+        let shared = Anchor::<SharedMerkleBlock>::from(anchor.clone());
+        let copies = deep_copies();
+
+        let mut clones =
+            (0..100).map(|_| shared.shallow_clone()).collect::<Vec<_>>();
+        assert!(clones
+            .iter()
+            .all(|clone| clone.lnpbp4_proof().ptr_eq(shared.lnpbp4_proof())));
+        assert_eq!(deep_copies(), copies);
+
+        // Merging identical blocks does not copy
+        let identical = clones
+            .pop()
+            .unwrap()
+            .merge_reveal(shared.shallow_clone())
+            .unwrap();
+        assert!(identical.lnpbp4_proof().ptr_eq(shared.lnpbp4_proof()));
+        assert_eq!(deep_copies(), copies);
+
+        // Mutation copies the block only once
+        let mut concealed = clones.pop().unwrap();
+        concealed.conceal_except([protocol_id]).unwrap();
+        assert_eq!(deep_copies(), copies + 1);
+        assert!(!concealed.lnpbp4_proof().ptr_eq(shared.lnpbp4_proof()));
+        concealed.conceal_except([protocol_id]).unwrap();
+        assert_eq!(deep_copies(), copies + 1);
+
+        let mut expected = anchor.clone();
+        expected.conceal_except([protocol_id]).unwrap();
+        assert_eq!(Anchor::<MerkleBlock>::from(concealed.clone()), expected);
+        assert_eq!(concealed.anchor_id(), anchor.anchor_id());
+        assert_eq!(
+            concealed.to_merkle_proof(protocol_id).unwrap(),
+            anchor.to_merkle_proof(protocol_id).unwrap()
+        );
+
+        // Merging behaves the same as for non-shared anchors
+        let merged = concealed.merge_reveal(shared.shallow_clone()).unwrap();
+        assert_eq!(
+            Anchor::<MerkleBlock>::from(merged),
+            expected.merge_reveal(anchor.clone()).unwrap()
+        );
+
+        // Unshared blocks are released without copying
+        drop(clones);
+        drop(identical);
+        let copies = deep_copies();
+        let _ = Anchor::<MerkleBlock>::from(shared);
+        assert_eq!(deep_copies(), copies);
+    }
+}
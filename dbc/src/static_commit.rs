@@ -0,0 +1,93 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Embed-commit-verify scheme producing the same commitment each time it is
+//! applied to the same container and message.
+//!
+//! [`EmbedCommitVerify`] implementations may use random numbers (like LNPBP-4
+//! entropy) when committing, so repeating the commitment produces a different
+//! container. Traits of this module are their static counterparts, which are
+//! not provided by `commit_verify`.
+
+use commit_verify::embed_commit::VerifyEq;
+#[cfg(doc)]
+use commit_verify::EmbedCommitVerify;
+use commit_verify::{CommitEncode, CommitmentProtocol};
+
+/// Proof type used by [`EmbedCommitVerifyStatic`] protocol.
+pub trait EmbedCommitProofStatic<Msg, Container, Protocol>
+where
+    Self: Sized + VerifyEq,
+    Container: EmbedCommitVerifyStatic<Msg, Protocol>,
+    Msg: CommitEncode,
+    Protocol: CommitmentProtocol,
+{
+    /// Restores original container before the commitment from the proof data
+    /// and a container containing embedded commitment.
+    fn restore_original_container(
+        &self,
+        commit_container: &Container,
+    ) -> Result<Container, Container::VerifyError>;
+}
+
+/// Trait for containers supporting embed-commit-verify scheme without random
+/// numbers, such that repeated commitments to the same message produce the
+/// same container and proof.
+pub trait EmbedCommitVerifyStatic<Msg, Protocol>
+where
+    Self: Sized,
+    Msg: CommitEncode,
+    Protocol: CommitmentProtocol,
+{
+    /// The proof of the commitment produced as a result of
+    /// [`Self::embed_commit_static`] procedure.
+    type Proof: EmbedCommitProofStatic<Msg, Self, Protocol>;
+
+    /// Error type that may be reported during [`Self::embed_commit_static`]
+    /// procedure.
+    type CommitError: std::error::Error;
+
+    /// Error type that may be reported during [`Self::verify_static`]
+    /// procedure. It must be a subset of [`Self::CommitError`].
+    type VerifyError: std::error::Error + From<Self::CommitError>;
+
+    /// Creates a commitment to a message and embeds it into the provided
+    /// container (`self`) by mutating it and returning commitment proof.
+    fn embed_commit_static(
+        &mut self,
+        msg: &Msg,
+    ) -> Result<Self::Proof, Self::CommitError>;
+
+    /// Verifies commitment with commitment proof against the message by
+    /// restoring the original container with
+    /// [`EmbedCommitProofStatic::restore_original_container`] and repeating
+    /// [`Self::embed_commit_static`] procedure.
+    ///
+    /// Errors if the commitment can't be re-created for the restored
+    /// container.
+    #[inline]
+    fn verify_static(
+        &self,
+        msg: &Msg,
+        proof: Self::Proof,
+    ) -> Result<bool, Self::VerifyError>
+    where
+        Self: VerifyEq,
+    {
+        let mut container_prime = proof.restore_original_container(self)?;
+        let proof_prime = container_prime.embed_commit_static(msg)?;
+        Ok(proof_prime.verify_eq(&proof) && self.verify_eq(&container_prime))
+    }
+}
@@ -0,0 +1,287 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Storage of anchors indexed by anchor id and witness transaction id.
+//!
+//! [`AnchorStore`] is the contract for the storage backends (databases,
+//! key-value stores etc) keeping anchors of a client-side-validated protocol.
+//! Backends implement only reading and writing of anchors, while adding
+//! anchors with [`AnchorStore::insert`] merges copies of the same anchor with
+//! different LNPBP-4 leaves revealed, like [`Anchor::merge_reveal`] does.
+//! [`MemAnchorStore`] is the in-memory reference implementation.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::Infallible;
+
+use bitcoin::Txid;
+use commit_verify::lnpbp4;
+
+use crate::anchor::MergeError;
+use crate::{Anchor, AnchorId};
+
+/// Result of adding anchor to [`AnchorStore`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum InsertOutcome {
+    /// Anchor was not known to the store.
+    Inserted,
+
+    /// Anchor was known to the store, and the stored copy was extended with
+    /// LNPBP-4 leaves revealed by the added anchor.
+    Merged,
+
+    /// Anchor was known to the store, and the stored copy already reveals all
+    /// LNPBP-4 leaves of the added anchor.
+    Known,
+
+    /// Anchor has the same id as the stored one, but their LNPBP-4 merkle
+    /// blocks can't be merged. Happens only with malformed merkle blocks; the
+    /// stored anchor is left unchanged.
+    Conflict(MergeError),
+}
+
+/// Storage of anchors indexed by anchor id and witness transaction id.
+pub trait AnchorStore {
+    /// Errors of the storage backend.
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Returns anchor with the given id, if known.
+    fn get(
+        &self,
+        id: &AnchorId,
+    ) -> Result<Option<Anchor<lnpbp4::MerkleBlock>>, Self::Error>;
+
+    /// Returns ids of the known anchors having witness transaction `txid`,
+    /// ordered by anchor id.
+    fn get_by_txid(&self, txid: &Txid) -> Result<Vec<AnchorId>, Self::Error>;
+
+    /// Iterates over ids of the known anchors.
+    fn iter_ids(
+        &self,
+    ) -> Box<dyn Iterator<Item = Result<AnchorId, Self::Error>> + '_>;
+
+    /// Writes `anchor` under its `id`, replacing previously stored anchor with
+    /// the same id, and indexes it by its witness transaction id.
+    ///
+    /// This is the backend primitive used by [`AnchorStore::insert`]; callers
+    /// should use `insert` instead, which never loses revealed data.
+    fn put(
+        &mut self,
+        id: AnchorId,
+        anchor: Anchor<lnpbp4::MerkleBlock>,
+    ) -> Result<(), Self::Error>;
+
+    /// Adds `anchor` to the store. If an anchor with the same id is already
+    /// known, the stored copy is merged with the added one with
+    /// [`Anchor::merge_reveal`].
+    fn insert(
+        &mut self,
+        anchor: Anchor<lnpbp4::MerkleBlock>,
+    ) -> Result<InsertOutcome, Self::Error> {
+        let id = anchor.anchor_id();
+        let known = match self.get(&id)? {
+            Some(known) => known,
+            None => {
+                self.put(id, anchor)?;
+                return Ok(InsertOutcome::Inserted);
+            }
+        };
+        match known.clone().merge_reveal(anchor) {
+            Ok(merged) if merged == known => Ok(InsertOutcome::Known),
+            Ok(merged) => {
+                self.put(id, merged)?;
+                Ok(InsertOutcome::Merged)
+            }
+            Err(err) => Ok(InsertOutcome::Conflict(err)),
+        }
+    }
+}
+
+/// In-memory [`AnchorStore`].
+///
+/// ```
+/// use dbc::store::{AnchorStore, InsertOutcome, MemAnchorStore};
+/// use dbc::test_utils::{opret_fixture, protocol_fixture};
+///
+/// let (anchor, _) = opret_fixture();
+/// let (protocol_id, _) = protocol_fixture(0);
+/// let mut concealed = anchor.clone();
+/// concealed.conceal_other(protocol_id).unwrap();
+///
+/// let mut store = MemAnchorStore::new();
+/// assert_eq!(store.insert(concealed.clone()), Ok(InsertOutcome::Inserted));
+/// assert_eq!(store.insert(concealed), Ok(InsertOutcome::Known));
+/// assert_eq!(store.insert(anchor.clone()), Ok(InsertOutcome::Merged));
+///
+/// let id = anchor.anchor_id();
+/// assert_eq!(store.get_by_txid(&anchor.txid()), Ok(vec![id]));
+/// assert_eq!(store.get(&id).unwrap().unwrap().known_protocol_count(), 3);
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct MemAnchorStore {
+    anchors: BTreeMap<AnchorId, Anchor<lnpbp4::MerkleBlock>>,
+    txids: BTreeMap<Txid, BTreeSet<AnchorId>>,
+}
+
+impl MemAnchorStore {
+    /// Constructs empty store.
+    #[inline]
+    pub fn new() -> MemAnchorStore { MemAnchorStore::default() }
+
+    /// Returns number of anchors in the store.
+    #[inline]
+    pub fn len(&self) -> usize { self.anchors.len() }
+
+    /// Detects whether the store contains no anchors.
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.anchors.is_empty() }
+}
+
+impl AnchorStore for MemAnchorStore {
+    type Error = Infallible;
+
+    fn get(
+        &self,
+        id: &AnchorId,
+    ) -> Result<Option<Anchor<lnpbp4::MerkleBlock>>, Self::Error> {
+        Ok(self.anchors.get(id).cloned())
+    }
+
+    fn get_by_txid(&self, txid: &Txid) -> Result<Vec<AnchorId>, Self::Error> {
+        Ok(self
+            .txids
+            .get(txid)
+            .map(|ids| ids.iter().copied().collect())
+            .unwrap_or_default())
+    }
+
+    fn iter_ids(
+        &self,
+    ) -> Box<dyn Iterator<Item = Result<AnchorId, Self::Error>> + '_> {
+        Box::new(self.anchors.keys().copied().map(Ok))
+    }
+
+    fn put(
+        &mut self,
+        id: AnchorId,
+        anchor: Anchor<lnpbp4::MerkleBlock>,
+    ) -> Result<(), Self::Error> {
+        self.txids.entry(anchor.txid()).or_default().insert(id);
+        self.anchors.insert(id, anchor);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::consts::ANCHOR_MIN_LNPBP4_DEPTH;
+    use crate::test_utils::{
+        lnpbp4_tree_fixture, opret_fixture, protocol_fixture, tapret_fixture,
+    };
+
+    #[test]
+    fn insert() {
+        let (anchor, _) = opret_fixture();
+        let id = anchor.anchor_id();
+        let copies = (0u8..3)
+            .map(|no| {
+                let mut copy = anchor.clone();
+                copy.conceal_other(protocol_fixture(no).0).unwrap();
+                copy
+            })
+            .collect::<Vec<_>>();
+
+        let mut store = MemAnchorStore::new();
+        assert_eq!(
+            store.insert(copies[0].clone()),
+            Ok(InsertOutcome::Inserted)
+        );
+        assert_eq!(store.insert(copies[0].clone()), Ok(InsertOutcome::Known));
+        assert_eq!(store.get(&id), Ok(Some(copies[0].clone())));
+
+        // Copies of the same anchor revealing other leaves are merged
+        assert_eq!(store.insert(copies[1].clone()), Ok(InsertOutcome::Merged));
+        assert_eq!(store.insert(copies[2].clone()), Ok(InsertOutcome::Merged));
+        let stored = store.get(&id).unwrap().unwrap();
+        assert_eq!(stored.anchor_id(), id);
+        assert_eq!(stored.known_protocol_count(), 3);
+        for copy in &copies {
+            assert_eq!(store.insert(copy.clone()), Ok(InsertOutcome::Known));
+        }
+        let mut concealed = anchor.clone();
+        concealed.conceal_all();
+        assert_eq!(store.insert(concealed), Ok(InsertOutcome::Known));
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.get(&id), Ok(Some(stored)));
+    }
+
+    #[test]
+    fn conflict() {
+        let (opret, _) = opret_fixture();
+        let (tapret, _) = tapret_fixture();
+        let id = opret.anchor_id();
+
+        // Backend returning other anchor under the requested id
+        let mut store = MemAnchorStore::new();
+        store.put(id, tapret.clone()).unwrap();
+        assert_eq!(
+            store.insert(opret),
+            Ok(InsertOutcome::Conflict(MergeError::TxidMismatch))
+        );
+        assert_eq!(store.get(&id), Ok(Some(tapret)));
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn index() {
+        let (opret, _) = opret_fixture();
+        let (tapret, _) = tapret_fixture();
+        // Anchor with the same witness transaction, but a different LNPBP-4
+        // tree, has a different id
+        let source = lnpbp4::MultiSource {
+            min_depth: ANCHOR_MIN_LNPBP4_DEPTH,
+            messages: (0u8..2).map(protocol_fixture).collect(),
+        };
+        let other = tapret.clone().with_lnpbp4_proof(
+            lnpbp4::MerkleBlock::from(lnpbp4_tree_fixture(&source)),
+        );
+
+        let mut store = MemAnchorStore::new();
+        assert!(store.is_empty());
+        assert_eq!(store.get(&opret.anchor_id()), Ok(None));
+        assert_eq!(store.get_by_txid(&opret.txid()), Ok(vec![]));
+        for anchor in [&opret, &tapret, &other] {
+            assert_eq!(
+                store.insert(anchor.clone()),
+                Ok(InsertOutcome::Inserted)
+            );
+        }
+        assert_eq!(store.len(), 3);
+
+        assert_eq!(
+            store.get_by_txid(&opret.txid()),
+            Ok(vec![opret.anchor_id()])
+        );
+        let mut expected = vec![tapret.anchor_id(), other.anchor_id()];
+        expected.sort();
+        assert_eq!(store.get_by_txid(&tapret.txid()), Ok(expected));
+
+        let ids = store.iter_ids().collect::<Result<Vec<_>, _>>().unwrap();
+        let mut expected =
+            vec![opret.anchor_id(), tapret.anchor_id(), other.anchor_id()];
+        expected.sort();
+        assert_eq!(ids, expected);
+    }
+}
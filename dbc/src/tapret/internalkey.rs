@@ -0,0 +1,324 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Reading taproot internal keys written by legacy PSBT tools.
+//!
+//! BIP-371 defines taproot internal keys in PSBT as 32-byte x-only keys, and
+//! PSBT parsers reject keys of other lengths. Some legacy tools write them as
+//! 33-byte compressed keys instead; [`normalize_internal_keys`] rewrites such
+//! PSBTs into the canonical form before they are parsed.
+
+use std::io::{self, Read};
+
+use bitcoin::consensus::encode::{self, Decodable, Encodable, VarInt};
+use bitcoin::{PublicKey, Transaction, XOnlyPublicKey};
+
+/// PSBT magic bytes preceding the global map.
+const PSBT_MAGIC: &[u8] = b"psbt\xff";
+/// Global PSBT key holding unsigned transaction (PSBT v0).
+const PSBT_GLOBAL_UNSIGNED_TX: u64 = 0x00;
+/// Global PSBT key holding the number of inputs (PSBT v2).
+const PSBT_GLOBAL_INPUT_COUNT: u64 = 0x04;
+/// Global PSBT key holding the number of outputs (PSBT v2).
+const PSBT_GLOBAL_OUTPUT_COUNT: u64 = 0x05;
+/// Input PSBT key holding taproot internal key.
+const PSBT_IN_TAP_INTERNAL_KEY: u64 = 0x17;
+/// Output PSBT key holding taproot internal key.
+const PSBT_OUT_TAP_INTERNAL_KEY: u64 = 0x05;
+
+/// Errors reading taproot internal keys.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum InternalKeyError {
+    /// taproot internal key has length of {0} bytes, while only 32-byte
+    /// x-only and 33-byte compressed keys are supported.
+    InvalidLength(usize),
+
+    /// taproot internal key is not a valid secp256k1 public key.
+    InvalidKey,
+
+    /// PSBT data are malformed.
+    MalformedPsbt,
+}
+
+impl From<encode::Error> for InternalKeyError {
+    fn from(_: encode::Error) -> Self { InternalKeyError::MalformedPsbt }
+}
+
+impl From<io::Error> for InternalKeyError {
+    fn from(_: io::Error) -> Self { InternalKeyError::MalformedPsbt }
+}
+
+/// Decodes taproot internal key from either 32-byte x-only or 33-byte
+/// compressed encoding. The parity byte of the compressed encoding is
+/// validated and dropped, since the internal key is always x-only.
+///
+/// ```
+/// use dbc::tapret::{decode_internal_key, InternalKeyError};
+/// use dbc::test_utils::internal_key_fixture;
+///
+/// let key = internal_key_fixture();
+/// let mut compressed = vec![0x03];
+/// compressed.extend(key.serialize());
+/// assert_eq!(decode_internal_key(&key.serialize()), Ok(key));
+/// assert_eq!(decode_internal_key(&compressed), Ok(key));
+/// assert_eq!(
+///     decode_internal_key(&compressed[..30]),
+///     Err(InternalKeyError::InvalidLength(30))
+/// );
+/// ```
+pub fn decode_internal_key(
+    data: &[u8],
+) -> Result<XOnlyPublicKey, InternalKeyError> {
+    match data.len() {
+        32 => XOnlyPublicKey::from_slice(data)
+            .map_err(|_| InternalKeyError::InvalidKey),
+        33 => PublicKey::from_slice(data)
+            .ok()
+            .filter(|key| key.compressed)
+            .map(|key| key.inner.x_only_public_key().0)
+            .ok_or(InternalKeyError::InvalidKey),
+        len => Err(InternalKeyError::InvalidLength(len)),
+    }
+}
+
+type Map = Vec<(Vec<u8>, Vec<u8>)>;
+
+fn read_bytes(
+    reader: &mut io::Cursor<&[u8]>,
+) -> Result<Vec<u8>, InternalKeyError> {
+    let len = VarInt::consensus_decode(reader)?.0;
+    let remaining = reader.get_ref().len() as u64 - reader.position();
+    if len > remaining {
+        return Err(InternalKeyError::MalformedPsbt);
+    }
+    let mut data = vec![0u8; len as usize];
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+fn read_map(reader: &mut io::Cursor<&[u8]>) -> Result<Map, InternalKeyError> {
+    let mut map = Map::new();
+    loop {
+        let key = read_bytes(reader)?;
+        if key.is_empty() {
+            return Ok(map);
+        }
+        let value = read_bytes(reader)?;
+        map.push((key, value));
+    }
+}
+
+fn write_map(map: &Map, data: &mut Vec<u8>) {
+    for (key, value) in map {
+        key.consensus_encode(data).expect("in-memory writer");
+        value.consensus_encode(data).expect("in-memory writer");
+    }
+    data.push(0x00);
+}
+
+fn key_type(key: &[u8]) -> Result<u64, InternalKeyError> {
+    Ok(VarInt::consensus_decode(&mut &key[..])?.0)
+}
+
+/// Returns number of the inputs and outputs of the PSBT from its global map.
+fn map_counts(global: &Map) -> Result<(u64, u64), InternalKeyError> {
+    let (mut inputs, mut outputs) = (None, None);
+    for (key, value) in global {
+        match key_type(key)? {
+            PSBT_GLOBAL_UNSIGNED_TX => {
+                let tx: Transaction = encode::deserialize(value)?;
+                inputs = Some(tx.input.len() as u64);
+                outputs = Some(tx.output.len() as u64);
+            }
+            PSBT_GLOBAL_INPUT_COUNT => {
+                inputs = Some(encode::deserialize::<VarInt>(value)?.0)
+            }
+            PSBT_GLOBAL_OUTPUT_COUNT => {
+                outputs = Some(encode::deserialize::<VarInt>(value)?.0)
+            }
+            _ => {}
+        }
+    }
+    inputs.zip(outputs).ok_or(InternalKeyError::MalformedPsbt)
+}
+
+fn normalize_map(map: &mut Map, key_ty: u64) -> Result<(), InternalKeyError> {
+    for (key, value) in map {
+        if key.len() == 1 && key_type(key)? == key_ty {
+            *value = decode_internal_key(value)?.serialize().to_vec();
+        }
+    }
+    Ok(())
+}
+
+/// Rewrites taproot internal keys of the serialized PSBT inputs and outputs
+/// into the canonical 32-byte x-only form, accepting keys written in 33-byte
+/// compressed form by legacy tools. All other PSBT data are kept as is.
+///
+/// # Errors
+///
+/// Errors if the PSBT can't be split into key-value maps, or if any of the
+/// taproot internal keys is invalid (see [`decode_internal_key`]).
+///
+/// ```
+/// # #[cfg(feature = "wallet")] {
+/// use dbc::tapret::normalize_internal_keys;
+/// use dbc::test_utils::psbt_fixture;
+/// use dbc::CloseMethod;
+/// use psbt::serialize::{Deserialize, Serialize};
+/// use psbt::Psbt;
+///
+/// let data = psbt_fixture(CloseMethod::TapretFirst).serialize();
+/// let normalized = normalize_internal_keys(&data).unwrap();
+/// assert_eq!(normalized, data);
+/// assert!(Psbt::deserialize(&normalized).is_ok());
+/// # }
+/// ```
+pub fn normalize_internal_keys(
+    psbt: &[u8],
+) -> Result<Vec<u8>, InternalKeyError> {
+    let data = psbt
+        .strip_prefix(PSBT_MAGIC)
+        .ok_or(InternalKeyError::MalformedPsbt)?;
+    let mut reader = io::Cursor::new(data);
+
+    let global = read_map(&mut reader)?;
+    let (inputs, outputs) = map_counts(&global)?;
+    let mut normalized = PSBT_MAGIC.to_vec();
+    write_map(&global, &mut normalized);
+    for (count, key_ty) in [
+        (inputs, PSBT_IN_TAP_INTERNAL_KEY),
+        (outputs, PSBT_OUT_TAP_INTERNAL_KEY),
+    ] {
+        for _ in 0..count {
+            let mut map = read_map(&mut reader)?;
+            normalize_map(&mut map, key_ty)?;
+            write_map(&map, &mut normalized);
+        }
+    }
+    // Trailing data are left for the PSBT parser to reject
+    normalized.extend(&data[reader.position() as usize..]);
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::internal_key_fixture;
+
+    fn compressed(parity: u8) -> Vec<u8> {
+        let mut data = vec![parity];
+        data.extend(internal_key_fixture().serialize());
+        data
+    }
+
+    #[test]
+    fn decode_x_only() {
+        let key = internal_key_fixture();
+        assert_eq!(decode_internal_key(&key.serialize()), Ok(key));
+        assert_eq!(
+            decode_internal_key(&[0xFF; 32]),
+            Err(InternalKeyError::InvalidKey)
+        );
+    }
+
+    #[test]
+    fn decode_compressed() {
+        let key = internal_key_fixture();
+        assert_eq!(decode_internal_key(&compressed(0x02)), Ok(key));
+        assert_eq!(decode_internal_key(&compressed(0x03)), Ok(key));
+        assert_eq!(
+            decode_internal_key(&compressed(0x04)),
+            Err(InternalKeyError::InvalidKey)
+        );
+    }
+
+    #[test]
+    fn decode_malformed() {
+        for len in [0, 1, 30, 31, 34, 65] {
+            assert_eq!(
+                decode_internal_key(&vec![0x02; len]),
+                Err(InternalKeyError::InvalidLength(len))
+            );
+        }
+    }
+
+    #[cfg(feature = "wallet")]
+    mod legacy_psbt {
+        use psbt::serialize::{Deserialize, Serialize};
+        use psbt::Psbt;
+
+        use super::*;
+        use crate::method::CloseMethod;
+        use crate::test_utils::psbt_fixture;
+
+        /// Replaces the output taproot internal key record of the serialized
+        /// PSBT with the given value.
+        fn with_key_value(data: &[u8], value: &[u8]) -> Vec<u8> {
+            let mut record = vec![0x01, PSBT_OUT_TAP_INTERNAL_KEY as u8, 0x20];
+            record.extend(internal_key_fixture().serialize());
+            let pos = data
+                .windows(record.len())
+                .position(|window| window == record)
+                .expect("PSBT has internal key");
+            let mut replaced = data[..pos + 2].to_vec();
+            replaced.push(value.len() as u8);
+            replaced.extend(value);
+            replaced.extend(&data[pos + record.len()..]);
+            replaced
+        }
+
+        #[test]
+        fn normalize_legacy_psbt() {
+            let psbt = psbt_fixture(CloseMethod::TapretFirst);
+            let data = psbt.serialize();
+            assert_eq!(normalize_internal_keys(&data), Ok(data.clone()));
+
+            for parity in [0x02, 0x03] {
+                let legacy = with_key_value(&data, &compressed(parity));
+                assert!(Psbt::deserialize(&legacy).is_err());
+                let normalized = normalize_internal_keys(&legacy).unwrap();
+                assert_eq!(normalized, data);
+                assert_eq!(
+                    Psbt::deserialize(&normalized).unwrap().outputs[0]
+                        .tap_internal_key,
+                    Some(internal_key_fixture())
+                );
+            }
+
+            let malformed = with_key_value(&data, &compressed(0x02)[..30]);
+            assert_eq!(
+                normalize_internal_keys(&malformed),
+                Err(InternalKeyError::InvalidLength(30))
+            );
+        }
+
+        #[test]
+        fn normalize_malformed_psbt() {
+            let data = psbt_fixture(CloseMethod::TapretFirst).serialize();
+            assert_eq!(
+                normalize_internal_keys(&data[1..]),
+                Err(InternalKeyError::MalformedPsbt)
+            );
+            for len in [5, 6, data.len() / 2, data.len() - 1] {
+                assert_eq!(
+                    normalize_internal_keys(&data[..len]),
+                    Err(InternalKeyError::MalformedPsbt)
+                );
+            }
+        }
+    }
+}
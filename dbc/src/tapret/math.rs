@@ -0,0 +1,217 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Pure functions implementing the individual steps of the tapret (LNPBP-6)
+//! commitment, independent of the PSBT and proof data types.
+//!
+//! Tapret commitment to an LNPBP-4 commitment `msg` with an internal key `P`
+//! is computed as follows:
+//! 1. commitment leaf script is constructed with [`commitment_script`];
+//! 2. if the taproot script tree has other nodes, the commitment leaf hash is
+//!    merged with the hash of its partner node with [`merge_branch`], producing
+//!    the script tree merkle root; otherwise the leaf hash is the root;
+//! 3. the internal key is tweaked with the merkle root with [`tweak_key`],
+//!    producing the output key.
+//!
+//! Embedding and verification of the tapret commitments use these functions,
+//! so there is exactly one implementation of each of the steps.
+
+use bitcoin::blockdata::opcodes::all;
+use bitcoin::blockdata::script;
+use bitcoin::hashes::Hash;
+use bitcoin::schnorr::{TapTweak, UntweakedPublicKey};
+use bitcoin::util::taproot::TapBranchHash;
+use bitcoin_scripts::{IntoNodeHash, TapNodeHash, TapScript};
+use commit_verify::{lnpbp4, CommitEncode};
+use secp256k1::{Parity, XOnlyPublicKey, SECP256K1};
+
+/// Number of `OP_RESERVED` opcodes starting the tapret commitment script.
+const RESERVED_OPCODES: usize = 30;
+
+/// Constructs tapret commitment leaf script for the LNPBP-4 commitment `msg`
+/// and the `nonce`.
+///
+/// The script consists of 30 `OP_RESERVED` opcodes, which prevent the script
+/// from being interpreted as a pair of child node hashes of a sibling branch,
+/// followed by `OP_RETURN` and a push of the 32-byte commitment concatenated
+/// with the nonce byte.
+///
+/// ```
+/// use amplify::Wrapper;
+/// use bitcoin::hashes::Hash;
+/// use commit_verify::lnpbp4::CommitmentHash;
+/// use dbc::tapret::math::commitment_script;
+/// use dbc::tapret::TAPRET_SCRIPT_COMMITMENT_PREFIX;
+///
+/// let msg = CommitmentHash::from_inner(Hash::hash(b"message"));
+/// let script = commitment_script(&msg, 2);
+/// assert_eq!(script.len(), 65);
+/// assert_eq!(script[..31], TAPRET_SCRIPT_COMMITMENT_PREFIX[..31]);
+/// assert_eq!(script[32..64], msg[..]);
+/// assert_eq!(script[64], 2);
+/// ```
+pub fn commitment_script(msg: &lnpbp4::CommitmentHash, nonce: u8) -> TapScript {
+    let mut builder = script::Builder::new();
+    for _ in 0..RESERVED_OPCODES {
+        builder = builder.push_opcode(all::OP_RESERVED);
+    }
+    let mut data = msg.commit_serialize();
+    data.push(nonce);
+    builder
+        .push_opcode(all::OP_RETURN)
+        .push_slice(&data)
+        .into_script()
+        .into()
+}
+
+/// Computes hash of the taproot script tree branch with child nodes `a` and
+/// `b`, which are ordered lexicographically by their hashes (BIP-341), so the
+/// order of the arguments does not matter.
+///
+/// ```
+/// use bitcoin::hashes::{sha256, Hash};
+/// use dbc::tapret::math::merge_branch;
+///
+/// let a = sha256::Hash::hash(b"a");
+/// let b = sha256::Hash::hash(b"b");
+/// assert_eq!(merge_branch(a, b), merge_branch(b, a));
+/// ```
+pub fn merge_branch(a: TapNodeHash, b: TapNodeHash) -> TapNodeHash {
+    TapBranchHash::from_node_hashes(a, b).into_node_hash()
+}
+
+/// Tweaks the `internal` key with the taproot script tree merkle `root`
+/// (BIP-341), returning the output key and its parity.
+///
+/// ```
+/// use bitcoin::hashes::{sha256, Hash};
+/// use bitcoin::Script;
+/// use dbc::tapret::math::tweak_key;
+/// use dbc::test_utils::internal_key_fixture;
+///
+/// let root = sha256::Hash::hash(b"root");
+/// let (output_key, _) = tweak_key(internal_key_fixture(), root);
+/// assert_eq!(
+///     Script::new_v1_p2tr(
+///         secp256k1::SECP256K1,
+///         internal_key_fixture(),
+///         Some(bitcoin::util::taproot::TapBranchHash::from_inner(
+///             root.into_inner()
+///         ))
+///     )[2..],
+///     output_key.serialize()[..]
+/// );
+/// ```
+pub fn tweak_key(
+    internal: UntweakedPublicKey,
+    root: TapNodeHash,
+) -> (XOnlyPublicKey, Parity) {
+    // rust-bitcoin API has this inefficiency: while `TapLeafHash` can be
+    // a valid merkle root (for script trees with a single leaf), it is not
+    // accepted by the tap_tweak API.
+    //
+    // Details: <https://github.com/rust-bitcoin/rust-bitcoin/issues/1393>
+    let merkle_root = TapBranchHash::from_inner(root.into_inner());
+    let (output_key, parity) = internal.tap_tweak(SECP256K1, Some(merkle_root));
+    (output_key.to_inner(), parity)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::str::FromStr;
+
+    use amplify::Wrapper;
+    use bitcoin::hashes::hex::{FromHex, ToHex};
+    use bitcoin_scripts::taproot::{Node, TreeNode};
+    use bitcoin_scripts::LeafScript;
+    use strict_encoding::StrictDecode;
+
+    use super::*;
+    use crate::tapret::{TapretNodePartner, TAPRET_SCRIPT_COMMITMENT_PREFIX};
+
+    #[test]
+    fn script_layout() {
+        let msg = lnpbp4::CommitmentHash::from_inner(Hash::hash(b"message"));
+        for nonce in [0u8, 1, 0xFF] {
+            let script = commitment_script(&msg, nonce);
+            assert_eq!(script[..30], TAPRET_SCRIPT_COMMITMENT_PREFIX[..30]);
+            // `OP_RETURN` followed by a 33-byte push
+            assert_eq!(script[30..32], [0x6a, 0x21]);
+            assert_eq!(script[32..64], msg[..]);
+            assert_eq!(script[64], nonce);
+            assert_eq!(script.len(), 65);
+        }
+    }
+
+    #[test]
+    fn branch_ordering() {
+        let leaf = LeafScript::tapscript(TapScript::default());
+        let leaf_hash = leaf.tap_leaf_hash().into_node_hash();
+        let hidden = bitcoin::hashes::sha256::Hash::hash(b"hidden");
+        let branch = TreeNode::with_branch(
+            TreeNode::Leaf(leaf, 0),
+            TreeNode::Hidden(hidden, 1),
+            0,
+        );
+        assert_eq!(merge_branch(leaf_hash, hidden), branch.node_hash());
+        assert_eq!(merge_branch(hidden, leaf_hash), branch.node_hash());
+    }
+
+    /// Reproduces the tapret vectors using only the functions of the module.
+    #[test]
+    fn tapret_vectors() {
+        let data = include_str!("../../tests/data/lnpbp/tapret.vectors");
+        let mut count = 0;
+        for section in data.split("\n\n") {
+            let values = section
+                .lines()
+                .skip(1)
+                .map(|line| line.split_once(" = ").unwrap())
+                .collect::<BTreeMap<_, _>>();
+            let internal_key =
+                XOnlyPublicKey::from_str(values["input.internal_key"]).unwrap();
+            let msg = lnpbp4::CommitmentHash::from_inner(
+                Hash::from_slice(
+                    &Vec::<u8>::from_hex(values["input.commitment"]).unwrap(),
+                )
+                .unwrap(),
+            );
+            let nonce = values
+                .get("input.nonce")
+                .map(|nonce| nonce.parse().unwrap())
+                .unwrap_or_default();
+
+            let script = commitment_script(&msg, nonce);
+            let mut root = LeafScript::tapscript(script)
+                .tap_leaf_hash()
+                .into_node_hash();
+            if let Some(partner) = values.get("input.partner") {
+                let partner = TapretNodePartner::strict_deserialize(
+                    Vec::<u8>::from_hex(partner).unwrap(),
+                )
+                .unwrap();
+                root = merge_branch(root, partner.node_hash());
+            }
+            let (output_key, _) = tweak_key(internal_key, root);
+            assert_eq!(
+                output_key.serialize().to_hex(),
+                values["output.output_key"]
+            );
+            count += 1;
+        }
+        assert_eq!(count, 4);
+    }
+}
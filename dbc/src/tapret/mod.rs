@@ -54,7 +54,35 @@
 //! a) `TapTree` / `TapRightPartner`
 //! b) `TapretProof` / `TweakedPublicKey'`
 //! b) `XOnlyPublicKey` / `TapretProof`
-
+//!
+//! ```
+//! use bitcoin::{Script, Transaction, TxOut};
+//! use commit_verify::convolve_commit::{
+//!     ConvolveCommitProof, ConvolveCommitVerify,
+//! };
+//! use commit_verify::ConsensusCommit;
+//! use dbc::tapret::TapretPathProof;
+//! use dbc::test_utils::{internal_key_fixture, lnpbp4_fixture, tx_fixture};
+//!
+//! // Convolve-commit by the receiver of the output without script tree (d)
+//! let msg = lnpbp4_fixture().consensus_commit();
+//! let (output_key, proof) = internal_key_fixture()
+//!     .convolve_commit(&TapretPathProof::new(), &msg)
+//!     .unwrap();
+//! let tx = tx_fixture(vec![TxOut {
+//!     value: 1000,
+//!     script_pubkey: Script::new_v1_p2tr_tweaked(output_key),
+//! }]);
+//!
+//! // Verification by the receiver (g)
+//! assert!(
+//!     ConvolveCommitProof::<_, Transaction, _>::verify(&proof, &msg, tx)
+//!         .unwrap()
+//! );
+//! ```
+
+mod internalkey;
+pub mod math;
 #[cfg(feature = "wallet")]
 mod psbtout;
 mod tapscript;
@@ -63,9 +91,14 @@ mod tx;
 mod txout;
 mod xonlypk;
 
+pub use internalkey::{
+    decode_internal_key, normalize_internal_keys, InternalKeyError,
+};
 #[cfg(feature = "wallet")]
 pub use psbtout::{PsbtCommitError, PsbtVerifyError};
 pub use tapscript::TAPRET_SCRIPT_COMMITMENT_PREFIX;
+#[cfg(feature = "wallet")]
+pub(crate) use taptree::TapretSourceInfo;
 pub use taptree::TapretTreeError;
 pub use tx::TapretError;
 
@@ -73,11 +106,12 @@ pub use tx::TapretError;
 /// protocol.
 pub enum Lnpbp6 {}
 
+use std::cmp::Ordering;
 use std::io::Read;
 
 use bitcoin::hashes::sha256::Midstate;
 use bitcoin::hashes::Hash;
-use bitcoin::schnorr::UntweakedPublicKey;
+use bitcoin::schnorr::{TweakedPublicKey, UntweakedPublicKey};
 use bitcoin::util::taproot::{TapBranchHash, TaprootMerkleBranch};
 use bitcoin::Script;
 use bitcoin_scripts::taproot::TreeNode;
@@ -127,6 +161,15 @@ pub struct TapretRightBranch {
 impl TapretRightBranch {
     /// Constructs [`TapretRightBranch`] by putting `a` and `b` branches hashes
     /// into the correct consensus order (i.e. lexicographically).
+    ///
+    /// ```
+    /// use bitcoin::hashes::{sha256, Hash};
+    /// use dbc::tapret::TapretRightBranch;
+    ///
+    /// let a = sha256::Hash::hash(b"a");
+    /// let b = sha256::Hash::hash(b"b");
+    /// assert_eq!(TapretRightBranch::with(a, b), TapretRightBranch::with(b, a));
+    /// ```
     pub fn with(a: TapNodeHash, b: TapNodeHash) -> TapretRightBranch {
         let (left, right) = if a < b { (a, b) } else { (b, a) };
         TapretRightBranch {
@@ -137,21 +180,47 @@ impl TapretRightBranch {
 
     /// Returns hash of the left-side child node of the branch (having smaller
     /// hash value).
+    ///
+    /// ```
+    /// # use bitcoin::hashes::{sha256, Hash};
+    /// # use dbc::tapret::TapretRightBranch;
+    /// let a = sha256::Hash::hash(b"a");
+    /// let b = sha256::Hash::hash(b"b");
+    /// assert_eq!(TapretRightBranch::with(a, b).left_node_hash(), a.min(b));
+    /// ```
     #[inline]
     pub fn left_node_hash(self) -> TapNodeHash { self.left_node_hash }
 
     /// Returns hash of the right-side child node of the branch (having smaller
     /// hash value).
+    ///
+    /// ```
+    /// # use bitcoin::hashes::{sha256, Hash};
+    /// # use dbc::tapret::TapretRightBranch;
+    /// let a = sha256::Hash::hash(b"a");
+    /// let b = sha256::Hash::hash(b"b");
+    /// assert_eq!(TapretRightBranch::with(a, b).right_node_hash(), a.max(b));
+    /// ```
     #[inline]
     pub fn right_node_hash(self) -> TapNodeHash { self.right_node_hash }
 
     /// Computes node hash of the partner node defined by this proof.
+    ///
+    /// ```
+    /// use bitcoin::hashes::{sha256, Hash};
+    /// use bitcoin::util::taproot::TapBranchHash;
+    /// use bitcoin_scripts::IntoNodeHash;
+    /// use dbc::tapret::TapretRightBranch;
+    ///
+    /// let a = sha256::Hash::hash(b"a");
+    /// let b = sha256::Hash::hash(b"b");
+    /// assert_eq!(
+    ///     TapretRightBranch::with(a, b).node_hash(),
+    ///     TapBranchHash::from_node_hashes(a.min(b), a.max(b)).into_node_hash()
+    /// );
+    /// ```
     pub fn node_hash(&self) -> TapNodeHash {
-        TapBranchHash::from_node_hashes(
-            self.left_node_hash,
-            self.right_node_hash,
-        )
-        .into_node_hash()
+        math::merge_branch(self.left_node_hash, self.right_node_hash)
     }
 }
 
@@ -210,6 +279,18 @@ impl TapretNodePartner {
     /// Constructs right-side tapret branch proof structuring `a` and `b`
     /// children node hashes in the correct consensus order (i.e.
     /// lexicographically).
+    ///
+    /// ```
+    /// use bitcoin::hashes::{sha256, Hash};
+    /// use dbc::tapret::{TapretNodePartner, TapretRightBranch};
+    ///
+    /// let a = sha256::Hash::hash(b"a");
+    /// let b = sha256::Hash::hash(b"b");
+    /// assert_eq!(
+    ///     TapretNodePartner::right_branch(b, a),
+    ///     TapretNodePartner::RightBranch(TapretRightBranch::with(a, b))
+    /// );
+    /// ```
     pub fn right_branch(a: TapNodeHash, b: TapNodeHash) -> TapretNodePartner {
         TapretNodePartner::RightBranch(TapretRightBranch::with(a, b))
     }
@@ -220,16 +301,32 @@ impl TapretNodePartner {
     /// bytes are not equal to [`TAPRET_SCRIPT_COMMITMENT_PREFIX`], and if
     /// the sibling is another node, the hash of its first child in the proof
     /// is smaller than the hash of the other.
+    ///
+    /// ```
+    /// use bitcoin::hashes::{sha256, Hash};
+    /// use dbc::tapret::{TapretNodePartner, TAPRET_SCRIPT_COMMITMENT_PREFIX};
+    ///
+    /// let node = sha256::Hash::hash(b"node");
+    /// assert!(TapretNodePartner::LeftNode(node).check_no_commitment());
+    ///
+    /// // The first child of the right branch may be an alternative commitment
+    /// let commitment = sha256::Hash::from_inner(TAPRET_SCRIPT_COMMITMENT_PREFIX);
+    /// let max = sha256::Hash::from_inner([0xFF; 32]);
+    /// assert!(
+    ///     !TapretNodePartner::right_branch(commitment, max).check_no_commitment()
+    /// );
+    /// ```
     pub fn check_no_commitment(&self) -> bool {
         match self {
             TapretNodePartner::LeftNode(_) => true,
             TapretNodePartner::RightLeaf(LeafScript { script, .. })
-                if script.len() < 32 =>
+                if script.len() < TAPRET_SCRIPT_COMMITMENT_PREFIX.len() =>
             {
                 true
             }
             TapretNodePartner::RightLeaf(LeafScript { script, .. }) => {
-                script[0..32] != TAPRET_SCRIPT_COMMITMENT_PREFIX[..]
+                script[..TAPRET_SCRIPT_COMMITMENT_PREFIX.len()]
+                    != TAPRET_SCRIPT_COMMITMENT_PREFIX[..]
             }
             TapretNodePartner::RightBranch(right_branch) => {
                 right_branch.left_node_hash()[..]
@@ -240,6 +337,15 @@ impl TapretNodePartner {
 
     /// Checks that the sibling has a correct ordering regarding some other
     /// node.
+    ///
+    /// ```
+    /// use bitcoin::hashes::{sha256, Hash};
+    /// use dbc::tapret::TapretNodePartner;
+    ///
+    /// let partner = TapretNodePartner::LeftNode(sha256::Hash::hash(b"node"));
+    /// assert!(partner.check_ordering(sha256::Hash::from_inner([0xFF; 32])));
+    /// assert!(!partner.check_ordering(sha256::Hash::from_inner([0x00; 32])));
+    /// ```
     pub fn check_ordering(&self, other_node: TapNodeHash) -> bool {
         match self {
             TapretNodePartner::LeftNode(left_node) => *left_node <= other_node,
@@ -255,6 +361,17 @@ impl TapretNodePartner {
     }
 
     /// Computes node hash of the partner node defined by this proof.
+    ///
+    /// ```
+    /// use bitcoin::Script;
+    /// use bitcoin_scripts::{IntoNodeHash, LeafScript, TapScript};
+    /// use dbc::tapret::TapretNodePartner;
+    ///
+    /// let leaf =
+    ///     LeafScript::tapscript(TapScript::from(Script::new_op_return(&[1])));
+    /// let partner = TapretNodePartner::from(leaf.clone());
+    /// assert_eq!(partner.node_hash(), leaf.tap_leaf_hash().into_node_hash());
+    /// ```
     pub fn node_hash(&self) -> TapNodeHash {
         match self {
             TapretNodePartner::LeftNode(hash) => *hash,
@@ -268,6 +385,27 @@ impl TapretNodePartner {
     }
 
     /// Constructs [`TreeNode`] for the node partner.
+    ///
+    /// ```
+    /// use bitcoin::hashes::{sha256, Hash};
+    /// use bitcoin::Script;
+    /// use bitcoin_scripts::taproot::TreeNode;
+    /// use bitcoin_scripts::{LeafScript, TapScript};
+    /// use dbc::tapret::TapretNodePartner;
+    ///
+    /// let node = sha256::Hash::hash(b"node");
+    /// assert_eq!(
+    ///     TapretNodePartner::LeftNode(node).to_tree_node(),
+    ///     TreeNode::Hidden(node, 1)
+    /// );
+    ///
+    /// let leaf =
+    ///     LeafScript::tapscript(TapScript::from(Script::new_op_return(&[1])));
+    /// assert_eq!(
+    ///     TapretNodePartner::from(leaf.clone()).to_tree_node(),
+    ///     TreeNode::Leaf(leaf, 0)
+    /// );
+    /// ```
     pub fn to_tree_node(&self) -> TreeNode {
         match self {
             TapretNodePartner::LeftNode(left_node) => {
@@ -306,10 +444,37 @@ pub struct TapretPathProof {
 
 impl TapretPathProof {
     /// Construct new empty path proof.
+    ///
+    /// ```
+    /// use dbc::tapret::TapretPathProof;
+    ///
+    /// let path_proof = TapretPathProof::new();
+    /// assert!(path_proof.check_no_commitment());
+    /// assert_eq!(path_proof.original_merkle_root(), None);
+    /// ```
     #[inline]
     pub fn new() -> TapretPathProof { TapretPathProof::default() }
 
     /// Adds element to the path proof.
+    ///
+    /// ```
+    /// use bitcoin::hashes::{sha256, Hash};
+    /// use dbc::tapret::{
+    ///     TapretNodePartner, TapretPathError, TapretPathProof,
+    ///     TAPRET_SCRIPT_COMMITMENT_PREFIX,
+    /// };
+    ///
+    /// let node = sha256::Hash::hash(b"node");
+    /// assert!(TapretPathProof::with(TapretNodePartner::LeftNode(node), 0).is_ok());
+    ///
+    /// let commitment = sha256::Hash::from_inner(TAPRET_SCRIPT_COMMITMENT_PREFIX);
+    /// let max = sha256::Hash::from_inner([0xFF; 32]);
+    /// let partner = TapretNodePartner::right_branch(commitment, max);
+    /// assert_eq!(
+    ///     TapretPathProof::with(partner.clone(), 0),
+    ///     Err(TapretPathError::InvalidNodePartner(partner))
+    /// );
+    /// ```
     pub fn with(
         elem: TapretNodePartner,
         nonce: u8,
@@ -325,6 +490,16 @@ impl TapretPathProof {
 
     /// Checks that the sibling data does not contain another tapret commitment
     /// for any step of the mekrle path.
+    ///
+    /// ```
+    /// use bitcoin::hashes::{sha256, Hash};
+    /// use dbc::tapret::{TapretNodePartner, TapretPathProof};
+    ///
+    /// let node = sha256::Hash::hash(b"node");
+    /// let path_proof =
+    ///     TapretPathProof::with(TapretNodePartner::LeftNode(node), 0).unwrap();
+    /// assert!(path_proof.check_no_commitment());
+    /// ```
     #[inline]
     pub fn check_no_commitment(&self) -> bool {
         self.partner_node
@@ -336,6 +511,22 @@ impl TapretPathProof {
     /// Returns original merkle root of the tree before deterministic bitcoin
     /// commitment. If originally there was no script path spendings, returns
     /// `None`.
+    ///
+    /// ```
+    /// use bitcoin::Script;
+    /// use bitcoin_scripts::{IntoNodeHash, LeafScript, TapScript};
+    /// use dbc::tapret::{TapretNodePartner, TapretPathProof};
+    ///
+    /// let leaf =
+    ///     LeafScript::tapscript(TapScript::from(Script::new_op_return(&[1])));
+    /// let path_proof =
+    ///     TapretPathProof::with(TapretNodePartner::from(leaf.clone()), 0)
+    ///         .unwrap();
+    /// assert_eq!(
+    ///     path_proof.original_merkle_root(),
+    ///     Some(leaf.tap_leaf_hash().into_node_hash())
+    /// );
+    /// ```
     #[inline]
     pub fn original_merkle_root(&self) -> Option<TapNodeHash> {
         self.partner_node
@@ -371,7 +562,12 @@ impl<'data> IntoIterator for &'data TapretPathProof {
 /// Information proving tapret determinism for a given tapret commitment.
 /// Used both in the commitment procedure for PSBTs and in
 /// client-side-validation of the commitment.
-#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+///
+/// Proofs are ordered lexicographically by the serialized internal key, then by
+/// the strict encoding of the script tree partner node, and then by the nonce.
+/// The order is a part of the deterministic serialization of the data
+/// containing the proofs and must not change.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
 #[cfg_attr(
     feature = "serde",
     derive(Serialize, Deserialize),
@@ -391,9 +587,78 @@ pub struct TapretProof {
     pub internal_key: UntweakedPublicKey,
 }
 
+impl Ord for TapretProof {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let partner_node = |proof: &TapretProof| {
+            strict_encoding::strict_serialize(&proof.path_proof.partner_node)
+                .expect("in-memory strict encoding")
+        };
+        self.internal_key
+            .serialize()
+            .cmp(&other.internal_key.serialize())
+            .then_with(|| partner_node(self).cmp(&partner_node(other)))
+            .then_with(|| self.path_proof.nonce.cmp(&other.path_proof.nonce))
+    }
+}
+
+impl PartialOrd for TapretProof {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl TapretProof {
+    /// Constructs tapret proof for the taproot output with the given internal
+    /// key and the proof of the tapret commitment path in its script tree.
+    ///
+    /// ```
+    /// use dbc::tapret::{TapretPathProof, TapretProof};
+    /// use dbc::test_utils::internal_key_fixture;
+    ///
+    /// let proof =
+    ///     TapretProof::with(internal_key_fixture(), TapretPathProof::new());
+    /// assert_eq!(proof.internal_key, internal_key_fixture());
+    /// assert_eq!(proof.path_proof, TapretPathProof::new());
+    /// ```
+    #[inline]
+    pub fn with(
+        internal_key: UntweakedPublicKey,
+        path_proof: TapretPathProof,
+    ) -> TapretProof {
+        TapretProof {
+            path_proof,
+            internal_key,
+        }
+    }
+
+    /// Computes length of the proof strict encoding without serializing it.
+    ///
+    /// ```
+    /// use dbc::test_utils::tapret_proof_fixture;
+    /// use strict_encoding::StrictEncode;
+    ///
+    /// let proof = tapret_proof_fixture();
+    /// let len = proof.strict_serialize().unwrap().len();
+    /// assert_eq!(proof.strict_encoded_len(), len);
+    /// ```
+    #[inline]
+    pub fn strict_encoded_len(&self) -> usize {
+        crate::anchor::strict_encoded_len(self)
+    }
+
     /// Restores original scripPubkey before deterministic bitcoin commitment
     /// applied.
+    ///
+    /// ```
+    /// use amplify::Wrapper;
+    /// use bitcoin::Script;
+    /// use dbc::test_utils::{internal_key_fixture, tapret_proof_fixture};
+    ///
+    /// assert_eq!(
+    ///     tapret_proof_fixture().original_pubkey_script().into_inner(),
+    ///     Script::new_v1_p2tr(secp256k1::SECP256K1, internal_key_fixture(), None)
+    /// );
+    /// ```
     #[inline]
     pub fn original_pubkey_script(&self) -> PubkeyScript {
         let merkle_root = self
@@ -403,6 +668,44 @@ impl TapretProof {
             .map(TapBranchHash::from_inner);
         Script::new_v1_p2tr(SECP256K1, self.internal_key, merkle_root).into()
     }
+
+    /// Checks that the output key of the taproot `script_pubkey` differs from
+    /// the proof internal key, i.e. the output key is tweaked.
+    ///
+    /// ```
+    /// use bitcoin::schnorr::TweakedPublicKey;
+    /// use bitcoin::Script;
+    /// use dbc::tapret::TapretError;
+    /// use dbc::test_utils::{internal_key_fixture, tapret_proof_fixture};
+    ///
+    /// let proof = tapret_proof_fixture();
+    /// let original = proof.original_pubkey_script();
+    /// assert_eq!(proof.check_output_key(&original), Ok(()));
+    ///
+    /// let untweaked = Script::new_v1_p2tr_tweaked(
+    ///     TweakedPublicKey::dangerous_assume_tweaked(internal_key_fixture()),
+    /// );
+    /// assert_eq!(
+    ///     proof.check_output_key(&untweaked),
+    ///     Err(TapretError::UntweakedOutput)
+    /// );
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// [`TapretError::UntweakedOutput`] if the output key is the proof
+    /// internal key.
+    pub fn check_output_key(
+        &self,
+        script_pubkey: &Script,
+    ) -> Result<(), TapretError> {
+        let untweaked =
+            TweakedPublicKey::dangerous_assume_tweaked(self.internal_key);
+        if *script_pubkey == Script::new_v1_p2tr_tweaked(untweaked) {
+            return Err(TapretError::UntweakedOutput);
+        }
+        Ok(())
+    }
 }
 
 /// Tapret value: a final tweak applied to the internal taproot key which
@@ -428,4 +731,64 @@ mod test {
             Lnpbp6::HASH_TAG_MIDSTATE.unwrap().into_inner()
         );
     }
+
+    #[test]
+    fn test_proof_order() {
+        use std::str::FromStr;
+
+        use crate::Proof;
+
+        // x-coordinates of the generator point and its double
+        let g = UntweakedPublicKey::from_str(
+            "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        )
+        .unwrap();
+        let g2 = UntweakedPublicKey::from_str(
+            "c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5",
+        )
+        .unwrap();
+        let tapret = |internal_key, partner_node, nonce| {
+            Proof::TapretFirst(TapretProof {
+                path_proof: TapretPathProof {
+                    partner_node,
+                    nonce,
+                },
+                internal_key,
+            })
+        };
+        let left = |byte| {
+            Some(TapretNodePartner::LeftNode(TapNodeHash::from_inner(
+                [byte; 32],
+            )))
+        };
+        let leaf = Some(TapretNodePartner::RightLeaf(LeafScript::tapscript(
+            default!(),
+        )));
+
+        let expected = vec![
+            Proof::OpretFirst,
+            tapret(g, None, 0),
+            tapret(g, None, 1),
+            tapret(g, left(0x00), 0),
+            tapret(g, left(0x00), 1),
+            tapret(g, left(0xFF), 0),
+            tapret(g, leaf.clone(), 0),
+            tapret(g2, None, 0),
+            tapret(g2, leaf, 0),
+        ];
+        let mut shuffled = expected.clone();
+        shuffled.reverse();
+        shuffled.swap(1, 6);
+        shuffled.swap(2, 4);
+        assert_ne!(shuffled, expected);
+        shuffled.sort();
+        assert_eq!(shuffled, expected);
+
+        let set = expected
+            .iter()
+            .chain(&expected)
+            .cloned()
+            .collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(set.into_iter().collect::<Vec<_>>(), expected);
+    }
 }
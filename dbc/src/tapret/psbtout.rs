@@ -14,12 +14,10 @@
 // If not, see <https://opensource.org/licenses/Apache-2.0>.
 
 use bitcoin::hashes::Hash;
-use bitcoin::psbt::TapTree;
 use bitcoin::util::taproot::TapBranchHash;
 use bitcoin::Script;
 use bitcoin_scripts::taproot::{Node, TaprootScriptTree, TreeNode};
 use bitcoin_scripts::TapNodeHash;
-use commit_verify::convolve_commit::ConvolveCommitVerify;
 use commit_verify::{lnpbp4, EmbedCommitProof, EmbedCommitVerify};
 use psbt::commit::{
     DfsPathEncodeError, Lnpbp4KeyError, OpretKeyError, TapretKeyError,
@@ -27,6 +25,9 @@ use psbt::commit::{
 use secp256k1::SECP256K1;
 
 use super::{Lnpbp6, TapretProof};
+use crate::consts::TAPRET_CONSENSUS_MAX_PATH_DEPTH;
+use crate::opret::OpretError;
+use crate::proprietary::KeyError;
 use crate::tapret::taptree::{
     TapretProofError, TapretSourceError, TapretSourceInfo,
 };
@@ -64,6 +65,16 @@ pub enum PsbtCommitError {
     #[display(inner)]
     Lnpbp4(lnpbp4::Error),
 
+    /// Opret commitment creation error
+    #[from]
+    #[display(inner)]
+    Opret(OpretError),
+
+    /// Invalid values of PSBT proprietary keys
+    #[from]
+    #[display(inner)]
+    ProprietaryKey(KeyError),
+
     /// tapret commitment can't be made in a transaction lacking any taproot
     /// outputs.
     NoTaprootOutput,
@@ -85,6 +96,111 @@ pub enum PsbtCommitError {
 
     /// producing taptree structure
     TapTreeError,
+
+    /// commitment host output does not contain any LNPBP-4 messages; anchors
+    /// committing to zero protocols are not allowed.
+    NoMessages,
+
+    /// commitment host output #{host} is preceded by output #{first} of the
+    /// same type, while the commitment is verified against the first output
+    /// of the type. Probably the outputs were reordered after the host was
+    /// selected.
+    HostNotFirst {
+        /// Number of the host output.
+        host: usize,
+
+        /// Number of the first output of the host type.
+        first: usize,
+    },
+
+    /// PSBT is malformed: it has {outputs} output maps, while its unsigned
+    /// transaction has {tx_outputs} outputs.
+    MalformedPsbt {
+        /// Number of the PSBT output maps.
+        outputs: usize,
+
+        /// Number of the unsigned transaction outputs.
+        tx_outputs: usize,
+    },
+
+    /// PSBT outputs {vouts:?} contain LNPBP-4 messages, while only messages
+    /// of the commitment host output are committed to. Hint: move the
+    /// messages to the host output, or check that a single output is marked
+    /// as the host.
+    MessagesOnNonHostOutput {
+        /// Numbers of the outputs other than the host with LNPBP-4 messages.
+        vouts: Vec<usize>,
+    },
+
+    /// output #{0} selected as the commitment host does not exist or is not
+    /// marked as a tapret or opret commitment host.
+    InvalidHost(usize),
+
+    /// PSBT output #{tapret_vout} is marked as a tapret commitment host, while
+    /// output #{opret_vout} is marked as an opret commitment host. Hint:
+    /// probably the PSBT was prepared by several tools; remove one of the host
+    /// marks or select the commitment method explicitly.
+    AmbiguousHosts {
+        /// Number of the first output marked as a tapret host.
+        tapret_vout: usize,
+
+        /// Number of the first output marked as an opret host.
+        opret_vout: usize,
+    },
+
+    /// PSBT has no outputs, so it can neither host a commitment nor be
+    /// restored from one.
+    EmptyPsbt,
+
+    /// taproot script tree of the tapret commitment host would have depth
+    /// {depth} after the commitment, exceeding the limit of {max}. Hint: some
+    /// wallets can't sign spendings from deep trees; raise the limit only if
+    /// the wallet supports such trees.
+    TreeTooDeep {
+        /// Depth of the script tree after the commitment.
+        depth: usize,
+
+        /// Maximal allowed depth of the script tree.
+        max: usize,
+    },
+
+    /// output #{0} is the only output of the transaction and can't host opret
+    /// commitment, since the `OP_RETURN` output would consume the transaction
+    /// funds. Hint: add a payment or change output to the transaction, or use
+    /// tapret commitment in the only output.
+    OpretSoleOutput(usize),
+
+    /// LNPBP-4 message for protocol {protocol_id} supplied for the commitment
+    /// differs from the message in the proprietary keys of the host output
+    /// #{vout}, or the output keys contain a message for the protocol which
+    /// was not supplied. Hint: remove the messages from the PSBT keys or
+    /// supply the same messages.
+    MessageConflict {
+        /// Number of the host output.
+        vout: usize,
+
+        /// Protocol with the conflicting message.
+        protocol_id: lnpbp4::ProtocolId,
+    },
+
+    /// minimal LNPBP-4 tree depth {supplied} supplied for the commitment
+    /// differs from the depth {present} in the proprietary keys of the host
+    /// output #{vout}.
+    MinDepthConflict {
+        /// Number of the host output.
+        vout: usize,
+
+        /// Minimal tree depth in the PSBT keys.
+        present: u8,
+
+        /// Minimal tree depth supplied for the commitment.
+        supplied: u8,
+    },
+
+    /// LNPBP-4 tree with the provided entropy can't be constructed, since the
+    /// tree encoding of the `commit_verify` library does not match the one
+    /// supported by this library. Hint: use commitment with static entropy.
+    EntropyUnsupported,
 }
 
 /// Errors during tapret PSBT commitment process.
@@ -115,8 +231,7 @@ impl EmbedCommitProof<lnpbp4::CommitmentHash, psbt::Output, Lnpbp6>
             .tap_internal_key
             .ok_or(PsbtCommitError::InternalKeyMissed)?;
         if internal_key != self.internal_key {
-            return Err(PsbtCommitError::InternalKeyMismatch)
-                .map_err(PsbtVerifyError::from);
+            return Err(PsbtCommitError::InternalKeyMismatch.into());
         }
 
         let tap_tree = original_container.tap_tree.map(TaprootScriptTree::from);
@@ -148,31 +263,24 @@ impl EmbedCommitVerify<lnpbp4::CommitmentHash, Lnpbp6> for psbt::Output {
         msg: &lnpbp4::CommitmentHash,
     ) -> Result<Self::Proof, Self::CommitError> {
         // TODO: Check TAPRET_COMMITABLE key
+        crate::host::embed_tapret(self, msg)
+    }
 
-        let internal_key = if let Some(internal_key) = self.tap_internal_key {
-            internal_key
-        } else {
-            return Err(PsbtCommitError::InternalKeyMissed);
-        };
-
-        let mut source =
-            TapretSourceInfo::<TapTree>::with(self.tap_tree.clone())?;
-
-        let path_proof = source.embed_commit(msg)?;
-
-        self.tap_tree = source.into_tap_tree();
-
-        let (output_key, _) = internal_key
-            .convolve_commit(&path_proof, msg)
-            .map_err(|_| PsbtCommitError::TapTreeError)?;
-
-        self.script = Script::new_v1_p2tr_tweaked(output_key).into();
-
-        let proof = TapretProof {
-            path_proof,
-            internal_key,
-        };
-
-        Ok(proof)
+    /// Verifies the commitment by restoring the original output and repeating
+    /// the commitment. Unlike the commitment, verification accepts script
+    /// trees up to [`TAPRET_CONSENSUS_MAX_PATH_DEPTH`] deep, such that
+    /// commitments made with a raised depth limit are verified as well.
+    fn verify(
+        &self,
+        msg: &lnpbp4::CommitmentHash,
+        proof: Self::Proof,
+    ) -> Result<bool, Self::VerifyError> {
+        let mut original = proof.restore_original_container(self)?;
+        let proof_prime = crate::host::embed_tapret_with_max_depth(
+            &mut original,
+            msg,
+            TAPRET_CONSENSUS_MAX_PATH_DEPTH,
+        )?;
+        Ok(proof_prime == proof && original == *self)
     }
 }
@@ -13,12 +13,10 @@
 // along with this software.
 // If not, see <https://opensource.org/licenses/Apache-2.0>.
 
-use bitcoin::blockdata::opcodes::all;
-use bitcoin::blockdata::script;
 use bitcoin_scripts::TapScript;
-use commit_verify::{lnpbp4, CommitEncode, CommitVerify};
+use commit_verify::{lnpbp4, CommitVerify};
 
-use super::Lnpbp6;
+use super::{math, Lnpbp6};
 
 /// Hardcoded tapret script prefix consisting of 30 `OP_RESERVED` pushes,
 /// followed by `OP_RETURN` and `OP_PUSHBYTES_32`.
@@ -31,18 +29,6 @@ pub const TAPRET_SCRIPT_COMMITMENT_PREFIX: [u8; 32] = [
 impl CommitVerify<(lnpbp4::CommitmentHash, u8), Lnpbp6> for TapScript {
     fn commit(msg: &(lnpbp4::CommitmentHash, u8)) -> Self {
         let (msg, nonce) = msg;
-        let mut builder = script::Builder::new();
-        for _ in 0..30 {
-            // Filling first 30 bytes with OP_RESERVED in order to avoid
-            // representation of sibling partner script as child hashes.
-            builder = builder.push_opcode(all::OP_RESERVED);
-        }
-        let mut data = msg.commit_serialize();
-        data.push(*nonce);
-        builder
-            .push_opcode(all::OP_RETURN)
-            .push_slice(&data)
-            .into_script()
-            .into()
+        math::commitment_script(msg, *nonce)
     }
 }
@@ -65,6 +65,10 @@ pub enum TapretTreeError {
     /// tapret node partner {0} has an invalid order with the commitment node
     /// {1}
     IncorrectOrdering(TapretNodePartner, TreeNode),
+
+    /// the tapret commitment does not tweak the internal key, so the output
+    /// key is the same as the internal key and contains no commitment.
+    UntweakedKey,
 }
 
 /// Errors during taproot script tree tapret commitment verification and
@@ -29,9 +29,18 @@ pub enum TapretError {
     #[display(inner)]
     TreeEmbedding(TapretTreeError),
 
-    /// tapret commitment in a transaction lacking any taproot outputs.
+    /// witness transaction has no taproot outputs and can't contain tapret
+    /// commitment. Hint: the anchor was probably attached to a wrong witness
+    /// transaction.
     #[display(doc_comments)]
     NoTaprootOutput,
+
+    /// the first taproot output of the witness transaction has the internal
+    /// key of the tapret proof as its output key, i.e. the output key is not
+    /// tweaked and can't contain tapret commitment. Hint: the tapret proof is
+    /// probably forged.
+    #[display(doc_comments)]
+    UntweakedOutput,
 }
 
 impl ConvolveCommitProof<lnpbp4::CommitmentHash, Transaction, Lnpbp6>
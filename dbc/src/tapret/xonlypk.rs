@@ -13,18 +13,15 @@
 // along with this software.
 // If not, see <https://opensource.org/licenses/Apache-2.0>.
 
-use bitcoin::hashes::Hash;
-use bitcoin::schnorr::{TapTweak, TweakedPublicKey, UntweakedPublicKey};
-use bitcoin::util::taproot::TapBranchHash;
-use bitcoin_scripts::taproot::{Node, TreeNode};
-use bitcoin_scripts::TapScript;
+use bitcoin::schnorr::{TweakedPublicKey, UntweakedPublicKey};
+use bitcoin_scripts::taproot::TreeNode;
+use bitcoin_scripts::{IntoNodeHash, LeafScript, TapScript};
 use commit_verify::convolve_commit::{
     ConvolveCommitProof, ConvolveCommitVerify,
 };
 use commit_verify::{lnpbp4, CommitVerify};
-use secp256k1::SECP256K1;
 
-use super::{Lnpbp6, TapretPathProof, TapretProof, TapretTreeError};
+use super::{math, Lnpbp6, TapretPathProof, TapretProof, TapretTreeError};
 
 impl ConvolveCommitProof<lnpbp4::CommitmentHash, UntweakedPublicKey, Lnpbp6>
     for TapretProof
@@ -51,6 +48,9 @@ impl ConvolveCommitVerify<lnpbp4::CommitmentHash, TapretProof, Lnpbp6>
     ) -> Result<(TweakedPublicKey, TapretProof), Self::CommitError> {
         let script_commitment = TapScript::commit(&(*msg, supplement.nonce));
 
+        let commitment_hash = LeafScript::tapscript(script_commitment.clone())
+            .tap_leaf_hash()
+            .into_node_hash();
         let root = if let Some(ref partner) = supplement.partner_node {
             if !partner.check_no_commitment() {
                 return Err(TapretTreeError::AlternativeCommitment(
@@ -58,31 +58,24 @@ impl ConvolveCommitVerify<lnpbp4::CommitmentHash, TapretProof, Lnpbp6>
                 ));
             }
 
-            let commitment_node =
-                TreeNode::with_tap_script(script_commitment, 1);
-
-            if !partner.check_ordering(commitment_node.node_hash()) {
+            if !partner.check_ordering(commitment_hash) {
                 return Err(TapretTreeError::IncorrectOrdering(
                     partner.clone(),
-                    commitment_node,
+                    TreeNode::with_tap_script(script_commitment, 1),
                 ));
             }
 
-            TreeNode::with_branch(commitment_node, partner.to_tree_node(), 0)
+            math::merge_branch(commitment_hash, partner.node_hash())
         } else {
-            TreeNode::with_tap_script(script_commitment, 0)
+            commitment_hash
         };
 
-        // rust-bitcoin API has this inefficiency: while `TapLeafHash` can be
-        // a valid merkle root (for script trees with a single leaf), it is not
-        // accepted by the tap_tweak API.
-        //
-        // Details: <https://github.com/rust-bitcoin/rust-bitcoin/issues/1393>
-        let merkle_root =
-            TapBranchHash::from_inner(root.node_hash().into_inner());
         // TODO: Use secp instance from Lnpbp6
-        let (output_key, _parity_not_used) =
-            self.tap_tweak(SECP256K1, Some(merkle_root));
+        let (output_key, _parity_not_used) = math::tweak_key(*self, root);
+        if output_key == *self {
+            return Err(TapretTreeError::UntweakedKey);
+        }
+        let output_key = TweakedPublicKey::dangerous_assume_tweaked(output_key);
 
         let proof = TapretProof {
             path_proof: supplement.clone(),
@@ -99,9 +92,11 @@ mod test {
 
     use amplify::Wrapper;
     use bitcoin::hashes::Hash;
-    use bitcoin_scripts::LeafScript;
+    use bitcoin::schnorr::TapTweak;
+    use bitcoin::util::taproot::TapBranchHash;
+    use bitcoin_scripts::taproot::Node;
     use commit_verify::lnpbp4::CommitmentHash;
-    use secp256k1::XOnlyPublicKey;
+    use secp256k1::{XOnlyPublicKey, SECP256K1};
 
     use super::*;
     use crate::tapret::TapretNodePartner;
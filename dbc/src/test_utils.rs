@@ -0,0 +1,327 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Deterministic fixtures for documentation examples and for tests of the
+//! crates using the library.
+//!
+//! All fixtures are fully deterministic (they use static LNPBP-4 entropy and
+//! hardcoded keys) and must never be used with real funds. The module is
+//! available with `test-utils` feature only.
+//!
+//! ```
+//! use dbc::test_utils::{opret_fixture, protocol_fixture};
+//!
+//! let (anchor, tx) = opret_fixture();
+//! let (protocol_id, message) = protocol_fixture(1);
+//! let anchor = anchor.to_merkle_proof(protocol_id).unwrap();
+//! assert!(anchor.verify(protocol_id, message, &tx).unwrap());
+//! ```
+
+use std::str::FromStr;
+
+use amplify::Wrapper;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::schnorr::TweakedPublicKey;
+use bitcoin::{PackedLockTime, Script, Transaction, TxIn, TxOut};
+use commit_verify::convolve_commit::ConvolveCommitVerify;
+use commit_verify::lnpbp4::{self, Message, ProtocolId};
+use commit_verify::ConsensusCommit;
+#[cfg(feature = "wallet")]
+use psbt::Psbt;
+use secp256k1::XOnlyPublicKey;
+
+use crate::anchor::lnpbp4_tree_with_entropy;
+use crate::consts::{
+    ANCHOR_MIN_LNPBP4_DEPTH, COMMITMENT_LEN, LNPBP4_STATIC_ENTROPY,
+};
+#[cfg(feature = "wallet")]
+use crate::method::CloseMethod;
+use crate::opret::opret_script;
+use crate::tapret::{TapretPathProof, TapretProof};
+use crate::{Anchor, Proof};
+
+/// Number of protocols committed by the fixture anchors and PSBTs.
+pub const FIXTURE_PROTOCOLS: u8 = 3;
+
+/// Returns protocol id and message of the fixture protocol number `no`.
+pub fn protocol_fixture(no: u8) -> (ProtocolId, Message) {
+    let protocol_id =
+        ProtocolId::from_inner(sha256::Hash::hash(&[no]).into_inner());
+    (protocol_id, Message::hash(&[no, no]))
+}
+
+/// Constructs LNPBP-4 tree committing to the fixture protocols
+/// `0..FIXTURE_PROTOCOLS` with static entropy.
+pub fn lnpbp4_fixture() -> lnpbp4::MerkleTree {
+    lnpbp4_fixture_with(0..FIXTURE_PROTOCOLS)
+}
+
+/// Constructs LNPBP-4 tree committing to the fixture protocols with the given
+/// numbers with static entropy.
+pub fn lnpbp4_fixture_with(
+    protocols: impl IntoIterator<Item = u8>,
+) -> lnpbp4::MerkleTree {
+    lnpbp4_tree_fixture(&lnpbp4::MultiSource {
+        min_depth: ANCHOR_MIN_LNPBP4_DEPTH,
+        messages: protocols.into_iter().map(protocol_fixture).collect(),
+    })
+}
+
+/// Constructs LNPBP-4 tree committing to the messages of `source` with static
+/// entropy.
+///
+/// # Panics
+///
+/// If the messages can't be committed to, for instance if there are no
+/// messages and the minimal depth of the tree is zero.
+pub fn lnpbp4_tree_fixture(source: &lnpbp4::MultiSource) -> lnpbp4::MerkleTree {
+    lnpbp4_tree_with_entropy(source, LNPBP4_STATIC_ENTROPY)
+        .expect("fixture LNPBP-4 messages")
+        .expect("LNPBP-4 tree encoding")
+}
+
+/// Returns taproot internal key used by the fixtures.
+pub fn internal_key_fixture() -> XOnlyPublicKey {
+    XOnlyPublicKey::from_str(
+        "c5f93479093e2b8f724a79844cc10928dd44e9a390b539843fb83fbf842723f3",
+    )
+    .expect("hardcoded key")
+}
+
+/// Constructs transaction with a single default input and given outputs.
+pub fn tx_fixture(output: Vec<TxOut>) -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time: PackedLockTime::ZERO,
+        input: vec![TxIn::default()],
+        output,
+    }
+}
+
+/// Constructs opret anchor committing to [`lnpbp4_fixture`] together with its
+/// witness transaction, which has taproot output followed by the commitment
+/// `OP_RETURN` output.
+pub fn opret_fixture() -> (Anchor<lnpbp4::MerkleBlock>, Transaction) {
+    let tree = lnpbp4_fixture();
+    let commitment = tree.consensus_commit();
+    let tx = tx_fixture(vec![
+        TxOut {
+            value: 1000,
+            script_pubkey: Script::new_v1_p2tr_tweaked(
+                TweakedPublicKey::dangerous_assume_tweaked(
+                    internal_key_fixture(),
+                ),
+            ),
+        },
+        TxOut {
+            value: 0,
+            script_pubkey: opret_script(&commitment[..])
+                .expect("32-byte commitment"),
+        },
+    ]);
+    let anchor = Anchor::from_parts(
+        tx.txid(),
+        lnpbp4::MerkleBlock::from(tree),
+        Proof::OpretFirst,
+    );
+    (anchor, tx)
+}
+
+/// Constructs tapret anchor committing to [`lnpbp4_fixture`] together with its
+/// witness transaction, which has `OP_RETURN` output followed by the
+/// commitment taproot output without script tree.
+pub fn tapret_fixture() -> (Anchor<lnpbp4::MerkleBlock>, Transaction) {
+    let tree = lnpbp4_fixture();
+    let commitment = tree.consensus_commit();
+    let (output_key, proof) = internal_key_fixture()
+        .convolve_commit(&TapretPathProof::new(), &commitment)
+        .expect("fixture tapret commitment");
+    let tx = tx_fixture(vec![
+        TxOut {
+            value: 0,
+            script_pubkey: Script::new_op_return(&[0u8; COMMITMENT_LEN]),
+        },
+        TxOut {
+            value: 1000,
+            script_pubkey: Script::new_v1_p2tr_tweaked(output_key),
+        },
+    ]);
+    let anchor = Anchor::from_parts(
+        tx.txid(),
+        lnpbp4::MerkleBlock::from(tree),
+        Proof::TapretFirst(proof),
+    );
+    (anchor, tx)
+}
+
+/// Returns tapret proof for [`internal_key_fixture`] without script tree.
+pub fn tapret_proof_fixture() -> TapretProof {
+    TapretProof::with(internal_key_fixture(), TapretPathProof::new())
+}
+
+/// Constructs PSBT ready to be committed with [`Anchor::commit`] using the
+/// given commitment `method`.
+///
+/// The PSBT is [`host_fixture`] PSBT with LNPBP-4 messages of the fixture
+/// protocols `0..FIXTURE_PROTOCOLS` in the host output.
+#[cfg(feature = "wallet")]
+pub fn psbt_fixture(method: CloseMethod) -> Psbt {
+    let mut psbt = host_fixture(method);
+    let output = match method {
+        CloseMethod::TapretFirst => &mut psbt.outputs[0],
+        CloseMethod::OpretFirst => &mut psbt.outputs[1],
+    };
+    for (protocol_id, message) in (0..FIXTURE_PROTOCOLS).map(protocol_fixture) {
+        output
+            .set_lnpbp4_message(protocol_id, message)
+            .expect("unique protocol ids");
+    }
+    psbt
+}
+
+/// Constructs PSBT with taproot output #0 (with [`internal_key_fixture`] key
+/// and without script tree) and empty `OP_RETURN` output #1, none of which is
+/// marked as the commitment host.
+#[cfg(feature = "wallet")]
+pub fn unmarked_psbt_fixture() -> Psbt {
+    let internal_key = internal_key_fixture();
+    let tx = tx_fixture(vec![
+        TxOut {
+            value: 1000,
+            script_pubkey: Script::new_v1_p2tr(
+                secp256k1::SECP256K1,
+                internal_key,
+                None,
+            ),
+        },
+        TxOut {
+            value: 0,
+            script_pubkey: Script::new_op_return(&[]),
+        },
+    ]);
+    let mut psbt =
+        Psbt::with(tx, psbt::PsbtVersion::V0).expect("valid fixture PSBT");
+    psbt.outputs[0].tap_internal_key = Some(internal_key);
+    psbt
+}
+
+/// Constructs [`unmarked_psbt_fixture`] PSBT, which output corresponding to
+/// the `method` is marked as the commitment host, but holds no LNPBP-4
+/// messages.
+#[cfg(feature = "wallet")]
+pub fn host_fixture(method: CloseMethod) -> Psbt {
+    let mut psbt = unmarked_psbt_fixture();
+    match method {
+        CloseMethod::TapretFirst => {
+            psbt.outputs[0]
+                .set_tapret_dfs_path(&bitcoin_scripts::taproot::DfsPath::new())
+                .expect("empty DFS path");
+        }
+        CloseMethod::OpretFirst => {
+            psbt.outputs[1].set_opret_host().expect("OP_RETURN output");
+        }
+    }
+    psbt
+}
+
+/// Deterministic random number generator for tests, producing either the
+/// given sequence of values or xorshift values from the given seed.
+///
+/// ```
+/// use dbc::test_utils::TestRng;
+///
+/// let mut rng = TestRng::with_values([0, 1]);
+/// assert_eq!(rng.next_u64(), 0);
+/// assert_eq!(rng.next_u64(), 1);
+/// assert_eq!(rng.next_u64(), 1);
+///
+/// let mut rng = TestRng::with_seed(0x5eed);
+/// assert!(rng.below(4) < 4);
+/// ```
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct TestRng(TestRngState);
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+enum TestRngState {
+    Values(Vec<u64>),
+    Xorshift(u64),
+}
+
+impl TestRng {
+    /// Constructs generator producing `values` in order and repeating the
+    /// last one once the others are exhausted.
+    ///
+    /// # Panics
+    ///
+    /// If `values` are empty.
+    pub fn with_values(values: impl Into<Vec<u64>>) -> TestRng {
+        let values = values.into();
+        assert!(!values.is_empty(), "test RNG requires at least one value");
+        TestRng(TestRngState::Values(values))
+    }
+
+    /// Constructs xorshift generator from non-zero `seed`.
+    ///
+    /// # Panics
+    ///
+    /// If `seed` is zero, which would produce only zeros.
+    pub fn with_seed(seed: u64) -> TestRng {
+        assert_ne!(seed, 0, "xorshift test RNG requires non-zero seed");
+        TestRng(TestRngState::Xorshift(seed))
+    }
+
+    /// Returns the next value of the generator.
+    pub fn next_u64(&mut self) -> u64 {
+        match self.0 {
+            TestRngState::Values(ref mut values) if values.len() > 1 => {
+                values.remove(0)
+            }
+            TestRngState::Values(ref values) => values[0],
+            TestRngState::Xorshift(ref mut state) => {
+                *state ^= *state << 13;
+                *state ^= *state >> 7;
+                *state ^= *state << 17;
+                *state
+            }
+        }
+    }
+
+    /// Returns the next value of the generator reduced to `0..max`.
+    pub fn below(&mut self, max: usize) -> usize {
+        (self.next_u64() % max as u64) as usize
+    }
+}
+
+#[cfg(feature = "rand")]
+impl secp256k1::rand::RngCore for TestRng {
+    fn next_u32(&mut self) -> u32 { TestRng::next_u64(self) as u32 }
+
+    fn next_u64(&mut self) -> u64 { TestRng::next_u64(self) }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = TestRng::next_u64(self).to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(
+        &mut self,
+        dest: &mut [u8],
+    ) -> Result<(), secp256k1::rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
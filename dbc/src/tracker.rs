@@ -0,0 +1,465 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Tracking of the witness transactions of accepted anchors across chain
+//! reorganizations.
+//!
+//! After a reorg a witness transaction may be mined in a different block or
+//! vanish from the chain, invalidating previously accepted anchors.
+//! [`AnchorTracker`] records the block which contained the witness transaction
+//! at the moment of the anchor acceptance, and [`AnchorTracker::recheck`]
+//! compares the records with the current transaction status reported by
+//! [`ResolveTxStatus`], emitting [`AnchorEvent`]s. The tracker is pure
+//! bookkeeping and may be persisted with strict encoding.
+
+use std::collections::BTreeMap;
+
+use bitcoin::{BlockHash, Txid};
+
+use crate::AnchorId;
+
+/// Status of a transaction in the blockchain.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+#[strict_encoding(by_order)]
+pub enum TxStatus {
+    /// Transaction is not known, neither mined nor in the mempool.
+    Unknown,
+
+    /// Transaction is in the mempool and is not mined yet.
+    Mempool,
+
+    /// Transaction is mined.
+    Mined {
+        /// Hash of the block containing the transaction.
+        block_hash: BlockHash,
+        /// Height of the block containing the transaction.
+        height: u32,
+    },
+}
+
+/// Retrieval of the transaction status, usually from an indexer or a node.
+pub trait ResolveTxStatus {
+    /// Errors retrieving the status.
+    type Error: std::error::Error;
+
+    /// Returns height of the chain tip.
+    fn tip_height(&self) -> Result<u32, Self::Error>;
+
+    /// Returns status of the transaction with the given `txid`.
+    fn tx_status(&self, txid: Txid) -> Result<TxStatus, Self::Error>;
+}
+
+/// Position of the anchor witness transaction in the blockchain, recorded by
+/// [`AnchorTracker`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct TrackedWitness {
+    /// Id of the witness transaction.
+    pub txid: Txid,
+
+    /// Hash of the block containing the witness transaction.
+    pub block_hash: BlockHash,
+
+    /// Height of the block containing the witness transaction.
+    pub height: u32,
+}
+
+/// Changes of the anchor witness transaction status reported by
+/// [`AnchorTracker::recheck`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum AnchorEvent {
+    /// Witness transaction was re-mined in a different block after a reorg.
+    Reorged {
+        /// Id of the anchor.
+        anchor_id: AnchorId,
+        /// Block which contained the witness transaction before the reorg.
+        old_block: BlockHash,
+        /// Block which contains the witness transaction now.
+        new_block: BlockHash,
+    },
+
+    /// Witness transaction is not mined anymore. The anchor is removed from
+    /// the tracker and must not be treated as settled.
+    Evicted {
+        /// Id of the anchor.
+        anchor_id: AnchorId,
+    },
+
+    /// Witness transaction remains in the same block.
+    Confirmed {
+        /// Id of the anchor.
+        anchor_id: AnchorId,
+        /// Number of the witness transaction confirmations, i.e. number of
+        /// blocks starting with the block containing the transaction up to
+        /// the chain tip.
+        depth: u32,
+    },
+}
+
+/// Tracker of the anchor witness transactions across chain reorganizations.
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use std::convert::Infallible;
+///
+/// use bitcoin::hashes::Hash;
+/// use bitcoin::{BlockHash, Txid};
+/// use dbc::test_utils::opret_fixture;
+/// use dbc::tracker::{AnchorEvent, AnchorTracker, ResolveTxStatus, TxStatus};
+///
+/// struct Chain(u32, BTreeMap<Txid, TxStatus>);
+///
+/// impl ResolveTxStatus for Chain {
+///     type Error = Infallible;
+///     fn tip_height(&self) -> Result<u32, Infallible> { Ok(self.0) }
+///     fn tx_status(&self, txid: Txid) -> Result<TxStatus, Infallible> {
+///         Ok(self.1.get(&txid).copied().unwrap_or(TxStatus::Unknown))
+///     }
+/// }
+///
+/// let (anchor, _) = opret_fixture();
+/// let block_hash = BlockHash::hash(b"block");
+/// let mut tracker = AnchorTracker::new();
+/// tracker.track(anchor.anchor_id(), anchor.txid(), block_hash, 100);
+///
+/// let status = TxStatus::Mined {
+///     block_hash,
+///     height: 100,
+/// };
+/// let chain = Chain(105, [(anchor.txid(), status)].into());
+/// assert_eq!(
+///     tracker.recheck(&chain),
+///     Ok(vec![AnchorEvent::Confirmed {
+///         anchor_id: anchor.anchor_id(),
+///         depth: 6
+///     }])
+/// );
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct AnchorTracker {
+    anchors: BTreeMap<AnchorId, TrackedWitness>,
+}
+
+impl AnchorTracker {
+    /// Constructs empty tracker.
+    #[inline]
+    pub fn new() -> AnchorTracker { AnchorTracker::default() }
+
+    /// Returns number of the tracked anchors.
+    #[inline]
+    pub fn len(&self) -> usize { self.anchors.len() }
+
+    /// Detects whether the tracker contains no anchors.
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.anchors.is_empty() }
+
+    /// Returns recorded position of the anchor witness transaction, if the
+    /// anchor is tracked.
+    #[inline]
+    pub fn get(&self, anchor_id: &AnchorId) -> Option<&TrackedWitness> {
+        self.anchors.get(anchor_id)
+    }
+
+    /// Iterates over the tracked anchors in the order of their ids.
+    pub fn iter(
+        &self,
+    ) -> impl Iterator<Item = (&AnchorId, &TrackedWitness)> + '_ {
+        self.anchors.iter()
+    }
+
+    /// Starts tracking of the anchor accepted with witness transaction `txid`
+    /// mined in the block `block_hash` at `height`. Returns previously
+    /// recorded position of the witness transaction, if the anchor was
+    /// already tracked.
+    pub fn track(
+        &mut self,
+        anchor_id: AnchorId,
+        txid: Txid,
+        block_hash: BlockHash,
+        height: u32,
+    ) -> Option<TrackedWitness> {
+        self.anchors.insert(anchor_id, TrackedWitness {
+            txid,
+            block_hash,
+            height,
+        })
+    }
+
+    /// Stops tracking of the anchor, returning its record, if any.
+    #[inline]
+    pub fn untrack(&mut self, anchor_id: &AnchorId) -> Option<TrackedWitness> {
+        self.anchors.remove(anchor_id)
+    }
+
+    /// Re-queries status of the witness transactions of all tracked anchors,
+    /// returning an event for each of the anchors, in the order of their ids.
+    ///
+    /// Records of the re-mined witness transactions are updated with their new
+    /// blocks, and the evicted anchors are removed from the tracker.
+    ///
+    /// # Errors
+    ///
+    /// If the `resolver` fails; the tracker is left unchanged in this case.
+    pub fn recheck<R: ResolveTxStatus>(
+        &mut self,
+        resolver: &R,
+    ) -> Result<Vec<AnchorEvent>, R::Error> {
+        let tip = resolver.tip_height()?;
+        let statuses = self
+            .anchors
+            .iter()
+            .map(|(anchor_id, witness)| {
+                resolver
+                    .tx_status(witness.txid)
+                    .map(|status| (*anchor_id, status))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut events = Vec::with_capacity(statuses.len());
+        for (anchor_id, status) in statuses {
+            let (block_hash, height) = match status {
+                TxStatus::Mined { block_hash, height } => (block_hash, height),
+                TxStatus::Unknown | TxStatus::Mempool => {
+                    self.anchors.remove(&anchor_id);
+                    events.push(AnchorEvent::Evicted { anchor_id });
+                    continue;
+                }
+            };
+            let witness = self
+                .anchors
+                .get_mut(&anchor_id)
+                .expect("statuses are collected for the tracked anchors");
+            if witness.block_hash == block_hash {
+                events.push(AnchorEvent::Confirmed {
+                    anchor_id,
+                    depth: (tip + 1).saturating_sub(height),
+                });
+            } else {
+                events.push(AnchorEvent::Reorged {
+                    anchor_id,
+                    old_block: witness.block_hash,
+                    new_block: block_hash,
+                });
+                witness.block_hash = block_hash;
+                witness.height = height;
+            }
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use bitcoin::hashes::Hash;
+    use strict_encoding::{StrictDecode, StrictEncode};
+
+    use super::*;
+    use crate::test_utils::{opret_fixture, tapret_fixture};
+
+    #[derive(Debug, Display, Error)]
+    #[display("status resolver is offline")]
+    struct Offline;
+
+    /// Chain emulation which may be reorganized between the status requests.
+    #[derive(Default)]
+    struct MockChain {
+        tip: u32,
+        statuses: BTreeMap<Txid, TxStatus>,
+        offline: Cell<bool>,
+    }
+
+    impl MockChain {
+        fn mine(&mut self, txid: Txid, block: &[u8], height: u32) {
+            self.statuses.insert(txid, TxStatus::Mined {
+                block_hash: BlockHash::hash(block),
+                height,
+            });
+            self.tip = self.tip.max(height);
+        }
+    }
+
+    impl ResolveTxStatus for MockChain {
+        type Error = Offline;
+
+        fn tip_height(&self) -> Result<u32, Offline> {
+            if self.offline.get() {
+                return Err(Offline);
+            }
+            Ok(self.tip)
+        }
+
+        fn tx_status(&self, txid: Txid) -> Result<TxStatus, Offline> {
+            if self.offline.get() {
+                return Err(Offline);
+            }
+            Ok(self
+                .statuses
+                .get(&txid)
+                .copied()
+                .unwrap_or(TxStatus::Unknown))
+        }
+    }
+
+    #[test]
+    fn reorg() {
+        let (opret, _) = opret_fixture();
+        let (tapret, _) = tapret_fixture();
+        let (opret_id, tapret_id) = (opret.anchor_id(), tapret.anchor_id());
+
+        let mut chain = MockChain::default();
+        chain.mine(opret.txid(), b"a", 100);
+        chain.mine(tapret.txid(), b"b", 101);
+        let mut tracker = AnchorTracker::new();
+        assert_eq!(
+            tracker.track(opret_id, opret.txid(), BlockHash::hash(b"a"), 100),
+            None
+        );
+        tracker.track(tapret_id, tapret.txid(), BlockHash::hash(b"b"), 101);
+        assert_eq!(tracker.len(), 2);
+
+        chain.tip = 102;
+        let events = tracker.recheck(&chain).unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events.contains(&AnchorEvent::Confirmed {
+            anchor_id: opret_id,
+            depth: 3
+        }));
+        assert!(events.contains(&AnchorEvent::Confirmed {
+            anchor_id: tapret_id,
+            depth: 2
+        }));
+
+        // Reorg moving the opret witness into another block and evicting the
+        // tapret witness back into the mempool
+        chain.mine(opret.txid(), b"a'", 101);
+        chain.statuses.insert(tapret.txid(), TxStatus::Mempool);
+        let snapshot = tracker.clone();
+        chain.offline.set(true);
+        assert_eq!(
+            tracker.recheck(&chain).unwrap_err().to_string(),
+            Offline.to_string()
+        );
+        assert_eq!(tracker, snapshot);
+        chain.offline.set(false);
+
+        let events = tracker.recheck(&chain).unwrap();
+        let expected = [
+            AnchorEvent::Reorged {
+                anchor_id: opret_id,
+                old_block: BlockHash::hash(b"a"),
+                new_block: BlockHash::hash(b"a'"),
+            },
+            AnchorEvent::Evicted {
+                anchor_id: tapret_id,
+            },
+        ];
+        assert_eq!(events.len(), 2);
+        for event in expected {
+            assert!(events.contains(&event));
+        }
+        assert_eq!(tracker.len(), 1);
+        assert_eq!(tracker.get(&tapret_id), None);
+        assert_eq!(
+            tracker.get(&opret_id),
+            Some(&TrackedWitness {
+                txid: opret.txid(),
+                block_hash: BlockHash::hash(b"a'"),
+                height: 101,
+            })
+        );
+
+        // Re-mined transaction is confirmed in the new block
+        assert_eq!(tracker.recheck(&chain).unwrap(), vec![
+            AnchorEvent::Confirmed {
+                anchor_id: opret_id,
+                depth: 2
+            }
+        ]);
+
+        // Transaction vanished from the chain
+        chain.statuses.clear();
+        assert_eq!(tracker.recheck(&chain).unwrap(), vec![
+            AnchorEvent::Evicted {
+                anchor_id: opret_id
+            }
+        ]);
+        assert!(tracker.is_empty());
+        assert_eq!(tracker.recheck(&chain).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn events_order() {
+        let (opret, _) = opret_fixture();
+        let (tapret, _) = tapret_fixture();
+        let mut chain = MockChain::default();
+        chain.mine(opret.txid(), b"a", 100);
+        chain.mine(tapret.txid(), b"a", 100);
+
+        let mut tracker = AnchorTracker::new();
+        for anchor in [&tapret, &opret] {
+            tracker.track(
+                anchor.anchor_id(),
+                anchor.txid(),
+                BlockHash::hash(b"a"),
+                100,
+            );
+        }
+        let mut ids = vec![opret.anchor_id(), tapret.anchor_id()];
+        ids.sort();
+        let events = tracker.recheck(&chain).unwrap();
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| match event {
+                    AnchorEvent::Confirmed {
+                        anchor_id,
+                        depth: 1,
+                    } => *anchor_id,
+                    _ => panic!("unexpected event {:?}", event),
+                })
+                .collect::<Vec<_>>(),
+            ids
+        );
+        assert_eq!(tracker.iter().map(|(id, _)| *id).collect::<Vec<_>>(), ids);
+        assert!(tracker.untrack(&ids[0]).is_some());
+        assert!(tracker.untrack(&ids[0]).is_none());
+    }
+
+    #[test]
+    fn strict_encoding() {
+        let (opret, _) = opret_fixture();
+        let mut tracker = AnchorTracker::new();
+        tracker.track(
+            opret.anchor_id(),
+            opret.txid(),
+            BlockHash::hash(b"a"),
+            100,
+        );
+        let data = tracker.strict_serialize().unwrap();
+        assert_eq!(AnchorTracker::strict_deserialize(data).unwrap(), tracker);
+
+        for status in [TxStatus::Unknown, TxStatus::Mempool, TxStatus::Mined {
+            block_hash: BlockHash::hash(b"a"),
+            height: 100,
+        }] {
+            let data = status.strict_serialize().unwrap();
+            assert_eq!(TxStatus::strict_deserialize(data).unwrap(), status);
+        }
+    }
+}
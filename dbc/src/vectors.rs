@@ -0,0 +1,383 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Test vectors for the LNPBP commitment schemes implemented by the library:
+//! LNPBP-4 multi-protocol commitments, tapret and opret deterministic bitcoin
+//! commitments, anchor ids, anchor commitment ids and anchor string
+//! representation.
+//!
+//! Vectors are produced from deterministic inputs (see [`crate::test_utils`])
+//! and can be used by other implementations to check their compatibility with
+//! this one. The specifications do not publish concrete vectors for these
+//! schemes, so the vectors are kept as candidates for upstreaming in
+//! `tests/data/lnpbp/` directory of the crate; crate tests check that the
+//! library reproduces them exactly. Files can be regenerated by running the
+//! tests with `BP_UPDATE_VECTORS` environment variable set.
+//!
+//! Vector files use a line-based format, where each vector is a section named
+//! in square brackets followed by `input.<name> = <value>` and
+//! `output.<name> = <value>` lines, with all binary values in hex.
+
+use std::fmt::{self, Display, Formatter};
+
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::Script;
+use bitcoin_scripts::{LeafScript, TapScript};
+use commit_verify::convolve_commit::ConvolveCommitVerify;
+use commit_verify::{lnpbp4, ConsensusCommit};
+use strict_encoding::StrictEncode;
+
+use crate::anchor::{lnpbp4_tree_with_entropy, ANCHOR_MIN_LNPBP4_DEPTH};
+use crate::opret::opret_script;
+use crate::tapret::{TapretNodePartner, TapretPathProof};
+use crate::test_utils::{
+    internal_key_fixture, lnpbp4_fixture, lnpbp4_tree_fixture, opret_fixture,
+    protocol_fixture, tapret_fixture, FIXTURE_PROTOCOLS,
+};
+use crate::Anchor;
+
+/// Single test vector, consisting of named inputs and outputs.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct TestVector {
+    /// Name of the vector, unique within a vector file.
+    pub name: String,
+
+    /// Named vector inputs.
+    pub input: Vec<(&'static str, String)>,
+
+    /// Named outputs which must be produced from the inputs.
+    pub output: Vec<(&'static str, String)>,
+}
+
+impl Display for TestVector {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "[{}]", self.name)?;
+        for (name, value) in &self.input {
+            writeln!(f, "input.{} = {}", name, value)?;
+        }
+        for (name, value) in &self.output {
+            writeln!(f, "output.{} = {}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders vectors into the vector file format.
+pub fn render(vectors: &[TestVector]) -> String {
+    vectors
+        .iter()
+        .map(TestVector::to_string)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns all vector sets as pairs of the vector file name (without
+/// extension) and the vectors.
+pub fn all() -> Vec<(&'static str, Vec<TestVector>)> {
+    vec![
+        ("lnpbp4", lnpbp4()),
+        ("tapret", tapret()),
+        ("opret", opret()),
+        ("anchor", anchor()),
+        ("anchor_commitment", anchor_commitment()),
+        ("anchor_armor", anchor_armor()),
+    ]
+}
+
+/// LNPBP-4 commitments for different number of protocols and minimal tree
+/// depths, constructed with static entropy.
+pub fn lnpbp4() -> Vec<TestVector> {
+    [(0u8, 1u8), (3, 1), (3, 3), (3, 8), (4, 16)]
+        .into_iter()
+        .map(|(min_depth, count)| {
+            let source = lnpbp4::MultiSource {
+                min_depth,
+                messages: (0..count).map(protocol_fixture).collect(),
+            };
+            let tree = lnpbp4_tree_fixture(&source);
+            let mut input = vec![
+                ("min_depth", min_depth.to_string()),
+                ("entropy", tree.entropy().to_string()),
+            ];
+            input.extend(source.messages.iter().map(|(protocol_id, msg)| {
+                ("message", format!("{}:{}", protocol_id[..].to_hex(), msg))
+            }));
+            TestVector {
+                name: format!("depth{}-protocols{}", min_depth, count),
+                input,
+                output: vec![
+                    ("depth", tree.depth().to_string()),
+                    ("width", tree.width().to_string()),
+                    ("commitment", tree.consensus_commit()[..].to_hex()),
+                ],
+            }
+        })
+        .collect()
+}
+
+/// Tapret commitments to the LNPBP-4 commitment of the fixture protocols for
+/// each type of the tapret path proof.
+pub fn tapret() -> Vec<TestVector> {
+    let msg = lnpbp4_fixture().consensus_commit();
+    let leaf =
+        LeafScript::tapscript(TapScript::from(Script::new_op_return(&[1])));
+    let cases = [
+        ("no-script-tree", None),
+        (
+            "left-node",
+            Some(TapretNodePartner::LeftNode(sha256::Hash::from_inner(
+                [0u8; 32],
+            ))),
+        ),
+        ("right-leaf", Some(TapretNodePartner::RightLeaf(leaf))),
+        (
+            "right-branch",
+            Some(TapretNodePartner::right_branch(
+                sha256::Hash::hash(b"a"),
+                sha256::Hash::hash(b"b"),
+            )),
+        ),
+    ];
+    cases
+        .into_iter()
+        .map(|(name, partner)| {
+            // Right-side partners require nonce putting the commitment into
+            // the consensus order, which is found in the same way as during
+            // the embedding.
+            let (nonce, output_key) = (0..=u8::MAX)
+                .find_map(|nonce| {
+                    let path_proof = match &partner {
+                        None => TapretPathProof::new(),
+                        Some(partner) => {
+                            TapretPathProof::with(partner.clone(), nonce)
+                                .expect("vector partner has no commitment")
+                        }
+                    };
+                    internal_key_fixture()
+                        .convolve_commit(&path_proof, &msg)
+                        .ok()
+                        .map(|(output_key, _)| (nonce, output_key))
+                })
+                .expect("vector tapret commitment");
+            let mut input = vec![
+                ("internal_key", internal_key_fixture().serialize().to_hex()),
+                ("commitment", msg[..].to_hex()),
+            ];
+            if let Some(partner) = partner {
+                input.push((
+                    "partner",
+                    partner.strict_serialize().expect("in-memory").to_hex(),
+                ));
+                input.push(("nonce", nonce.to_string()));
+            }
+            TestVector {
+                name: name.to_owned(),
+                input,
+                output: vec![
+                    ("output_key", output_key.to_inner().serialize().to_hex()),
+                    (
+                        "script_pubkey",
+                        Script::new_v1_p2tr_tweaked(output_key)
+                            .as_bytes()
+                            .to_hex(),
+                    ),
+                ],
+            }
+        })
+        .collect()
+}
+
+/// Opret commitments to the LNPBP-4 commitment of the fixture protocols and
+/// to all-zero commitment.
+pub fn opret() -> Vec<TestVector> {
+    [
+        ("fixture", lnpbp4_fixture().consensus_commit()[..].to_vec()),
+        ("zero", vec![0u8; 32]),
+    ]
+    .into_iter()
+    .map(|(name, commitment)| TestVector {
+        name: name.to_owned(),
+        input: vec![("commitment", commitment[..].to_hex())],
+        output: vec![(
+            "script_pubkey",
+            opret_script(&commitment[..])
+                .expect("32-byte commitment")
+                .as_bytes()
+                .to_hex(),
+        )],
+    })
+    .collect()
+}
+
+/// Ids of the fixture anchors, both in form of the LNPBP-4 merkle block and
+/// in form of the LNPBP-4 merkle proof for the fixture protocol #1. The proof
+/// form input also includes the protocol id and message required to compute
+/// the id.
+pub fn anchor() -> Vec<TestVector> {
+    let (protocol_id, message) = protocol_fixture(1);
+    [("opret", opret_fixture().0), ("tapret", tapret_fixture().0)]
+        .into_iter()
+        .flat_map(|(name, anchor)| {
+            let proof = anchor
+                .to_merkle_proof(protocol_id)
+                .expect("fixture protocol");
+            [
+                TestVector {
+                    name: format!("{}-merkle-block", name),
+                    input: vec![(
+                        "anchor",
+                        anchor.strict_serialize().expect("in-memory").to_hex(),
+                    )],
+                    output: vec![("anchor_id", anchor.anchor_id().to_hex())],
+                },
+                TestVector {
+                    name: format!("{}-merkle-proof", name),
+                    input: vec![
+                        (
+                            "anchor",
+                            proof
+                                .strict_serialize()
+                                .expect("in-memory")
+                                .to_hex(),
+                        ),
+                        ("protocol_id", protocol_id[..].to_hex()),
+                        ("message", message.to_hex()),
+                    ],
+                    output: vec![(
+                        "anchor_id",
+                        proof
+                            .anchor_id(protocol_id, message)
+                            .expect("fixture protocol")
+                            .to_hex(),
+                    )],
+                },
+            ]
+        })
+        .collect()
+}
+
+/// Commitment ids of the fixture anchors and of the anchors with the same
+/// witness transaction id and LNPBP-4 messages, but the LNPBP-4 tree
+/// constructed with a different entropy. Anchors of each pair have different
+/// anchor ids, but the same commitment id.
+pub fn anchor_commitment() -> Vec<TestVector> {
+    let source = lnpbp4::MultiSource {
+        min_depth: ANCHOR_MIN_LNPBP4_DEPTH,
+        messages: (0..FIXTURE_PROTOCOLS).map(protocol_fixture).collect(),
+    };
+    let tree = lnpbp4_tree_with_entropy(&source, 0x5eed)
+        .expect("fixture LNPBP-4 tree")
+        .expect("LNPBP-4 tree encoding");
+    [("opret", opret_fixture().0), ("tapret", tapret_fixture().0)]
+        .into_iter()
+        .flat_map(|(name, anchor)| {
+            let other = Anchor::from_parts(
+                anchor.txid(),
+                lnpbp4::MerkleBlock::from(tree.clone()),
+                anchor.dbc_proof().clone(),
+            );
+            [("static-entropy", anchor), ("entropy-5eed", other)]
+                .into_iter()
+                .map(move |(suffix, anchor)| TestVector {
+                    name: format!("{}-{}", name, suffix),
+                    input: vec![(
+                        "anchor",
+                        anchor.strict_serialize().expect("in-memory").to_hex(),
+                    )],
+                    output: vec![
+                        ("anchor_id", anchor.anchor_id().to_hex()),
+                        (
+                            "commitment_id",
+                            anchor
+                                .commitment_id()
+                                .expect("fixture LNPBP-4 block")
+                                .to_hex(),
+                        ),
+                    ],
+                })
+        })
+        .collect()
+}
+
+/// Bech32m string representation of the fixture anchors in form of the
+/// LNPBP-4 merkle proof for the fixture protocol #1.
+pub fn anchor_armor() -> Vec<TestVector> {
+    let (protocol_id, _) = protocol_fixture(1);
+    [("opret", opret_fixture().0), ("tapret", tapret_fixture().0)]
+        .into_iter()
+        .map(|(name, anchor)| {
+            let proof = anchor
+                .to_merkle_proof(protocol_id)
+                .expect("fixture protocol");
+            TestVector {
+                name: format!("{}-merkle-proof", name),
+                input: vec![(
+                    "anchor",
+                    proof.strict_serialize().expect("in-memory").to_hex(),
+                )],
+                output: vec![(
+                    "bech32m",
+                    proof.to_bech32m_string().expect("small anchor"),
+                )],
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use std::{env, fs};
+
+    use super::*;
+
+    fn vector_path(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/data/lnpbp")
+            .join(format!("{}.vectors", name))
+    }
+
+    #[test]
+    fn reproduce_vectors() {
+        let update = env::var_os("BP_UPDATE_VECTORS").is_some();
+        for (name, vectors) in all() {
+            let path = vector_path(name);
+            let rendered = render(&vectors);
+            if update {
+                fs::write(&path, &rendered).unwrap();
+            }
+            let stored = fs::read_to_string(&path).unwrap();
+            assert_eq!(rendered, stored, "mismatch in {}", path.display());
+        }
+    }
+
+    #[test]
+    fn anchor_id_matches_forms() {
+        let vectors = anchor();
+        for pair in vectors.chunks(2) {
+            assert_eq!(pair[0].output, pair[1].output);
+        }
+    }
+
+    #[test]
+    fn commitment_id_ignores_entropy() {
+        let vectors = anchor_commitment();
+        for pair in vectors.chunks(2) {
+            assert_ne!(pair[0].output[0], pair[1].output[0]);
+            assert_eq!(pair[0].output[1], pair[1].output[1]);
+        }
+    }
+}
@@ -0,0 +1,769 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Integration point for verification of anchors by client-side-validated
+//! protocols.
+//!
+//! Downstream protocols (RGB, Storm, timestamping services etc) implement
+//! [`DbcVerify`] for their data types committed with deterministic bitcoin
+//! commitments, and use [`verify_anchor`] or [`resolve_verify_anchor`] to
+//! check that the data are committed by an anchor. Consignments, carrying
+//! multiple anchors with their witness transactions, may be verified with
+//! [`verify_stored_anchors`] using a de-duplicating [`WitnessStore`]; items
+//! referencing anchors kept in an [`AnchorStore`] are verified with
+//! [`verify_by_anchor_id`].
+//!
+//! Verification of large batches may be cancelled by the caller (for instance
+//! on a client disconnect) using `_with` variants of the batch functions, which
+//! check a cancellation flag before each of the items.
+//!
+//! Batch functions stop on the first item which is not committed; their
+//! `_each` variants verify all the items and report outcome of each of them
+//! (see [`crate::report`]).
+
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use bitcoin::{Transaction, Txid};
+use bitcoin_onchain::{ResolveTx, TxResolverError};
+use commit_verify::lnpbp4::{self, Message, ProtocolId};
+
+use crate::anchor::VerifyError;
+use crate::report::ItemResult;
+use crate::store::AnchorStore;
+use crate::witness::WitnessStore;
+use crate::{Anchor, AnchorId, PreparedTx, WitnessId};
+
+/// Data committed under some LNPBP-4 protocol with a deterministic bitcoin
+/// commitment.
+pub trait DbcVerify {
+    /// Returns id of the LNPBP-4 protocol the data are committed under.
+    fn protocol_id(&self) -> ProtocolId;
+
+    /// Returns LNPBP-4 message representing the data.
+    fn message(&self) -> Message;
+}
+
+/// Errors verifying anchor with a witness transaction retrieved using
+/// transaction resolver.
+#[derive(Debug, Display, Error, From)]
+#[display(inner)]
+pub enum ResolveVerifyError {
+    /// Anchor verification failure.
+    #[from]
+    Verify(VerifyError),
+
+    /// Failure retrieving witness transaction.
+    #[from]
+    Resolver(TxResolverError),
+
+    /// Batch verification was cancelled.
+    #[display("verification cancelled after {completed} verified items")]
+    Cancelled {
+        /// Number of items verified before the cancellation.
+        completed: usize,
+    },
+
+    /// Anchor referenced by an item is absent in the anchor store.
+    #[display("anchor {0} is not known to the anchor store")]
+    AnchorNotFound(AnchorId),
+
+    /// Failure reading anchor store.
+    Store(Box<dyn std::error::Error + Send + Sync>),
+}
+
+fn check_cancelled(
+    cancel: &AtomicBool,
+    completed: usize,
+) -> Result<(), ResolveVerifyError> {
+    if cancel.load(Ordering::Relaxed) {
+        return Err(ResolveVerifyError::Cancelled { completed });
+    }
+    Ok(())
+}
+
+/// Verifies that the witness transaction `tx` contains commitment to the
+/// `item` via the provided `anchor`.
+///
+/// Returns `Ok(false)` if the transaction does not match anchor witness
+/// transaction id or does not commit to the item.
+pub fn verify_anchor(
+    item: &impl DbcVerify,
+    anchor: &Anchor<lnpbp4::MerkleProof>,
+    tx: &Transaction,
+) -> Result<bool, VerifyError> {
+    if !anchor.is_witness(tx) {
+        return Ok(false);
+    }
+    anchor.verify(item.protocol_id(), item.message(), tx)
+}
+
+/// Verifies that the prepared witness transaction `tx` contains commitment to
+/// the `item` via the provided `anchor`. See [`verify_anchor`] for the
+/// details.
+pub fn verify_anchor_prepared(
+    item: &impl DbcVerify,
+    anchor: &Anchor<lnpbp4::MerkleProof>,
+    tx: &PreparedTx,
+) -> Result<bool, VerifyError> {
+    if tx.txid() != anchor.txid() {
+        return Ok(false);
+    }
+    anchor.verify_prepared(item.protocol_id(), item.message(), tx)
+}
+
+/// Verifies that the witness transaction `tx` contains commitments to all
+/// items via the anchors provided together with each of the items.
+///
+/// The transaction is prepared for the verification only once; see
+/// [`PreparedTx`]. Returns `Ok(false)` if any of the items is not committed.
+pub fn verify_anchors<'item, I>(
+    batch: impl IntoIterator<Item = (&'item I, &'item Anchor<lnpbp4::MerkleProof>)>,
+    tx: &Transaction,
+) -> Result<bool, VerifyError>
+where
+    I: DbcVerify + 'item,
+{
+    let tx = PreparedTx::new(tx.clone());
+    for (item, anchor) in batch {
+        if !verify_anchor_prepared(item, anchor, &tx)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Verifies items with [`verify_anchors`], checking the `cancel` flag before
+/// verification of each of the items.
+///
+/// # Errors
+///
+/// [`ResolveVerifyError::Cancelled`] with the number of already verified items
+/// if the flag is set before the verification is complete, or
+/// [`ResolveVerifyError::Verify`] if anchor verification fails.
+pub fn verify_anchors_with<'item, I>(
+    batch: impl IntoIterator<Item = (&'item I, &'item Anchor<lnpbp4::MerkleProof>)>,
+    tx: &Transaction,
+    cancel: &AtomicBool,
+) -> Result<bool, ResolveVerifyError>
+where
+    I: DbcVerify + 'item,
+{
+    let tx = PreparedTx::new(tx.clone());
+    for (completed, (item, anchor)) in batch.into_iter().enumerate() {
+        check_cancelled(cancel, completed)?;
+        if !verify_anchor_prepared(item, anchor, &tx)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Verifies that witness transactions from the `store` contain commitments to
+/// all items via the anchors provided together with each of the items.
+///
+/// Anchors may reference different witness transactions; each of the unique
+/// transactions is prepared for the verification only once, and its id is not
+/// re-computed. Returns `Ok(false)` if any of the items is not committed.
+///
+/// # Errors
+///
+/// If the store does not contain some of the witness transactions, or anchor
+/// verification fails.
+pub fn verify_stored_anchors<'item, I>(
+    batch: impl IntoIterator<Item = (&'item I, &'item Anchor<lnpbp4::MerkleProof>)>,
+    store: &WitnessStore,
+) -> Result<bool, ResolveVerifyError>
+where
+    I: DbcVerify + 'item,
+{
+    verify_stored_anchors_with(batch, store, &AtomicBool::new(false))
+}
+
+/// Verifies items with [`verify_stored_anchors`], checking the `cancel` flag
+/// before verification of each of the items.
+///
+/// # Errors
+///
+/// [`ResolveVerifyError::Cancelled`] with the number of already verified items
+/// if the flag is set before the verification is complete; other errors are
+/// the same as for [`verify_stored_anchors`].
+pub fn verify_stored_anchors_with<'item, I>(
+    batch: impl IntoIterator<Item = (&'item I, &'item Anchor<lnpbp4::MerkleProof>)>,
+    store: &WitnessStore,
+    cancel: &AtomicBool,
+) -> Result<bool, ResolveVerifyError>
+where
+    I: DbcVerify + 'item,
+{
+    let mut prepared = BTreeMap::<Txid, PreparedTx>::new();
+    for (completed, (item, anchor)) in batch.into_iter().enumerate() {
+        check_cancelled(cancel, completed)?;
+        let tx = match prepared.entry(anchor.txid()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let tx =
+                    store.prepare(&anchor.witness_id()).ok_or_else(|| {
+                        #[cfg(feature = "metrics")]
+                        crate::metrics::record_resolver_failure();
+                        TxResolverError::with(anchor.txid())
+                    })?;
+                entry.insert(tx)
+            }
+        };
+        if !verify_anchor_prepared(item, anchor, tx)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Verifies all items with [`verify_stored_anchors`], reporting outcome of
+/// each of the items instead of stopping on the first failure.
+///
+/// Items are identified by the witness transaction id of their anchors, since
+/// [`crate::AnchorId`] of an anchor with a merkle proof may be computed only
+/// from the message it commits to.
+pub fn verify_stored_anchors_each<'item, I>(
+    batch: impl IntoIterator<Item = (&'item I, &'item Anchor<lnpbp4::MerkleProof>)>,
+    store: &WitnessStore,
+) -> Vec<ItemResult<WitnessId, ResolveVerifyError>>
+where
+    I: DbcVerify + 'item,
+{
+    let mut prepared = BTreeMap::<Txid, PreparedTx>::new();
+    batch
+        .into_iter()
+        .enumerate()
+        .map(|(index, (item, anchor))| {
+            let tx = match prepared.entry(anchor.txid()) {
+                Entry::Occupied(entry) => Some(entry.into_mut()),
+                Entry::Vacant(entry) => store
+                    .prepare(&anchor.witness_id())
+                    .map(|tx| entry.insert(tx)),
+            };
+            let outcome = match tx {
+                Some(tx) => verify_anchor_prepared(item, anchor, tx)
+                    .map_err(ResolveVerifyError::from),
+                None => {
+                    #[cfg(feature = "metrics")]
+                    crate::metrics::record_resolver_failure();
+                    Err(TxResolverError::with(anchor.txid()).into())
+                }
+            };
+            ItemResult {
+                index,
+                id: anchor.witness_id(),
+                outcome,
+            }
+        })
+        .collect()
+}
+
+/// Verifies that witness transactions from the `witnesses` store contain
+/// commitments to all items via the anchors from the `anchors` store, which
+/// are referenced by their ids together with each of the items.
+///
+/// Returns `Ok(false)` if any of the items is not committed, including the
+/// case when the referenced anchor does not reveal the item protocol.
+///
+/// # Errors
+///
+/// [`ResolveVerifyError::AnchorNotFound`] or [`ResolveVerifyError::Store`] if
+/// some of the anchors can't be retrieved from the anchor store; other errors
+/// are the same as for [`verify_stored_anchors`].
+pub fn verify_by_anchor_id<'item, I>(
+    batch: impl IntoIterator<Item = (&'item I, AnchorId)>,
+    anchors: &impl AnchorStore,
+    witnesses: &WitnessStore,
+) -> Result<bool, ResolveVerifyError>
+where
+    I: DbcVerify + 'item,
+{
+    let mut items = vec![];
+    let mut proofs = vec![];
+    for (item, id) in batch {
+        let anchor = anchors
+            .get(&id)
+            .map_err(|err| ResolveVerifyError::Store(Box::new(err)))?
+            .ok_or(ResolveVerifyError::AnchorNotFound(id))?;
+        match anchor.into_merkle_proof(item.protocol_id()) {
+            Ok(proof) => proofs.push(proof),
+            Err(_) => return Ok(false),
+        }
+        items.push(item);
+    }
+    verify_stored_anchors(items.into_iter().zip(&proofs), witnesses)
+}
+
+/// Verifies that the `anchor` commits to the `item`, retrieving witness
+/// transaction with the provided `resolver`. See [`verify_anchor`] for the
+/// details.
+pub fn resolve_verify_anchor(
+    item: &impl DbcVerify,
+    anchor: &Anchor<lnpbp4::MerkleProof>,
+    resolver: &impl ResolveTx,
+) -> Result<bool, ResolveVerifyError> {
+    let tx = match resolver.resolve_tx(anchor.txid()) {
+        Ok(tx) => tx,
+        Err(err) => {
+            #[cfg(feature = "metrics")]
+            crate::metrics::record_resolver_failure();
+            return Err(err.into());
+        }
+    };
+    verify_anchor(item, anchor, &tx).map_err(ResolveVerifyError::from)
+}
+
+/// Verifies that all items are committed by the anchors provided together with
+/// each of the items, retrieving witness transactions with the provided
+/// `resolver`.
+///
+/// Each of the unique witness transactions is retrieved and prepared for the
+/// verification only once. Returns `Ok(false)` if any of the items is not
+/// committed.
+///
+/// # Errors
+///
+/// If the resolver fails to retrieve some of the witness transactions, or
+/// anchor verification fails.
+pub fn resolve_verify_anchors<'item, I>(
+    batch: impl IntoIterator<Item = (&'item I, &'item Anchor<lnpbp4::MerkleProof>)>,
+    resolver: &impl ResolveTx,
+) -> Result<bool, ResolveVerifyError>
+where
+    I: DbcVerify + 'item,
+{
+    resolve_verify_anchors_with(batch, resolver, &AtomicBool::new(false))
+}
+
+/// Verifies items with [`resolve_verify_anchors`], checking the `cancel` flag
+/// before verification of each of the items, and thus before each of the
+/// resolver calls.
+///
+/// # Errors
+///
+/// [`ResolveVerifyError::Cancelled`] with the number of already verified items
+/// if the flag is set before the verification is complete; other errors are
+/// the same as for [`resolve_verify_anchors`].
+pub fn resolve_verify_anchors_with<'item, I>(
+    batch: impl IntoIterator<Item = (&'item I, &'item Anchor<lnpbp4::MerkleProof>)>,
+    resolver: &impl ResolveTx,
+    cancel: &AtomicBool,
+) -> Result<bool, ResolveVerifyError>
+where
+    I: DbcVerify + 'item,
+{
+    let mut prepared = BTreeMap::<Txid, PreparedTx>::new();
+    for (completed, (item, anchor)) in batch.into_iter().enumerate() {
+        check_cancelled(cancel, completed)?;
+        let tx = match prepared.entry(anchor.txid()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let tx = match resolver.resolve_tx(anchor.txid()) {
+                    Ok(tx) => tx,
+                    Err(err) => {
+                        #[cfg(feature = "metrics")]
+                        crate::metrics::record_resolver_failure();
+                        return Err(err.into());
+                    }
+                };
+                entry.insert(PreparedTx::new(tx))
+            }
+        };
+        if !verify_anchor_prepared(item, anchor, tx)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Verifies all items with [`resolve_verify_anchors`], reporting outcome of
+/// each of the items instead of stopping on the first failure; see
+/// [`verify_stored_anchors_each`] for the details.
+///
+/// Witness transactions which the resolver failed to retrieve are requested
+/// again for each of the items referencing them.
+pub fn resolve_verify_anchors_each<'item, I>(
+    batch: impl IntoIterator<Item = (&'item I, &'item Anchor<lnpbp4::MerkleProof>)>,
+    resolver: &impl ResolveTx,
+) -> Vec<ItemResult<WitnessId, ResolveVerifyError>>
+where
+    I: DbcVerify + 'item,
+{
+    let mut prepared = BTreeMap::<Txid, PreparedTx>::new();
+    batch
+        .into_iter()
+        .enumerate()
+        .map(|(index, (item, anchor))| {
+            let tx = match prepared.entry(anchor.txid()) {
+                Entry::Occupied(entry) => Ok(entry.into_mut()),
+                Entry::Vacant(entry) => resolver
+                    .resolve_tx(anchor.txid())
+                    .map(|tx| entry.insert(PreparedTx::new(tx))),
+            };
+            let outcome = match tx {
+                Ok(tx) => verify_anchor_prepared(item, anchor, tx)
+                    .map_err(ResolveVerifyError::from),
+                Err(err) => {
+                    #[cfg(feature = "metrics")]
+                    crate::metrics::record_resolver_failure();
+                    Err(err.into())
+                }
+            };
+            ItemResult {
+                index,
+                id: anchor.witness_id(),
+                outcome,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::Wrapper;
+    use bitcoin::hashes::{sha256, Hash};
+    use bitcoin::TxOut;
+    use commit_verify::ConsensusCommit;
+
+    use super::*;
+    use crate::consts::ANCHOR_MIN_LNPBP4_DEPTH;
+    use crate::opret::opret_script;
+    use crate::report::summary;
+    use crate::store::MemAnchorStore;
+    use crate::test_utils::{lnpbp4_tree_fixture, tx_fixture};
+    use crate::{ErrorClass, Proof};
+
+    /// Toy timestamping record committing to a document.
+    struct Timestamp {
+        document: Vec<u8>,
+    }
+
+    impl DbcVerify for Timestamp {
+        fn protocol_id(&self) -> ProtocolId {
+            ProtocolId::from_inner(
+                sha256::Hash::hash(b"timestamp").into_inner(),
+            )
+        }
+
+        fn message(&self) -> Message { Message::hash(&self.document) }
+    }
+
+    struct Resolver(Transaction);
+
+    /// Resolver setting cancellation flag on the first lookup.
+    struct CancellingResolver<'cancel>(WitnessStore, &'cancel AtomicBool);
+
+    impl ResolveTx for CancellingResolver<'_> {
+        fn resolve_tx(
+            &self,
+            txid: Txid,
+        ) -> Result<Transaction, TxResolverError> {
+            self.1.store(true, Ordering::Relaxed);
+            self.0.resolve_tx(txid)
+        }
+    }
+
+    impl ResolveTx for Resolver {
+        fn resolve_tx(
+            &self,
+            txid: Txid,
+        ) -> Result<Transaction, TxResolverError> {
+            if self.0.txid() == txid {
+                Ok(self.0.clone())
+            } else {
+                Err(TxResolverError::with(txid))
+            }
+        }
+    }
+
+    fn anchor_fixture(
+        item: &impl DbcVerify,
+    ) -> (Anchor<lnpbp4::MerkleProof>, Transaction) {
+        let (anchor, tx) = block_anchor_fixture(item);
+        (anchor.into_merkle_proof(item.protocol_id()).unwrap(), tx)
+    }
+
+    fn block_anchor_fixture(
+        item: &impl DbcVerify,
+    ) -> (Anchor<lnpbp4::MerkleBlock>, Transaction) {
+        let source = lnpbp4::MultiSource {
+            min_depth: ANCHOR_MIN_LNPBP4_DEPTH,
+            messages: bmap! { item.protocol_id() => item.message() },
+        };
+        let tree = lnpbp4_tree_fixture(&source);
+        let commitment = tree.consensus_commit();
+        let tx = tx_fixture(vec![TxOut {
+            value: 0,
+            script_pubkey: opret_script(&commitment[..]).unwrap(),
+        }]);
+        let anchor = Anchor::from_parts(
+            tx.txid(),
+            lnpbp4::MerkleBlock::from(tree),
+            Proof::OpretFirst,
+        );
+        (anchor, tx)
+    }
+
+    #[test]
+    fn timestamp_verification() {
+        let item = Timestamp {
+            document: b"document".to_vec(),
+        };
+        let other = Timestamp {
+            document: b"other document".to_vec(),
+        };
+        let (anchor, tx) = anchor_fixture(&item);
+        let (_, other_tx) = anchor_fixture(&other);
+
+        assert!(verify_anchor(&item, &anchor, &tx).unwrap());
+        assert!(!verify_anchor(&item, &anchor, &other_tx).unwrap());
+        assert!(!verify_anchor(&other, &anchor, &tx).unwrap_or(false));
+
+        assert!(resolve_verify_anchor(&item, &anchor, &Resolver(tx.clone()))
+            .unwrap());
+        assert!(verify_anchors([(&item, &anchor)], &tx).unwrap());
+        assert!(!verify_anchors([(&item, &anchor), (&other, &anchor)], &tx)
+            .unwrap_or(false));
+        assert!(
+            verify_anchors(std::iter::empty::<(&Timestamp, _)>(), &tx).unwrap()
+        );
+
+        assert!(matches!(
+            resolve_verify_anchor(&item, &anchor, &Resolver(other_tx)),
+            Err(ResolveVerifyError::Resolver(_))
+        ));
+    }
+
+    #[test]
+    fn stored_verification() {
+        let item = Timestamp {
+            document: b"document".to_vec(),
+        };
+        let other = Timestamp {
+            document: b"other document".to_vec(),
+        };
+        let (anchor, tx) = anchor_fixture(&item);
+        let (other_anchor, other_tx) = anchor_fixture(&other);
+
+        let mut store = WitnessStore::new();
+        assert!(matches!(
+            verify_stored_anchors([(&item, &anchor)], &store),
+            Err(ResolveVerifyError::Resolver(_))
+        ));
+
+        // Each of the anchors carries its own copy of the witness
+        for _ in 0..3 {
+            store
+                .insert_checked(anchor.witness_id(), tx.clone())
+                .unwrap();
+            store
+                .insert_checked(other_anchor.witness_id(), other_tx.clone())
+                .unwrap();
+        }
+        assert_eq!(store.len(), 2);
+        assert!(verify_stored_anchors(
+            [(&item, &anchor), (&other, &other_anchor), (&item, &anchor)],
+            &store
+        )
+        .unwrap());
+        assert!(!verify_stored_anchors(
+            [(&item, &anchor), (&other, &anchor)],
+            &store
+        )
+        .unwrap_or(false));
+        assert!(resolve_verify_anchor(&item, &anchor, &store).unwrap());
+    }
+
+    #[test]
+    fn anchor_store_verification() {
+        let item = Timestamp {
+            document: b"document".to_vec(),
+        };
+        let other = Timestamp {
+            document: b"other document".to_vec(),
+        };
+        let (anchor, tx) = block_anchor_fixture(&item);
+        let (other_anchor, other_tx) = block_anchor_fixture(&other);
+        let (id, other_id) = (anchor.anchor_id(), other_anchor.anchor_id());
+        let mut witnesses = WitnessStore::new();
+        witnesses.insert(tx);
+        witnesses.insert(other_tx);
+
+        let mut anchors = MemAnchorStore::new();
+        anchors.insert(anchor).unwrap();
+        assert!(
+            verify_by_anchor_id([(&item, id)], &anchors, &witnesses).unwrap()
+        );
+        assert!(matches!(
+            verify_by_anchor_id(
+                [(&item, id), (&other, other_id)],
+                &anchors,
+                &witnesses
+            ),
+            Err(ResolveVerifyError::AnchorNotFound(missing)) if missing == other_id
+        ));
+
+        anchors.insert(other_anchor).unwrap();
+        assert!(verify_by_anchor_id(
+            [(&item, id), (&other, other_id), (&item, id)],
+            &anchors,
+            &witnesses
+        )
+        .unwrap());
+        // Anchor does not reveal the protocol of the item
+        assert!(!verify_by_anchor_id([(&other, id)], &anchors, &witnesses)
+            .unwrap_or(false));
+        assert!(verify_by_anchor_id(
+            std::iter::empty::<(&Timestamp, _)>(),
+            &anchors,
+            &witnesses
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn localized_verification() {
+        let item = Timestamp {
+            document: b"document".to_vec(),
+        };
+        let other = Timestamp {
+            document: b"other document".to_vec(),
+        };
+        let unknown = Timestamp {
+            document: b"unknown document".to_vec(),
+        };
+        let (anchor, tx) = anchor_fixture(&item);
+        let (other_anchor, other_tx) = anchor_fixture(&other);
+        let (unknown_anchor, _) = anchor_fixture(&unknown);
+        let mut store = WitnessStore::new();
+        store.insert(tx.clone());
+        store.insert(other_tx);
+
+        let batch = [
+            (&item, &anchor),
+            (&other, &anchor),
+            (&unknown, &unknown_anchor),
+            (&other, &other_anchor),
+            (&unknown, &unknown_anchor),
+        ];
+        let stored = verify_stored_anchors_each(batch, &store);
+        let resolved = resolve_verify_anchors_each(batch, &store);
+        for results in [stored, resolved] {
+            assert_eq!(results.len(), batch.len());
+            for (index, (result, (_, anchor))) in
+                results.iter().zip(batch).enumerate()
+            {
+                assert_eq!(result.index, index);
+                assert_eq!(result.id, anchor.witness_id());
+            }
+            assert_eq!(
+                results.iter().map(ItemResult::is_valid).collect::<Vec<_>>(),
+                [true, false, false, true, false]
+            );
+            assert!(matches!(results[1].outcome, Ok(false)));
+            assert!(matches!(
+                results[2].outcome,
+                Err(ResolveVerifyError::Resolver(_))
+            ));
+
+            let summary = summary(&results);
+            assert_eq!(summary.valid, 2);
+            assert_eq!(summary.invalid, 1);
+            assert_eq!(summary.errors(), 2);
+            assert_eq!(summary.total(), batch.len());
+            assert_eq!(summary.classes, bmap! { ErrorClass::Transient => 2 });
+            assert_eq!(summary.codes, bmap! { 140 => 2 });
+            assert!(!summary.is_valid());
+        }
+
+        let results = verify_stored_anchors_each([(&item, &anchor); 2], &store);
+        assert!(summary(&results).is_valid());
+        assert!(summary(&verify_stored_anchors_each(
+            std::iter::empty::<(&Timestamp, _)>(),
+            &store
+        ))
+        .is_valid());
+    }
+
+    /// Iterates over the `batch`, setting the `cancel` flag when item number
+    /// `at` is taken.
+    fn cancel_at<'batch, T: Copy>(
+        batch: &'batch [T],
+        at: usize,
+        cancel: &'batch AtomicBool,
+    ) -> impl Iterator<Item = T> + 'batch {
+        batch.iter().enumerate().map(move |(n, item)| {
+            if n == at {
+                cancel.store(true, Ordering::Relaxed);
+            }
+            *item
+        })
+    }
+
+    #[test]
+    fn cancelled_verification() {
+        let item = Timestamp {
+            document: b"document".to_vec(),
+        };
+        let other = Timestamp {
+            document: b"other document".to_vec(),
+        };
+        let (anchor, tx) = anchor_fixture(&item);
+        let (other_anchor, other_tx) = anchor_fixture(&other);
+        let mut store = WitnessStore::new();
+        store.insert(tx.clone());
+        store.insert(other_tx);
+
+        let batch = [
+            (&item, &anchor),
+            (&other, &other_anchor),
+            (&item, &anchor),
+            (&other, &other_anchor),
+        ];
+        let cancel = AtomicBool::new(false);
+        assert!(verify_stored_anchors_with(batch, &store, &cancel).unwrap());
+        assert!(matches!(
+            verify_stored_anchors_with(
+                cancel_at(&batch, 2, &cancel),
+                &store,
+                &cancel
+            ),
+            Err(ResolveVerifyError::Cancelled { completed: 2 })
+        ));
+
+        let batch = [(&item, &anchor); 3];
+        let cancel = AtomicBool::new(false);
+        assert!(verify_anchors_with(batch, &tx, &cancel).unwrap());
+        assert!(matches!(
+            verify_anchors_with(cancel_at(&batch, 1, &cancel), &tx, &cancel),
+            Err(ResolveVerifyError::Cancelled { completed: 1 })
+        ));
+
+        let batch = [(&item, &anchor), (&other, &other_anchor)];
+        let cancel = AtomicBool::new(false);
+        assert!(resolve_verify_anchors(batch, &store).unwrap());
+        assert!(matches!(
+            resolve_verify_anchors_with(
+                batch,
+                &CancellingResolver(store.clone(), &cancel),
+                &cancel
+            ),
+            Err(ResolveVerifyError::Cancelled { completed: 1 })
+        ));
+    }
+}
@@ -0,0 +1,296 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! De-duplicating store of witness transactions.
+//!
+//! Consignments carry the same witness transaction once per each anchor
+//! referencing it. [`WitnessStore`] interns transactions by their
+//! [`WitnessId`], such that
+//! each unique transaction is decoded and hashed only once, and all anchors
+//! referencing it share a single [`Arc<Transaction>`].
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use bitcoin::{consensus, Transaction, Txid};
+use bitcoin_onchain::{ResolveTx, TxResolverError};
+
+use crate::{PreparedTx, WitnessId};
+
+#[cfg(test)]
+thread_local! {
+    /// Number of transaction id computations made by the current thread.
+    static TXID_HASHES: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Errors adding transactions to the [`WitnessStore`].
+#[derive(Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum WitnessStoreError {
+    /// transaction provided as witness {expected} has a different id {actual}.
+    TxidMismatch {
+        /// Id under which the transaction was provided.
+        expected: WitnessId,
+        /// Actual id of the transaction.
+        actual: WitnessId,
+    },
+
+    /// witness transaction {0} is not correctly encoded – {1}
+    Decoding(WitnessId, consensus::encode::Error),
+}
+
+/// Store of witness transactions interned by their transaction id.
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use dbc::test_utils::opret_fixture;
+/// use dbc::witness::WitnessStore;
+///
+/// let (_, tx) = opret_fixture();
+/// let mut store = WitnessStore::new();
+/// let first = store.insert(tx.clone());
+/// let second = store.insert(tx.clone());
+/// assert!(Arc::ptr_eq(&first, &second));
+/// assert_eq!(store.len(), 1);
+/// assert_eq!(store.get(&tx.txid().into()), Some(first));
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct WitnessStore {
+    txes: BTreeMap<WitnessId, Arc<Transaction>>,
+}
+
+impl WitnessStore {
+    /// Constructs empty store.
+    #[inline]
+    pub fn new() -> WitnessStore { WitnessStore::default() }
+
+    /// Returns number of unique transactions in the store.
+    #[inline]
+    pub fn len(&self) -> usize { self.txes.len() }
+
+    /// Detects whether the store contains no transactions.
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.txes.is_empty() }
+
+    /// Detects whether the store contains transaction with the given id.
+    #[inline]
+    pub fn contains(&self, id: &WitnessId) -> bool {
+        self.txes.contains_key(id)
+    }
+
+    /// Returns transaction with the given id, if known.
+    #[inline]
+    pub fn get(&self, id: &WitnessId) -> Option<Arc<Transaction>> {
+        self.txes.get(id).cloned()
+    }
+
+    /// Iterates over transaction ids known to the store.
+    #[inline]
+    pub fn witness_ids(&self) -> impl Iterator<Item = WitnessId> + '_ {
+        self.txes.keys().copied()
+    }
+
+    /// Adds transaction to the store, returning reference to the interned
+    /// copy. If the transaction is already known, the provided copy is dropped.
+    pub fn insert(&mut self, tx: Transaction) -> Arc<Transaction> {
+        self.txes
+            .entry(witness_id(&tx))
+            .or_insert_with(|| Arc::new(tx))
+            .clone()
+    }
+
+    /// Adds transaction which is referenced under `id` (for instance by an
+    /// anchor) to the store. The transaction id is computed only if the
+    /// transaction is not known yet; otherwise the interned copy is returned.
+    ///
+    /// # Errors
+    ///
+    /// If the transaction has an id different from `id`.
+    ///
+    /// ```
+    /// use bitcoin::hashes::Hash;
+    /// use bitcoin::Txid;
+    /// use dbc::test_utils::opret_fixture;
+    /// use dbc::witness::{WitnessStore, WitnessStoreError};
+    /// use dbc::WitnessId;
+    ///
+    /// let (anchor, tx) = opret_fixture();
+    /// let mut store = WitnessStore::new();
+    /// assert!(matches!(
+    ///     store.insert_checked(WitnessId::from(Txid::all_zeros()), tx.clone()),
+    ///     Err(WitnessStoreError::TxidMismatch { .. })
+    /// ));
+    /// assert!(store.is_empty());
+    /// assert!(store.insert_checked(anchor.witness_id(), tx).is_ok());
+    /// ```
+    pub fn insert_checked(
+        &mut self,
+        id: WitnessId,
+        tx: Transaction,
+    ) -> Result<Arc<Transaction>, WitnessStoreError> {
+        if let Some(interned) = self.txes.get(&id) {
+            return Ok(interned.clone());
+        }
+        let actual = witness_id(&tx);
+        if actual != id {
+            return Err(WitnessStoreError::TxidMismatch {
+                expected: id,
+                actual,
+            });
+        }
+        let tx = Arc::new(tx);
+        self.txes.insert(id, tx.clone());
+        Ok(tx)
+    }
+
+    /// Adds consensus-encoded transaction referenced under `id` to the
+    /// store. The data are decoded and hashed only if the transaction is not
+    /// known yet.
+    ///
+    /// # Errors
+    ///
+    /// If the data can't be decoded, or decode into a transaction with an id
+    /// different from `id`.
+    pub fn insert_encoded(
+        &mut self,
+        id: WitnessId,
+        data: impl AsRef<[u8]>,
+    ) -> Result<Arc<Transaction>, WitnessStoreError> {
+        if let Some(interned) = self.txes.get(&id) {
+            return Ok(interned.clone());
+        }
+        let tx = consensus::deserialize(data.as_ref())
+            .map_err(|err| WitnessStoreError::Decoding(id, err))?;
+        self.insert_checked(id, tx)
+    }
+
+    /// Prepares transaction with the given id for the verification of
+    /// deterministic bitcoin commitments, without re-computing its id.
+    pub fn prepare(&self, id: &WitnessId) -> Option<PreparedTx> {
+        self.txes
+            .get(id)
+            .map(|tx| PreparedTx::with_txid(Transaction::clone(tx), id.txid()))
+    }
+}
+
+impl ResolveTx for WitnessStore {
+    fn resolve_tx(&self, txid: Txid) -> Result<Transaction, TxResolverError> {
+        self.txes
+            .get(&WitnessId::from(txid))
+            .map(|tx| Transaction::clone(tx))
+            .ok_or_else(|| TxResolverError::with(txid))
+    }
+}
+
+fn witness_id(tx: &Transaction) -> WitnessId {
+    #[cfg(test)]
+    TXID_HASHES.with(|hashes| hashes.set(hashes.get() + 1));
+    WitnessId::from(tx.txid())
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use bitcoin::{PackedLockTime, TxIn, TxOut};
+
+    use super::*;
+    use crate::consts::COMMITMENT_LEN;
+
+    fn tx_fixture(value: u64) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![TxOut {
+                value,
+                script_pubkey: bitcoin::Script::new_op_return(
+                    &[0u8; COMMITMENT_LEN],
+                ),
+            }],
+        }
+    }
+
+    fn txid_hashes() -> usize { TXID_HASHES.with(|hashes| hashes.get()) }
+
+    #[test]
+    fn interning() {
+        let tx = tx_fixture(0);
+        let txid = tx.txid();
+        let id = WitnessId::from(txid);
+        let mut store = WitnessStore::new();
+
+        let first = store.insert(tx.clone());
+        let second = store.insert_checked(id, tx.clone()).unwrap();
+        let third =
+            store.insert_encoded(id, consensus::serialize(&tx)).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(Arc::ptr_eq(&first, &third));
+        assert!(Arc::ptr_eq(&first, &store.get(&id).unwrap()));
+        assert_eq!(*first, tx);
+
+        let other = store.insert(tx_fixture(1));
+        assert!(!Arc::ptr_eq(&first, &other));
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.witness_ids().count(), 2);
+        assert!(store.contains(&id));
+        assert_eq!(store.resolve_tx(txid).unwrap(), tx);
+        assert!(store.resolve_tx(Txid::all_zeros()).is_err());
+        assert_eq!(store.get(&Txid::all_zeros().into()), None);
+    }
+
+    #[test]
+    fn single_hashing() {
+        let tx = tx_fixture(0);
+        let txid = tx.txid();
+        let id = WitnessId::from(txid);
+        let data = consensus::serialize(&tx);
+        let mut store = WitnessStore::new();
+
+        let hashes = txid_hashes();
+        for _ in 0..10 {
+            store.insert_encoded(id, &data).unwrap();
+            store.insert_checked(id, tx.clone()).unwrap();
+        }
+        assert_eq!(txid_hashes(), hashes + 1);
+
+        let prepared = store.prepare(&id).unwrap();
+        assert_eq!(prepared, PreparedTx::new(tx));
+        assert_eq!(txid_hashes(), hashes + 1);
+    }
+
+    #[test]
+    fn txid_mismatch() {
+        let tx = tx_fixture(0);
+        let other = tx_fixture(1);
+        let mut store = WitnessStore::new();
+
+        assert!(matches!(
+            store.insert_checked(other.txid().into(), tx.clone()),
+            Err(WitnessStoreError::TxidMismatch { expected, actual })
+                if expected.txid() == other.txid() && actual.txid() == tx.txid()
+        ));
+        assert!(matches!(
+            store
+                .insert_encoded(other.txid().into(), consensus::serialize(&tx)),
+            Err(WitnessStoreError::TxidMismatch { .. })
+        ));
+        assert!(matches!(
+            store.insert_encoded(tx.txid().into(), [0u8; 3]),
+            Err(WitnessStoreError::Decoding(id, _)) if id.txid() == tx.txid()
+        ));
+        assert!(store.is_empty());
+    }
+}
@@ -0,0 +1,655 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Typestate wrapper guarding the PSBT commitment workflow.
+//!
+//! Committing to a PSBT consists of steps which must be done in order: the
+//! commitment host output is selected and LNPBP-4 messages are added, the
+//! commitment is embedded, the witness transaction is extracted after the PSBT
+//! is signed and the proprietary keys are stripped. [`DbcPsbt`] tracks the
+//! state of the workflow in its type, exposing only the operations valid in
+//! the current state, such that committing twice or extracting transaction
+//! before committing does not compile.
+//!
+//! ```
+//! use dbc::test_utils::{internal_key_fixture, protocol_fixture, tx_fixture};
+//! use dbc::workflow::DbcPsbt;
+//! use dbc::CloseMethod;
+//! use psbt::Psbt;
+//!
+//! // Transaction with a single taproot output
+//! let key = internal_key_fixture();
+//! let tx = tx_fixture(vec![bitcoin::TxOut {
+//!     value: 1000,
+//!     script_pubkey: bitcoin::Script::new_v1_p2tr(
+//!         secp256k1::SECP256K1,
+//!         key,
+//!         None,
+//!     ),
+//! }]);
+//! let mut psbt = Psbt::with(tx, psbt::PsbtVersion::V0).unwrap();
+//! psbt.outputs[0].tap_internal_key = Some(key);
+//!
+//! let mut psbt = DbcPsbt::new(psbt).unwrap();
+//! psbt.set_host(0, CloseMethod::TapretFirst).unwrap();
+//! let (protocol_id, message) = protocol_fixture(0);
+//! psbt.add_message(protocol_id, message).unwrap();
+//! # #[cfg(not(feature = "deterministic"))]
+//! let mut psbt = psbt.commit().unwrap();
+//! # #[cfg(feature = "deterministic")]
+//! # let mut psbt = psbt.commit_with_entropy(0x5eed).unwrap();
+//!
+//! // Signing happens here; inputs are finalized with the signatures
+//! psbt.psbt_mut().inputs[0].final_script_witness =
+//!     Some(bitcoin::Witness::new());
+//! let tx = psbt.extract_tx().unwrap();
+//! let proof = psbt.anchor().to_merkle_proof(protocol_id).unwrap();
+//! assert!(proof.verify(protocol_id, message, &tx).unwrap());
+//!
+//! psbt.strip_keys();
+//! let (psbt, anchor) = psbt.into_parts();
+//! assert!(psbt.outputs[0].proprietary.is_empty());
+//! assert_eq!(anchor.txid(), psbt.to_txid());
+//! ```
+//!
+//! The wrapper does not prevent use of the raw APIs ([`Anchor::commit`],
+//! [`extract_witness_tx`] etc) for the workflows it does not cover.
+//!
+//! Aggregators committing messages of multiple protocols into a single PSBT
+//! and sending each protocol its own anchor use [`commit_and_export`], which
+//! performs the whole workflow up to the signing in one call.
+
+use std::collections::BTreeMap;
+
+use bitcoin::Transaction;
+use bitcoin_scripts::taproot::DfsPath;
+use commit_verify::lnpbp4::{self, Message, ProtocolId};
+use psbt::commit::{Lnpbp4KeyError, OpretKeyError, TapretKeyError};
+use psbt::Psbt;
+
+use crate::commit::PsbtAnchorExt;
+use crate::extract::{extract_witness_tx, ExtractTxError};
+use crate::method::CloseMethod;
+use crate::proprietary::{self, KeyError};
+use crate::{anchor, Anchor, AnchorId};
+
+/// Errors preparing PSBT for the commitment.
+#[derive(Clone, PartialEq, Eq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum DbcPsbtError {
+    /// PSBT has no output #{0}.
+    NoOutput(usize),
+
+    /// commitment host is already set to output #{0}.
+    HostAlreadySet(usize),
+
+    /// commitment host output is not set.
+    NoHost,
+
+    /// tapret host output #{0} has no taproot internal key.
+    InternalKeyMissed(usize),
+
+    /// invalid values of PSBT proprietary keys – {0}
+    #[from]
+    ProprietaryKey(KeyError),
+
+    /// invalid LNPBP-4 data – {0}
+    #[from]
+    Lnpbp4(Lnpbp4KeyError),
+
+    /// invalid opret host – {0}
+    #[from]
+    Opret(OpretKeyError),
+
+    /// invalid tapret host – {0}
+    #[from]
+    Tapret(TapretKeyError),
+}
+
+/// Errors of [`commit_and_export`].
+#[derive(Clone, PartialEq, Eq, Debug, Display, Error, From)]
+#[display(inner)]
+pub enum CommitExportError {
+    /// Errors adding the messages to the PSBT.
+    #[from]
+    Prepare(DbcPsbtError),
+
+    /// Errors embedding the commitment into the PSBT.
+    #[from]
+    Commit(anchor::Error),
+}
+
+/// Result of [`commit_and_export`]: anchor revealing all the committed
+/// messages and anchors revealing only one protocol each, by their protocol
+/// ids.
+pub type CommitExport = (
+    Anchor<lnpbp4::MerkleBlock>,
+    BTreeMap<ProtocolId, Anchor<lnpbp4::MerkleProof>>,
+);
+
+/// State of the PSBT which is not committed yet.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Uncommitted {
+    host: Option<usize>,
+}
+
+/// State of the committed PSBT.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Committed {
+    anchor: Anchor<lnpbp4::MerkleBlock>,
+}
+
+/// PSBT with the state of the commitment workflow tracked in its type.
+///
+/// Committed PSBT can't be committed again:
+///
+/// ```compile_fail
+/// use dbc::workflow::{Committed, DbcPsbt};
+///
+/// fn commit_twice(psbt: DbcPsbt<Committed>) { psbt.commit(); }
+/// ```
+///
+/// and transaction can't be extracted from the PSBT before the commitment:
+///
+/// ```compile_fail
+/// use dbc::workflow::{DbcPsbt, Uncommitted};
+///
+/// fn extract(psbt: DbcPsbt<Uncommitted>) { psbt.extract_tx(); }
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DbcPsbt<State> {
+    psbt: Psbt,
+    state: State,
+}
+
+impl<State> DbcPsbt<State> {
+    /// Returns reference to the wrapped PSBT.
+    #[inline]
+    pub fn psbt(&self) -> &Psbt { &self.psbt }
+}
+
+impl DbcPsbt<Uncommitted> {
+    /// Wraps PSBT which is not committed yet, checking values of its
+    /// proprietary keys with [`proprietary::check_psbt`]. If some output of
+    /// the PSBT is already marked as a commitment host, it is used as the
+    /// host.
+    pub fn new(psbt: Psbt) -> Result<Self, DbcPsbtError> {
+        proprietary::check_psbt(&psbt)?;
+        let host = psbt
+            .outputs
+            .iter()
+            .position(|output| output.is_tapret_host())
+            .or_else(|| {
+                psbt.outputs
+                    .iter()
+                    .position(|output| output.is_opret_host())
+            });
+        Ok(DbcPsbt {
+            psbt,
+            state: Uncommitted { host },
+        })
+    }
+
+    /// Returns number of the commitment host output, if it is set.
+    #[inline]
+    pub fn host(&self) -> Option<usize> { self.state.host }
+
+    /// Marks output `vout` as the host of the commitment with the given
+    /// `method`. Tapret hosts must have taproot internal key set; opret hosts
+    /// must be `OP_RETURN` outputs.
+    ///
+    /// The host can be set only once.
+    pub fn set_host(
+        &mut self,
+        vout: usize,
+        method: CloseMethod,
+    ) -> Result<(), DbcPsbtError> {
+        if let Some(host) = self.state.host {
+            return Err(DbcPsbtError::HostAlreadySet(host));
+        }
+        let output = self
+            .psbt
+            .outputs
+            .get_mut(vout)
+            .ok_or(DbcPsbtError::NoOutput(vout))?;
+        match method {
+            CloseMethod::TapretFirst => {
+                if output.tap_internal_key.is_none() {
+                    return Err(DbcPsbtError::InternalKeyMissed(vout));
+                }
+                output.set_tapret_dfs_path(&DfsPath::new())?;
+            }
+            CloseMethod::OpretFirst => {
+                output.set_opret_host()?;
+            }
+        }
+        self.state.host = Some(vout);
+        Ok(())
+    }
+
+    /// Adds LNPBP-4 `message` under `protocol_id` to the commitment host
+    /// output. Returns `false` if the same message was already added.
+    ///
+    /// Fails if the host is not set or if a different message was added for
+    /// the same protocol.
+    pub fn add_message(
+        &mut self,
+        protocol_id: ProtocolId,
+        message: Message,
+    ) -> Result<bool, DbcPsbtError> {
+        let host = self.state.host.ok_or(DbcPsbtError::NoHost)?;
+        self.psbt
+            .outputs
+            .get_mut(host)
+            .ok_or(DbcPsbtError::NoOutput(host))?
+            .set_lnpbp4_message(protocol_id, message)
+            .map_err(DbcPsbtError::from)
+    }
+
+    /// Computes id of the anchor which would be produced by the commitment
+    /// with static entropy, without consuming the PSBT. Can be used to check
+    /// that [`DbcPsbt::commit`] will succeed.
+    #[inline]
+    pub fn expected_anchor_id(&self) -> Result<AnchorId, anchor::Error> {
+        self.psbt.expected_anchor_id()
+    }
+
+    /// Embeds the commitment into the PSBT with [`Anchor::commit`].
+    ///
+    /// The PSBT is consumed; use [`DbcPsbt::expected_anchor_id`] or clone the
+    /// PSBT beforehand if it is needed after a failure.
+    ///
+    /// Not available with `deterministic` feature; use
+    /// [`DbcPsbt::commit_with_entropy`] instead.
+    #[cfg(not(feature = "deterministic"))]
+    pub fn commit(mut self) -> Result<DbcPsbt<Committed>, anchor::Error> {
+        let anchor = Anchor::commit(&mut self.psbt)?;
+        Ok(DbcPsbt {
+            psbt: self.psbt,
+            state: Committed { anchor },
+        })
+    }
+
+    /// Embeds the commitment into the PSBT with
+    /// [`Anchor::commit_with_entropy`], using the LNPBP-4 `entropy` provided
+    /// by the caller.
+    pub fn commit_with_entropy(
+        mut self,
+        entropy: u64,
+    ) -> Result<DbcPsbt<Committed>, anchor::Error> {
+        let anchor = Anchor::commit_with_entropy(&mut self.psbt, entropy)?;
+        Ok(DbcPsbt {
+            psbt: self.psbt,
+            state: Committed { anchor },
+        })
+    }
+
+    /// Unwraps the PSBT.
+    #[inline]
+    pub fn into_psbt(self) -> Psbt { self.psbt }
+}
+
+impl DbcPsbt<Committed> {
+    /// Returns anchor produced by the commitment.
+    #[inline]
+    pub fn anchor(&self) -> &Anchor<lnpbp4::MerkleBlock> { &self.state.anchor }
+
+    /// Returns mutable reference to the wrapped PSBT, which is required for
+    /// signing it.
+    ///
+    /// Signers must not modify the transaction outputs; otherwise
+    /// [`DbcPsbt::extract_tx`] fails since the transaction no longer matches
+    /// the anchor.
+    #[inline]
+    pub fn psbt_mut(&mut self) -> &mut Psbt { &mut self.psbt }
+
+    /// Extracts finalized witness transaction from the PSBT, checking that it
+    /// matches the anchor with [`extract_witness_tx`].
+    #[inline]
+    pub fn extract_tx(&self) -> Result<Transaction, ExtractTxError> {
+        extract_witness_tx(&self.psbt, &self.state.anchor)
+    }
+
+    /// Removes proprietary keys used by the commitment from the PSBT with
+    /// [`proprietary::strip_psbt`], returning the number of the removed keys.
+    #[inline]
+    pub fn strip_keys(&mut self) -> usize {
+        proprietary::strip_psbt(&mut self.psbt)
+    }
+
+    /// Unwraps the PSBT and the anchor.
+    #[inline]
+    pub fn into_parts(self) -> (Psbt, Anchor<lnpbp4::MerkleBlock>) {
+        (self.psbt, self.state.anchor)
+    }
+}
+
+/// Adds `messages` to the commitment host of the PSBT, embeds the commitment
+/// with [`DbcPsbt::commit`] and exports anchor for each of the protocols with
+/// [`Anchor::sanitize_for`], so it reveals only this protocol and may be sent
+/// to its counterparty.
+///
+/// Returns the anchor in the merkle block form, revealing all the messages,
+/// for the own records of the caller, and the exported anchors by their
+/// protocol ids. The PSBT must have the commitment host output set; it is
+/// modified only if the commitment succeeds.
+///
+/// ```
+/// use std::collections::BTreeMap;
+///
+/// use dbc::test_utils::{protocol_fixture, psbt_fixture};
+/// use dbc::workflow::commit_and_export;
+/// use dbc::CloseMethod;
+///
+/// let mut psbt = psbt_fixture(CloseMethod::OpretFirst);
+/// let messages = (3..6).map(protocol_fixture).collect::<BTreeMap<_, _>>();
+/// let (anchor, exported) =
+///     commit_and_export(&mut psbt, messages.clone()).unwrap();
+/// assert_eq!(anchor.txid(), psbt.to_txid());
+/// assert_eq!(exported.len(), 3);
+/// for (protocol_id, proof) in exported {
+///     assert!(proof.reveals_only(protocol_id));
+///     let message = messages[&protocol_id];
+///     assert_eq!(
+///         proof.anchor_id(protocol_id, message),
+///         Ok(anchor.anchor_id())
+///     );
+/// }
+/// ```
+///
+/// Not available with `deterministic` feature; use
+/// [`commit_and_export_with_entropy`] instead.
+///
+/// # Errors
+///
+/// [`CommitExportError::Prepare`] if the PSBT has no commitment host or
+/// already has a different message for one of the protocols, and
+/// [`CommitExportError::Commit`] if the commitment can't be embedded.
+#[cfg(not(feature = "deterministic"))]
+pub fn commit_and_export(
+    psbt: &mut Psbt,
+    messages: BTreeMap<ProtocolId, Message>,
+) -> Result<CommitExport, CommitExportError> {
+    export(psbt, messages, DbcPsbt::commit)
+}
+
+/// Version of [`commit_and_export`] embedding the commitment with
+/// [`DbcPsbt::commit_with_entropy`], using the LNPBP-4 `entropy` provided by
+/// the caller.
+///
+/// ```
+/// use std::collections::BTreeMap;
+///
+/// use dbc::test_utils::{protocol_fixture, psbt_fixture};
+/// use dbc::workflow::commit_and_export_with_entropy;
+/// use dbc::CloseMethod;
+///
+/// let mut psbt = psbt_fixture(CloseMethod::TapretFirst);
+/// let messages = (3..5).map(protocol_fixture).collect::<BTreeMap<_, _>>();
+/// let (anchor, exported) =
+///     commit_and_export_with_entropy(&mut psbt, messages, 0x5eed).unwrap();
+/// assert_eq!(anchor.lnpbp4_proof().entropy(), Some(0x5eed));
+/// assert!(exported
+///     .values()
+///     .all(|proof| proof.txid() == psbt.to_txid()));
+/// ```
+pub fn commit_and_export_with_entropy(
+    psbt: &mut Psbt,
+    messages: BTreeMap<ProtocolId, Message>,
+    entropy: u64,
+) -> Result<CommitExport, CommitExportError> {
+    export(psbt, messages, |psbt| psbt.commit_with_entropy(entropy))
+}
+
+fn export(
+    psbt: &mut Psbt,
+    messages: BTreeMap<ProtocolId, Message>,
+    commit: impl FnOnce(
+        DbcPsbt<Uncommitted>,
+    ) -> Result<DbcPsbt<Committed>, anchor::Error>,
+) -> Result<CommitExport, CommitExportError> {
+    let mut uncommitted = DbcPsbt::new(psbt.clone())?;
+    for (protocol_id, message) in &messages {
+        uncommitted.add_message(*protocol_id, *message)?;
+    }
+    let (committed, anchor) = commit(uncommitted)?.into_parts();
+    let exported = messages
+        .into_keys()
+        .map(|protocol_id| {
+            let proof = anchor
+                .sanitize_for(protocol_id)
+                .expect("committed protocols are revealed by the anchor");
+            (protocol_id, proof)
+        })
+        .collect();
+    *psbt = committed;
+    Ok((anchor, exported))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::{
+        protocol_fixture, unmarked_psbt_fixture, FIXTURE_PROTOCOLS,
+    };
+
+    #[cfg(not(feature = "deterministic"))]
+    fn commit(
+        psbt: DbcPsbt<Uncommitted>,
+    ) -> Result<DbcPsbt<Committed>, anchor::Error> {
+        psbt.commit()
+    }
+
+    /// OS entropy is not available in deterministic builds.
+    #[cfg(feature = "deterministic")]
+    fn commit(
+        psbt: DbcPsbt<Uncommitted>,
+    ) -> Result<DbcPsbt<Committed>, anchor::Error> {
+        psbt.commit_with_entropy(0x5eed)
+    }
+
+    fn finalize(psbt: &mut DbcPsbt<Committed>) {
+        for input in &mut psbt.psbt_mut().inputs {
+            input.final_script_witness = Some(bitcoin::Witness::new());
+        }
+    }
+
+    #[test]
+    fn workflow() {
+        for (vout, method) in
+            [(0, CloseMethod::TapretFirst), (1, CloseMethod::OpretFirst)]
+        {
+            let mut psbt = DbcPsbt::new(unmarked_psbt_fixture()).unwrap();
+            assert_eq!(psbt.host(), None);
+            psbt.set_host(vout, method).unwrap();
+            assert_eq!(psbt.host(), Some(vout));
+            for (protocol_id, message) in
+                (0..FIXTURE_PROTOCOLS).map(protocol_fixture)
+            {
+                assert!(psbt.add_message(protocol_id, message).unwrap());
+                assert!(!psbt.add_message(protocol_id, message).unwrap());
+            }
+            let expected_id = psbt.expected_anchor_id().unwrap();
+
+            let mut psbt = commit(psbt).unwrap();
+            assert_eq!(psbt.anchor().txid(), psbt.psbt().to_txid());
+            assert_ne!(psbt.anchor().anchor_id(), expected_id);
+
+            assert_eq!(psbt.extract_tx(), Err(ExtractTxError::NotFinalized(0)));
+            finalize(&mut psbt);
+            let tx = psbt.extract_tx().unwrap();
+            for (protocol_id, message) in
+                (0..FIXTURE_PROTOCOLS).map(protocol_fixture)
+            {
+                let proof = psbt.anchor().to_merkle_proof(protocol_id).unwrap();
+                assert!(proof.verify(protocol_id, message, &tx).unwrap());
+            }
+
+            assert!(psbt.strip_keys() > 0);
+            assert_eq!(psbt.strip_keys(), 0);
+            assert_eq!(psbt.extract_tx().unwrap(), tx);
+            let (psbt, _) = psbt.into_parts();
+            assert!(psbt.outputs.iter().all(|o| o.proprietary.is_empty()));
+        }
+    }
+
+    #[test]
+    fn host_errors() {
+        let mut psbt = DbcPsbt::new(unmarked_psbt_fixture()).unwrap();
+        let (protocol_id, message) = protocol_fixture(0);
+        assert_eq!(
+            psbt.add_message(protocol_id, message),
+            Err(DbcPsbtError::NoHost)
+        );
+        assert_eq!(
+            psbt.set_host(2, CloseMethod::OpretFirst),
+            Err(DbcPsbtError::NoOutput(2))
+        );
+        assert_eq!(
+            psbt.set_host(0, CloseMethod::OpretFirst),
+            Err(DbcPsbtError::Opret(OpretKeyError::NonOpReturnOutput))
+        );
+        assert_eq!(
+            psbt.set_host(1, CloseMethod::TapretFirst),
+            Err(DbcPsbtError::InternalKeyMissed(1))
+        );
+        assert_eq!(psbt.host(), None);
+
+        psbt.set_host(1, CloseMethod::OpretFirst).unwrap();
+        assert_eq!(
+            psbt.set_host(0, CloseMethod::TapretFirst),
+            Err(DbcPsbtError::HostAlreadySet(1))
+        );
+
+        // Host is detected from the PSBT keys
+        let psbt = DbcPsbt::new(psbt.into_psbt()).unwrap();
+        assert_eq!(psbt.host(), Some(1));
+
+        // Commitment without messages fails
+        assert!(psbt.expected_anchor_id().is_err());
+        assert!(psbt.clone().commit_with_entropy(0x5eed).is_err());
+        assert!(commit(psbt).is_err());
+    }
+
+    #[cfg(not(feature = "deterministic"))]
+    fn export(
+        psbt: &mut Psbt,
+        messages: BTreeMap<ProtocolId, Message>,
+    ) -> Result<CommitExport, CommitExportError> {
+        commit_and_export(psbt, messages)
+    }
+
+    /// OS entropy is not available in deterministic builds.
+    #[cfg(feature = "deterministic")]
+    fn export(
+        psbt: &mut Psbt,
+        messages: BTreeMap<ProtocolId, Message>,
+    ) -> Result<CommitExport, CommitExportError> {
+        commit_and_export_with_entropy(psbt, messages, 0x5eed)
+    }
+
+    #[test]
+    fn commit_export() {
+        let messages = (0..3).map(protocol_fixture).collect::<BTreeMap<_, _>>();
+        for (vout, method) in
+            [(0, CloseMethod::TapretFirst), (1, CloseMethod::OpretFirst)]
+        {
+            let mut host = DbcPsbt::new(unmarked_psbt_fixture()).unwrap();
+            host.set_host(vout, method).unwrap();
+            let mut psbt = host.into_psbt();
+            let (anchor, exported) =
+                export(&mut psbt, messages.clone()).unwrap();
+            assert_eq!(anchor.known_protocol_count(), 3);
+            assert_eq!(
+                exported.keys().collect::<Vec<_>>(),
+                messages.keys().collect::<Vec<_>>()
+            );
+
+            // Signing happens here
+            for input in &mut psbt.inputs {
+                input.final_script_witness = Some(bitcoin::Witness::new());
+            }
+            let tx = extract_witness_tx(&psbt, &anchor).unwrap();
+            for (protocol_id, proof) in &exported {
+                assert!(proof.reveals_only(*protocol_id));
+                assert_eq!(
+                    proof.anchor_id(*protocol_id, messages[protocol_id]),
+                    Ok(anchor.anchor_id())
+                );
+                for (other_id, message) in &messages {
+                    assert_eq!(
+                        proof.verify(*other_id, *message, &tx).unwrap_or(false),
+                        other_id == protocol_id
+                    );
+                    assert_eq!(
+                        proof.reveals_only(*other_id),
+                        other_id == protocol_id
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn commit_export_errors() {
+        let messages = (0..3).map(protocol_fixture).collect::<BTreeMap<_, _>>();
+
+        // No commitment host
+        let mut psbt = unmarked_psbt_fixture();
+        assert_eq!(
+            export(&mut psbt, messages.clone()),
+            Err(CommitExportError::Prepare(DbcPsbtError::NoHost))
+        );
+        assert_eq!(psbt, unmarked_psbt_fixture());
+
+        // Conflicting message leaves the PSBT unchanged
+        let mut host = DbcPsbt::new(unmarked_psbt_fixture()).unwrap();
+        host.set_host(1, CloseMethod::OpretFirst).unwrap();
+        let (protocol_id, _) = protocol_fixture(0);
+        let (_, other_message) = protocol_fixture(1);
+        host.add_message(protocol_id, other_message).unwrap();
+        let mut psbt = host.into_psbt();
+        let original = psbt.clone();
+        assert!(matches!(
+            export(&mut psbt, messages),
+            Err(CommitExportError::Prepare(DbcPsbtError::Lnpbp4(_)))
+        ));
+        assert_eq!(psbt, original);
+
+        // Commitment without messages fails
+        let mut host = DbcPsbt::new(unmarked_psbt_fixture()).unwrap();
+        host.set_host(1, CloseMethod::OpretFirst).unwrap();
+        let mut psbt = host.into_psbt();
+        let original = psbt.clone();
+        assert!(matches!(
+            export(&mut psbt, BTreeMap::new()),
+            Err(CommitExportError::Commit(_))
+        ));
+        assert_eq!(psbt, original);
+    }
+
+    #[test]
+    fn conflicting_messages() {
+        let mut psbt = DbcPsbt::new(unmarked_psbt_fixture()).unwrap();
+        psbt.set_host(0, CloseMethod::TapretFirst).unwrap();
+        let (protocol_id, message) = protocol_fixture(0);
+        let (_, other_message) = protocol_fixture(1);
+        psbt.add_message(protocol_id, message).unwrap();
+        assert!(matches!(
+            psbt.add_message(protocol_id, other_message),
+            Err(DbcPsbtError::Lnpbp4(_))
+        ));
+    }
+}
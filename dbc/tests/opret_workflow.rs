@@ -0,0 +1,299 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Opret commitment through the complete PSBT workflow, from an unsigned
+//! transaction to the signed witness transaction.
+//!
+//! The test spends the UTXO of the synthetic funding transaction recorded in
+//! `tests/data/opret_workflow/funding.fixture` with the fixed test key,
+//! committing to the fixture protocols with an opret commitment through the
+//! [`DbcPsbt`] workflow. The finalized witness transaction must match the one
+//! recorded in `tests/data/opret_workflow/opret.fixture`, and the anchor must
+//! verify against the decoded recorded transaction. The funding transaction is
+//! not mined on any network, so the witness transaction is checked for a valid
+//! spending but is not broadcastable.
+//!
+//! Fixtures are regenerated by running the test with `BP_UPDATE_FIXTURES`
+//! environment variable set. A different funding transaction paying to the
+//! P2WPKH address of the test key is recorded by additionally setting
+//! `BP_FUNDING` to `<raw funding tx hex>:<vout>`.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::{env, fs};
+
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::util::sighash::SighashCache;
+use bitcoin::{
+    EcdsaSig, EcdsaSighashType, OutPoint, PackedLockTime, PrivateKey, Script,
+    Sequence, Transaction, TxIn, TxOut, Witness,
+};
+use commit_verify::lnpbp4;
+use dbc::anchor::VerificationOutcome;
+use dbc::test_utils::{protocol_fixture, FIXTURE_PROTOCOLS};
+use dbc::workflow::DbcPsbt;
+use dbc::{Anchor, CloseMethod};
+use psbt::Psbt;
+use secp256k1::{SecretKey, SECP256K1};
+
+/// Fixture file: lines of `<name> = <value>` pairs, grouped into sections
+/// named in square brackets. Lines starting with `#` are comments.
+struct Fixture(BTreeMap<String, String>);
+
+impl Fixture {
+    fn path(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/data/opret_workflow")
+            .join(format!("{}.fixture", name))
+    }
+
+    fn load(name: &str) -> Fixture {
+        let data = fs::read_to_string(Fixture::path(name)).unwrap();
+        Fixture(
+            data.lines()
+                .filter(|line| {
+                    !line.is_empty()
+                        && !line.starts_with('#')
+                        && !line.starts_with('[')
+                })
+                .map(|line| {
+                    let (name, value) = line.split_once(" = ").unwrap();
+                    (name.to_owned(), value.to_owned())
+                })
+                .collect(),
+        )
+    }
+
+    fn get(&self, name: &str) -> &str { &self.0[name] }
+}
+
+/// Funding UTXO spent by the witness transaction.
+struct Funding {
+    outpoint: OutPoint,
+    txout: TxOut,
+}
+
+impl Funding {
+    fn with(tx: &Transaction, vout: u32) -> Funding {
+        Funding {
+            outpoint: OutPoint::new(tx.txid(), vout),
+            txout: tx.output[vout as usize].clone(),
+        }
+    }
+
+    fn load() -> Funding {
+        let fixture = Fixture::load("funding");
+        let tx: Transaction =
+            deserialize(&Vec::<u8>::from_hex(fixture.get("tx")).unwrap())
+                .unwrap();
+        Funding::with(&tx, fixture.get("vout").parse().unwrap())
+    }
+}
+
+/// Inputs of the witness transaction construction.
+struct Params {
+    key: PrivateKey,
+    fee: u64,
+    entropy: u64,
+}
+
+impl Params {
+    fn load(fixture: &Fixture) -> Params {
+        Params {
+            key: PrivateKey::new(
+                SecretKey::from_str(fixture.get("input.secret_key")).unwrap(),
+                bitcoin::Network::Signet,
+            ),
+            fee: fixture.get("input.fee").parse().unwrap(),
+            entropy: fixture.get("input.entropy").parse().unwrap(),
+        }
+    }
+
+    /// Output script of the test key, used both for the funding UTXO and for
+    /// the change output.
+    fn script_pubkey(&self) -> Script {
+        let pubkey = self.key.public_key(SECP256K1);
+        Script::new_v0_p2wpkh(&pubkey.wpubkey_hash().expect("compressed key"))
+    }
+}
+
+/// Builds the opret commitment PSBT spending `funding`, commits to the
+/// fixture protocols and finalizes the PSBT with the test key.
+fn commit_and_sign(
+    funding: &Funding,
+    params: &Params,
+) -> (Transaction, Anchor<lnpbp4::MerkleBlock>) {
+    let tx = Transaction {
+        version: 2,
+        lock_time: PackedLockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: funding.outpoint,
+            script_sig: Script::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        }],
+        output: vec![
+            TxOut {
+                value: funding.txout.value - params.fee,
+                script_pubkey: params.script_pubkey(),
+            },
+            TxOut {
+                value: 0,
+                script_pubkey: Script::new_op_return(&[]),
+            },
+        ],
+    };
+    let mut psbt = Psbt::with(tx, psbt::PsbtVersion::V0).unwrap();
+    psbt.inputs[0].witness_utxo = Some(funding.txout.clone());
+
+    let mut psbt = DbcPsbt::new(psbt).unwrap();
+    psbt.set_host(1, CloseMethod::OpretFirst).unwrap();
+    for (protocol_id, message) in (0..FIXTURE_PROTOCOLS).map(protocol_fixture) {
+        psbt.add_message(protocol_id, message).unwrap();
+    }
+    let mut psbt = psbt.commit_with_entropy(params.entropy).unwrap();
+
+    // P2WPKH signing with deterministic (RFC-6979) ECDSA nonces
+    let unsigned_tx = psbt.psbt().to_unsigned_tx();
+    let script_code = funding.txout.script_pubkey.p2wpkh_script_code().unwrap();
+    let sighash = SighashCache::new(&unsigned_tx)
+        .segwit_signature_hash(
+            0,
+            &script_code,
+            funding.txout.value,
+            EcdsaSighashType::All,
+        )
+        .unwrap();
+    let sig = EcdsaSig::sighash_all(SECP256K1.sign_ecdsa(
+        &secp256k1::Message::from_slice(&sighash[..]).unwrap(),
+        &params.key.inner,
+    ));
+    let pubkey = params.key.public_key(SECP256K1);
+    psbt.psbt_mut().inputs[0].final_script_witness =
+        Some(Witness::from_vec(vec![sig.to_vec(), pubkey.to_bytes()]));
+
+    let tx = psbt.extract_tx().unwrap();
+    psbt.strip_keys();
+    let (_, anchor) = psbt.into_parts();
+    (tx, anchor)
+}
+
+/// Checks that the recorded witness transaction `tx` is a valid spending of
+/// the funding UTXO by the test key.
+fn check_spending(tx: &Transaction, funding: &Funding, params: &Params) {
+    assert_eq!(tx.input.len(), 1);
+    assert_eq!(tx.input[0].previous_output, funding.outpoint);
+    let spent = tx.output.iter().map(|txout| txout.value).sum::<u64>();
+    assert_eq!(spent + params.fee, funding.txout.value);
+    assert_eq!(funding.txout.script_pubkey, params.script_pubkey());
+
+    let witness = tx.input[0].witness.to_vec();
+    assert_eq!(witness.len(), 2);
+    let pubkey = bitcoin::PublicKey::from_slice(&witness[1]).unwrap();
+    assert_eq!(pubkey, params.key.public_key(SECP256K1));
+    let sig = EcdsaSig::from_slice(&witness[0]).unwrap();
+    assert_eq!(sig.hash_ty, EcdsaSighashType::All);
+    let script_code = funding.txout.script_pubkey.p2wpkh_script_code().unwrap();
+    let sighash = SighashCache::new(tx)
+        .segwit_signature_hash(
+            0,
+            &script_code,
+            funding.txout.value,
+            EcdsaSighashType::All,
+        )
+        .unwrap();
+    SECP256K1
+        .verify_ecdsa(
+            &secp256k1::Message::from_slice(&sighash[..]).unwrap(),
+            &sig.sig,
+            &pubkey.inner,
+        )
+        .unwrap();
+}
+
+/// Regenerates the fixtures, recording the funding UTXO from `BP_FUNDING` if
+/// it is set.
+fn update_fixtures(fixture: &Fixture, params: &Params) {
+    if let Some(funding) = env::var_os("BP_FUNDING") {
+        let funding = funding.into_string().unwrap();
+        let (tx, vout) = funding.split_once(':').unwrap();
+        let tx: Transaction =
+            deserialize(&Vec::<u8>::from_hex(tx).unwrap()).unwrap();
+        let vout: u32 = vout.parse().unwrap();
+        assert_eq!(
+            tx.output[vout as usize].script_pubkey,
+            params.script_pubkey(),
+            "funding output must pay to the test key"
+        );
+        fs::write(
+            Fixture::path("funding"),
+            format!(
+                "[funding]\ntx = {}\nvout = {}\n",
+                serialize(&tx).to_hex(),
+                vout
+            ),
+        )
+        .unwrap();
+    }
+
+    let (tx, anchor) = commit_and_sign(&Funding::load(), params);
+    fs::write(
+        Fixture::path("opret"),
+        format!(
+            "[opret]\ninput.secret_key = {}\ninput.fee = {}\ninput.entropy = \
+             {}\noutput.tx = {}\noutput.anchor = {}\n",
+            fixture.get("input.secret_key"),
+            params.fee,
+            params.entropy,
+            serialize(&tx).to_hex(),
+            anchor.to_hex()
+        ),
+    )
+    .unwrap();
+}
+
+#[test]
+fn opret_workflow() {
+    let fixture = Fixture::load("opret");
+    let params = Params::load(&fixture);
+    if env::var_os("BP_UPDATE_FIXTURES").is_some() {
+        update_fixtures(&fixture, &params);
+    }
+    let fixture = Fixture::load("opret");
+    let funding = Funding::load();
+
+    let (tx, anchor) = commit_and_sign(&funding, &params);
+    assert_eq!(serialize(&tx).to_hex(), fixture.get("output.tx"));
+    assert_eq!(anchor.to_hex(), fixture.get("output.anchor"));
+
+    let recorded: Transaction =
+        deserialize(&Vec::<u8>::from_hex(fixture.get("output.tx")).unwrap())
+            .unwrap();
+    let recorded_anchor =
+        Anchor::<lnpbp4::MerkleBlock>::from_hex(fixture.get("output.anchor"))
+            .unwrap();
+    check_spending(&recorded, &funding, &params);
+    assert_eq!(recorded_anchor.txid(), recorded.txid());
+    for (protocol_id, message) in (0..FIXTURE_PROTOCOLS).map(protocol_fixture) {
+        let proof = recorded_anchor.to_merkle_proof(protocol_id).unwrap();
+        assert!(proof.verify(protocol_id, message, &recorded).unwrap());
+        assert_eq!(
+            proof.verify_detailed(protocol_id, message, &recorded),
+            Ok(VerificationOutcome::Committed)
+        );
+    }
+}
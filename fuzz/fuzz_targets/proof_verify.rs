@@ -0,0 +1,80 @@
+// Deterministic bitcoin commitments library, implementing LNPBP standards
+// Part of bitcoin protocol core library (BP Core Lib)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Feeds witness transactions controlled by the fuzzer into opret and tapret
+//! `Proof::verify`, checking that the verification never panics and agrees
+//! with the verification of the prepared transaction.
+//!
+//! Run with `cargo +nightly fuzz run proof_verify` from the repository root.
+
+#![no_main]
+
+use std::iter;
+
+use bitcoin::consensus::deserialize;
+use bitcoin::{PackedLockTime, Script, Transaction, TxIn, TxOut};
+use commit_verify::ConsensusCommit;
+use dbc::test_utils::{opret_fixture, tapret_fixture};
+use dbc::PreparedTx;
+use libfuzzer_sys::fuzz_target;
+
+/// Maximal number of outputs produced from a single output template.
+const MAX_REPEAT: usize = 10_000;
+
+/// Constructs transaction by consensus-decoding the fuzzer input or, if the
+/// input is not a valid transaction, by interpreting it as a sequence of
+/// `(repeat: u16, len: u16, script: [u8; len])` output templates. Templates
+/// allow small inputs to produce transactions with a huge number of outputs.
+fn tx_from(data: &[u8]) -> Transaction {
+    if let Ok(tx) = deserialize(data) {
+        return tx;
+    }
+    let mut output = vec![];
+    let mut data = data;
+    while data.len() >= 4 {
+        let repeat =
+            u16::from_le_bytes([data[0], data[1]]) as usize % (MAX_REPEAT + 1);
+        let len = (u16::from_le_bytes([data[2], data[3]]) as usize)
+            .min(data.len() - 4);
+        let txout = TxOut {
+            value: 0,
+            script_pubkey: Script::from(data[4..4 + len].to_vec()),
+        };
+        output.extend(iter::repeat(txout).take(repeat));
+        data = &data[4 + len..];
+    }
+    Transaction {
+        version: 2,
+        lock_time: PackedLockTime::ZERO,
+        input: vec![TxIn::default()],
+        output,
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let hostile = tx_from(data);
+    for (anchor, witness) in [opret_fixture(), tapret_fixture()] {
+        let msg = anchor.lnpbp4_proof.consensus_commit();
+
+        // Hostile outputs alone and following the valid commitment
+        let mut appended = witness.clone();
+        appended.output.extend(hostile.output.iter().cloned());
+        for tx in [&hostile, &appended] {
+            let res = anchor.dbc_proof.verify(&msg, tx);
+            let prepared = PreparedTx::new(tx.clone());
+            assert_eq!(anchor.dbc_proof.verify_prepared(&msg, &prepared), res);
+        }
+    }
+});
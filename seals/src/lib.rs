@@ -30,4 +30,55 @@ extern crate async_trait;
 #[macro_use]
 extern crate serde_crate as serde;
 
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
 pub mod txout;
+
+/// Compile tests of the `deterministic` feature, which removes all seal
+/// constructors generating blinding factors with OS entropy, leaving only the
+/// ones using caller-provided random number generator:
+///
+/// ```
+/// use bitcoin::secp256k1::rand::RngCore;
+/// use seals::txout::blind::RevealedSeal;
+/// use seals::txout::CloseMethod;
+///
+/// fn seal(rng: &mut impl RngCore) -> RevealedSeal {
+///     RevealedSeal::with(CloseMethod::OpretFirst, None, 0, rng).unwrap()
+/// }
+/// ```
+///
+/// ```compile_fail
+/// use seals::txout::blind::RevealedSeal;
+///
+/// let _ = RevealedSeal::new;
+/// ```
+///
+/// ```compile_fail
+/// use bitcoin::OutPoint;
+/// use seals::txout::blind::RevealedSeal;
+///
+/// fn seal(outpoint: OutPoint) -> RevealedSeal {
+///     RevealedSeal::try_from(outpoint).unwrap()
+/// }
+/// ```
+///
+/// ```compile_fail
+/// use seals::txout::blind::RevealedSeal;
+/// use seals::txout::ExplicitSeal;
+///
+/// fn seal(seal: ExplicitSeal) -> RevealedSeal {
+///     RevealedSeal::try_from(seal).unwrap()
+/// }
+/// ```
+///
+/// ```compile_fail
+/// use bitcoin::OutPoint;
+/// use seals::txout::blind::ConcealedSeal;
+///
+/// fn seal(outpoint: OutPoint) -> ConcealedSeal {
+///     ConcealedSeal::try_from(outpoint).unwrap()
+/// }
+/// ```
+#[cfg(all(doctest, feature = "deterministic", feature = "rand"))]
+pub struct DeterministicBuild;
@@ -0,0 +1,66 @@
+// BP Core Library implementing LNP/BP specifications & standards related to
+// bitcoin protocol
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Deterministic seal fixtures for documentation examples and for tests of
+//! the crates using the library, complementing `dbc::test_utils`.
+//!
+//! The fixtures must never be used with real funds. The module is available
+//! with `test-utils` feature only.
+
+use bitcoin::hashes::Hash;
+use bitcoin::{Transaction, Txid};
+use commit_verify::lnpbp4;
+use dbc::test_utils::opret_fixture;
+use dbc::Anchor;
+
+use crate::txout::blind::RevealedSeal;
+use crate::txout::{CloseMethod, TxoSeal};
+
+/// Constructs opret seal defined over output 1 of the transaction with id
+/// `sha256d("seal")`.
+pub fn seal_fixture() -> RevealedSeal {
+    RevealedSeal::checked(
+        CloseMethod::OpretFirst,
+        Some(Txid::hash(b"seal")),
+        1,
+        54683213134637,
+    )
+    .expect("non-zero blinding")
+}
+
+/// Constructs [`seal_fixture`] seal together with the witness transaction
+/// closing it and the anchor of `dbc::test_utils::opret_fixture` committed
+/// to by the transaction.
+///
+/// ```
+/// use dbc::test_utils::protocol_fixture;
+/// use seals::test_utils::closure_fixture;
+/// use seals::txout::ClosureProof;
+///
+/// let (seal, anchor, tx) = closure_fixture();
+/// let (protocol_id, message) = protocol_fixture(1);
+/// let proof =
+///     ClosureProof::with(seal, &anchor, protocol_id, message).unwrap();
+/// assert_eq!(proof.witness_txid, tx.txid());
+/// ```
+pub fn closure_fixture(
+) -> (RevealedSeal, Anchor<lnpbp4::MerkleBlock>, Transaction) {
+    let seal = seal_fixture();
+    let (anchor, mut tx) = opret_fixture();
+    tx.input[0].previous_output =
+        seal.outpoint().expect("seal with explicit txid");
+    let anchor = anchor.with_txid(tx.txid());
+    (seal, anchor, tx)
+}
@@ -20,14 +20,23 @@ use std::fmt::{self, Display, Formatter};
 use std::str::FromStr;
 
 use bitcoin::hashes::{sha256, sha256t, Hash, HashEngine};
-use bitcoin::secp256k1::rand::{thread_rng, RngCore};
-use bitcoin::{OutPoint, Txid};
+#[cfg(all(feature = "rand", not(feature = "deterministic")))]
+use bitcoin::secp256k1::rand::thread_rng;
+#[cfg(feature = "rand")]
+use bitcoin::secp256k1::rand::RngCore;
+use bitcoin::{Network, OutPoint, Txid};
 use commit_verify::{commit_encode, CommitConceal, CommitVerify, TaggedHash};
+use dbc::anchor::network_tag;
 use dbc::tapret::Lnpbp6;
 use lnpbp_bech32::{FromBech32Str, ToBech32String};
 
+#[cfg(feature = "rand")]
+use super::reuse::MAX_BLINDING_ATTEMPTS;
+use super::reuse::{BlindingReuse, BlindingTracker};
 use super::{CloseMethod, MethodParseError, WitnessVoutError};
-use crate::txout::{ExplicitSeal, TxoSeal};
+#[cfg(all(feature = "rand", not(feature = "deterministic")))]
+use crate::txout::ExplicitSeal;
+use crate::txout::TxoSeal;
 
 /// Revealed seal definition which may point to a witness transactions and
 /// contains blinding data.
@@ -84,38 +93,98 @@ impl TryFrom<RevealedSeal> for OutPoint {
     }
 }
 
-impl From<&OutPoint> for RevealedSeal {
-    #[inline]
-    fn from(outpoint: &OutPoint) -> Self {
-        Self {
-            method: CloseMethod::TapretFirst,
-            blinding: thread_rng().next_u64(),
-            txid: Some(outpoint.txid),
-            vout: outpoint.vout,
+/// Maximal number of outputs a transaction may have under the consensus block
+/// weight limit of 4 000 000 weight units, where each output takes at least
+/// 36 weight units (8-byte value and 1-byte empty scriptPubkey length).
+pub const MAX_TX_OUTPUTS: u32 = 4_000_000 / 36;
+
+/// Constraints applied by [`RevealedSeal::checked_with`] to the components of
+/// the seal definition.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct SealConstraints {
+    /// Maximal allowed output number. Defaults to `MAX_TX_OUTPUTS - 1`.
+    pub max_vout: u32,
+
+    /// Whether zero blinding factor is allowed. Defaults to `false`.
+    pub allow_zero_blinding: bool,
+}
+
+impl Default for SealConstraints {
+    fn default() -> Self {
+        SealConstraints {
+            max_vout: MAX_TX_OUTPUTS - 1,
+            allow_zero_blinding: false,
         }
     }
 }
 
-impl From<OutPoint> for RevealedSeal {
+/// Errors constructing [`RevealedSeal`] from its components.
+#[derive(
+    Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display, Error
+)]
+#[display(doc_comments)]
+pub enum SealConstructError {
+    /// seal output number {0} is negative.
+    NegativeVout(i64),
+
+    /// seal output number {0} exceeds the maximum of {1}.
+    VoutOutOfRange(i64, u32),
+
+    /// seal transaction id consists of zero bytes only.
+    ZeroTxid,
+
+    /// seal blinding factor is zero.
+    ZeroBlinding,
+}
+
+#[cfg(feature = "rand")]
+fn random_blinding(rng: &mut impl RngCore) -> u64 {
+    loop {
+        match rng.next_u64() {
+            0 => continue,
+            blinding => return blinding,
+        }
+    }
+}
+
+#[cfg(all(feature = "rand", not(feature = "deterministic")))]
+impl TryFrom<&OutPoint> for RevealedSeal {
+    type Error = SealConstructError;
+
+    #[inline]
+    fn try_from(outpoint: &OutPoint) -> Result<Self, Self::Error> {
+        RevealedSeal::new(CloseMethod::TapretFirst, *outpoint)
+    }
+}
+
+#[cfg(all(feature = "rand", not(feature = "deterministic")))]
+impl TryFrom<OutPoint> for RevealedSeal {
+    type Error = SealConstructError;
+
     #[inline]
-    fn from(outpoint: OutPoint) -> Self { RevealedSeal::from(&outpoint) }
+    fn try_from(outpoint: OutPoint) -> Result<Self, Self::Error> {
+        RevealedSeal::try_from(&outpoint)
+    }
 }
 
-impl From<&ExplicitSeal> for RevealedSeal {
+#[cfg(all(feature = "rand", not(feature = "deterministic")))]
+impl TryFrom<&ExplicitSeal> for RevealedSeal {
+    type Error = SealConstructError;
+
     #[inline]
-    fn from(seal: &ExplicitSeal) -> Self {
-        Self {
-            method: seal.method,
-            blinding: thread_rng().next_u64(),
-            txid: seal.txid,
-            vout: seal.vout,
-        }
+    fn try_from(seal: &ExplicitSeal) -> Result<Self, Self::Error> {
+        RevealedSeal::with(seal.method, seal.txid, seal.vout, &mut thread_rng())
     }
 }
 
-impl From<ExplicitSeal> for RevealedSeal {
+#[cfg(all(feature = "rand", not(feature = "deterministic")))]
+impl TryFrom<ExplicitSeal> for RevealedSeal {
+    type Error = SealConstructError;
+
     #[inline]
-    fn from(seal: ExplicitSeal) -> Self { RevealedSeal::from(&seal) }
+    fn try_from(seal: ExplicitSeal) -> Result<Self, Self::Error> {
+        RevealedSeal::try_from(&seal)
+    }
 }
 
 impl CommitConceal for RevealedSeal {
@@ -157,37 +226,503 @@ impl TxoSeal for RevealedSeal {
 
 impl RevealedSeal {
     /// Constructs seal for the provided outpoint and seal closing method. Uses
-    /// `thread_rng` to initialize blinding factor.
+    /// `thread_rng` to initialize non-zero blinding factor.
+    ///
+    /// ```
+    /// use bitcoin::hashes::Hash;
+    /// use bitcoin::{OutPoint, Txid};
+    /// use seals::txout::blind::RevealedSeal;
+    /// use seals::txout::{CloseMethod, TxoSeal};
+    ///
+    /// let outpoint = OutPoint::new(Txid::hash(b"seal"), 1);
+    /// let seal = RevealedSeal::new(CloseMethod::TapretFirst, outpoint).unwrap();
+    /// assert_eq!(seal.outpoint(), Some(outpoint));
+    /// assert_ne!(seal.blinding, 0);
+    ///
+    /// // Blinding hides the outpoint behind the concealed seal
+    /// let other = RevealedSeal::new(CloseMethod::TapretFirst, outpoint).unwrap();
+    /// assert_ne!(seal.to_concealed_seal(), other.to_concealed_seal());
+    /// ```
+    ///
+    /// Not available with `deterministic` feature; use [`RevealedSeal::with`]
+    /// with a caller-provided random number generator instead.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the outpoint violates the default [`SealConstraints`]; see
+    /// [`RevealedSeal::checked`].
+    #[cfg(all(feature = "rand", not(feature = "deterministic")))]
     #[inline]
-    pub fn new(method: CloseMethod, outpoint: OutPoint) -> RevealedSeal {
-        Self {
+    pub fn new(
+        method: CloseMethod,
+        outpoint: OutPoint,
+    ) -> Result<RevealedSeal, SealConstructError> {
+        RevealedSeal::with(
             method,
-            blinding: thread_rng().next_u64(),
-            txid: Some(outpoint.txid),
-            vout: outpoint.vout,
-        }
+            Some(outpoint.txid),
+            outpoint.vout,
+            &mut thread_rng(),
+        )
     }
 
     /// Constructs seal using the provided random number generator for creating
-    /// blinding factor value
+    /// non-zero blinding factor value
+    ///
+    /// ```
+    /// use bitcoin::secp256k1::rand::thread_rng;
+    /// use seals::txout::blind::{RevealedSeal, SealConstructError};
+    /// use seals::txout::{CloseMethod, TxoSeal};
+    ///
+    /// let seal =
+    ///     RevealedSeal::with(CloseMethod::OpretFirst, None, 0, &mut thread_rng())
+    ///         .unwrap();
+    /// assert_eq!(seal.outpoint(), None);
+    /// assert_ne!(seal.blinding, 0);
+    ///
+    /// assert!(matches!(
+    ///     RevealedSeal::with(
+    ///         CloseMethod::OpretFirst,
+    ///         None,
+    ///         u32::MAX,
+    ///         &mut thread_rng()
+    ///     ),
+    ///     Err(SealConstructError::VoutOutOfRange(..))
+    /// ));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Errors if the seal components violate the default [`SealConstraints`];
+    /// see [`RevealedSeal::checked`].
+    #[cfg(feature = "rand")]
     #[inline]
     pub fn with(
         method: CloseMethod,
         txid: Option<Txid>,
         vout: u32,
         rng: &mut impl RngCore,
-    ) -> RevealedSeal {
-        RevealedSeal {
+    ) -> Result<RevealedSeal, SealConstructError> {
+        RevealedSeal::checked(method, txid, vout, random_blinding(rng))
+    }
+
+    /// Constructs seal for the provided outpoint using the provided random
+    /// number generator for creating non-zero blinding factor, which was not
+    /// issued before according to the `tracker`. Blinding factors colliding
+    /// with the issued ones are regenerated up to [`MAX_BLINDING_ATTEMPTS`]
+    /// times.
+    ///
+    /// ```
+    /// use bitcoin::hashes::Hash;
+    /// use bitcoin::secp256k1::rand::thread_rng;
+    /// use bitcoin::{OutPoint, Txid};
+    /// use seals::txout::blind::RevealedSeal;
+    /// use seals::txout::reuse::BlindingTracker;
+    /// use seals::txout::CloseMethod;
+    ///
+    /// let mut rng = thread_rng();
+    /// let mut tracker = BlindingTracker::new(&mut rng);
+    /// let outpoint = OutPoint::new(Txid::hash(b"seal"), 1);
+    /// let seal = RevealedSeal::with_unique_blinding(
+    ///     CloseMethod::TapretFirst,
+    ///     outpoint,
+    ///     &mut rng,
+    ///     &mut tracker,
+    /// )
+    /// .unwrap();
+    /// assert!(tracker.is_issued(seal.blinding));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Errors with [`BlindingReuse::AttemptsExhausted`] if the random number
+    /// generator doesn't produce a new blinding factor; the tracker is not
+    /// changed in this case.
+    #[cfg(feature = "rand")]
+    pub fn with_unique_blinding(
+        method: CloseMethod,
+        outpoint: OutPoint,
+        rng: &mut impl RngCore,
+        tracker: &mut BlindingTracker,
+    ) -> Result<RevealedSeal, BlindingReuse> {
+        for _ in 0..MAX_BLINDING_ATTEMPTS {
+            let blinding = random_blinding(rng);
+            if tracker.issue(blinding).is_ok() {
+                return Ok(RevealedSeal {
+                    method,
+                    txid: Some(outpoint.txid),
+                    vout: outpoint.vout,
+                    blinding,
+                });
+            }
+        }
+        Err(BlindingReuse::AttemptsExhausted(MAX_BLINDING_ATTEMPTS))
+    }
+
+    /// Constructs seal for the provided outpoint with the blinding factor
+    /// provided by the caller, recording it in the `tracker`.
+    ///
+    /// # Errors
+    ///
+    /// Errors with [`BlindingReuse::Reused`] if the blinding factor was
+    /// already issued according to the `tracker`.
+    pub fn with_tracked_blinding(
+        method: CloseMethod,
+        outpoint: OutPoint,
+        blinding: u64,
+        tracker: &mut BlindingTracker,
+    ) -> Result<RevealedSeal, BlindingReuse> {
+        tracker.issue(blinding)?;
+        Ok(RevealedSeal {
+            method,
+            txid: Some(outpoint.txid),
+            vout: outpoint.vout,
+            blinding,
+        })
+    }
+
+    /// Constructs seal from the components coming from an untrusted source
+    /// (user input, database records etc), validating them against the
+    /// default [`SealConstraints`].
+    ///
+    /// The same constraints are applied by all other seal constructors
+    /// generating the blinding factor and by parsing the seal with
+    /// [`FromStr`], which reports violations with
+    /// [`ParseError::Constraint`].
+    ///
+    /// Output number is accepted as a signed integer, such that values read
+    /// from signed storage columns are checked before the conversion instead
+    /// of wrapping around into huge numbers.
+    ///
+    /// ```
+    /// use bitcoin::hashes::Hash;
+    /// use bitcoin::Txid;
+    /// use seals::txout::blind::{RevealedSeal, SealConstructError};
+    /// use seals::txout::{CloseMethod, TxoSeal};
+    ///
+    /// let txid = Some(Txid::hash(b"seal"));
+    /// let seal =
+    ///     RevealedSeal::checked(CloseMethod::OpretFirst, txid, 1, 1).unwrap();
+    /// assert_eq!(seal.vout(), 1);
+    ///
+    /// assert_eq!(
+    ///     RevealedSeal::checked(CloseMethod::OpretFirst, txid, -1, 1),
+    ///     Err(SealConstructError::NegativeVout(-1))
+    /// );
+    /// assert_eq!(
+    ///     RevealedSeal::checked(CloseMethod::OpretFirst, txid, 1, 0),
+    ///     Err(SealConstructError::ZeroBlinding)
+    /// );
+    /// ```
+    #[inline]
+    pub fn checked(
+        method: CloseMethod,
+        txid: Option<Txid>,
+        vout: impl Into<i64>,
+        blinding: u64,
+    ) -> Result<RevealedSeal, SealConstructError> {
+        RevealedSeal::checked_with(
             method,
             txid,
             vout,
-            blinding: rng.next_u64(),
+            blinding,
+            &SealConstraints::default(),
+        )
+    }
+
+    /// Constructs seal from the components coming from an untrusted source,
+    /// validating them against the provided `constraints`. See
+    /// [`RevealedSeal::checked`] for the details.
+    ///
+    /// ```
+    /// use bitcoin::hashes::Hash;
+    /// use bitcoin::Txid;
+    /// use seals::txout::blind::{
+    ///     RevealedSeal, SealConstraints, SealConstructError,
+    /// };
+    /// use seals::txout::CloseMethod;
+    ///
+    /// let constraints = SealConstraints {
+    ///     max_vout: 10,
+    ///     allow_zero_blinding: true,
+    /// };
+    /// let txid = Some(Txid::hash(b"seal"));
+    /// let method = CloseMethod::TapretFirst;
+    /// assert!(
+    ///     RevealedSeal::checked_with(method, txid, 10, 0, &constraints).is_ok()
+    /// );
+    /// assert_eq!(
+    ///     RevealedSeal::checked_with(method, txid, 11, 0, &constraints),
+    ///     Err(SealConstructError::VoutOutOfRange(11, 10))
+    /// );
+    /// ```
+    pub fn checked_with(
+        method: CloseMethod,
+        txid: Option<Txid>,
+        vout: impl Into<i64>,
+        blinding: u64,
+        constraints: &SealConstraints,
+    ) -> Result<RevealedSeal, SealConstructError> {
+        let vout = vout.into();
+        if vout < 0 {
+            return Err(SealConstructError::NegativeVout(vout));
+        }
+        if vout > constraints.max_vout as i64 {
+            return Err(SealConstructError::VoutOutOfRange(
+                vout,
+                constraints.max_vout,
+            ));
         }
+        if txid == Some(Txid::all_zeros()) {
+            return Err(SealConstructError::ZeroTxid);
+        }
+        if blinding == 0 && !constraints.allow_zero_blinding {
+            return Err(SealConstructError::ZeroBlinding);
+        }
+        Ok(RevealedSeal {
+            method,
+            txid,
+            vout: vout as u32,
+            blinding,
+        })
     }
 
     /// Converts revealed seal into concealed.
+    ///
+    /// ```
+    /// # use bitcoin::hashes::Hash;
+    /// # use bitcoin::Txid;
+    /// # use seals::txout::blind::RevealedSeal;
+    /// # use seals::txout::CloseMethod;
+    /// let txid = Some(Txid::hash(b"seal"));
+    /// let blinding = 54683213134637;
+    /// let seal =
+    ///     RevealedSeal::checked(CloseMethod::OpretFirst, txid, 1, blinding)
+    ///         .unwrap();
+    ///
+    /// let concealed = seal.to_concealed_seal();
+    /// assert!(!concealed.is_zero());
+    ///
+    /// // Concealed seal depends on the blinding factor
+    /// let reblinded = RevealedSeal {
+    ///     blinding: 1,
+    ///     ..seal
+    /// };
+    /// assert_ne!(reblinded.to_concealed_seal(), concealed);
+    /// ```
     #[inline]
     pub fn to_concealed_seal(&self) -> ConcealedSeal { self.commit_conceal() }
+
+    /// Converts revealed seal into concealed seal scoped to the bitcoin
+    /// `network`.
+    ///
+    /// Network-agnostic [`RevealedSeal::to_concealed_seal`] produces the same
+    /// concealed seal for the same outpoint and blinding factor on any
+    /// network, so seals defined on test networks may be mistaken for the
+    /// mainnet ones. Network-scoped concealment additionally commits to the
+    /// [`network_tag`] using a separate hash tag
+    /// `bp:txout:concealed:network:v1`, and thus never matches
+    /// network-agnostic concealed seals or seals scoped to other networks.
+    ///
+    /// The choice between the two forms is an application policy, which must
+    /// be applied consistently: network-scoped concealed seals (including the
+    /// ones for mainnet) are incompatible with the network-agnostic ones used
+    /// by the existing software and stored data, and both the seal definition
+    /// owner and the verifier must use the same form to match seals.
+    ///
+    /// ```
+    /// # use bitcoin::hashes::Hash;
+    /// # use bitcoin::{Network, Txid};
+    /// # use seals::txout::blind::RevealedSeal;
+    /// # use seals::txout::CloseMethod;
+    /// let txid = Some(Txid::hash(b"seal"));
+    /// let blinding = 54683213134637;
+    /// let seal =
+    ///     RevealedSeal::checked(CloseMethod::OpretFirst, txid, 1, blinding)
+    ///         .unwrap();
+    ///
+    /// let mainnet = seal.conceal_for(Network::Bitcoin);
+    /// assert_ne!(mainnet, seal.to_concealed_seal());
+    /// assert_ne!(mainnet, seal.conceal_for(Network::Signet));
+    /// ```
+    pub fn conceal_for(&self, network: Network) -> ConcealedSeal {
+        let mut engine = sha256t::Hash::<NetworkConcealedSealTag>::engine();
+        engine.input(&network_tag(network));
+        self.hash_into(&mut engine);
+        let hash =
+            sha256t::Hash::<NetworkConcealedSealTag>::from_engine(engine);
+        ConcealedSeal::from_hash(sha256t::Hash::<ConcealedSealTag>::from_inner(
+            hash.into_inner(),
+        ))
+    }
+
+    fn hash_into(&self, engine: &mut sha256::HashEngine) {
+        engine.input(&[self.method as u8]);
+        engine.input(
+            &self.txid.unwrap_or_else(|| {
+                Txid::from_slice(&[0u8; 32]).expect("hardcoded")
+            })[..],
+        );
+        engine.input(&self.vout.to_le_bytes()[..]);
+        engine.input(&self.blinding.to_le_bytes()[..]);
+    }
+
+    /// Wraps the seal into [`VersionedReveal`], keeping its original
+    /// concealment procedure.
+    ///
+    /// ```
+    /// # use bitcoin::hashes::Hash;
+    /// # use bitcoin::Txid;
+    /// # use seals::txout::blind::{RevealedSeal, VersionedReveal};
+    /// # use seals::txout::CloseMethod;
+    /// let txid = Some(Txid::hash(b"seal"));
+    /// let blinding = 54683213134637;
+    /// let seal =
+    ///     RevealedSeal::checked(CloseMethod::OpretFirst, txid, 1, blinding)
+    ///         .unwrap();
+    ///
+    /// let versioned = seal.upgrade();
+    /// assert_eq!(versioned, VersionedReveal::Legacy(seal));
+    /// assert_eq!(versioned.to_concealed_seal(), seal.to_concealed_seal());
+    /// ```
+    #[inline]
+    pub fn upgrade(self) -> VersionedReveal { VersionedReveal::Legacy(self) }
+}
+
+/// Revealed seal definition tagged with the version of the blinding scheme.
+///
+/// Allows wallets to keep seals created with the legacy 64-bit blinding
+/// factor alongside the seals using newer blinding schemes, such that the
+/// concealed form of the legacy seals remains reproducible.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, From)]
+#[derive(StrictEncode, StrictDecode)]
+#[strict_encoding(by_order)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+#[non_exhaustive]
+pub enum VersionedReveal {
+    /// Seal using legacy 64-bit blinding factor.
+    #[from]
+    Legacy(RevealedSeal),
+}
+
+impl VersionedReveal {
+    /// Returns legacy seal definition, if the seal uses legacy 64-bit
+    /// blinding factor.
+    ///
+    /// ```
+    /// # use bitcoin::hashes::Hash;
+    /// # use bitcoin::Txid;
+    /// # use seals::txout::blind::RevealedSeal;
+    /// # use seals::txout::CloseMethod;
+    /// let txid = Some(Txid::hash(b"seal"));
+    /// let blinding = 54683213134637;
+    /// let seal =
+    ///     RevealedSeal::checked(CloseMethod::OpretFirst, txid, 1, blinding)
+    ///         .unwrap();
+    ///
+    /// assert_eq!(seal.upgrade().as_legacy(), Some(&seal));
+    /// ```
+    #[inline]
+    pub fn as_legacy(&self) -> Option<&RevealedSeal> {
+        match self {
+            VersionedReveal::Legacy(seal) => Some(seal),
+        }
+    }
+
+    /// Computes concealed seal using the legacy concealment procedure, if the
+    /// seal uses legacy 64-bit blinding factor. The returned value is always
+    /// equal to [`RevealedSeal::to_concealed_seal`] for the original seal.
+    ///
+    /// ```
+    /// # use bitcoin::hashes::Hash;
+    /// # use bitcoin::Txid;
+    /// # use seals::txout::blind::RevealedSeal;
+    /// # use seals::txout::CloseMethod;
+    /// let txid = Some(Txid::hash(b"seal"));
+    /// let blinding = 54683213134637;
+    /// let seal =
+    ///     RevealedSeal::checked(CloseMethod::OpretFirst, txid, 1, blinding)
+    ///         .unwrap();
+    ///
+    /// assert_eq!(
+    ///     seal.upgrade().conceal_legacy(),
+    ///     Some(seal.to_concealed_seal())
+    /// );
+    /// ```
+    #[inline]
+    pub fn conceal_legacy(&self) -> Option<ConcealedSeal> {
+        self.as_legacy().map(RevealedSeal::to_concealed_seal)
+    }
+
+    /// Computes concealed seal using the concealment procedure defined by the
+    /// seal version.
+    ///
+    /// ```
+    /// # use bitcoin::hashes::Hash;
+    /// # use bitcoin::Txid;
+    /// # use seals::txout::blind::RevealedSeal;
+    /// # use seals::txout::CloseMethod;
+    /// let txid = Some(Txid::hash(b"seal"));
+    /// let blinding = 54683213134637;
+    /// let seal =
+    ///     RevealedSeal::checked(CloseMethod::OpretFirst, txid, 1, blinding)
+    ///         .unwrap();
+    ///
+    /// assert_eq!(seal.upgrade().to_concealed_seal(), seal.to_concealed_seal());
+    /// ```
+    #[inline]
+    pub fn to_concealed_seal(&self) -> ConcealedSeal {
+        match self {
+            VersionedReveal::Legacy(seal) => seal.to_concealed_seal(),
+        }
+    }
+
+    /// Computes concealed seal scoped to the bitcoin `network` using the
+    /// concealment procedure defined by the seal version. See
+    /// [`RevealedSeal::conceal_for`] for the details.
+    ///
+    /// ```
+    /// # use bitcoin::hashes::Hash;
+    /// # use bitcoin::{Network, Txid};
+    /// # use seals::txout::blind::RevealedSeal;
+    /// # use seals::txout::CloseMethod;
+    /// let txid = Some(Txid::hash(b"seal"));
+    /// let blinding = 54683213134637;
+    /// let seal =
+    ///     RevealedSeal::checked(CloseMethod::OpretFirst, txid, 1, blinding)
+    ///         .unwrap();
+    ///
+    /// assert_eq!(
+    ///     seal.upgrade().conceal_for(Network::Testnet),
+    ///     seal.conceal_for(Network::Testnet)
+    /// );
+    /// ```
+    #[inline]
+    pub fn conceal_for(&self, network: Network) -> ConcealedSeal {
+        match self {
+            VersionedReveal::Legacy(seal) => seal.conceal_for(network),
+        }
+    }
+}
+
+impl FromStr for VersionedReveal {
+    type Err = ParseError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RevealedSeal::from_str(s).map(VersionedReveal::Legacy)
+    }
+}
+
+impl Display for VersionedReveal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionedReveal::Legacy(seal) => Display::fmt(seal, f),
+        }
+    }
 }
 
 /// Errors happening during parsing string representation of different forms of
@@ -231,6 +766,10 @@ pub enum ParseError {
     /// wrong Bech32 representation of the blinded TxOut seal – {0}
     #[from]
     Bech32(lnpbp_bech32::Error),
+
+    /// {0}
+    #[from]
+    Constraint(SealConstructError),
 }
 
 impl FromStr for RevealedSeal {
@@ -255,31 +794,20 @@ impl FromStr for RevealedSeal {
             {
                 Err(ParseError::NonHexBlinding)
             }
-            (Some(method), Some("~"), Some(vout), Some(blinding), None) => {
-                Ok(RevealedSeal {
-                    method: method.parse()?,
-                    blinding: u64::from_str_radix(
-                        blinding.trim_start_matches("0x"),
-                        16,
-                    )
-                    .map_err(|_| ParseError::WrongBlinding)?,
-                    txid: None,
-                    vout: vout.parse().map_err(|_| ParseError::WrongVout)?,
-                })
-            }
             (Some(method), Some(txid), Some(vout), Some(blinding), None) => {
-                Ok(RevealedSeal {
-                    method: method.parse()?,
-                    blinding: u64::from_str_radix(
-                        blinding.trim_start_matches("0x"),
-                        16,
-                    )
-                    .map_err(|_| ParseError::WrongBlinding)?,
-                    txid: Some(
-                        txid.parse().map_err(|_| ParseError::WrongTxid)?,
-                    ),
-                    vout: vout.parse().map_err(|_| ParseError::WrongVout)?,
-                })
+                let method = method.parse()?;
+                let blinding =
+                    u64::from_str_radix(blinding.trim_start_matches("0x"), 16)
+                        .map_err(|_| ParseError::WrongBlinding)?;
+                let txid = match txid {
+                    "~" => None,
+                    txid => {
+                        Some(txid.parse().map_err(|_| ParseError::WrongTxid)?)
+                    }
+                };
+                let vout: u32 =
+                    vout.parse().map_err(|_| ParseError::WrongVout)?;
+                Ok(RevealedSeal::checked(method, txid, vout, blinding)?)
             }
             _ => Err(ParseError::WrongStructure),
         }
@@ -323,10 +851,27 @@ impl lnpbp_bech32::Strategy for ConcealedSealTag {
     type Strategy = lnpbp_bech32::strategies::UsingStrictEncoding;
 }
 
+static MIDSTATE_NETWORK_CONCEALED_SEAL: [u8; 32] = [
+    84, 161, 34, 156, 42, 28, 146, 137, 235, 223, 174, 84, 237, 130, 195, 184,
+    92, 3, 184, 62, 57, 144, 197, 182, 80, 249, 178, 200, 130, 146, 126, 240,
+];
+
+/// Tag used for network-scoped [`ConcealedSeal`]s produced by
+/// [`RevealedSeal::conceal_for`]
+pub struct NetworkConcealedSealTag;
+
+impl sha256t::Tag for NetworkConcealedSealTag {
+    #[inline]
+    fn engine() -> sha256::HashEngine {
+        let midstate =
+            sha256::Midstate::from_inner(MIDSTATE_NETWORK_CONCEALED_SEAL);
+        sha256::HashEngine::from_midstate(midstate, 64)
+    }
+}
+
 /// Blind version of transaction outpoint-based single-use-seal
 #[derive(
-    Wrapper, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
-    Display, From
+    Wrapper, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Display, From
 )]
 #[wrapper(Debug, LowerHex, Index, IndexRange, IndexFrom, IndexTo, IndexFull)]
 #[display(ConcealedSeal::to_bech32_string)]
@@ -371,31 +916,151 @@ impl<'de> serde::Deserialize<'de> for ConcealedSeal {
                 ConcealedSeal::from_str(v).map_err(serde::de::Error::custom)
             }
 
-            fn visit_string<E>(self, v: String) -> Result<Self::Value, E>
-            where
-                E: serde::de::Error,
-            {
-                self.visit_str(&v)
-            }
-
-            fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
             where
                 E: serde::de::Error,
             {
-                ConcealedSeal::from_bytes(&v).map_err(|_| {
+                ConcealedSeal::from_bytes(v).map_err(|_| {
                     serde::de::Error::invalid_length(v.len(), &"32 bytes")
                 })
             }
         }
 
+        // Strings and bytes are parsed in place, without copying them into
+        // an owned buffer, unless the deserializer owns them already
         if deserializer.is_human_readable() {
             deserializer.deserialize_str(Visitor)
         } else {
-            deserializer.deserialize_byte_buf(Visitor)
+            deserializer.deserialize_bytes(Visitor)
         }
     }
 }
 
+impl ConcealedSeal {
+    /// Constructs all-zero concealed seal, which must be used only as a
+    /// sentinel value and never matches any real seal definition. Replaces
+    /// [`Default`], which the type intentionally does not implement.
+    ///
+    /// ```
+    /// use seals::txout::blind::ConcealedSeal;
+    ///
+    /// let zero = ConcealedSeal::zero();
+    /// assert!(zero.is_zero());
+    /// assert_eq!(zero.short_id(), 0);
+    /// ```
+    #[inline]
+    pub fn zero() -> ConcealedSeal { ConcealedSeal(sha256t::Hash::all_zeros()) }
+
+    /// Detects whether the concealed seal is an all-zero sentinel value
+    /// produced by [`ConcealedSeal::zero`].
+    ///
+    /// ```
+    /// # use bitcoin::hashes::Hash;
+    /// # use bitcoin::Txid;
+    /// # use seals::txout::blind::{ConcealedSeal, RevealedSeal};
+    /// # use seals::txout::CloseMethod;
+    /// let txid = Some(Txid::hash(b"seal"));
+    /// let blinding = 54683213134637;
+    /// let seal =
+    ///     RevealedSeal::checked(CloseMethod::OpretFirst, txid, 1, blinding)
+    ///         .unwrap();
+    ///
+    /// assert!(ConcealedSeal::zero().is_zero());
+    /// assert!(!seal.to_concealed_seal().is_zero());
+    /// ```
+    #[inline]
+    pub fn is_zero(&self) -> bool { self[..] == [0u8; 32] }
+
+    /// Returns compact 64-bit concealed seal identifier, suitable for use as a
+    /// database index key.
+    ///
+    /// The id is the little-endian value of the first 8 hash bytes and is not
+    /// unique among seals.
+    ///
+    /// ```
+    /// # use bitcoin::hashes::Hash;
+    /// # use bitcoin::Txid;
+    /// # use seals::txout::blind::RevealedSeal;
+    /// # use seals::txout::CloseMethod;
+    /// let txid = Some(Txid::hash(b"seal"));
+    /// let blinding = 54683213134637;
+    /// let seal =
+    ///     RevealedSeal::checked(CloseMethod::OpretFirst, txid, 1, blinding)
+    ///         .unwrap();
+    ///
+    /// let concealed = seal.to_concealed_seal();
+    /// let mut prefix = [0u8; 8];
+    /// prefix.copy_from_slice(concealed.prefix(8).unwrap());
+    /// assert_eq!(concealed.short_id(), u64::from_le_bytes(prefix));
+    /// ```
+    #[inline]
+    pub fn short_id(&self) -> u64 {
+        let mut prefix = [0u8; 8];
+        prefix.copy_from_slice(&self[..8]);
+        u64::from_le_bytes(prefix)
+    }
+
+    /// Returns first `len` bytes of the concealed seal hash, or `None` if `len`
+    /// exceeds the hash length of 32 bytes.
+    ///
+    /// ```
+    /// # use bitcoin::hashes::Hash;
+    /// # use bitcoin::Txid;
+    /// # use seals::txout::blind::RevealedSeal;
+    /// # use seals::txout::CloseMethod;
+    /// let txid = Some(Txid::hash(b"seal"));
+    /// let blinding = 54683213134637;
+    /// let seal =
+    ///     RevealedSeal::checked(CloseMethod::OpretFirst, txid, 1, blinding)
+    ///         .unwrap();
+    ///
+    /// let concealed = seal.to_concealed_seal();
+    /// assert_eq!(concealed.prefix(4), Some(&concealed[..4]));
+    /// assert_eq!(concealed.prefix(32), Some(&concealed[..]));
+    /// assert_eq!(concealed.prefix(33), None);
+    /// ```
+    #[inline]
+    pub fn prefix(&self, len: usize) -> Option<&[u8]> { self[..].get(..len) }
+
+    /// Returns short form of the concealed seal for use in logs and user
+    /// interfaces. See [`ConcealedSeal::short_id`] for the details.
+    ///
+    /// ```
+    /// # use bitcoin::hashes::Hash;
+    /// # use bitcoin::Txid;
+    /// # use seals::txout::blind::RevealedSeal;
+    /// # use seals::txout::CloseMethod;
+    /// let txid = Some(Txid::hash(b"seal"));
+    /// let blinding = 54683213134637;
+    /// let seal =
+    ///     RevealedSeal::checked(CloseMethod::OpretFirst, txid, 1, blinding)
+    ///         .unwrap();
+    ///
+    /// let concealed = seal.to_concealed_seal();
+    /// assert_eq!(
+    ///     concealed.to_short().to_string(),
+    ///     format!("{:016x}…", concealed.short_id())
+    /// );
+    /// ```
+    #[inline]
+    pub fn to_short(&self) -> ShortConcealedSeal {
+        ShortConcealedSeal(self.short_id())
+    }
+}
+
+/// Compact 64-bit form of the [`ConcealedSeal`], produced with
+/// [`ConcealedSeal::to_short`].
+///
+/// Displays as a hexadecimal representation of [`ConcealedSeal::short_id`]
+/// value followed by `…` suffix, distinguishing it from the full Bech32
+/// representation of the concealed seal.
+#[derive(
+    Wrapper, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, From,
+    Display
+)]
+#[display("{0:016x}…")]
+pub struct ShortConcealedSeal(u64);
+
 impl FromStr for ConcealedSeal {
     type Err = ParseError;
 
@@ -404,10 +1069,13 @@ impl FromStr for ConcealedSeal {
     }
 }
 
-impl From<OutPoint> for ConcealedSeal {
+#[cfg(all(feature = "rand", not(feature = "deterministic")))]
+impl TryFrom<OutPoint> for ConcealedSeal {
+    type Error = SealConstructError;
+
     #[inline]
-    fn from(outpoint: OutPoint) -> Self {
-        RevealedSeal::from(outpoint).commit_conceal()
+    fn try_from(outpoint: OutPoint) -> Result<Self, Self::Error> {
+        RevealedSeal::try_from(outpoint).map(|seal| seal.commit_conceal())
     }
 }
 
@@ -422,14 +1090,7 @@ impl commit_encode::Strategy for ConcealedSeal {
 impl CommitVerify<RevealedSeal, Lnpbp6> for ConcealedSeal {
     fn commit(reveal: &RevealedSeal) -> Self {
         let mut engine = sha256t::Hash::<ConcealedSealTag>::engine();
-        engine.input(&[reveal.method as u8]);
-        engine.input(
-            &reveal.txid.unwrap_or_else(|| {
-                Txid::from_slice(&[0u8; 32]).expect("hardcoded")
-            })[..],
-        );
-        engine.input(&reveal.vout.to_le_bytes()[..]);
-        engine.input(&reveal.blinding.to_le_bytes()[..]);
+        reveal.hash_into(&mut engine);
         let inner = sha256t::Hash::<ConcealedSealTag>::from_engine(engine);
         ConcealedSeal::from_hash(inner)
     }
@@ -454,6 +1115,48 @@ mod test {
         assert_eq!(midstate.into_inner().into_inner(), MIDSTATE_CONCEALED_SEAL);
     }
 
+    #[test]
+    fn network_outpoint_hash_midstate() {
+        let midstate =
+            tagged_hash::Midstate::with(b"bp:txout:concealed:network:v1");
+        assert_eq!(
+            midstate.into_inner().into_inner(),
+            MIDSTATE_NETWORK_CONCEALED_SEAL
+        );
+    }
+
+    #[test]
+    fn network_outpoint_hash() {
+        let reveal = RevealedSeal {
+            method: CloseMethod::TapretFirst,
+            blinding: 54683213134637,
+            txid: Some(Txid::from_hex("646ca5c1062619e2a2d60771c9dfd820551fb773e4dc8c4ed67965a8d1fae839").unwrap()),
+            vout: 2,
+        };
+        let mut engine = sha256t::Hash::<NetworkConcealedSealTag>::engine();
+        engine.input(&[0x0a, 0x03, 0xcf, 0x40]);
+        engine.input(&[reveal.method as u8]);
+        engine.input(&reveal.txid.unwrap()[..]);
+        engine.input(&reveal.vout.to_le_bytes()[..]);
+        engine.input(&reveal.blinding.to_le_bytes()[..]);
+        assert_eq!(
+            reveal.conceal_for(Network::Signet)[..],
+            sha256t::Hash::<NetworkConcealedSealTag>::from_engine(engine)[..]
+        );
+
+        let concealed = [
+            Network::Bitcoin,
+            Network::Testnet,
+            Network::Signet,
+            Network::Regtest,
+        ]
+        .map(|network| reveal.conceal_for(network));
+        for (no, seal) in concealed.iter().enumerate() {
+            assert_ne!(*seal, reveal.to_concealed_seal());
+            assert!(!concealed[no + 1..].contains(seal));
+        }
+    }
+
     #[test]
     fn outpoint_hash_is_sha256d() {
         let reveal = RevealedSeal {
@@ -490,6 +1193,111 @@ mod test {
         assert_eq!(reconstructed, outpoint_hash);
     }
 
+    #[test]
+    #[cfg(feature = "serde")]
+    fn concealed_seal_serde() {
+        use serde::de::value::{BorrowedBytesDeserializer, Error};
+        use serde::Deserialize;
+
+        let outpoint_hash = RevealedSeal {
+            method: CloseMethod::TapretFirst,
+            blinding: 54683213134637,
+            txid: Some(Txid::from_hex("646ca5c1062619e2a2d60771c9dfd820551fb773e4dc8c4ed67965a8d1fae839").unwrap()),
+            vout: 2,
+        }.to_concealed_seal();
+        let json = r#""txob1a9peq6yx9x6ajt584qp5ge4jk9v7tmtgs3x2gntk2nf425cvpdgszt65je""#;
+        assert_eq!(serde_json::to_string(&outpoint_hash).unwrap(), json);
+        assert_eq!(
+            serde_json::from_str::<ConcealedSeal>(json).unwrap(),
+            outpoint_hash
+        );
+        assert_eq!(
+            serde_json::from_slice::<ConcealedSeal>(json.as_bytes()).unwrap(),
+            outpoint_hash
+        );
+
+        // Bytes are parsed in place
+        let bytes = outpoint_hash[..].to_vec();
+        let deserializer = BorrowedBytesDeserializer::<Error>::new(&bytes);
+        assert_eq!(
+            ConcealedSeal::deserialize(deserializer).unwrap(),
+            outpoint_hash
+        );
+        let deserializer =
+            BorrowedBytesDeserializer::<Error>::new(&bytes[..31]);
+        assert!(ConcealedSeal::deserialize(deserializer).is_err());
+    }
+
+    #[test]
+    fn concealed_seal_short_id() {
+        let outpoint_hash = RevealedSeal {
+            method: CloseMethod::TapretFirst,
+            blinding: 54683213134637,
+            txid: Some(Txid::from_hex("646ca5c1062619e2a2d60771c9dfd820551fb773e4dc8c4ed67965a8d1fae839").unwrap()),
+            vout: 2,
+        }.to_concealed_seal();
+        assert_eq!(outpoint_hash.short_id(), 0xd9b52986689043e9);
+        assert_eq!(outpoint_hash.to_short().to_string(), "d9b52986689043e9…");
+        assert_eq!(outpoint_hash.prefix(2), Some(&[0xe9u8, 0x43][..]));
+        assert_eq!(outpoint_hash.prefix(32), Some(&outpoint_hash[..]));
+        assert_eq!(outpoint_hash.prefix(33), None);
+    }
+
+    #[test]
+    fn concealed_seal_zero() {
+        assert!(ConcealedSeal::zero().is_zero());
+        assert_eq!(ConcealedSeal::zero()[..], [0u8; 32]);
+        let outpoint_hash = RevealedSeal {
+            method: CloseMethod::TapretFirst,
+            blinding: 54683213134637,
+            txid: Some(Txid::from_hex("646ca5c1062619e2a2d60771c9dfd820551fb773e4dc8c4ed67965a8d1fae839").unwrap()),
+            vout: 2,
+        }.to_concealed_seal();
+        assert!(!outpoint_hash.is_zero());
+    }
+
+    #[test]
+    fn versioned_reveal_legacy() {
+        let reveal = RevealedSeal {
+            method: CloseMethod::TapretFirst,
+            blinding: 54683213134637,
+            txid: Some(Txid::from_hex("646ca5c1062619e2a2d60771c9dfd820551fb773e4dc8c4ed67965a8d1fae839").unwrap()),
+            vout: 2,
+        };
+        let concealed = reveal.to_concealed_seal();
+        let versioned = reveal.upgrade();
+
+        assert_eq!(versioned.as_legacy(), Some(&reveal));
+        assert_eq!(versioned.conceal_legacy(), Some(concealed));
+        assert_eq!(versioned.to_concealed_seal(), concealed);
+        assert_eq!(
+            versioned.to_concealed_seal().to_string(),
+            "txob1a9peq6yx9x6ajt584qp5ge4jk9v7tmtgs3x2gntk2nf425cvpdgszt65je"
+        );
+
+        let s = versioned.to_string();
+        assert_eq!(s, reveal.to_string());
+        assert_eq!(VersionedReveal::from_str(&s).unwrap(), versioned);
+
+        let data = strict_encoding::strict_serialize(&versioned).unwrap();
+        assert_eq!(data[0], 0x00);
+        assert_eq!(
+            &data[1..],
+            &strict_encoding::strict_serialize(&reveal).unwrap()[..]
+        );
+        let decoded: VersionedReveal =
+            strict_encoding::strict_deserialize(&data).unwrap();
+        assert_eq!(decoded, versioned);
+        assert_eq!(decoded.conceal_legacy(), Some(concealed));
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&versioned).unwrap();
+            let decoded: VersionedReveal = serde_json::from_str(&json).unwrap();
+            assert_eq!(decoded, versioned);
+        }
+    }
+
     #[test]
     fn outpoint_reveal_str() {
         let mut outpoint_reveal = RevealedSeal {
@@ -585,4 +1393,163 @@ mod test {
             Err(ParseError::MethodRequired)
         );
     }
+
+    #[test]
+    fn checked_construction() {
+        let txid = Txid::from_hex(
+            "646ca5c1062619e2a2d60771c9dfd820551fb773e4dc8c4ed67965a8d1fae839",
+        )
+        .unwrap();
+        let seal = RevealedSeal {
+            method: CloseMethod::OpretFirst,
+            txid: Some(txid),
+            vout: 2,
+            blinding: 0x78ca95,
+        };
+        assert_eq!(
+            RevealedSeal::checked(
+                CloseMethod::OpretFirst,
+                Some(txid),
+                2i32,
+                0x78ca95
+            ),
+            Ok(seal)
+        );
+        assert_eq!(
+            RevealedSeal::checked(CloseMethod::OpretFirst, None, 0u32, 1)
+                .unwrap()
+                .txid,
+            None
+        );
+
+        assert_eq!(
+            RevealedSeal::checked(CloseMethod::OpretFirst, Some(txid), -1, 1),
+            Err(SealConstructError::NegativeVout(-1))
+        );
+        assert_eq!(
+            RevealedSeal::checked(
+                CloseMethod::OpretFirst,
+                Some(txid),
+                u32::MAX,
+                1
+            ),
+            Err(SealConstructError::VoutOutOfRange(
+                u32::MAX as i64,
+                MAX_TX_OUTPUTS - 1
+            ))
+        );
+        assert_eq!(
+            RevealedSeal::checked(
+                CloseMethod::OpretFirst,
+                Some(Txid::all_zeros()),
+                0,
+                1
+            ),
+            Err(SealConstructError::ZeroTxid)
+        );
+        assert_eq!(
+            RevealedSeal::checked(CloseMethod::OpretFirst, Some(txid), 0, 0),
+            Err(SealConstructError::ZeroBlinding)
+        );
+
+        let constraints = SealConstraints {
+            max_vout: 10,
+            allow_zero_blinding: true,
+        };
+        assert!(RevealedSeal::checked_with(
+            CloseMethod::OpretFirst,
+            Some(txid),
+            10,
+            0,
+            &constraints
+        )
+        .is_ok());
+        assert_eq!(
+            RevealedSeal::checked_with(
+                CloseMethod::OpretFirst,
+                Some(txid),
+                11,
+                0,
+                &constraints
+            ),
+            Err(SealConstructError::VoutOutOfRange(11, 10))
+        );
+
+        // String parsing applies the default constraints
+        assert_eq!(
+            RevealedSeal::from_str(
+                "opret1st:646ca5c1062619e2a2d60771c9dfd820551fb773e4dc8c4ed67965a8d1fae839:5#0x0",
+            ),
+            Err(ParseError::Constraint(SealConstructError::ZeroBlinding))
+        );
+        assert_eq!(
+            RevealedSeal::from_str("opret1st:~:4294967295#0x78ca95"),
+            Err(ParseError::Constraint(SealConstructError::VoutOutOfRange(
+                u32::MAX as i64,
+                MAX_TX_OUTPUTS - 1
+            )))
+        );
+        assert_eq!(
+            RevealedSeal::from_str(
+                "opret1st:0000000000000000000000000000000000000000000000000000000000000000:5#0x78ca95",
+            ),
+            Err(ParseError::Constraint(SealConstructError::ZeroTxid))
+        );
+        let max_vout = format!("opret1st:~:{}#0x78ca95", MAX_TX_OUTPUTS - 1);
+        assert_eq!(
+            RevealedSeal::from_str(&max_vout).unwrap().vout,
+            MAX_TX_OUTPUTS - 1
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn random_blinding_non_zero() {
+        use dbc::test_utils::TestRng;
+
+        let seal = RevealedSeal::with(
+            CloseMethod::OpretFirst,
+            None,
+            0,
+            &mut TestRng::with_values([0, 1]),
+        )
+        .unwrap();
+        assert_eq!(seal.blinding, 1);
+    }
+
+    #[test]
+    #[cfg(all(feature = "rand", not(feature = "deterministic")))]
+    fn random_blinding_checked() {
+        let txid = Txid::hash(b"seal");
+        let err = SealConstructError::VoutOutOfRange(
+            MAX_TX_OUTPUTS as i64,
+            MAX_TX_OUTPUTS - 1,
+        );
+        let outpoint = OutPoint::new(txid, MAX_TX_OUTPUTS);
+        assert_eq!(
+            RevealedSeal::new(CloseMethod::OpretFirst, outpoint),
+            Err(err)
+        );
+        assert_eq!(RevealedSeal::try_from(outpoint), Err(err));
+        assert_eq!(ConcealedSeal::try_from(outpoint), Err(err));
+        let seal = ExplicitSeal {
+            method: CloseMethod::OpretFirst,
+            txid: Some(txid),
+            vout: MAX_TX_OUTPUTS,
+        };
+        assert_eq!(RevealedSeal::try_from(seal), Err(err));
+        assert_eq!(
+            RevealedSeal::new(
+                CloseMethod::OpretFirst,
+                OutPoint::new(Txid::all_zeros(), 1)
+            ),
+            Err(SealConstructError::ZeroTxid)
+        );
+
+        let outpoint = OutPoint::new(txid, MAX_TX_OUTPUTS - 1);
+        assert_eq!(
+            RevealedSeal::try_from(outpoint).unwrap().vout,
+            MAX_TX_OUTPUTS - 1
+        );
+    }
 }
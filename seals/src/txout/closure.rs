@@ -0,0 +1,932 @@
+// BP Core Library implementing LNP/BP specifications & standards related to
+// bitcoin protocol
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Self-contained proofs of TxOut seal closing, suitable for archival.
+
+use std::fmt::{self, Display, Formatter};
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use bitcoin::consensus::{serialize, Decodable, Encodable};
+use bitcoin::hashes::{sha256, sha256t};
+use bitcoin::{MerkleBlock, Txid};
+use bitcoin_onchain::ResolveTx;
+use commit_verify::lnpbp4::{self, Message, ProtocolId};
+use commit_verify::{
+    CommitEncode, CommitVerify, ConsensusCommit, PrehashedProtocol, TaggedHash,
+};
+use dbc::anchor::OfflineReport;
+use dbc::policy::ValidationPolicy;
+use dbc::{Anchor, ProofExtractError};
+use lnpbp_bech32::{FromBech32Str, ToBech32String};
+use strict_encoding::{StrictDecode, StrictEncode};
+
+use super::blind::{ConcealedSeal, RevealedSeal};
+use super::{TxoSeal, VerifyError};
+
+static MIDSTATE_CLOSURE_ID: [u8; 32] = [
+    87, 149, 81, 64, 1, 40, 38, 42, 145, 96, 61, 180, 2, 211, 238, 160, 129,
+    113, 97, 192, 57, 92, 57, 211, 198, 129, 219, 147, 34, 17, 162, 103,
+];
+
+/// Tag used for [`ClosureId`] hash type
+pub struct ClosureIdTag;
+
+impl sha256t::Tag for ClosureIdTag {
+    #[inline]
+    fn engine() -> sha256::HashEngine {
+        let midstate = sha256::Midstate::from_inner(MIDSTATE_CLOSURE_ID);
+        sha256::HashEngine::from_midstate(midstate, 64)
+    }
+}
+
+/// Unique identifier of the seal closing fact, used for deduplication of
+/// [`ClosureProof`]s.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", transparent)
+)]
+#[derive(Wrapper, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, From)]
+#[wrapper(
+    Debug, Display, LowerHex, Index, IndexRange, IndexFrom, IndexTo, IndexFull
+)]
+pub struct ClosureId(sha256t::Hash<ClosureIdTag>);
+
+impl<Msg> CommitVerify<Msg, PrehashedProtocol> for ClosureId
+where
+    Msg: AsRef<[u8]>,
+{
+    #[inline]
+    fn commit(msg: &Msg) -> ClosureId { ClosureId::hash(msg) }
+}
+
+impl strict_encoding::Strategy for ClosureId {
+    type Strategy = strict_encoding::strategies::Wrapped;
+}
+
+/// Seal closed by the witness transaction, which may be kept either in
+/// revealed or in concealed form.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, From)]
+#[derive(StrictEncode, StrictDecode)]
+#[strict_encoding(by_order)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+pub enum ClosedSeal {
+    /// Revealed seal definition, allowing to check that the witness
+    /// transaction spends the seal outpoint.
+    #[from]
+    Revealed(RevealedSeal),
+
+    /// Concealed seal definition; the proof does not disclose which outpoint
+    /// was spent.
+    #[from]
+    Concealed(ConcealedSeal),
+}
+
+impl ClosedSeal {
+    /// Returns concealed form of the seal.
+    ///
+    /// ```
+    /// # use seals::test_utils::seal_fixture;
+    /// # use seals::txout::ClosedSeal;
+    /// let seal = seal_fixture();
+    ///
+    /// assert_eq!(
+    ///     ClosedSeal::from(seal).to_concealed_seal(),
+    ///     seal.to_concealed_seal()
+    /// );
+    /// assert_eq!(
+    ///     ClosedSeal::from(seal.to_concealed_seal()).to_concealed_seal(),
+    ///     seal.to_concealed_seal()
+    /// );
+    /// ```
+    #[inline]
+    pub fn to_concealed_seal(&self) -> ConcealedSeal {
+        match self {
+            ClosedSeal::Revealed(seal) => seal.to_concealed_seal(),
+            ClosedSeal::Concealed(seal) => *seal,
+        }
+    }
+
+    /// Returns revealed seal definition, if known.
+    ///
+    /// ```
+    /// # use seals::test_utils::seal_fixture;
+    /// # use seals::txout::ClosedSeal;
+    /// let seal = seal_fixture();
+    ///
+    /// assert_eq!(ClosedSeal::from(seal).as_revealed(), Some(&seal));
+    /// assert_eq!(
+    ///     ClosedSeal::from(seal.to_concealed_seal()).as_revealed(),
+    ///     None
+    /// );
+    /// ```
+    #[inline]
+    pub fn as_revealed(&self) -> Option<&RevealedSeal> {
+        match self {
+            ClosedSeal::Revealed(seal) => Some(seal),
+            ClosedSeal::Concealed(_) => None,
+        }
+    }
+}
+
+/// SPV proof of the witness transaction inclusion into a block.
+///
+/// Proofs are compared by their consensus encoding, since partial merkle tree
+/// flag bits are padded to a full byte once decoded.
+#[derive(Clone, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+pub struct SpvProof {
+    /// Height of the block containing witness transaction.
+    pub height: u32,
+
+    /// Block header and partial merkle tree proving inclusion of the witness
+    /// transaction.
+    #[cfg_attr(feature = "serde", serde(with = "serde_merkle_block"))]
+    pub merkle_block: MerkleBlock,
+}
+
+impl SpvProof {
+    /// Checks that the partial merkle tree matches block header merkle root
+    /// and includes the transaction with the given `txid`.
+    ///
+    /// NB: the check does not validate block header against the blockchain;
+    /// this has to be done by the caller using block [`SpvProof::height`].
+    ///
+    /// ```
+    /// use bitcoin::blockdata::constants::genesis_block;
+    /// use bitcoin::{Block, MerkleBlock, Network};
+    /// use dbc::test_utils::opret_fixture;
+    /// use seals::txout::SpvProof;
+    ///
+    /// let (_, tx) = opret_fixture();
+    /// let genesis = genesis_block(Network::Regtest);
+    /// let mut block = Block {
+    ///     header: genesis.header,
+    ///     txdata: vec![genesis.txdata[0].clone(), tx.clone()],
+    /// };
+    /// block.header.merkle_root = block.compute_merkle_root().unwrap();
+    ///
+    /// let txid = tx.txid();
+    /// let spv = SpvProof {
+    ///     height: 1,
+    ///     merkle_block: MerkleBlock::from_block_with_predicate(&block, |id| {
+    ///         *id == txid
+    ///     }),
+    /// };
+    /// assert!(spv.verify(txid));
+    /// assert!(!spv.verify(genesis.txdata[0].txid()));
+    /// ```
+    pub fn verify(&self, txid: Txid) -> bool {
+        let mut matches = vec![];
+        let mut indexes = vec![];
+        self.merkle_block
+            .extract_matches(&mut matches, &mut indexes)
+            .is_ok()
+            && matches.contains(&txid)
+    }
+}
+
+impl PartialEq for SpvProof {
+    fn eq(&self, other: &Self) -> bool {
+        self.height == other.height
+            && serialize(&self.merkle_block) == serialize(&other.merkle_block)
+    }
+}
+
+impl Eq for SpvProof {}
+
+impl StrictEncode for SpvProof {
+    fn strict_encode<E: Write>(
+        &self,
+        mut e: E,
+    ) -> Result<usize, strict_encoding::Error> {
+        let len = self.height.strict_encode(&mut e)?;
+        Ok(len + self.merkle_block.consensus_encode(&mut e)?)
+    }
+}
+
+impl StrictDecode for SpvProof {
+    fn strict_decode<D: Read>(
+        mut d: D,
+    ) -> Result<Self, strict_encoding::Error> {
+        Ok(SpvProof {
+            height: u32::strict_decode(&mut d)?,
+            merkle_block: MerkleBlock::consensus_decode(&mut d)?,
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_merkle_block {
+    use bitcoin::consensus::encode::{self, serialize_hex};
+    use bitcoin::hashes::hex::FromHex;
+    use bitcoin::MerkleBlock;
+    use serde::de::{Error, Visitor};
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S>(
+        merkle_block: &MerkleBlock,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&serialize_hex(merkle_block))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<MerkleBlock, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct HexVisitor;
+        impl Visitor<'_> for HexVisitor {
+            type Value = MerkleBlock;
+
+            fn expecting(
+                &self,
+                formatter: &mut std::fmt::Formatter,
+            ) -> std::fmt::Result {
+                formatter.write_str("hex-encoded bitcoin merkle block")
+            }
+
+            fn visit_str<E: Error>(self, v: &str) -> Result<Self::Value, E> {
+                let data = Vec::<u8>::from_hex(v).map_err(E::custom)?;
+                encode::deserialize(&data).map_err(E::custom)
+            }
+        }
+
+        // The hex string is decoded in place, without copying it into an
+        // owned buffer
+        deserializer.deserialize_str(HexVisitor)
+    }
+}
+
+/// Result of [`ClosureProof`] verification against the witness transaction.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display)]
+#[display(doc_comments)]
+pub enum ClosureStatus {
+    /// witness transaction spends the seal outpoint and commits to the
+    /// message.
+    Closed,
+
+    /// witness transaction commits to the message, but the seal is concealed,
+    /// so it is unknown whether the witness transaction spends it.
+    SealUnchecked,
+
+    /// witness transaction does not commit to the message.
+    NotCommitted,
+}
+
+impl ClosureStatus {
+    /// Detects whether the seal is proven to be closed over the message.
+    /// Returns `false` for [`ClosureStatus::SealUnchecked`], which requires
+    /// the seal to be checked against the witness transaction by the caller.
+    #[inline]
+    pub fn is_closed(self) -> bool { self == ClosureStatus::Closed }
+
+    /// Detects whether the witness transaction commits to the message,
+    /// independently of the seal being checked.
+    #[inline]
+    pub fn is_committed(self) -> bool { self != ClosureStatus::NotCommitted }
+}
+
+/// Self-contained proof that a seal was closed by a specific witness
+/// transaction over a message under a given LNPBP-4 protocol.
+///
+/// The proof allows applications to persist the fact of the seal closing
+/// after the validation, without keeping the rest of the client-side data.
+/// It can be re-verified later either against the blockchain with
+/// [`ClosureProof::verify`], or without access to it with
+/// [`ClosureProof::verify_offline`].
+///
+/// String representation of the proof is a Bech32m-encoded strict
+/// serialization with `closure` HRP, which can be used for ASCII armoring.
+#[derive(Clone, PartialEq, Eq, Debug, StrictEncode, StrictDecode)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+pub struct ClosureProof {
+    /// Seal closed by the witness transaction.
+    pub seal: ClosedSeal,
+
+    /// Id of the witness transaction closing the seal.
+    pub witness_txid: Txid,
+
+    /// Anchor proving commitment of the witness transaction to the message.
+    pub anchor: Anchor<lnpbp4::MerkleProof>,
+
+    /// LNPBP-4 protocol under which the message is committed.
+    pub protocol_id: ProtocolId,
+
+    /// Message the seal was closed over.
+    pub message: Message,
+
+    /// Optional SPV proof of the witness transaction mining.
+    pub spv: Option<SpvProof>,
+}
+
+impl CommitEncode for ClosureProof {
+    fn commit_encode<E: Write>(&self, mut e: E) -> usize {
+        let mut len = self
+            .seal
+            .to_concealed_seal()
+            .strict_encode(&mut e)
+            .expect("memory encoders do not fail");
+        len += self
+            .witness_txid
+            .strict_encode(&mut e)
+            .expect("memory encoders do not fail");
+        len += self
+            .anchor
+            .strict_encode(&mut e)
+            .expect("memory encoders do not fail");
+        len += self
+            .protocol_id
+            .strict_encode(&mut e)
+            .expect("memory encoders do not fail");
+        len + self
+            .message
+            .strict_encode(&mut e)
+            .expect("memory encoders do not fail")
+    }
+}
+
+impl ConsensusCommit for ClosureProof {
+    type Commitment = ClosureId;
+}
+
+impl ClosureProof {
+    /// Constructs proof of the `seal` closing over the `message` under
+    /// `protocol_id` from the anchor of the witness transaction, without SPV
+    /// proof.
+    ///
+    /// Errors if the anchor does not contain the message for the protocol.
+    ///
+    /// ```
+    /// use dbc::test_utils::{protocol_fixture, FIXTURE_PROTOCOLS};
+    /// use seals::test_utils::closure_fixture;
+    /// use seals::txout::ClosureProof;
+    ///
+    /// // Witness transaction spending the seal outpoint
+    /// let (seal, anchor, tx) = closure_fixture();
+    ///
+    /// let (protocol_id, message) = protocol_fixture(1);
+    /// let proof =
+    ///     ClosureProof::with(seal, &anchor, protocol_id, message).unwrap();
+    /// assert_eq!(proof.witness_txid, tx.txid());
+    /// assert_eq!(proof.spv, None);
+    ///
+    /// // Protocol not committed by the anchor
+    /// let (unknown, _) = protocol_fixture(FIXTURE_PROTOCOLS);
+    /// assert!(ClosureProof::with(seal, &anchor, unknown, message).is_err());
+    /// ```
+    pub fn with(
+        seal: impl Into<ClosedSeal>,
+        anchor: &Anchor<lnpbp4::MerkleBlock>,
+        protocol_id: ProtocolId,
+        message: Message,
+    ) -> Result<ClosureProof, ProofExtractError> {
+        Ok(ClosureProof {
+            seal: seal.into(),
+            witness_txid: anchor.txid(),
+            anchor: anchor.to_merkle_proof(protocol_id)?,
+            protocol_id,
+            message,
+            spv: None,
+        })
+    }
+
+    /// Returns id of the seal closing fact.
+    ///
+    /// The id commits to the concealed form of the seal and does not commit
+    /// to the SPV proof, such that the same closing fact always has the same
+    /// id independently of the seal being revealed and SPV proof presence.
+    ///
+    /// ```
+    /// # use dbc::test_utils::protocol_fixture;
+    /// # use seals::test_utils::closure_fixture;
+    /// # use seals::txout::ClosureProof;
+    /// # let (seal, anchor, tx) = closure_fixture();
+    /// # let (protocol_id, message) = protocol_fixture(1);
+    /// let proof =
+    ///     ClosureProof::with(seal, &anchor, protocol_id, message).unwrap();
+    ///
+    /// let concealed = ClosureProof {
+    ///     seal: seal.to_concealed_seal().into(),
+    ///     ..proof.clone()
+    /// };
+    /// assert_eq!(concealed.closure_id(), proof.closure_id());
+    ///
+    /// let (_, other_message) = protocol_fixture(2);
+    /// let other = ClosureProof {
+    ///     message: other_message,
+    ///     ..proof.clone()
+    /// };
+    /// assert_ne!(other.closure_id(), proof.closure_id());
+    /// ```
+    #[inline]
+    pub fn closure_id(&self) -> ClosureId { self.clone().consensus_commit() }
+
+    /// Verifies the proof against the witness transaction retrieved with the
+    /// provided `resolver`.
+    ///
+    /// If the seal is revealed, checks that the witness transaction spends
+    /// the seal outpoint. For concealed seals only the commitment to the
+    /// message is checked, which is reported with
+    /// [`ClosureStatus::SealUnchecked`].
+    ///
+    /// ```
+    /// # use std::collections::BTreeMap;
+    /// # use dbc::test_utils::protocol_fixture;
+    /// # use seals::test_utils::closure_fixture;
+    /// # use seals::txout::{ClosureProof, ClosureStatus};
+    /// # let (seal, anchor, tx) = closure_fixture();
+    /// # let (protocol_id, message) = protocol_fixture(1);
+    /// let proof =
+    ///     ClosureProof::with(seal, &anchor, protocol_id, message).unwrap();
+    ///
+    /// let resolver = BTreeMap::from([(tx.txid(), tx)]);
+    /// assert_eq!(proof.verify(&resolver).unwrap(), ClosureStatus::Closed);
+    ///
+    /// // Concealed seals are checked only for the commitment to the message
+    /// let concealed = ClosureProof {
+    ///     seal: seal.to_concealed_seal().into(),
+    ///     ..proof.clone()
+    /// };
+    /// assert_eq!(
+    ///     concealed.verify(&resolver).unwrap(),
+    ///     ClosureStatus::SealUnchecked
+    /// );
+    /// ```
+    pub fn verify(
+        &self,
+        resolver: &impl ResolveTx,
+    ) -> Result<ClosureStatus, VerifyError> {
+        self.verify_with_policy(resolver, &ValidationPolicy::default(), 0)
+    }
+
+    /// Verifies the proof with [`ClosureProof::verify`] after checking the
+    /// anchor and the witness transaction against the validation `policy`.
+    ///
+    /// If the policy requires some number of confirmations, the number is
+    /// computed from the SPV proof and the current blockchain `tip_height`;
+    /// proofs without SPV proof are considered unconfirmed.
+    ///
+    /// ```
+    /// # use std::collections::BTreeMap;
+    /// # use dbc::test_utils::protocol_fixture;
+    /// # use seals::test_utils::closure_fixture;
+    /// # use seals::txout::ClosureProof;
+    /// use dbc::policy::{PolicyError, ValidationPolicy};
+    /// use seals::txout::VerifyError;
+    ///
+    /// # let (seal, anchor, tx) = closure_fixture();
+    /// # let (protocol_id, message) = protocol_fixture(1);
+    /// let proof =
+    ///     ClosureProof::with(seal, &anchor, protocol_id, message).unwrap();
+    /// let resolver = BTreeMap::from([(tx.txid(), tx)]);
+    ///
+    /// let policy = ValidationPolicy {
+    ///     min_confirmations: Some(1),
+    ///     ..Default::default()
+    /// };
+    /// assert!(matches!(
+    ///     proof.verify_with_policy(&resolver, &policy, 800_000),
+    ///     Err(VerifyError::Policy(
+    ///         PolicyError::InsufficientConfirmations {
+    ///             confirmations: 0,
+    ///             min: 1
+    ///         }
+    ///     ))
+    /// ));
+    /// ```
+    pub fn verify_with_policy(
+        &self,
+        resolver: &impl ResolveTx,
+        policy: &ValidationPolicy,
+        tip_height: u32,
+    ) -> Result<ClosureStatus, VerifyError> {
+        let res = self.check(resolver, policy, tip_height);
+        #[cfg(feature = "metrics")]
+        dbc::metrics::record_seal(
+            (&res.as_ref().map(|status| status.is_committed())).into(),
+        );
+        res
+    }
+
+    fn check(
+        &self,
+        resolver: &impl ResolveTx,
+        policy: &ValidationPolicy,
+        tip_height: u32,
+    ) -> Result<ClosureStatus, VerifyError> {
+        self.verify_consistency()?;
+        if policy.min_confirmations.is_some() {
+            let confirmations = match &self.spv {
+                Some(spv) if !spv.verify(self.witness_txid) => {
+                    return Err(VerifyError::InvalidSpvProof(self.witness_txid))
+                }
+                Some(spv) if tip_height >= spv.height => {
+                    tip_height - spv.height + 1
+                }
+                _ => 0,
+            };
+            policy.check_confirmations(confirmations)?;
+        }
+        policy.check_anchor(&self.anchor)?;
+        let tx = resolver.resolve_tx(self.witness_txid)?;
+        policy.check_tx(&tx)?;
+        if let Some(reference_time) = policy.reference_time {
+            reference_time.check_final(&tx)?;
+        }
+
+        let status = match self.seal {
+            ClosedSeal::Revealed(seal) => {
+                let outpoint = seal.outpoint_or(self.witness_txid);
+                if !tx.input.iter().any(|txin| txin.previous_output == outpoint)
+                {
+                    return Err(VerifyError::WitnessNotClosingSeal(
+                        self.witness_txid,
+                        outpoint,
+                    ));
+                }
+                ClosureStatus::Closed
+            }
+            ClosedSeal::Concealed(_) => ClosureStatus::SealUnchecked,
+        };
+
+        if !self.anchor.verify(self.protocol_id, self.message, &tx)? {
+            return Ok(ClosureStatus::NotCommitted);
+        }
+        Ok(status)
+    }
+
+    /// Performs all verification steps not requiring access to the witness
+    /// transaction, including SPV proof check, if the proof is present.
+    ///
+    /// Returns [`OfflineReport`] listing checks which must be completed
+    /// against the witness transaction. NB: checking that the witness
+    /// transaction spends the seal outpoint is not a part of the report.
+    ///
+    /// ```
+    /// # use dbc::test_utils::protocol_fixture;
+    /// # use seals::test_utils::closure_fixture;
+    /// # use seals::txout::ClosureProof;
+    /// # let (seal, anchor, tx) = closure_fixture();
+    /// # let (protocol_id, message) = protocol_fixture(1);
+    /// let proof =
+    ///     ClosureProof::with(seal, &anchor, protocol_id, message).unwrap();
+    ///
+    /// let report = proof.verify_offline().unwrap();
+    /// assert!(report.verify_witness(&tx));
+    /// ```
+    pub fn verify_offline(&self) -> Result<OfflineReport, VerifyError> {
+        self.verify_consistency()?;
+        if let Some(spv) = &self.spv {
+            if !spv.verify(self.witness_txid) {
+                return Err(VerifyError::InvalidSpvProof(self.witness_txid));
+            }
+        }
+        self.anchor
+            .verify_offline(self.protocol_id, self.message)
+            .map_err(VerifyError::from)
+    }
+
+    fn verify_consistency(&self) -> Result<(), VerifyError> {
+        if self.anchor.txid() != self.witness_txid {
+            return Err(VerifyError::AnchorTxidMismatch(
+                self.anchor.txid(),
+                self.witness_txid,
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl lnpbp_bech32::Strategy for ClosureProof {
+    const HRP: &'static str = "closure";
+    type Strategy = lnpbp_bech32::strategies::UsingStrictEncoding;
+}
+
+impl FromStr for ClosureProof {
+    type Err = lnpbp_bech32::Error;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ClosureProof::from_bech32_str(s)
+    }
+}
+
+impl Display for ClosureProof {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_bech32_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use amplify::Wrapper;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{
+        Block, Network, PackedLockTime, Script, Sequence, Transaction, TxIn,
+        TxOut, Witness,
+    };
+    use bitcoin_onchain::TxResolverError;
+    use commit_verify::tagged_hash;
+    use dbc::opret::opret_script;
+    use dbc::policy::PolicyError;
+    use dbc::test_utils::{lnpbp4_fixture, protocol_fixture};
+    use dbc::{Proof, ReferenceTime};
+
+    use super::*;
+    use crate::test_utils::seal_fixture;
+
+    struct Resolver(BTreeMap<Txid, Transaction>);
+
+    impl ResolveTx for Resolver {
+        fn resolve_tx(
+            &self,
+            txid: Txid,
+        ) -> Result<Transaction, TxResolverError> {
+            self.0
+                .get(&txid)
+                .cloned()
+                .ok_or_else(|| TxResolverError::with(txid))
+        }
+    }
+
+    fn closure_fixture() -> (ClosureProof, Transaction) {
+        let (protocol_id, message) = protocol_fixture(1);
+        let tree = lnpbp4_fixture();
+        let commitment = tree.consensus_commit();
+        let seal = seal_fixture();
+        let tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: seal.outpoint().unwrap(),
+                script_sig: Script::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: 0,
+                script_pubkey: opret_script(commitment.as_slice()).unwrap(),
+            }],
+        };
+        let anchor = Anchor::from_parts(
+            tx.txid(),
+            lnpbp4::MerkleBlock::from(tree),
+            Proof::OpretFirst,
+        );
+        let proof = ClosureProof {
+            seal: seal.into(),
+            witness_txid: tx.txid(),
+            anchor: anchor.to_merkle_proof(protocol_id).unwrap(),
+            protocol_id,
+            message,
+            spv: None,
+        };
+        (proof, tx)
+    }
+
+    fn spv_fixture(tx: &Transaction) -> SpvProof {
+        let mut block = Block {
+            header: genesis_block(Network::Regtest).header,
+            txdata: vec![
+                genesis_block(Network::Regtest).txdata[0].clone(),
+                tx.clone(),
+            ],
+        };
+        block.header.merkle_root = block.compute_merkle_root().unwrap();
+        let txid = tx.txid();
+        SpvProof {
+            height: 1,
+            merkle_block: MerkleBlock::from_block_with_predicate(
+                &block,
+                |id| *id == txid,
+            ),
+        }
+    }
+
+    #[test]
+    fn closure_id_midstate() {
+        let midstate = tagged_hash::Midstate::with(b"bp:txout:closure");
+        assert_eq!(midstate.into_inner().into_inner(), MIDSTATE_CLOSURE_ID);
+    }
+
+    #[test]
+    fn closure_proof_verify() {
+        let (mut proof, tx) = closure_fixture();
+        let resolver = Resolver(bmap! { tx.txid() => tx.clone() });
+
+        assert_eq!(proof.verify(&resolver).unwrap(), ClosureStatus::Closed);
+        assert!(proof.verify_offline().unwrap().verify_witness(&tx));
+
+        proof.spv = Some(spv_fixture(&tx));
+        assert_eq!(proof.verify(&resolver).unwrap(), ClosureStatus::Closed);
+        assert!(proof.verify_offline().unwrap().verify_witness(&tx));
+
+        let concealed = ClosureProof {
+            seal: proof.seal.to_concealed_seal().into(),
+            ..proof.clone()
+        };
+        let status = concealed.verify(&resolver).unwrap();
+        assert_eq!(status, ClosureStatus::SealUnchecked);
+        assert!(!status.is_closed());
+        assert!(status.is_committed());
+        assert_eq!(concealed.closure_id(), proof.closure_id());
+
+        let (_, other_message) = protocol_fixture(2);
+        let wrong = ClosureProof {
+            message: other_message,
+            ..proof.clone()
+        };
+        assert!(wrong.closure_id() != proof.closure_id());
+        assert!(!wrong
+            .verify(&resolver)
+            .map(ClosureStatus::is_committed)
+            .unwrap_or(false));
+
+        let mut wrong = proof.clone();
+        wrong.seal = RevealedSeal {
+            vout: 0,
+            ..seal_fixture()
+        }
+        .into();
+        assert!(matches!(
+            wrong.verify(&resolver),
+            Err(VerifyError::WitnessNotClosingSeal(..))
+        ));
+
+        let mut wrong = proof.clone();
+        wrong.spv = Some(spv_fixture(&Transaction {
+            lock_time: PackedLockTime(1),
+            ..tx.clone()
+        }));
+        assert!(matches!(
+            wrong.verify_offline(),
+            Err(VerifyError::InvalidSpvProof(_))
+        ));
+
+        let mut wrong = proof;
+        wrong.witness_txid = Txid::hash(b"other");
+        assert!(matches!(
+            wrong.verify_offline(),
+            Err(VerifyError::AnchorTxidMismatch(..))
+        ));
+        assert!(matches!(
+            wrong.verify(&resolver),
+            Err(VerifyError::AnchorTxidMismatch(..))
+        ));
+    }
+
+    #[test]
+    fn closure_proof_policy() {
+        let (mut proof, tx) = closure_fixture();
+        let resolver = Resolver(bmap! { tx.txid() => tx.clone() });
+        let policy = ValidationPolicy {
+            max_tx_weight: Some(tx.weight()),
+            max_anchor_leaves: Some(proof.anchor.lnpbp4_proof().width()),
+            max_tapret_depth: Some(0),
+            min_confirmations: Some(1),
+            max_tx_outputs: Some(tx.output.len()),
+            reference_time: Some(ReferenceTime::after_tip(1, 0)),
+        };
+        assert!(matches!(
+            proof.verify_with_policy(&resolver, &policy, 1),
+            Err(VerifyError::Policy(
+                PolicyError::InsufficientConfirmations {
+                    confirmations: 0,
+                    min: 1
+                }
+            ))
+        ));
+
+        proof.spv = Some(spv_fixture(&tx));
+        assert!(proof
+            .verify_with_policy(&resolver, &policy, 1)
+            .unwrap()
+            .is_closed());
+        assert!(matches!(
+            proof.verify_with_policy(
+                &resolver,
+                &ValidationPolicy {
+                    min_confirmations: Some(7),
+                    ..policy
+                },
+                6
+            ),
+            Err(VerifyError::Policy(
+                PolicyError::InsufficientConfirmations {
+                    confirmations: 6,
+                    min: 7
+                }
+            ))
+        ));
+        assert!(matches!(
+            proof.verify_with_policy(
+                &resolver,
+                &ValidationPolicy {
+                    max_tx_weight: Some(tx.weight() - 1),
+                    ..policy
+                },
+                1
+            ),
+            Err(VerifyError::Policy(PolicyError::TxWeightExceeded { .. }))
+        ));
+        assert!(matches!(
+            proof.verify_with_policy(
+                &resolver,
+                &ValidationPolicy {
+                    max_anchor_leaves: Some(1),
+                    ..policy
+                },
+                1
+            ),
+            Err(VerifyError::Policy(PolicyError::AnchorLeavesExceeded {
+                max: 1,
+                ..
+            }))
+        ));
+
+        let mut wrong = proof;
+        wrong.spv = Some(spv_fixture(&Transaction {
+            lock_time: PackedLockTime(1),
+            ..tx
+        }));
+        assert!(matches!(
+            wrong.verify_with_policy(&resolver, &policy, 1),
+            Err(VerifyError::InvalidSpvProof(_))
+        ));
+        assert!(wrong.verify(&resolver).unwrap().is_closed());
+    }
+
+    #[test]
+    fn closure_proof_encoding() {
+        let (mut proof, tx) = closure_fixture();
+        proof.spv = Some(spv_fixture(&tx));
+
+        let data = strict_encoding::strict_serialize(&proof).unwrap();
+        assert_eq!(
+            strict_encoding::strict_deserialize::<ClosureProof>(&data).unwrap(),
+            proof
+        );
+
+        let armor = proof.to_string();
+        assert!(armor.starts_with("closure1"));
+        assert_eq!(ClosureProof::from_str(&armor).unwrap(), proof);
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&proof).unwrap();
+            assert_eq!(
+                serde_json::from_str::<ClosureProof>(&json).unwrap(),
+                proof
+            );
+
+            let spv = proof.spv.as_ref().unwrap();
+            let mut value = serde_json::to_value(&proof).unwrap();
+            assert_eq!(
+                value["spv"]["merkle_block"],
+                bitcoin::consensus::encode::serialize_hex(&spv.merkle_block)
+            );
+            value["spv"]["merkle_block"] = "00zz".into();
+            assert!(serde_json::from_value::<ClosureProof>(value).is_err());
+        }
+
+        let concealed = ClosureProof {
+            seal: proof.seal.to_concealed_seal().into(),
+            spv: None,
+            ..proof
+        };
+        let armor = concealed.to_string();
+        assert_eq!(ClosureProof::from_str(&armor).unwrap(), concealed);
+    }
+}
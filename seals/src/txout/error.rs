@@ -13,33 +13,112 @@
 // along with this software.
 // If not, see <https://opensource.org/licenses/Apache-2.0>.
 
+use std::io;
+
 use bitcoin::{OutPoint, Txid};
 use bitcoin_onchain::TxResolverError;
+use dbc::policy::PolicyError;
+use dbc::{ErrorClass, ErrorKindExt};
 
 /// Seal verification errors.
 #[derive(Debug, Display, From, Error)]
 #[display(doc_comments)]
 pub enum VerifyError {
-    /// seals provided for a batch verification have inconsistent close method.
+    /// seals closed by the same witness transaction use different close
+    /// methods. Hint: all seals closed by a witness transaction must use the
+    /// same method (opret or tapret); the seal definitions are inconsistent.
     InconsistentCloseMethod,
 
-    /// witness transaction can't be found in the publication medium
-    /// (blockchain or channel) by the given id {0}.
+    /// witness transaction {0} is not found in the blockchain or the channel.
+    /// Hint: the transaction may be not broadcasted or mined yet, or the
+    /// indexer may be out of sync; retry later.
     WitnessTxUnknown(Txid),
 
-    /// the provided witness transaction {0} does not closes seal {1}.
+    /// witness transaction {0} does not spend seal output {1}. Hint: the seal
+    /// is probably closed by another transaction, or the consignment is
+    /// invalid.
     WitnessNotClosingSeal(Txid, OutPoint),
 
-    /// tapret commitment is invalid.
-    ///
-    /// Details: {0}
+    /// anchor references witness transaction {0}, while the closure proof
+    /// references witness transaction {1}. Hint: the closure proof is
+    /// corrupted; request it again from its source.
+    AnchorTxidMismatch(Txid, Txid),
+
+    /// SPV proof is malformed or does not include witness transaction {0}.
+    /// Hint: request a fresh SPV proof from the indexer.
+    InvalidSpvProof(Txid),
+
+    /// witness transaction does not contain a valid tapret commitment: {0}
     #[from]
     InvalidTapretCommitment(dbc::tapret::TapretError),
 
-    /// unable to access commitment publication medium.
+    /// witness transaction does not contain a valid commitment: {0}
+    #[from]
+    InvalidCommitment(dbc::anchor::VerifyError),
+
+    /// unable to retrieve witness transaction: {0}. Hint: check the
+    /// connection to the indexer and retry later.
     #[from]
-    #[display(inner)]
     TxResolverError(TxResolverError),
+
+    /// closure proof is rejected by the validation policy: {0}
+    #[from]
+    Policy(PolicyError),
+}
+
+impl ErrorKindExt for VerifyError {
+    fn error_class(&self) -> ErrorClass {
+        match self {
+            VerifyError::InconsistentCloseMethod => ErrorClass::MalformedInput,
+            VerifyError::WitnessTxUnknown(_)
+            | VerifyError::TxResolverError(_) => ErrorClass::Transient,
+            VerifyError::WitnessNotClosingSeal(..)
+            | VerifyError::AnchorTxidMismatch(..)
+            | VerifyError::InvalidSpvProof(_) => ErrorClass::ValidationFailure,
+            VerifyError::InvalidTapretCommitment(err) => err.error_class(),
+            VerifyError::InvalidCommitment(err) => err.error_class(),
+            VerifyError::Policy(err) => err.error_class(),
+        }
+    }
+
+    fn error_code(&self) -> u16 {
+        match self {
+            VerifyError::InconsistentCloseMethod => 300,
+            VerifyError::WitnessTxUnknown(_) => 301,
+            VerifyError::WitnessNotClosingSeal(..) => 302,
+            VerifyError::AnchorTxidMismatch(..) => 303,
+            VerifyError::InvalidSpvProof(_) => 304,
+            VerifyError::InvalidTapretCommitment(err) => err.error_code(),
+            VerifyError::InvalidCommitment(err) => err.error_code(),
+            VerifyError::TxResolverError(_) => 305,
+            VerifyError::Policy(err) => err.error_code(),
+        }
+    }
+}
+
+/// Since [`TxResolverError`] may hold an error which can't be sent between
+/// threads, the resulting I/O error keeps only the error description.
+impl From<VerifyError> for io::Error {
+    #[inline]
+    fn from(err: VerifyError) -> Self {
+        io::Error::new(err.io_error_kind(), err.to_string())
+    }
+}
+
+/// Errors constructing [`super::Witness`] from the witness transaction and
+/// deterministic bitcoin commitment proof.
+#[derive(
+    Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display, Error
+)]
+#[display(doc_comments)]
+pub enum WitnessError {
+    /// witness transaction {0} has tapret commitment proof, but contains no
+    /// taproot outputs.
+    NoTaprootOutput(Txid),
+
+    /// witness transaction {0} has opret commitment proof, but contains no
+    /// OP_RETURN outputs.
+    NoOpretOutput(Txid),
 }
 
 /// Error happening if the seal data holds only witness transaction output
@@ -52,7 +131,168 @@ pub enum VerifyError {
 #[display("witness txid is unknown; unable to reconstruct full outpoint data")]
 pub struct WitnessVoutError;
 
-/// wrong transaction output-based single-use-seal closing method id '{0}'.
-#[derive(Clone, PartialEq, Eq, Debug, Display, Error, From)]
-#[display(doc_comments)]
-pub struct MethodParseError(pub String);
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use dbc::tapret::TapretError;
+
+    use super::*;
+
+    #[test]
+    fn verify_error_classes() {
+        let txid = Txid::hash(b"txid");
+        let errors = [
+            (
+                VerifyError::InconsistentCloseMethod,
+                ErrorClass::MalformedInput,
+                300,
+            ),
+            (
+                VerifyError::WitnessTxUnknown(txid),
+                ErrorClass::Transient,
+                301,
+            ),
+            (
+                VerifyError::WitnessNotClosingSeal(
+                    txid,
+                    OutPoint::new(txid, 0),
+                ),
+                ErrorClass::ValidationFailure,
+                302,
+            ),
+            (
+                VerifyError::AnchorTxidMismatch(txid, txid),
+                ErrorClass::ValidationFailure,
+                303,
+            ),
+            (
+                VerifyError::InvalidSpvProof(txid),
+                ErrorClass::ValidationFailure,
+                304,
+            ),
+            (
+                VerifyError::InvalidTapretCommitment(
+                    TapretError::NoTaprootOutput,
+                ),
+                ErrorClass::ValidationFailure,
+                101,
+            ),
+            (
+                VerifyError::InvalidCommitment(
+                    dbc::anchor::VerifyError::UnknownProofType(2),
+                ),
+                ErrorClass::MalformedInput,
+                121,
+            ),
+            (
+                VerifyError::TxResolverError(TxResolverError::with(txid)),
+                ErrorClass::Transient,
+                305,
+            ),
+            (
+                VerifyError::Policy(PolicyError::InsufficientConfirmations {
+                    confirmations: 0,
+                    min: 1,
+                }),
+                ErrorClass::Transient,
+                133,
+            ),
+        ];
+        for (err, class, code) in errors {
+            assert_eq!(err.error_class(), class);
+            assert_eq!(err.error_code(), code);
+            let kind = err.io_error_kind();
+            assert_eq!(io::Error::from(err).kind(), kind);
+        }
+        assert_eq!(
+            io::Error::from(VerifyError::WitnessTxUnknown(txid)).kind(),
+            io::ErrorKind::Other
+        );
+        assert_eq!(
+            io::Error::from(VerifyError::InvalidSpvProof(txid)).kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn display_messages() {
+        let txid = Txid::all_zeros();
+        let messages = [
+            (
+                VerifyError::InconsistentCloseMethod,
+                "seals closed by the same witness transaction use different \
+                 close methods. Hint: all seals closed by a witness \
+                 transaction must use the same method (opret or tapret); the \
+                 seal definitions are inconsistent.",
+            ),
+            (
+                VerifyError::WitnessTxUnknown(txid),
+                "witness transaction {txid} is not found in the blockchain or \
+                 the channel. Hint: the transaction may be not broadcasted or \
+                 mined yet, or the indexer may be out of sync; retry later.",
+            ),
+            (
+                VerifyError::WitnessNotClosingSeal(
+                    txid,
+                    OutPoint::new(txid, 1),
+                ),
+                "witness transaction {txid} does not spend seal output \
+                 {txid}:1. Hint: the seal is probably closed by another \
+                 transaction, or the consignment is invalid.",
+            ),
+            (
+                VerifyError::AnchorTxidMismatch(txid, txid),
+                "anchor references witness transaction {txid}, while the \
+                 closure proof references witness transaction {txid}. Hint: \
+                 the closure proof is corrupted; request it again from its \
+                 source.",
+            ),
+            (
+                VerifyError::InvalidSpvProof(txid),
+                "SPV proof is malformed or does not include witness \
+                 transaction {txid}. Hint: request a fresh SPV proof from the \
+                 indexer.",
+            ),
+            (
+                VerifyError::InvalidTapretCommitment(
+                    TapretError::NoTaprootOutput,
+                ),
+                "witness transaction does not contain a valid tapret \
+                 commitment: witness transaction has no taproot outputs and \
+                 can't contain tapret commitment. Hint: the anchor was \
+                 probably attached to a wrong witness transaction.",
+            ),
+            (
+                VerifyError::InvalidCommitment(
+                    dbc::anchor::VerifyError::Lnpbp4UnrelatedProtocol,
+                ),
+                "witness transaction does not contain a valid commitment: \
+                 anchor does not commit to any data under the protocol being \
+                 verified. Hint: the anchor was probably attached to data of \
+                 another contract or protocol; check the origin of the \
+                 consignment.",
+            ),
+            (
+                VerifyError::TxResolverError(TxResolverError::with(txid)),
+                "unable to retrieve witness transaction: unable to locate \
+                 transaction {txid}. Hint: check the connection to the \
+                 indexer and retry later.",
+            ),
+            (
+                VerifyError::Policy(PolicyError::InsufficientConfirmations {
+                    confirmations: 0,
+                    min: 1,
+                }),
+                "closure proof is rejected by the validation policy: witness \
+                 transaction has 0 confirmations, while the policy requires \
+                 at least 1. Hint: wait for more confirmations and retry.",
+            ),
+        ];
+        for (err, message) in messages {
+            assert_eq!(
+                err.to_string(),
+                message.replace("{txid}", &txid.to_string())
+            );
+        }
+    }
+}
@@ -121,6 +121,17 @@ impl TxoSeal for ExplicitSeal {
 
 impl ExplicitSeal {
     /// Constructs seal for the provided outpoint and seal closing method.
+    ///
+    /// ```
+    /// use bitcoin::hashes::Hash;
+    /// use bitcoin::{OutPoint, Txid};
+    /// use seals::txout::{CloseMethod, ExplicitSeal, TxoSeal};
+    ///
+    /// let outpoint = OutPoint::new(Txid::hash(b"seal"), 1);
+    /// let seal = ExplicitSeal::new(CloseMethod::TapretFirst, outpoint);
+    /// assert_eq!(seal.outpoint(), Some(outpoint));
+    /// assert_eq!(seal, ExplicitSeal::from(outpoint));
+    /// ```
     #[inline]
     pub fn new(method: CloseMethod, outpoint: OutPoint) -> ExplicitSeal {
         Self {
@@ -131,6 +142,22 @@ impl ExplicitSeal {
     }
 
     /// Constructs seal.
+    ///
+    /// ```
+    /// use bitcoin::hashes::Hash;
+    /// use bitcoin::{OutPoint, Txid};
+    /// use seals::txout::{CloseMethod, ExplicitSeal, TxoSeal};
+    ///
+    /// // Seal defined over the output of the witness transaction itself
+    /// let seal = ExplicitSeal::with(CloseMethod::OpretFirst, None, 0);
+    /// assert_eq!(seal.outpoint(), None);
+    ///
+    /// let witness_txid = Txid::hash(b"witness");
+    /// assert_eq!(
+    ///     seal.outpoint_or(witness_txid),
+    ///     OutPoint::new(witness_txid, 0)
+    /// );
+    /// ```
     #[inline]
     pub fn with(
         method: CloseMethod,
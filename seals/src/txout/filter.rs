@@ -0,0 +1,520 @@
+// BP Core Library implementing LNP/BP specifications & standards related to
+// bitcoin protocol
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Probabilistic set of concealed seals for fast pre-screening of
+//! consignments.
+//!
+//! Wallets may keep a [`SealFilter`] of all owned concealed seals and check
+//! seals from a received consignment against it before running the full
+//! validation. The filter never gives false negatives, but may give false
+//! positives with a configured probability, so the seals reported by
+//! [`SealFilter::screen`] must be checked against the actual wallet data.
+
+use std::io;
+
+use strict_encoding::{StrictDecode, StrictEncode};
+
+use super::blind::ConcealedSeal;
+
+/// Maximum number of bits in a [`SealFilter`] (128 MiB of filter data).
+pub const MAX_FILTER_BITS: u32 = 1 << 30;
+
+/// Maximum number of hash functions used by a [`SealFilter`].
+pub const MAX_FILTER_HASHES: u8 = 32;
+
+/// Errors constructing or decoding [`SealFilter`].
+#[derive(Copy, Clone, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum FilterError {
+    /// false positive rate {0} is outside of (0, 1) range.
+    InvalidFpRate(f64),
+
+    /// filter for the requested capacity and false positive rate requires
+    /// more than the maximum of 2^30 bits.
+    Oversized,
+
+    /// filter data contain invalid number of bits {0}.
+    InvalidBitCount(u32),
+
+    /// filter data contain invalid number of hash functions {0}.
+    InvalidHashCount(u8),
+
+    /// filter data have bits set beyond the filter size.
+    NonZeroPadding,
+}
+
+/// Result of screening consignment seals with [`SealFilter::screen`].
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct ScreenResult {
+    /// Number of the checked seals.
+    pub checked: usize,
+
+    /// Seals which may belong to the filter; they have to be checked against
+    /// the actual set of owned seals.
+    pub candidates: Vec<ConcealedSeal>,
+}
+
+impl ScreenResult {
+    /// Detects whether none of the checked seals belongs to the filter, in
+    /// which case the consignment can be skipped without further validation.
+    ///
+    /// ```
+    /// # use bitcoin::hashes::Hash;
+    /// # use bitcoin::Txid;
+    /// # use seals::txout::blind::{ConcealedSeal, RevealedSeal};
+    /// # use seals::txout::{CloseMethod, SealFilter};
+    /// fn seal(vout: u32) -> ConcealedSeal {
+    ///     let txid = Some(Txid::hash(b"seal"));
+    ///     RevealedSeal::checked(CloseMethod::TapretFirst, txid, vout, 1)
+    ///         .unwrap()
+    ///         .to_concealed_seal()
+    /// }
+    ///
+    /// let mut filter = SealFilter::new(100, 0.01).unwrap();
+    /// filter.insert(seal(0));
+    /// assert!(!filter.screen([seal(0)].into_iter()).is_clear());
+    /// assert!(filter.screen(std::iter::empty()).is_clear());
+    /// ```
+    #[inline]
+    pub fn is_clear(&self) -> bool { self.candidates.is_empty() }
+}
+
+/// Bloom filter over concealed seals.
+///
+/// Since concealed seals are already uniformly distributed hashes, filter bit
+/// positions are derived directly from the seal bytes using double hashing,
+/// without additional hash computations.
+///
+/// The filter is constructed for a given capacity (expected number of seals)
+/// and false positive rate `p`. Up to the capacity, the probability that
+/// [`SealFilter::maybe_contains`] returns `true` for a seal not inserted into
+/// the filter is approximately `p`; inserting more seals increases the rate,
+/// which can be checked with [`SealFilter::estimated_fp_rate`].
+///
+/// Seals are chosen by the consignment creator, who may grind them to cause
+/// false positives; this only makes the wallet run the full validation.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct SealFilter {
+    bits: Vec<u64>,
+    bit_count: u32,
+    hashes: u8,
+    items: u32,
+}
+
+impl SealFilter {
+    /// Constructs empty filter for the `capacity` seals with false positive
+    /// rate of `fp_rate` (which must be in `(0, 1)` range). The filter takes
+    /// about `1.44 * log2(1 / fp_rate)` bits per seal, i.e. ~1.2 KiB per
+    /// thousand seals for 1% rate.
+    ///
+    /// ```
+    /// use seals::txout::filter::{FilterError, SealFilter};
+    ///
+    /// let filter = SealFilter::new(1000, 0.01).unwrap();
+    /// assert!(filter.is_empty());
+    /// assert_eq!(filter.bit_count(), 9586);
+    /// assert_eq!(filter.hash_count(), 7);
+    ///
+    /// assert_eq!(
+    ///     SealFilter::new(1000, 1.0),
+    ///     Err(FilterError::InvalidFpRate(1.0))
+    /// );
+    /// assert_eq!(SealFilter::new(u32::MAX, 1e-9), Err(FilterError::Oversized));
+    /// ```
+    pub fn new(capacity: u32, fp_rate: f64) -> Result<SealFilter, FilterError> {
+        if !(fp_rate > 0.0 && fp_rate < 1.0) {
+            return Err(FilterError::InvalidFpRate(fp_rate));
+        }
+        let capacity = capacity.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-capacity * fp_rate.ln() / (ln2 * ln2)).ceil().max(64.0);
+        if bits > MAX_FILTER_BITS as f64 {
+            return Err(FilterError::Oversized);
+        }
+        let bit_count = bits as u32;
+        let hashes = (bits / capacity * ln2)
+            .round()
+            .clamp(1.0, MAX_FILTER_HASHES as f64) as u8;
+        Ok(SealFilter {
+            bits: vec![0u64; Self::words(bit_count)],
+            bit_count,
+            hashes,
+            items: 0,
+        })
+    }
+
+    /// Returns number of seals inserted into the filter.
+    ///
+    /// ```
+    /// # use bitcoin::hashes::Hash;
+    /// # use bitcoin::Txid;
+    /// # use seals::txout::blind::{ConcealedSeal, RevealedSeal};
+    /// # use seals::txout::{CloseMethod, SealFilter};
+    /// fn seal(vout: u32) -> ConcealedSeal {
+    ///     let txid = Some(Txid::hash(b"seal"));
+    ///     RevealedSeal::checked(CloseMethod::TapretFirst, txid, vout, 1)
+    ///         .unwrap()
+    ///         .to_concealed_seal()
+    /// }
+    ///
+    /// let mut filter = SealFilter::new(100, 0.01).unwrap();
+    /// filter.extend((0..10).map(seal));
+    /// assert_eq!(filter.len(), 10);
+    /// ```
+    #[inline]
+    pub fn len(&self) -> u32 { self.items }
+
+    /// Detects whether no seals were inserted into the filter.
+    ///
+    /// ```
+    /// # use bitcoin::hashes::Hash;
+    /// # use bitcoin::Txid;
+    /// # use seals::txout::blind::{ConcealedSeal, RevealedSeal};
+    /// # use seals::txout::{CloseMethod, SealFilter};
+    /// fn seal(vout: u32) -> ConcealedSeal {
+    ///     let txid = Some(Txid::hash(b"seal"));
+    ///     RevealedSeal::checked(CloseMethod::TapretFirst, txid, vout, 1)
+    ///         .unwrap()
+    ///         .to_concealed_seal()
+    /// }
+    ///
+    /// let mut filter = SealFilter::new(100, 0.01).unwrap();
+    /// assert!(filter.is_empty());
+    /// filter.insert(seal(0));
+    /// assert!(!filter.is_empty());
+    /// ```
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.items == 0 }
+
+    /// Returns number of bits used by the filter.
+    ///
+    /// ```
+    /// use seals::txout::SealFilter;
+    ///
+    /// // ~1.2 KiB per thousand seals for 1% false positive rate
+    /// let filter = SealFilter::new(1000, 0.01).unwrap();
+    /// assert_eq!(filter.bit_count(), 9586);
+    /// ```
+    #[inline]
+    pub fn bit_count(&self) -> u32 { self.bit_count }
+
+    /// Returns number of hash functions used by the filter.
+    ///
+    /// ```
+    /// use seals::txout::SealFilter;
+    ///
+    /// assert_eq!(SealFilter::new(1000, 0.01).unwrap().hash_count(), 7);
+    /// assert_eq!(SealFilter::new(1000, 0.5).unwrap().hash_count(), 1);
+    /// ```
+    #[inline]
+    pub fn hash_count(&self) -> u8 { self.hashes }
+
+    /// Estimates false positive rate of the filter for the current number of
+    /// inserted seals.
+    ///
+    /// ```
+    /// # use bitcoin::hashes::Hash;
+    /// # use bitcoin::Txid;
+    /// # use seals::txout::blind::{ConcealedSeal, RevealedSeal};
+    /// # use seals::txout::{CloseMethod, SealFilter};
+    /// fn seal(vout: u32) -> ConcealedSeal {
+    ///     let txid = Some(Txid::hash(b"seal"));
+    ///     RevealedSeal::checked(CloseMethod::TapretFirst, txid, vout, 1)
+    ///         .unwrap()
+    ///         .to_concealed_seal()
+    /// }
+    ///
+    /// let mut filter = SealFilter::new(1000, 0.01).unwrap();
+    /// assert_eq!(filter.estimated_fp_rate(), 0.0);
+    ///
+    /// filter.extend((0..1000).map(seal));
+    /// assert!(filter.estimated_fp_rate() < 0.011);
+    ///
+    /// // Overfilled filter loses precision
+    /// filter.extend((1000..2000).map(seal));
+    /// assert!(filter.estimated_fp_rate() > 0.1);
+    /// ```
+    pub fn estimated_fp_rate(&self) -> f64 {
+        let k = self.hashes as f64;
+        let exp = -k * self.items as f64 / self.bit_count as f64;
+        (1.0 - exp.exp()).powf(k)
+    }
+
+    /// Inserts seal into the filter.
+    ///
+    /// ```
+    /// # use bitcoin::hashes::Hash;
+    /// # use bitcoin::Txid;
+    /// # use seals::txout::blind::{ConcealedSeal, RevealedSeal};
+    /// # use seals::txout::{CloseMethod, SealFilter};
+    /// fn seal(vout: u32) -> ConcealedSeal {
+    ///     let txid = Some(Txid::hash(b"seal"));
+    ///     RevealedSeal::checked(CloseMethod::TapretFirst, txid, vout, 1)
+    ///         .unwrap()
+    ///         .to_concealed_seal()
+    /// }
+    ///
+    /// let mut filter = SealFilter::new(100, 0.01).unwrap();
+    /// filter.insert(seal(0));
+    /// assert!(filter.maybe_contains(&seal(0)));
+    /// assert_eq!(filter.len(), 1);
+    /// ```
+    pub fn insert(&mut self, seal: ConcealedSeal) {
+        for pos in self.positions(&seal) {
+            self.bits[pos / 64] |= 1 << (pos % 64);
+        }
+        self.items = self.items.saturating_add(1);
+    }
+
+    /// Checks whether the seal may belong to the filter. Returns `false` only
+    /// if the seal was never inserted into the filter.
+    ///
+    /// ```
+    /// # use bitcoin::hashes::Hash;
+    /// # use bitcoin::Txid;
+    /// # use seals::txout::blind::{ConcealedSeal, RevealedSeal};
+    /// # use seals::txout::{CloseMethod, SealFilter};
+    /// fn seal(vout: u32) -> ConcealedSeal {
+    ///     let txid = Some(Txid::hash(b"seal"));
+    ///     RevealedSeal::checked(CloseMethod::TapretFirst, txid, vout, 1)
+    ///         .unwrap()
+    ///         .to_concealed_seal()
+    /// }
+    ///
+    /// let mut filter = SealFilter::new(100, 0.01).unwrap();
+    /// filter.extend((0..100).map(seal));
+    /// assert!((0..100).map(seal).all(|seal| filter.maybe_contains(&seal)));
+    ///
+    /// let false_positives = (100..10_100)
+    ///     .map(seal)
+    ///     .filter(|seal| filter.maybe_contains(seal))
+    ///     .count();
+    /// assert!(false_positives < 200);
+    /// ```
+    pub fn maybe_contains(&self, seal: &ConcealedSeal) -> bool {
+        self.positions(seal)
+            .all(|pos| self.bits[pos / 64] & (1 << (pos % 64)) != 0)
+    }
+
+    /// Checks seals of a consignment against the filter, returning seals which
+    /// may belong to the filter.
+    ///
+    /// ```
+    /// # use bitcoin::hashes::Hash;
+    /// # use bitcoin::Txid;
+    /// # use seals::txout::blind::{ConcealedSeal, RevealedSeal};
+    /// # use seals::txout::{CloseMethod, SealFilter};
+    /// fn seal(vout: u32) -> ConcealedSeal {
+    ///     let txid = Some(Txid::hash(b"seal"));
+    ///     RevealedSeal::checked(CloseMethod::TapretFirst, txid, vout, 1)
+    ///         .unwrap()
+    ///         .to_concealed_seal()
+    /// }
+    ///
+    /// // Wallet filter over the owned seals
+    /// let mut filter = SealFilter::new(100, 0.01).unwrap();
+    /// filter.extend((0..100).map(seal));
+    ///
+    /// // Consignment containing a single owned seal
+    /// let consignment_seals = (1000..1010).map(seal).chain([seal(1)]);
+    /// let result = filter.screen(consignment_seals);
+    /// assert_eq!(result.checked, 11);
+    /// assert!(result.candidates.contains(&seal(1)));
+    /// assert!(!result.is_clear());
+    /// ```
+    pub fn screen(
+        &self,
+        consignment_seals: impl Iterator<Item = ConcealedSeal>,
+    ) -> ScreenResult {
+        let mut result = ScreenResult::default();
+        for seal in consignment_seals {
+            result.checked += 1;
+            if self.maybe_contains(&seal) {
+                result.candidates.push(seal);
+            }
+        }
+        result
+    }
+
+    fn words(bit_count: u32) -> usize { (bit_count as usize + 63) / 64 }
+
+    fn positions(&self, seal: &ConcealedSeal) -> impl Iterator<Item = usize> {
+        let mut h1 = [0u8; 8];
+        let mut h2 = [0u8; 8];
+        h1.copy_from_slice(&seal[..8]);
+        h2.copy_from_slice(&seal[8..16]);
+        let h1 = u64::from_le_bytes(h1);
+        let h2 = u64::from_le_bytes(h2) | 1;
+        let bit_count = self.bit_count as u64;
+        (0..self.hashes as u64).map(move |i| {
+            (h1.wrapping_add(i.wrapping_mul(h2)) % bit_count) as usize
+        })
+    }
+}
+
+impl Extend<ConcealedSeal> for SealFilter {
+    fn extend<T: IntoIterator<Item = ConcealedSeal>>(&mut self, iter: T) {
+        for seal in iter {
+            self.insert(seal);
+        }
+    }
+}
+
+impl StrictEncode for SealFilter {
+    fn strict_encode<E: io::Write>(
+        &self,
+        mut e: E,
+    ) -> Result<usize, strict_encoding::Error> {
+        let mut len =
+            strict_encode_list!(e; self.bit_count, self.hashes, self.items);
+        for word in &self.bits {
+            len += word.strict_encode(&mut e)?;
+        }
+        Ok(len)
+    }
+}
+
+impl StrictDecode for SealFilter {
+    fn strict_decode<D: io::Read>(
+        mut d: D,
+    ) -> Result<Self, strict_encoding::Error> {
+        let bit_count = u32::strict_decode(&mut d)?;
+        if bit_count == 0 || bit_count > MAX_FILTER_BITS {
+            return Err(strict_encoding::Error::DataIntegrityError(
+                FilterError::InvalidBitCount(bit_count).to_string(),
+            ));
+        }
+        let hashes = u8::strict_decode(&mut d)?;
+        if hashes == 0 || hashes > MAX_FILTER_HASHES {
+            return Err(strict_encoding::Error::DataIntegrityError(
+                FilterError::InvalidHashCount(hashes).to_string(),
+            ));
+        }
+        let items = u32::strict_decode(&mut d)?;
+        let bits = (0..Self::words(bit_count))
+            .map(|_| u64::strict_decode(&mut d))
+            .collect::<Result<Vec<_>, _>>()?;
+        let padding = bit_count % 64;
+        if padding != 0 && bits[bits.len() - 1] >> padding != 0 {
+            return Err(strict_encoding::Error::DataIntegrityError(
+                FilterError::NonZeroPadding.to_string(),
+            ));
+        }
+        Ok(SealFilter {
+            bits,
+            bit_count,
+            hashes,
+            items,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::Wrapper;
+    use bitcoin::hashes::{sha256t, Hash};
+    use strict_encoding::{strict_deserialize, strict_serialize};
+
+    use super::*;
+
+    fn seal_fixture(no: u32) -> ConcealedSeal {
+        ConcealedSeal::from_inner(sha256t::Hash::hash(&no.to_le_bytes()))
+    }
+
+    #[test]
+    fn no_false_negatives() {
+        let mut filter = SealFilter::new(1000, 0.01).unwrap();
+        assert!(filter.is_empty());
+        filter.extend((0..1000).map(seal_fixture));
+        assert_eq!(filter.len(), 1000);
+        assert!((0..1000)
+            .map(seal_fixture)
+            .all(|seal| filter.maybe_contains(&seal)));
+
+        let result = filter.screen((0..10).map(seal_fixture));
+        assert_eq!(result.checked, 10);
+        assert_eq!(
+            result.candidates,
+            (0..10).map(seal_fixture).collect::<Vec<_>>()
+        );
+        assert!(!result.is_clear());
+        assert!(SealFilter::new(1000, 0.01)
+            .unwrap()
+            .screen((0..10).map(seal_fixture))
+            .is_clear());
+    }
+
+    #[test]
+    fn fp_rate() {
+        const ITEMS: u32 = 10_000;
+        const PROBES: u32 = 200_000;
+        for fp_rate in [0.1, 0.01, 0.001] {
+            let mut filter = SealFilter::new(ITEMS, fp_rate).unwrap();
+            filter.extend((0..ITEMS).map(seal_fixture));
+            assert!(filter.estimated_fp_rate() <= fp_rate * 1.1);
+
+            let result =
+                filter.screen((ITEMS..ITEMS + PROBES).map(seal_fixture));
+            assert_eq!(result.checked, PROBES as usize);
+            let observed = result.candidates.len() as f64 / PROBES as f64;
+            assert!(
+                observed <= fp_rate * 1.25,
+                "observed false positive rate {} exceeds configured {}",
+                observed,
+                fp_rate
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_params() {
+        for fp_rate in [0.0, 1.0, -0.5, f64::NAN] {
+            assert!(matches!(
+                SealFilter::new(10, fp_rate),
+                Err(FilterError::InvalidFpRate(_))
+            ));
+        }
+        assert_eq!(
+            SealFilter::new(u32::MAX, 1e-9),
+            Err(FilterError::Oversized)
+        );
+    }
+
+    #[test]
+    fn strict_encoding() {
+        let mut filter = SealFilter::new(100, 0.01).unwrap();
+        filter.extend((0..100).map(seal_fixture));
+        let data = strict_serialize(&filter).unwrap();
+        assert_eq!(data.len(), 4 + 1 + 4 + filter.bits.len() * 8);
+        let decoded: SealFilter = strict_deserialize(&data).unwrap();
+        assert_eq!(decoded, filter);
+
+        let mut invalid = data.clone();
+        invalid[..4].copy_from_slice(&0u32.to_le_bytes());
+        assert!(strict_deserialize::<SealFilter>(&invalid).is_err());
+
+        let mut invalid = data.clone();
+        invalid[4] = MAX_FILTER_HASHES + 1;
+        assert!(strict_deserialize::<SealFilter>(&invalid).is_err());
+
+        let mut invalid = data.clone();
+        *invalid.last_mut().unwrap() = 0xFF;
+        assert!(strict_deserialize::<SealFilter>(&invalid).is_err());
+
+        assert!(
+            strict_deserialize::<SealFilter>(&data[..data.len() - 1]).is_err()
+        );
+    }
+}
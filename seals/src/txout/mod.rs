@@ -17,12 +17,21 @@
 //! spending that output ("TxOut seals").
 
 pub mod blind;
+mod closure;
 mod error;
 pub mod explicit;
+pub mod filter;
 mod proto;
+pub mod reuse;
 mod seal;
 
-pub use error::{MethodParseError, VerifyError, WitnessVoutError};
+pub use closure::{
+    ClosedSeal, ClosureId, ClosureIdTag, ClosureProof, ClosureStatus, SpvProof,
+};
+pub use dbc::{CloseMethod, MethodParseError};
+pub use error::{VerifyError, WitnessError, WitnessVoutError};
 pub use explicit::ExplicitSeal;
-pub use proto::TxoProtocol;
-pub use seal::{CloseMethod, TxoSeal};
+pub use filter::{ScreenResult, SealFilter};
+pub use proto::{TxoProtocol, Witness};
+pub use reuse::{audit_reuse, BlindingReuse, BlindingTracker};
+pub use seal::TxoSeal;
@@ -13,33 +13,120 @@
 // along with this software.
 // If not, see <https://opensource.org/licenses/Apache-2.0>.
 
-use bitcoin::Txid;
+use std::iter;
+
+use bitcoin::{OutPoint, Transaction, Txid};
 use bitcoin_onchain::ResolveTx;
 use commit_verify::lnpbp4;
-use dbc::{Anchor, Proof};
+use dbc::{Anchor, ItemResult, Proof, WitnessId};
 use single_use_seals::{SealProtocol, SealStatus, VerifySeal};
 
-use crate::txout::{TxoSeal, VerifyError};
+use crate::txout::{TxoSeal, VerifyError, WitnessError};
 
 // TODO: #8 Implement proper operations with SealMedium
 // TODO: #9 Do asynchronous version
 // #[cfg(feature = "async")]
 // use single_use_seals::SealMediumAsync;
 
+/// Witness of the TxOut seal closing, consisting of the witness transaction id
+/// and the proof of the deterministic bitcoin commitment in that transaction.
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Witness {
+    /// Id of the witness transaction.
     pub txid: Txid,
+
+    /// Proof of the deterministic bitcoin commitment.
     pub proof: Proof,
 }
 
+impl Witness {
+    /// Constructs witness from the witness transaction and DBC proof, checking
+    /// that the transaction structurally matches the proof: tapret proofs
+    /// require presence of a taproot output and opret proofs require presence
+    /// of an `OP_RETURN` output.
+    ///
+    /// The commitment itself is not verified; this is done during the seal
+    /// verification.
+    ///
+    /// ```
+    /// use dbc::test_utils::{opret_fixture, tapret_proof_fixture, tx_fixture};
+    /// use dbc::Proof;
+    /// use seals::txout::{Witness, WitnessError};
+    ///
+    /// let (_, tx) = opret_fixture();
+    /// let witness =
+    ///     Witness::from_tx_and_proof(tx.clone(), Proof::OpretFirst).unwrap();
+    /// assert_eq!(witness.txid, tx.txid());
+    ///
+    /// // Transaction without taproot outputs can't host tapret commitment
+    /// let tx = tx_fixture(vec![]);
+    /// let proof = Proof::TapretFirst(tapret_proof_fixture());
+    /// assert_eq!(
+    ///     Witness::from_tx_and_proof(tx.clone(), proof),
+    ///     Err(WitnessError::NoTaprootOutput(tx.txid()))
+    /// );
+    /// ```
+    pub fn from_tx_and_proof(
+        tx: Transaction,
+        proof: Proof,
+    ) -> Result<Witness, WitnessError> {
+        let txid = tx.txid();
+        match proof {
+            Proof::OpretFirst | Proof::OpretFirstAt(_)
+                if !tx
+                    .output
+                    .iter()
+                    .any(|txout| txout.script_pubkey.is_op_return()) =>
+            {
+                Err(WitnessError::NoOpretOutput(txid))
+            }
+            Proof::TapretFirst(_)
+                if !tx
+                    .output
+                    .iter()
+                    .any(|txout| txout.script_pubkey.is_v1_p2tr()) =>
+            {
+                Err(WitnessError::NoTaprootOutput(txid))
+            }
+            proof => Ok(Witness { txid, proof }),
+        }
+    }
+
+    /// Splits witness into the witness transaction id and DBC proof.
+    ///
+    /// ```
+    /// use dbc::test_utils::tapret_fixture;
+    /// use seals::txout::Witness;
+    ///
+    /// let (anchor, tx) = tapret_fixture();
+    /// let (txid, proof) = Witness::from(anchor.clone()).into_parts();
+    /// assert_eq!(txid, tx.txid());
+    /// assert_eq!(&proof, anchor.dbc_proof());
+    /// ```
+    #[inline]
+    pub fn into_parts(self) -> (Txid, Proof) { (self.txid, self.proof) }
+
+    /// Returns id of the witness transaction.
+    ///
+    /// ```
+    /// use dbc::test_utils::opret_fixture;
+    /// use seals::txout::Witness;
+    ///
+    /// let (anchor, _) = opret_fixture();
+    /// let witness = Witness::from(anchor.clone());
+    /// assert_eq!(witness.witness_id(), anchor.witness_id());
+    /// ```
+    #[inline]
+    pub fn witness_id(&self) -> WitnessId { WitnessId::from(self.txid) }
+}
+
 impl<L> From<Anchor<L>> for Witness
 where
     L: lnpbp4::Proof,
 {
     fn from(anchor: Anchor<L>) -> Self {
-        Witness {
-            txid: anchor.txid,
-            proof: anchor.dbc_proof,
-        }
+        let (txid, _, proof) = anchor.into_parts();
+        Witness { txid, proof }
     }
 }
 
@@ -63,39 +150,79 @@ where
     }
 }
 
-impl<'seal, Seal, Resolver> VerifySeal<'seal, Seal> for TxoProtocol<Resolver>
-where
-    Seal: TxoSeal + 'seal,
-    Resolver: ResolveTx,
-{
-    fn verify_seal(
+impl<Resolver: ResolveTx> TxoProtocol<Resolver> {
+    /// Constructs seal engine retrieving witness transactions with the
+    /// provided `resolver`.
+    #[inline]
+    pub fn new(resolver: Resolver) -> Self { TxoProtocol { resolver } }
+
+    /// Verifies that the `witness` closes each of the `seals` over the `msg`,
+    /// reporting outcome for each of the seals, identified by their outpoints,
+    /// instead of stopping on the first failure. Outcomes may be aggregated
+    /// with [`dbc::report::summary`].
+    ///
+    /// Seals with a close method different from the method of the first seal
+    /// fail with [`VerifyError::InconsistentCloseMethod`]; seals which are
+    /// not spent by the witness transaction fail with
+    /// [`VerifyError::WitnessNotClosingSeal`]. The rest of the seals share
+    /// outcome of the witness commitment verification.
+    ///
+    /// # Errors
+    ///
+    /// If the witness transaction can't be retrieved, which prevents
+    /// verification of all the seals.
+    pub fn verify_seals_each<'seal, Seal: TxoSeal + 'seal>(
         &self,
-        seal: &'seal Seal,
-        msg: &Self::Message,
-        witness: &Self::Witness,
-    ) -> Result<bool, Self::Error> {
-        // 1. Get tx
+        seals: impl IntoIterator<Item = &'seal Seal>,
+        msg: &lnpbp4::CommitmentHash,
+        witness: &Witness,
+    ) -> Result<Vec<ItemResult<OutPoint, VerifyError>>, VerifyError> {
         let tx = self.resolver.resolve_tx(witness.txid)?;
 
-        // 2. The seal must match tx inputs
-        let outpoint = seal.outpoint_or(witness.txid);
-        if !tx.input.iter().any(|txin| txin.previous_output == outpoint) {
-            return Err(VerifyError::WitnessNotClosingSeal(
-                witness.txid,
-                outpoint,
-            ));
-        }
-
-        // 3. Verify DBC with the giving closing method
-        witness.proof.verify(msg, tx).map_err(VerifyError::from)
+        let mut method = None;
+        let mut commitment = None;
+        let results = seals
+            .into_iter()
+            .enumerate()
+            .map(|(index, seal)| {
+                let outpoint = seal.outpoint_or(witness.txid);
+                let outcome = if *method.get_or_insert(seal.method())
+                    != seal.method()
+                {
+                    Err(VerifyError::InconsistentCloseMethod)
+                } else if !tx
+                    .input
+                    .iter()
+                    .any(|txin| txin.previous_output == outpoint)
+                {
+                    Err(VerifyError::WitnessNotClosingSeal(
+                        witness.txid,
+                        outpoint,
+                    ))
+                } else {
+                    commitment
+                        .get_or_insert_with(|| witness.proof.verify(msg, &tx))
+                        .clone()
+                        .map_err(VerifyError::from)
+                };
+                #[cfg(feature = "metrics")]
+                dbc::metrics::record_seal((&outcome).into());
+                ItemResult {
+                    index,
+                    id: outpoint,
+                    outcome,
+                }
+            })
+            .collect();
+        Ok(results)
     }
 
-    fn verify_seal_all(
+    fn check_seals<'seal, Seal: TxoSeal + 'seal>(
         &self,
         seals: impl IntoIterator<Item = &'seal Seal>,
-        msg: &Self::Message,
-        witness: &Self::Witness,
-    ) -> Result<bool, Self::Error> {
+        msg: &lnpbp4::CommitmentHash,
+        witness: &Witness,
+    ) -> Result<bool, VerifyError> {
         // 1. Get tx
         let tx = self.resolver.resolve_tx(witness.txid)?;
 
@@ -121,6 +248,176 @@ where
         }
 
         // 4. Verify DBC with the giving closing method
-        witness.proof.verify(msg, tx).map_err(VerifyError::from)
+        witness.proof.verify(msg, &tx).map_err(VerifyError::from)
+    }
+}
+
+impl<'seal, Seal, Resolver> VerifySeal<'seal, Seal> for TxoProtocol<Resolver>
+where
+    Seal: TxoSeal + 'seal,
+    Resolver: ResolveTx,
+{
+    fn verify_seal(
+        &self,
+        seal: &'seal Seal,
+        msg: &Self::Message,
+        witness: &Self::Witness,
+    ) -> Result<bool, Self::Error> {
+        self.verify_seal_all(iter::once(seal), msg, witness)
+    }
+
+    fn verify_seal_all(
+        &self,
+        seals: impl IntoIterator<Item = &'seal Seal>,
+        msg: &Self::Message,
+        witness: &Self::Witness,
+    ) -> Result<bool, Self::Error> {
+        let res = self.check_seals(seals, msg, witness);
+        #[cfg(feature = "metrics")]
+        dbc::metrics::record_seal((&res).into());
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::Wrapper;
+    use bitcoin::hashes::Hash;
+    use bitcoin::schnorr::TweakedPublicKey;
+    use bitcoin::{Script, TxIn, TxOut};
+    use bitcoin_onchain::TxResolverError;
+    use dbc::consts::COMMITMENT_LEN;
+    use dbc::report::summary;
+    use dbc::test_utils::{internal_key_fixture, tapret_proof_fixture};
+
+    use super::*;
+    use crate::txout::{CloseMethod, ExplicitSeal};
+
+    fn tx_fixture(script_pubkey: Script) -> Transaction {
+        dbc::test_utils::tx_fixture(vec![TxOut {
+            value: 0,
+            script_pubkey,
+        }])
+    }
+
+    fn tapret_fixture() -> Proof { Proof::TapretFirst(tapret_proof_fixture()) }
+
+    #[test]
+    fn witness_from_tx_and_proof() {
+        let opret_tx =
+            tx_fixture(Script::new_op_return(&[0u8; COMMITMENT_LEN]));
+        let tapret_tx = tx_fixture(Script::new_v1_p2tr_tweaked(
+            TweakedPublicKey::dangerous_assume_tweaked(internal_key_fixture()),
+        ));
+
+        let witness =
+            Witness::from_tx_and_proof(opret_tx.clone(), Proof::OpretFirst)
+                .unwrap();
+        assert_eq!(witness.txid, opret_tx.txid());
+        assert_eq!(witness.into_parts(), (opret_tx.txid(), Proof::OpretFirst));
+
+        let witness =
+            Witness::from_tx_and_proof(tapret_tx.clone(), tapret_fixture())
+                .unwrap();
+        assert_eq!(witness.into_parts(), (tapret_tx.txid(), tapret_fixture()));
+    }
+
+    struct Resolver(Transaction);
+
+    impl ResolveTx for Resolver {
+        fn resolve_tx(
+            &self,
+            txid: Txid,
+        ) -> Result<Transaction, TxResolverError> {
+            if self.0.txid() == txid {
+                Ok(self.0.clone())
+            } else {
+                Err(TxResolverError::with(txid))
+            }
+        }
+    }
+
+    #[test]
+    fn seals_closing_localization() {
+        let msg = lnpbp4::CommitmentHash::from_inner(Hash::hash(b"message"));
+        let closed = (0..3)
+            .map(|vout| OutPoint::new(Txid::hash(b"prev"), vout))
+            .collect::<Vec<_>>();
+        let mut tx = tx_fixture(Script::new_op_return(&msg[..]));
+        tx.input = closed
+            .iter()
+            .map(|outpoint| TxIn {
+                previous_output: *outpoint,
+                ..default!()
+            })
+            .collect();
+        let witness =
+            Witness::from_tx_and_proof(tx.clone(), Proof::OpretFirst).unwrap();
+        let protocol = TxoProtocol::new(Resolver(tx));
+
+        let unspent = OutPoint::new(Txid::hash(b"other"), 0);
+        let seals = [
+            ExplicitSeal::new(CloseMethod::OpretFirst, closed[0]),
+            ExplicitSeal::new(CloseMethod::OpretFirst, unspent),
+            ExplicitSeal::new(CloseMethod::TapretFirst, closed[1]),
+            ExplicitSeal::new(CloseMethod::OpretFirst, closed[2]),
+        ];
+        let results =
+            protocol.verify_seals_each(&seals, &msg, &witness).unwrap();
+        assert_eq!(results.iter().map(|res| res.index).collect::<Vec<_>>(), [
+            0, 1, 2, 3
+        ]);
+        assert_eq!(results.iter().map(|res| res.id).collect::<Vec<_>>(), [
+            closed[0], unspent, closed[1], closed[2]
+        ]);
+        assert!(results[0].is_valid());
+        assert!(matches!(
+            results[1].outcome,
+            Err(VerifyError::WitnessNotClosingSeal(txid, outpoint))
+                if txid == witness.txid && outpoint == unspent
+        ));
+        assert!(matches!(
+            results[2].outcome,
+            Err(VerifyError::InconsistentCloseMethod)
+        ));
+        assert!(results[3].is_valid());
+
+        let summary = summary(&results);
+        assert_eq!(summary.valid, 2);
+        assert_eq!(summary.codes, bmap! { 300 => 1, 302 => 1 });
+
+        // Seals are not closed over a different message
+        let other =
+            lnpbp4::CommitmentHash::from_inner(Hash::hash(b"other message"));
+        let results = protocol
+            .verify_seals_each(&seals[..1], &other, &witness)
+            .unwrap();
+        assert!(matches!(results[0].outcome, Ok(false)));
+
+        // Unknown witness transaction fails the whole batch
+        let unknown = Witness {
+            txid: unspent.txid,
+            proof: Proof::OpretFirst,
+        };
+        assert!(matches!(
+            protocol.verify_seals_each(&seals, &msg, &unknown),
+            Err(VerifyError::TxResolverError(_))
+        ));
+    }
+
+    #[test]
+    fn witness_structural_mismatch() {
+        let opret_tx =
+            tx_fixture(Script::new_op_return(&[0u8; COMMITMENT_LEN]));
+        let empty_tx = tx_fixture(Script::new());
+
+        assert_eq!(
+            Witness::from_tx_and_proof(opret_tx.clone(), tapret_fixture()),
+            Err(WitnessError::NoTaprootOutput(opret_tx.txid()))
+        );
+        assert_eq!(
+            Witness::from_tx_and_proof(empty_tx.clone(), Proof::OpretFirst),
+            Err(WitnessError::NoOpretOutput(empty_tx.txid()))
+        );
     }
 }
@@ -0,0 +1,288 @@
+// BP Core Library implementing LNP/BP specifications & standards related to
+// bitcoin protocol
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Detection of blinding factors reused across seals.
+//!
+//! Seals sharing the same blinding factor are linkable: an observer learning
+//! the reveal of one of them may test whether other concealed seals use the
+//! same blinding with the outpoints known to them. Wallets may keep a
+//! [`BlindingTracker`] of all issued blinding factors and construct seals
+//! with [`RevealedSeal::with_unique_blinding`], or audit already existing
+//! seals with [`audit_reuse`].
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+#[cfg(feature = "rand")]
+use bitcoin::secp256k1::rand::RngCore;
+
+use super::blind::RevealedSeal;
+
+/// Maximal number of attempts to generate a blinding factor not issued before
+/// by [`RevealedSeal::with_unique_blinding`].
+pub const MAX_BLINDING_ATTEMPTS: usize = 16;
+
+/// Errors issuing blinding factors tracked by [`BlindingTracker`].
+#[derive(
+    Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display, Error
+)]
+#[display(doc_comments)]
+pub enum BlindingReuse {
+    /// blinding factor was already issued to another seal. Hint: reused
+    /// blinding factors make seals linkable; generate a new one.
+    Reused,
+
+    /// random number generator produced only already issued blinding
+    /// factors in {0} attempts. Hint: the random number generator is broken
+    /// or seeded with a constant value.
+    AttemptsExhausted(usize),
+}
+
+/// Set of issued blinding factors.
+///
+/// The tracker does not keep blinding factors themselves: they are stored as
+/// hashes salted with a tracker-specific salt, such that the tracker data do
+/// not reveal the seals and can't be matched against data of other wallets.
+/// Since the salt is required for checking blinding factors, it is kept
+/// together with the hashes when the tracker is persisted.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct BlindingTracker {
+    salt: [u8; 32],
+    issued: BTreeSet<sha256::Hash>,
+}
+
+impl BlindingTracker {
+    /// Constructs empty tracker with a salt generated by the provided random
+    /// number generator.
+    ///
+    /// ```
+    /// use bitcoin::secp256k1::rand::thread_rng;
+    /// use seals::txout::reuse::BlindingTracker;
+    ///
+    /// let tracker = BlindingTracker::new(&mut thread_rng());
+    /// assert!(tracker.is_empty());
+    /// ```
+    #[cfg(feature = "rand")]
+    pub fn new(rng: &mut impl RngCore) -> BlindingTracker {
+        let mut salt = [0u8; 32];
+        rng.fill_bytes(&mut salt);
+        BlindingTracker::with_salt(salt)
+    }
+
+    /// Constructs empty tracker with the provided salt.
+    #[inline]
+    pub fn with_salt(salt: [u8; 32]) -> BlindingTracker {
+        BlindingTracker {
+            salt,
+            issued: empty!(),
+        }
+    }
+
+    /// Returns number of the issued blinding factors.
+    #[inline]
+    pub fn len(&self) -> usize { self.issued.len() }
+
+    /// Detects whether no blinding factors were issued.
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.issued.is_empty() }
+
+    fn blinding_hash(&self, blinding: u64) -> sha256::Hash {
+        let mut engine = sha256::Hash::engine();
+        engine.input(&self.salt);
+        engine.input(&blinding.to_le_bytes());
+        sha256::Hash::from_engine(engine)
+    }
+
+    /// Detects whether the blinding factor was already issued.
+    #[inline]
+    pub fn is_issued(&self, blinding: u64) -> bool {
+        self.issued.contains(&self.blinding_hash(blinding))
+    }
+
+    /// Records the blinding factor as issued.
+    ///
+    /// ```
+    /// use seals::txout::reuse::{BlindingReuse, BlindingTracker};
+    ///
+    /// let mut tracker = BlindingTracker::with_salt([0x5e; 32]);
+    /// assert_eq!(tracker.issue(54683213134637), Ok(()));
+    /// assert!(tracker.is_issued(54683213134637));
+    /// assert_eq!(tracker.issue(54683213134637), Err(BlindingReuse::Reused));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Errors with [`BlindingReuse::Reused`] if the blinding factor was
+    /// already issued; the tracker is not changed in this case.
+    pub fn issue(&mut self, blinding: u64) -> Result<(), BlindingReuse> {
+        if self.issued.insert(self.blinding_hash(blinding)) {
+            Ok(())
+        } else {
+            Err(BlindingReuse::Reused)
+        }
+    }
+}
+
+/// Reports pairs of indexes of the seals sharing the same blinding factor.
+/// Pairs are ordered, with the first index of each pair being less than the
+/// second one.
+///
+/// ```
+/// use bitcoin::hashes::Hash;
+/// use bitcoin::Txid;
+/// use seals::txout::blind::RevealedSeal;
+/// use seals::txout::reuse::audit_reuse;
+/// use seals::txout::CloseMethod;
+///
+/// let seal = |vout: u32, blinding: u64| {
+///     let txid = Some(Txid::hash(b"seal"));
+///     RevealedSeal::checked(CloseMethod::TapretFirst, txid, vout, blinding)
+///         .unwrap()
+/// };
+/// let seals = [seal(0, 1), seal(1, 2), seal(2, 1), seal(3, 1)];
+/// assert_eq!(audit_reuse(&seals), vec![(0, 2), (0, 3), (2, 3)]);
+/// ```
+pub fn audit_reuse(reveals: &[RevealedSeal]) -> Vec<(usize, usize)> {
+    let mut groups = BTreeMap::<u64, Vec<usize>>::new();
+    for (index, reveal) in reveals.iter().enumerate() {
+        groups.entry(reveal.blinding).or_default().push(index);
+    }
+    let mut pairs = groups
+        .values()
+        .flat_map(|group| {
+            group.iter().enumerate().flat_map(move |(pos, first)| {
+                group[pos + 1..].iter().map(move |second| (*first, *second))
+            })
+        })
+        .collect::<Vec<_>>();
+    pairs.sort_unstable();
+    pairs
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use bitcoin::{OutPoint, Txid};
+    use strict_encoding::{strict_deserialize, strict_serialize};
+
+    use super::*;
+    use crate::txout::CloseMethod;
+
+    fn seal(vout: u32, blinding: u64) -> RevealedSeal {
+        let txid = Some(Txid::hash(b"seal"));
+        RevealedSeal::checked(CloseMethod::OpretFirst, txid, vout, blinding)
+            .unwrap()
+    }
+
+    #[test]
+    fn tracker_detects_reuse() {
+        let mut tracker = BlindingTracker::with_salt([1u8; 32]);
+        for blinding in 1..=10 {
+            assert!(!tracker.is_issued(blinding));
+            tracker.issue(blinding).unwrap();
+        }
+        assert_eq!(tracker.len(), 10);
+        assert_eq!(tracker.issue(5), Err(BlindingReuse::Reused));
+        assert_eq!(tracker.len(), 10);
+
+        // Hashes depend on the salt
+        let other = BlindingTracker::with_salt([2u8; 32]);
+        assert!(!other.is_issued(5));
+
+        let data = strict_serialize(&tracker).unwrap();
+        let decoded: BlindingTracker = strict_deserialize(data).unwrap();
+        assert_eq!(decoded, tracker);
+        assert!(decoded.is_issued(5));
+    }
+
+    #[test]
+    fn tracker_explicit_blinding() {
+        let mut tracker = BlindingTracker::with_salt([1u8; 32]);
+        let outpoint = OutPoint::new(Txid::hash(b"seal"), 1);
+        let seal = RevealedSeal::with_tracked_blinding(
+            CloseMethod::OpretFirst,
+            outpoint,
+            54683213134637,
+            &mut tracker,
+        )
+        .unwrap();
+        assert_eq!(seal.blinding, 54683213134637);
+        assert_eq!(
+            RevealedSeal::with_tracked_blinding(
+                CloseMethod::TapretFirst,
+                OutPoint::new(Txid::hash(b"other"), 0),
+                54683213134637,
+                &mut tracker,
+            ),
+            Err(BlindingReuse::Reused)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn tracker_retries_collisions() {
+        use dbc::test_utils::TestRng;
+
+        let mut tracker = BlindingTracker::with_salt([1u8; 32]);
+        let outpoint = OutPoint::new(Txid::hash(b"seal"), 1);
+        let mut rng = TestRng::with_values([1, 1, 0, 2, 1, 2, 3]);
+        let method = CloseMethod::TapretFirst;
+        let seals = (0..3)
+            .map(|_| {
+                RevealedSeal::with_unique_blinding(
+                    method,
+                    outpoint,
+                    &mut rng,
+                    &mut tracker,
+                )
+                .unwrap()
+                .blinding
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(seals, vec![1, 2, 3]);
+        assert_eq!(tracker.len(), 3);
+
+        // Stuck generator exhausts attempts without changing the tracker
+        assert_eq!(
+            RevealedSeal::with_unique_blinding(
+                method,
+                outpoint,
+                &mut rng,
+                &mut tracker,
+            ),
+            Err(BlindingReuse::AttemptsExhausted(MAX_BLINDING_ATTEMPTS))
+        );
+        assert_eq!(tracker.len(), 3);
+    }
+
+    #[test]
+    fn audit() {
+        assert!(audit_reuse(&[]).is_empty());
+        assert!(audit_reuse(&[seal(0, 1), seal(0, 2), seal(1, 3)]).is_empty());
+        // Same seal listed twice is reported as well
+        assert_eq!(audit_reuse(&[seal(0, 1), seal(0, 1)]), vec![(0, 1)]);
+        assert_eq!(
+            audit_reuse(&[
+                seal(0, 7),
+                seal(1, 2),
+                seal(2, 2),
+                seal(3, 7),
+                seal(4, 9)
+            ]),
+            vec![(0, 3), (1, 2)]
+        );
+    }
+}
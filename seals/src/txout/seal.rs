@@ -13,11 +13,9 @@
 // along with this software.
 // If not, see <https://opensource.org/licenses/Apache-2.0>.
 
-use std::str::FromStr;
-
 use bitcoin::{OutPoint, Txid};
 
-use super::MethodParseError;
+use super::CloseMethod;
 
 /// Methods common for all transaction-output based seal types.
 pub trait TxoSeal {
@@ -41,42 +39,3 @@ pub trait TxoSeal {
     /// one using the provided `default_txid`.
     fn outpoint_or(&self, default_txid: Txid) -> OutPoint;
 }
-
-/// Method of single-use-seal closing.
-#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
-#[cfg_attr(
-    feature = "serde",
-    derive(Serialize, Deserialize),
-    serde(crate = "serde_crate")
-)]
-#[derive(StrictEncode, StrictDecode)]
-#[strict_encoding(by_value)]
-#[repr(u8)]
-#[non_exhaustive]
-pub enum CloseMethod {
-    /// Seal is closed over the message in form of OP_RETURN commitment present
-    /// in the first OP_RETURN-containing transaction output.
-    #[display("opret1st")]
-    OpretFirst = 0x00,
-
-    /// Seal is closed over the message in form of Taproot-based OP_RETURN
-    /// commitment present in the first Taproot transaction output.
-    #[display("tapret1st")]
-    TapretFirst = 0x01,
-}
-
-impl FromStr for CloseMethod {
-    type Err = MethodParseError;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(match s.to_lowercase() {
-            s if s == CloseMethod::OpretFirst.to_string() => {
-                CloseMethod::OpretFirst
-            }
-            s if s == CloseMethod::TapretFirst.to_string() => {
-                CloseMethod::TapretFirst
-            }
-            _ => return Err(MethodParseError(s.to_owned())),
-        })
-    }
-}
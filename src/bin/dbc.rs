@@ -26,9 +26,12 @@ use bitcoin::psbt::serialize::{Deserialize, Serialize};
 use bitcoin::psbt::PsbtParseError;
 use clap::Parser;
 use colored::Colorize;
+#[cfg(not(feature = "deterministic"))]
 use commit_verify::EmbedCommitVerify;
+#[cfg(not(feature = "deterministic"))]
 use dbc::anchor::PsbtEmbeddedMessage;
-use dbc::tapret::PsbtCommitError;
+use dbc::tapret::{normalize_internal_keys, InternalKeyError, PsbtCommitError};
+use dbc::Anchor;
 use psbt::Psbt;
 
 /// Command-line arguments
@@ -64,18 +67,43 @@ pub enum Command {
         /// Output file to save the PSBT updated with state transition(s)
         /// information. If not given, the source PSBT file is overwritten.
         psbt_out: Option<PathBuf>,
+
+        /// LNPBP-4 entropy to use for the commitment. If not given, the
+        /// entropy is generated with OS random number generator; required
+        /// in deterministic builds.
+        #[clap(short, long)]
+        entropy: Option<u64>,
     },
 }
 
 impl Args {
     pub fn exec(self) -> Result<(), Error> {
         match self.command {
-            Command::Commit { psbt_in, psbt_out } => {
+            Command::Commit {
+                psbt_in,
+                psbt_out,
+                entropy,
+            } => {
                 let psbt_bytes = fs::read(&psbt_in)?;
+                let psbt_bytes = normalize_internal_keys(&psbt_bytes)?;
                 let mut psbt = Psbt::deserialize(&psbt_bytes)?;
 
-                let anchor = psbt.embed_commit(&PsbtEmbeddedMessage)?;
-                eprintln!("Anchor: {:?}", anchor);
+                let anchor = match entropy {
+                    Some(entropy) => format!(
+                        "{:?}",
+                        Anchor::commit_with_entropy(&mut psbt, entropy)?
+                    ),
+                    #[cfg(not(feature = "deterministic"))]
+                    None => {
+                        format!(
+                            "{:?}",
+                            psbt.embed_commit(&PsbtEmbeddedMessage)?
+                        )
+                    }
+                    #[cfg(feature = "deterministic")]
+                    None => return Err(Error::EntropyRequired),
+                };
+                eprintln!("Anchor: {}", anchor);
 
                 let psbt_bytes = psbt.serialize();
                 fs::write(psbt_out.unwrap_or(psbt_in), psbt_bytes)?;
@@ -101,9 +129,21 @@ pub enum Error {
     #[from]
     PsbtBase58(PsbtParseError),
 
+    #[from]
+    #[display(inner)]
+    InternalKey(InternalKeyError),
+
     #[from]
     #[display(inner)]
     Commitment(PsbtCommitError),
+
+    #[from]
+    #[display(inner)]
+    Anchor(dbc::anchor::Error),
+
+    #[cfg(feature = "deterministic")]
+    #[display("LNPBP-4 entropy must be provided in deterministic builds")]
+    EntropyRequired,
 }
 
 fn main() {
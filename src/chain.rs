@@ -0,0 +1,409 @@
+// BP Core Library implementing LNP/BP specifications & standards related to
+// bitcoin protocol
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Chains of seal closing proofs with pruning of the validated history.
+//!
+//! Validation of a deep history requires keeping all witness transactions
+//! and anchors of the chain of state transitions. Once a prefix of the chain
+//! is validated, it may be replaced with a [`ChainCheckpoint`]: a digest over
+//! the ids of the closures in the prefix. Validation of the chain resumed
+//! from a checkpoint trusts the checkpoint and verifies only the remaining
+//! proofs; tampering with the pruned prefix is detectable only by comparing
+//! the checkpoint with the one computed by a party keeping the full chain.
+
+use bitcoin::hashes::{sha256, sha256t, Hash, HashEngine};
+use bitcoin_onchain::ResolveTx;
+use seals::txout::{ClosureId, ClosureProof, ClosureStatus, VerifyError};
+
+static MIDSTATE_CHAIN_CHECKPOINT: [u8; 32] = [
+    3, 19, 137, 5, 192, 59, 92, 111, 115, 30, 125, 207, 131, 100, 77, 102, 97,
+    16, 165, 6, 97, 29, 243, 200, 37, 159, 92, 53, 91, 197, 72, 35,
+];
+
+/// Tag used for [`ChainCheckpoint`] digest hash type
+pub struct ChainCheckpointTag;
+
+impl sha256t::Tag for ChainCheckpointTag {
+    #[inline]
+    fn engine() -> sha256::HashEngine {
+        let midstate = sha256::Midstate::from_inner(MIDSTATE_CHAIN_CHECKPOINT);
+        sha256::HashEngine::from_midstate(midstate, 64)
+    }
+}
+
+/// Errors computing checkpoints of a [`ClosureChain`].
+#[derive(
+    Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display, Error
+)]
+#[display(doc_comments)]
+pub enum ChainError {
+    /// checkpoint position {at} exceeds the chain length {len}.
+    OutOfRange {
+        /// Requested checkpoint position.
+        at: usize,
+        /// Length of the chain.
+        len: usize,
+    },
+
+    /// checkpoint position {at} lies inside the pruned prefix of the chain
+    /// of {pruned} closures.
+    Pruned {
+        /// Requested checkpoint position.
+        at: usize,
+        /// Length of the pruned prefix.
+        pruned: usize,
+    },
+}
+
+/// Digest of a prefix of a [`ClosureChain`].
+///
+/// The digest is computed by chaining tagged hashes over the closure ids
+/// (see [`ClosureProof::closure_id`]), starting from zero hash for the empty
+/// prefix, such that a checkpoint can be extended with new closures without
+/// access to the prefix.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct ChainCheckpoint {
+    /// Number of closures in the prefix.
+    pub length: u64,
+
+    /// Digest over the closure ids of the prefix.
+    pub digest: sha256t::Hash<ChainCheckpointTag>,
+}
+
+impl Default for ChainCheckpoint {
+    fn default() -> Self {
+        ChainCheckpoint {
+            length: 0,
+            digest: sha256t::Hash::all_zeros(),
+        }
+    }
+}
+
+impl ChainCheckpoint {
+    /// Returns checkpoint of the prefix extended with the closure.
+    pub fn extend(&self, closure_id: ClosureId) -> ChainCheckpoint {
+        let mut engine = sha256t::Hash::<ChainCheckpointTag>::engine();
+        engine.input(&self.digest[..]);
+        engine.input(&closure_id[..]);
+        ChainCheckpoint {
+            length: self.length + 1,
+            digest: sha256t::Hash::from_engine(engine),
+        }
+    }
+}
+
+/// Append-only chain of seal closing proofs, which prefix may be pruned and
+/// replaced with a [`ChainCheckpoint`].
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct ClosureChain {
+    pruned: ChainCheckpoint,
+    proofs: Vec<ClosureProof>,
+}
+
+impl ClosureChain {
+    /// Constructs empty chain.
+    #[inline]
+    pub fn new() -> ClosureChain { ClosureChain::default() }
+
+    /// Constructs chain resumed from the `checkpoint` of the already
+    /// validated prefix, followed by the `remaining` proofs. The checkpoint
+    /// is trusted: [`ClosureChain::verify_chain`] verifies only the remaining
+    /// proofs.
+    #[inline]
+    pub fn resume_from(
+        checkpoint: ChainCheckpoint,
+        remaining: impl IntoIterator<Item = ClosureProof>,
+    ) -> ClosureChain {
+        ClosureChain {
+            pruned: checkpoint,
+            proofs: remaining.into_iter().collect(),
+        }
+    }
+
+    /// Returns number of closures in the chain, including the pruned ones.
+    #[inline]
+    pub fn len(&self) -> usize { self.pruned_len() + self.proofs.len() }
+
+    /// Detects whether the chain has no closures, including the pruned ones.
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// Returns number of closures in the pruned prefix of the chain.
+    #[inline]
+    pub fn pruned_len(&self) -> usize { self.pruned.length as usize }
+
+    /// Returns checkpoint of the pruned prefix of the chain.
+    #[inline]
+    pub fn pruned_checkpoint(&self) -> ChainCheckpoint { self.pruned }
+
+    /// Returns proofs of the chain which were not pruned.
+    #[inline]
+    pub fn proofs(&self) -> &[ClosureProof] { &self.proofs }
+
+    /// Appends proof to the end of the chain.
+    #[inline]
+    pub fn push(&mut self, proof: ClosureProof) { self.proofs.push(proof) }
+
+    /// Verifies all proofs of the chain which were not pruned against the
+    /// witness transactions retrieved with the provided `resolver`, stopping
+    /// on the first proof which fails the verification. See
+    /// [`ClosureProof::verify`] for the details.
+    ///
+    /// Returns [`ClosureStatus::SealUnchecked`] if some of the proofs have
+    /// concealed seals and all proofs commit to their messages.
+    #[allow(clippy::result_large_err)]
+    pub fn verify_chain(
+        &self,
+        resolver: &impl ResolveTx,
+    ) -> Result<ClosureStatus, VerifyError> {
+        let mut status = ClosureStatus::Closed;
+        for proof in &self.proofs {
+            match proof.verify(resolver)? {
+                ClosureStatus::Closed => {}
+                ClosureStatus::SealUnchecked => {
+                    status = ClosureStatus::SealUnchecked
+                }
+                ClosureStatus::NotCommitted => {
+                    return Ok(ClosureStatus::NotCommitted)
+                }
+            }
+        }
+        Ok(status)
+    }
+
+    /// Computes checkpoint of the first `at` closures of the chain.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `at` exceeds the chain length or lies inside the pruned
+    /// prefix of the chain.
+    pub fn checkpoint(&self, at: usize) -> Result<ChainCheckpoint, ChainError> {
+        let pruned = self.pruned_len();
+        if at > self.len() {
+            return Err(ChainError::OutOfRange {
+                at,
+                len: self.len(),
+            });
+        }
+        if at < pruned {
+            return Err(ChainError::Pruned { at, pruned });
+        }
+        Ok(self.proofs[..at - pruned]
+            .iter()
+            .fold(self.pruned, |checkpoint, proof| {
+                checkpoint.extend(proof.closure_id())
+            }))
+    }
+
+    /// Prunes the first `at` closures of the chain, replacing them with their
+    /// checkpoint, which is returned. The pruned proofs must be validated
+    /// before pruning.
+    ///
+    /// # Errors
+    ///
+    /// Errors in the same cases as [`ClosureChain::checkpoint`]; the chain is
+    /// not changed in this case.
+    pub fn prune(&mut self, at: usize) -> Result<ChainCheckpoint, ChainError> {
+        let checkpoint = self.checkpoint(at)?;
+        self.proofs.drain(..at - self.pruned_len());
+        self.pruned = checkpoint;
+        Ok(checkpoint)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::Wrapper;
+    use commit_verify::tagged_hash;
+
+    use super::*;
+
+    #[test]
+    fn checkpoint_midstate() {
+        let midstate = tagged_hash::Midstate::with(b"bp:closure:checkpoint");
+        assert_eq!(
+            midstate.into_inner().into_inner(),
+            MIDSTATE_CHAIN_CHECKPOINT
+        );
+    }
+
+    #[cfg(feature = "rand")]
+    mod chain {
+        use std::collections::BTreeMap;
+
+        use bitcoin::{Transaction, Txid};
+        use dbc::test_utils::{opret_fixture, protocol_fixture};
+        use seals::txout::blind::RevealedSeal;
+        use seals::txout::{CloseMethod, TxoSeal};
+        use strict_encoding::{strict_deserialize, strict_serialize};
+
+        use super::*;
+
+        /// Constructs chain of `len` closures, each spending the output of
+        /// the previous witness transaction, together with the resolver of
+        /// the witness transactions.
+        fn chain_fixture(
+            len: u8,
+        ) -> (ClosureChain, BTreeMap<Txid, Transaction>) {
+            let (anchor, tx) = opret_fixture();
+            let (protocol_id, _) = protocol_fixture(1);
+            let mut prev_txid = Txid::hash(b"genesis");
+            let mut chain = ClosureChain::new();
+            let mut resolver = BTreeMap::new();
+            for no in 0..len {
+                let seal = RevealedSeal::checked(
+                    CloseMethod::OpretFirst,
+                    Some(prev_txid),
+                    0,
+                    no as u64 + 1,
+                )
+                .unwrap();
+                let mut tx = tx.clone();
+                tx.input[0].previous_output = seal.outpoint().unwrap();
+                let anchor = anchor.clone().with_txid(tx.txid());
+                let (_, message) = protocol_fixture(1);
+                chain.push(
+                    ClosureProof::with(seal, &anchor, protocol_id, message)
+                        .unwrap(),
+                );
+                prev_txid = tx.txid();
+                resolver.insert(tx.txid(), tx);
+            }
+            (chain, resolver)
+        }
+
+        #[test]
+        fn verify_chain() {
+            let (chain, resolver) = chain_fixture(4);
+            assert_eq!(chain.len(), 4);
+            assert_eq!(
+                chain.verify_chain(&resolver).unwrap(),
+                ClosureStatus::Closed
+            );
+            assert_eq!(
+                ClosureChain::new().verify_chain(&resolver).unwrap(),
+                ClosureStatus::Closed
+            );
+
+            let mut concealed = chain.clone();
+            concealed.proofs[1].seal =
+                concealed.proofs[1].seal.to_concealed_seal().into();
+            assert_eq!(
+                concealed.verify_chain(&resolver).unwrap(),
+                ClosureStatus::SealUnchecked
+            );
+
+            let mut broken = concealed;
+            broken.proofs[2].message = protocol_fixture(2).1;
+            assert_eq!(
+                broken.verify_chain(&resolver).unwrap(),
+                ClosureStatus::NotCommitted
+            );
+
+            let data = strict_serialize(&chain).unwrap();
+            let decoded: ClosureChain = strict_deserialize(data).unwrap();
+            assert_eq!(decoded, chain);
+        }
+
+        #[test]
+        fn checkpoints() {
+            let (chain, _) = chain_fixture(4);
+            assert_eq!(chain.checkpoint(0), Ok(ChainCheckpoint::default()));
+            let checkpoint = chain.checkpoint(2).unwrap();
+            assert_eq!(checkpoint.length, 2);
+            assert_eq!(
+                checkpoint,
+                ChainCheckpoint::default()
+                    .extend(chain.proofs[0].closure_id())
+                    .extend(chain.proofs[1].closure_id())
+            );
+            assert_ne!(checkpoint, chain.checkpoint(3).unwrap());
+            assert_eq!(
+                chain.checkpoint(5),
+                Err(ChainError::OutOfRange { at: 5, len: 4 })
+            );
+
+            let data = strict_serialize(&checkpoint).unwrap();
+            let decoded: ChainCheckpoint = strict_deserialize(data).unwrap();
+            assert_eq!(decoded, checkpoint);
+        }
+
+        #[test]
+        fn prune_and_resume() {
+            let (chain, resolver) = chain_fixture(4);
+            let full = chain.checkpoint(4).unwrap();
+
+            let mut pruned = chain.clone();
+            let checkpoint = pruned.prune(2).unwrap();
+            assert_eq!(checkpoint, chain.checkpoint(2).unwrap());
+            assert_eq!(pruned.len(), 4);
+            assert_eq!(pruned.pruned_len(), 2);
+            assert_eq!(pruned.proofs(), &chain.proofs[2..]);
+            assert_eq!(pruned.checkpoint(4), Ok(full));
+            assert_eq!(
+                pruned.checkpoint(1),
+                Err(ChainError::Pruned { at: 1, pruned: 2 })
+            );
+            assert!(pruned.verify_chain(&resolver).unwrap().is_closed());
+
+            let resumed = ClosureChain::resume_from(
+                checkpoint,
+                chain.proofs[2..].to_vec(),
+            );
+            assert_eq!(resumed, pruned);
+
+            // Pruning is incremental
+            let mut twice = pruned.clone();
+            twice.prune(3).unwrap();
+            let mut once = chain.clone();
+            once.prune(3).unwrap();
+            assert_eq!(twice, once);
+            assert_eq!(
+                twice.prune(1),
+                Err(ChainError::Pruned { at: 1, pruned: 3 })
+            );
+            assert_eq!(twice, once);
+        }
+
+        #[test]
+        fn tampered_prefix() {
+            let (chain, resolver) = chain_fixture(4);
+            let checkpoint = chain.checkpoint(2).unwrap();
+
+            let mut tampered = chain.clone();
+            tampered.proofs[1].message = protocol_fixture(2).1;
+            let forged = tampered.checkpoint(2).unwrap();
+            assert_ne!(forged, checkpoint);
+
+            // Validation resumed from the pruned tampered prefix trusts the
+            // checkpoint, so tampering is detected only by comparing it with
+            // the checkpoint of the original chain
+            let resumed =
+                ClosureChain::resume_from(forged, chain.proofs[2..].to_vec());
+            assert!(resumed.verify_chain(&resolver).unwrap().is_closed());
+            assert_ne!(resumed.pruned_checkpoint(), checkpoint);
+            assert_ne!(resumed.checkpoint(4), chain.checkpoint(4));
+
+            // Prefix tampering not changing closure ids (like SPV proof
+            // removal or seal concealment) keeps the checkpoint
+            let mut concealed = chain.clone();
+            concealed.proofs[0].seal =
+                concealed.proofs[0].seal.to_concealed_seal().into();
+            assert_eq!(concealed.checkpoint(2), Ok(checkpoint));
+        }
+    }
+}
@@ -38,6 +38,13 @@ extern crate strict_encoding;
 pub extern crate dbc;
 pub extern crate seals;
 
+pub use chain::{ChainCheckpoint, ChainError, ClosureChain};
+pub use commit_verify::lnpbp4::{Message, ProtocolId};
+pub use dbc::{Msg, ProtoId};
+
+/// Chains of seal closing proofs with pruning of the validated history
+pub mod chain;
+
 /// pub short_id module
 /// allows efficient representation of protocol entities
 pub mod short_id;